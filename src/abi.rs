@@ -184,9 +184,19 @@ macro_rules! impl_CallFromGuest {
                 let args: ($($P,)*) = {
                     ($(read_next_arg::<$P>(&mut reg_offset, regs, Ptr::from_bits(regs[Cpu::SP]), &env.mem),)*)
                 };
-                log_dbg!("CallFromGuest {:?}", args);
+                let traced = env.traced_host_call.take();
+                if let Some(ref symbol) = traced {
+                    let args: &[String] = &[$(format!("{:?}", args.$p)),*];
+                    echo!("{}({})", symbol, args.join(", "));
+                } else {
+                    log_dbg!("CallFromGuest {:?}", args);
+                }
                 let retval = self(env, $(args.$p),*);
-                log_dbg!("CallFromGuest => {:?}", retval);
+                if let Some(ref symbol) = traced {
+                    echo!("{} => {:?}", symbol, retval);
+                } else {
+                    log_dbg!("CallFromGuest => {:?}", retval);
+                }
                 if let Some(retval_ptr) = retval_ptr {
                     retval.to_mem(retval_ptr, &mut env.mem);
                 } else {
@@ -211,9 +221,19 @@ macro_rules! impl_CallFromGuest {
                     reg_offset,
                     stack_pointer: Ptr::from_bits(regs[Cpu::SP])
                 });
-                log_dbg!("CallFromGuest {:?}, ...{:?}", args, va_list);
+                let traced = env.traced_host_call.take();
+                if let Some(ref symbol) = traced {
+                    let args: &[String] = &[$(format!("{:?}", args.$p),)* format!("...{:?}", va_list)];
+                    echo!("{}({})", symbol, args.join(", "));
+                } else {
+                    log_dbg!("CallFromGuest {:?}, ...{:?}", args, va_list);
+                }
                 let retval = self(env, $(args.$p,)* va_list);
-                log_dbg!("CallFromGuest => {:?}", retval);
+                if let Some(ref symbol) = traced {
+                    echo!("{} => {:?}", symbol, retval);
+                } else {
+                    log_dbg!("CallFromGuest => {:?}", retval);
+                }
                 if let Some(retval_ptr) = retval_ptr {
                     retval.to_mem(retval_ptr, &mut env.mem);
                 } else {
@@ -670,6 +690,15 @@ impl<T, const MUT: bool> GuestRet for Ptr<T, MUT> {
     }
 }
 
+impl GuestRet for GuestFunction {
+    fn from_regs(regs: &[u32]) -> Self {
+        GuestFunction(<ConstVoidPtr as GuestRet>::from_regs(regs))
+    }
+    fn to_regs(self, regs: &mut [u32]) {
+        <ConstVoidPtr as GuestRet>::to_regs(self.0, regs)
+    }
+}
+
 // GuestRet implementations for u64-like types
 
 impl GuestRet for u64 {
@@ -6,20 +6,26 @@
 //! Audio file decoding and OpenAL bindings.
 //!
 //! The audio file decoding support is an abstraction over various libraries
-//! (currently [caf], [hound], and dr_mp3), usage of which should be confined to
-//! this module.
+//! (currently [hound], dr_mp3 and Symphonia) and touchHLE's own CAF, MP3 and
+//! MPEG-4 file readers, usage of which should be confined to this module. Files
+//! are read on demand rather than being loaded into memory all at once.
 //!
 //! Resources:
 //! - [Apple Core Audio Format Specification 1.0](https://developer.apple.com/library/archive/documentation/MusicAudio/Reference/CAFSpec/CAF_intro/CAF_intro.html)
 
+mod caf;
+mod decoder;
 mod ima4;
+mod m4a;
+mod mp3;
 
-pub use ima4::decode_ima4;
+pub use decoder::AudioDecoder;
+pub use ima4::decode_ima4_interleaved;
 use touchHLE_dr_mp3_wrapper as dr_mp3;
 pub use touchHLE_openal_soft_wrapper as openal;
 
-use crate::fs::{Fs, GuestPath};
-use std::io::Cursor;
+use crate::fs::{Fs, GuestFile, GuestPath};
+use std::io::{BufReader, Read, Seek};
 
 #[derive(Debug)]
 pub enum AudioFormat {
@@ -28,6 +34,9 @@ pub enum AudioFormat {
         is_little_endian: bool,
     },
     AppleIma4,
+    /// MPEG-4 AAC-LC
+    Aac,
+    AppleLossless,
 }
 /// Fields have the same meanings as in the Core Audio Format's
 /// Audio Description chunk, which is in turn similar to Core Audio Types'
@@ -43,37 +52,53 @@ pub struct AudioDescription {
     pub bits_per_channel: u32,
 }
 
+/// Location of a packet within a buffer, similar to Core Audio Types'
+/// `AudioStreamPacketDescription`.
+#[derive(Debug)]
+pub struct PacketDescription {
+    /// Byte offset within the buffer.
+    pub offset: u64,
+    /// Size in bytes.
+    pub size: u32,
+    /// Number of frames in the packet if the format has a variable number of
+    /// frames per packet, otherwise 0.
+    pub frames: u32,
+}
+
 pub struct AudioFile(AudioFileInner);
 enum AudioFileInner {
-    Wave(hound::WavReader<Cursor<Vec<u8>>>),
-    Caf(caf::CafPacketReader<Cursor<Vec<u8>>>),
-    Mp3(dr_mp3::Mp3DecodedToPcm),
+    Wave(hound::WavReader<BufReader<GuestFile>>),
+    Caf(caf::CafReader<BufReader<GuestFile>>),
+    Mp3(mp3::Mp3Reader<GuestFile>),
+    M4a(m4a::M4aReader<BufReader<GuestFile>>),
 }
 
 impl AudioFile {
     pub fn open_for_reading<P: AsRef<GuestPath>>(path: P, fs: &Fs) -> Result<Self, ()> {
-        // TODO: it would be better not to load the whole file at once
-        let bytes = fs.read(path.as_ref())?;
+        let mut file = fs.open(path.as_ref())?;
 
-        // Both WavReader::new() and CafPacketReader::new() consume the reader
-        // (in this case, a Cursor) passed to them. This is a bit annoying
-        // considering we don't know which is appropriate for the file without
-        // trying both. This is worked around here by using temporary readers
-        // for checking if the file is the supported format, then recreating the
-        // reader if that works.
+        // Check the magic number first, because the readers consume the file
+        // passed to them even if they fail.
+        let mut magic = [0u8; 12];
+        let magic_len = file.read(&mut magic).map_err(|_| ())?;
+        let magic = &magic[..magic_len];
+        file.rewind().map_err(|_| ())?;
 
-        if hound::WavReader::new(Cursor::new(&bytes)).is_ok() {
-            let reader = hound::WavReader::new(Cursor::new(bytes)).unwrap();
+        if magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"WAVE") {
+            let reader = hound::WavReader::new(BufReader::new(file)).map_err(|_| ())?;
             Ok(AudioFile(AudioFileInner::Wave(reader)))
-        } else if caf::CafPacketReader::new(Cursor::new(&bytes), vec![]).is_ok() {
-            let reader = caf::CafPacketReader::new(Cursor::new(bytes), vec![]).unwrap();
+        } else if magic.starts_with(b"caff") {
+            let reader = caf::CafReader::new(BufReader::new(file))?;
             Ok(AudioFile(AudioFileInner::Caf(reader)))
-        // TODO: Real MP3 container handling. Currently we are immediately
-        // decoding the entire file to PCM and acting as if it's a PCM file,
-        // simply because because this is easier. Full MP3 support would require
-        // a lot of changes in Audio Toolbox.
-        } else if let Ok(pcm) = dr_mp3::decode_mp3_to_pcm(&bytes) {
-            Ok(AudioFile(AudioFileInner::Mp3(pcm)))
+        } else if magic.get(4..8) == Some(b"ftyp") {
+            let reader = m4a::M4aReader::new(BufReader::new(file))?;
+            Ok(AudioFile(AudioFileInner::M4a(reader)))
+        // TODO: Real MP3 container handling. Currently we are decoding MP3
+        // frames to PCM on demand and acting as if it's a PCM file, simply
+        // because this is easier. Full MP3 support would require a lot of
+        // changes in Audio Toolbox.
+        } else if let Ok(reader) = mp3::Mp3Reader::new(file) {
+            Ok(AudioFile(AudioFileInner::Mp3(reader)))
         } else {
             // We may eventually want to return an error here, this is just more
             // useful currently.
@@ -113,9 +138,9 @@ impl AudioFile {
                 }
             }
             AudioFileInner::Caf(ref caf_reader) => {
-                let caf::chunks::AudioDescription {
+                let caf::CafAudioDescription {
                     sample_rate,
-                    ref format_id,
+                    format_id,
                     format_flags,
                     bytes_per_packet,
                     frames_per_packet,
@@ -125,8 +150,8 @@ impl AudioFile {
 
                 AudioDescription {
                     sample_rate,
-                    format: match format_id {
-                        caf::FormatType::LinearPcm => {
+                    format: match &format_id {
+                        b"lpcm" => {
                             assert!((format_flags & !3) == 0);
                             let is_float = (format_flags & 1) == 1;
                             let is_little_endian = (format_flags & 2) == 2;
@@ -135,14 +160,19 @@ impl AudioFile {
                                 is_little_endian,
                             }
                         }
-                        caf::FormatType::AppleIma4 => {
+                        b"ima4" => {
                             assert!(format_flags == 0);
                             AudioFormat::AppleIma4
                         }
+                        b"aac " => AudioFormat::Aac,
+                        b"alac" => AudioFormat::AppleLossless,
                         //
                         // We should expose all of the formats eventually, but
                         // the others haven't been tested yet.
-                        _ => panic!("{:?} not supported yet", format_id),
+                        _ => panic!(
+                            "{:?} not supported yet",
+                            String::from_utf8_lossy(&format_id)
+                        ),
                     },
                     bytes_per_packet,
                     frames_per_packet,
@@ -150,21 +180,38 @@ impl AudioFile {
                     bits_per_channel,
                 }
             }
-            AudioFileInner::Mp3(dr_mp3::Mp3DecodedToPcm {
-                sample_rate,
-                channels,
-                ..
-            }) => AudioDescription {
-                sample_rate: f64::from(sample_rate),
+            AudioFileInner::Mp3(ref mp3_reader) => AudioDescription {
+                sample_rate: f64::from(mp3_reader.sample_rate),
                 format: AudioFormat::LinearPcm {
                     is_float: false,
                     is_little_endian: true,
                 },
-                bytes_per_packet: channels * 2,
+                bytes_per_packet: mp3_reader.channels * 2,
                 frames_per_packet: 1,
-                channels_per_frame: channels,
+                channels_per_frame: mp3_reader.channels,
                 bits_per_channel: 16,
             },
+            AudioFileInner::M4a(ref m4a_reader) => {
+                let (format, frames_per_packet) = if &m4a_reader.format_id == b"alac" {
+                    // The frame length is the first field of the config.
+                    let frame_length = m4a_reader
+                        .magic_cookie
+                        .as_ref()
+                        .and_then(|cookie| cookie.get(..4))
+                        .map_or(4096, |bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+                    (AudioFormat::AppleLossless, frame_length)
+                } else {
+                    (AudioFormat::Aac, 1024)
+                };
+                AudioDescription {
+                    sample_rate: m4a_reader.sample_rate,
+                    format,
+                    bytes_per_packet: 0, // variable
+                    frames_per_packet,
+                    channels_per_frame: m4a_reader.channels,
+                    bits_per_channel: 0,
+                }
+            }
         }
     }
 
@@ -188,23 +235,20 @@ impl AudioFile {
                 let sample_count = wave_reader.len(); // position-independent
                 u64::from(sample_count) * self.bytes_per_sample()
             }
-            AudioFileInner::Caf(_) => {
-                // variable size not implemented
-                u64::from(self.packet_size_fixed()) * self.packet_count()
-            }
-            AudioFileInner::Mp3(dr_mp3::Mp3DecodedToPcm { ref bytes, .. }) => bytes.len() as u64,
+            AudioFileInner::Caf(ref caf_reader) => caf_reader.data_size(),
+            AudioFileInner::Mp3(ref mp3_reader) => mp3_reader.byte_count(),
+            AudioFileInner::M4a(ref m4a_reader) => m4a_reader.data_size(),
         }
     }
 
     pub fn packet_count(&self) -> u64 {
         match self.0 {
-            AudioFileInner::Wave(_) | AudioFileInner::Mp3(dr_mp3::Mp3DecodedToPcm { .. }) => {
+            AudioFileInner::Wave(_) | AudioFileInner::Mp3(_) => {
                 // never variable-size
                 self.byte_count() / u64::from(self.packet_size_fixed())
             }
-            AudioFileInner::Caf(ref caf_reader) => {
-                caf_reader.get_packet_count().unwrap().try_into().unwrap()
-            }
+            AudioFileInner::Caf(ref caf_reader) => caf_reader.packet_count(),
+            AudioFileInner::M4a(ref m4a_reader) => m4a_reader.packet_count(),
         }
     }
 
@@ -219,11 +263,16 @@ impl AudioFile {
     }
 
     pub fn packet_size_upper_bound(&self) -> u32 {
-        self.packet_size_fixed() // variable size not implemented
+        match self.0 {
+            AudioFileInner::Wave(_) | AudioFileInner::Mp3(_) => self.packet_size_fixed(),
+            AudioFileInner::Caf(ref caf_reader) => caf_reader.packet_size_upper_bound(),
+            AudioFileInner::M4a(ref m4a_reader) => m4a_reader.packet_size_upper_bound(),
+        }
     }
 
     /// Read `buffer.len()` bytes of audio data from byte offset `offset`.
-    /// Returns the number of bytes read.
+    /// Returns the number of bytes read, which is less than `buffer.len()` only
+    /// if the end of the audio data was reached.
     pub fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, ()> {
         match self.0 {
             AudioFileInner::Wave(_) => {
@@ -236,8 +285,9 @@ impl AudioFile {
 
                 let AudioFileInner::Wave(ref mut wave_reader) = self.0 else { unreachable!() };
 
+                let sample_offset = (offset / bytes_per_sample).min(wave_reader.len().into());
                 wave_reader
-                    .seek((offset / bytes_per_sample).try_into().unwrap())
+                    .seek(sample_offset.try_into().unwrap())
                     .map_err(|_| ())?;
 
                 assert!(bytes_per_sample == 2);
@@ -249,40 +299,91 @@ impl AudioFile {
                 }
                 Ok(byte_offset)
             }
-            AudioFileInner::Caf(_) => {
-                // variable size not implemented
-                let packet_size = self.packet_size_fixed();
-                assert!(offset % u64::from(packet_size) == 0);
-                assert!(u64::try_from(buffer.len()).unwrap() % u64::from(packet_size) == 0);
-
-                let packet_count = u64::try_from(buffer.len()).unwrap() / u64::from(packet_size);
-
-                let AudioFileInner::Caf(ref mut caf_reader) = self.0 else { unreachable!() };
-
-                caf_reader
-                    .seek_to_packet(usize::try_from(offset / u64::from(packet_size)).unwrap())
-                    .map_err(|_| ())?;
-
-                let packet_size = usize::try_from(packet_size).unwrap();
+            AudioFileInner::Caf(ref mut caf_reader) => caf_reader.read_bytes(offset, buffer),
+            AudioFileInner::Mp3(ref mut mp3_reader) => mp3_reader.read_bytes(offset, buffer),
+            AudioFileInner::M4a(ref mut m4a_reader) => m4a_reader.read_bytes(offset, buffer),
+        }
+    }
 
-                let mut i = 0;
-                let mut byte_offset = 0;
-                while i < packet_count {
-                    caf_reader
-                        .read_packet_into(&mut buffer[byte_offset..][..packet_size])
-                        .map_err(|_| ())?;
-                    byte_offset += packet_size;
-                    i += 1;
-                }
-                Ok(byte_offset)
+    /// Read up to `packet_count` packets starting from packet `first_packet`,
+    /// as many as fit into `buffer`. Returns the location of each packet read
+    /// within `buffer`.
+    pub fn read_packets(
+        &mut self,
+        first_packet: u64,
+        packet_count: u32,
+        buffer: &mut [u8],
+    ) -> Result<Vec<PacketDescription>, ()> {
+        match self.0 {
+            AudioFileInner::Caf(ref mut caf_reader) if caf_reader.has_packet_table() => {
+                caf_reader.read_packets(first_packet, packet_count, buffer)
+            }
+            AudioFileInner::M4a(ref mut m4a_reader) => {
+                m4a_reader.read_packets(first_packet, packet_count, buffer)
             }
-            AudioFileInner::Mp3(dr_mp3::Mp3DecodedToPcm { ref bytes, .. }) => {
-                let bytes = bytes.get(offset as usize..).ok_or(())?;
-                let bytes_to_read = buffer.len().min(bytes.len());
-                let bytes = &bytes[..bytes_to_read];
-                buffer[..bytes_to_read].copy_from_slice(bytes);
-                Ok(bytes_to_read)
+            _ => {
+                let packet_size = self.packet_size_fixed();
+                let packet_count = (packet_count as usize).min(buffer.len() / packet_size as usize);
+                let buffer = &mut buffer[..packet_count * packet_size as usize];
+                let bytes_read = self.read_bytes(first_packet * u64::from(packet_size), buffer)?;
+                Ok((0..(bytes_read as u64 / u64::from(packet_size)))
+                    .map(|i| PacketDescription {
+                        offset: i * u64::from(packet_size),
+                        size: packet_size,
+                        frames: 0,
+                    })
+                    .collect())
             }
         }
     }
+
+    /// Codec configuration needed to decode some compressed formats, see
+    /// [AudioDecoder::new].
+    pub fn magic_cookie(&self) -> Option<&[u8]> {
+        match self.0 {
+            AudioFileInner::Wave(_) | AudioFileInner::Mp3(_) => None,
+            AudioFileInner::Caf(ref caf_reader) => caf_reader.magic_cookie.as_deref(),
+            AudioFileInner::M4a(ref m4a_reader) => m4a_reader.magic_cookie.as_deref(),
+        }
+    }
+}
+
+/// Convert integer or floating-point linear PCM to 16-bit signed samples.
+/// `bytes_per_sample` is the size of a single channel's sample, which must be
+/// 1, 2, 3 or 4 for integers and 4 or 8 for floating-point. Integer samples
+/// are truncated to their two most significant bytes.
+pub fn pcm_to_i16(
+    bytes: &[u8],
+    bytes_per_sample: usize,
+    is_float: bool,
+    is_signed: bool,
+    is_big_endian: bool,
+) -> Vec<i16> {
+    let bytes_per_sample = bytes_per_sample.max(1);
+    bytes
+        .chunks_exact(bytes_per_sample)
+        .map(|sample| {
+            let mut sample = sample.to_vec();
+            if !is_big_endian {
+                sample.reverse();
+            }
+            if is_float {
+                let value = match bytes_per_sample {
+                    4 => f64::from(f32::from_be_bytes(sample[..].try_into().unwrap())),
+                    8 => f64::from_be_bytes(sample[..].try_into().unwrap()),
+                    _ => 0.0,
+                };
+                return (value.clamp(-1.0, 1.0) * 32767.0) as i16;
+            }
+            if !is_signed {
+                sample[0] ^= 0x80;
+            }
+            if bytes_per_sample == 1 {
+                i16::from(sample[0] as i8) << 8
+            } else {
+                // Keep the two most significant bytes.
+                i16::from_be_bytes([sample[0], sample[1]])
+            }
+        })
+        .collect()
 }
@@ -0,0 +1,266 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Core Audio Format (`.caf`) file reading.
+//!
+//! Only the chunks needed to locate the audio data are parsed. The audio data
+//! is read on demand, so the file doesn't have to be loaded into memory.
+
+use super::PacketDescription;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Audio Description chunk (`desc`) contents.
+#[derive(Debug)]
+pub struct CafAudioDescription {
+    /// Hz
+    pub sample_rate: f64,
+    /// FourCC
+    pub format_id: [u8; 4],
+    pub format_flags: u32,
+    pub bytes_per_packet: u32,
+    pub frames_per_packet: u32,
+    pub channels_per_frame: u32,
+    pub bits_per_channel: u32,
+}
+
+/// Location of a packet within the audio data.
+struct PacketLocation {
+    offset: u64,
+    size: u32,
+    frames: u32,
+}
+
+pub struct CafReader<R: Read + Seek> {
+    reader: R,
+    pub audio_desc: CafAudioDescription,
+    /// Magic Cookie chunk (`kuki`) contents, needed by some compressed formats.
+    pub magic_cookie: Option<Vec<u8>>,
+    /// Offset of the audio data in the file.
+    data_offset: u64,
+    data_size: u64,
+    /// Only present for formats with variable packet sizes or frame counts.
+    packet_table: Option<Vec<PacketLocation>>,
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], ()> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes).map_err(|_| ())?;
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, ()> {
+    read_array(reader).map(u32::from_be_bytes)
+}
+
+fn read_i64<R: Read>(reader: &mut R) -> Result<i64, ()> {
+    read_array(reader).map(i64::from_be_bytes)
+}
+
+/// Read an integer from the packet table, which uses a variable-length
+/// encoding: 7 bits per byte, most significant first, with the high bit set on
+/// all bytes but the last.
+fn read_variable_length_int(bytes: &mut &[u8]) -> Result<u64, ()> {
+    let mut value: u64 = 0;
+    loop {
+        let (&byte, rest) = bytes.split_first().ok_or(())?;
+        *bytes = rest;
+        value = value.checked_mul(128).ok_or(())? | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+impl<R: Read + Seek> CafReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, ()> {
+        let header: [u8; 8] = read_array(&mut reader)?;
+        if &header[..4] != b"caff" || header[4..6] != [0, 1] {
+            return Err(());
+        }
+
+        let mut audio_desc = None;
+        let mut data = None;
+        let mut packet_table_chunk = None;
+        let mut magic_cookie = None;
+        let mut offset = 8;
+        let file_size = reader.seek(SeekFrom::End(0)).map_err(|_| ())?;
+        while offset < file_size {
+            reader.seek(SeekFrom::Start(offset)).map_err(|_| ())?;
+            let chunk_type: [u8; 4] = read_array(&mut reader)?;
+            let chunk_size = read_i64(&mut reader)?;
+            let chunk_offset = offset + 12;
+            // Only the data chunk may have an unknown size (-1), in which case
+            // it extends to the end of the file.
+            let chunk_size = match u64::try_from(chunk_size) {
+                Ok(size) => size,
+                Err(_) if &chunk_type == b"data" => file_size.saturating_sub(chunk_offset),
+                Err(_) => return Err(()),
+            };
+
+            match &chunk_type {
+                b"desc" => {
+                    let sample_rate = f64::from_be_bytes(read_array(&mut reader)?);
+                    audio_desc = Some(CafAudioDescription {
+                        sample_rate,
+                        format_id: read_array(&mut reader)?,
+                        format_flags: read_u32(&mut reader)?,
+                        bytes_per_packet: read_u32(&mut reader)?,
+                        frames_per_packet: read_u32(&mut reader)?,
+                        channels_per_frame: read_u32(&mut reader)?,
+                        bits_per_channel: read_u32(&mut reader)?,
+                    });
+                }
+                b"data" => {
+                    // The audio data is preceded by an edit count.
+                    data = Some((chunk_offset + 4, chunk_size.checked_sub(4).ok_or(())?));
+                }
+                b"pakt" | b"kuki" => {
+                    let mut chunk = vec![0; chunk_size.try_into().map_err(|_| ())?];
+                    reader.read_exact(&mut chunk).map_err(|_| ())?;
+                    if &chunk_type == b"pakt" {
+                        packet_table_chunk = Some(chunk);
+                    } else {
+                        magic_cookie = Some(chunk);
+                    }
+                }
+                _ => (),
+            }
+
+            offset = chunk_offset.checked_add(chunk_size).ok_or(())?;
+        }
+
+        let audio_desc = audio_desc.ok_or(())?;
+        let (data_offset, data_size) = data.ok_or(())?;
+
+        let packet_table = if audio_desc.bytes_per_packet == 0 || audio_desc.frames_per_packet == 0
+        {
+            let chunk = packet_table_chunk.ok_or(())?;
+            Some(Self::parse_packet_table(&audio_desc, &chunk, data_size)?)
+        } else {
+            None
+        };
+
+        Ok(CafReader {
+            reader,
+            audio_desc,
+            magic_cookie,
+            data_offset,
+            data_size,
+            packet_table,
+        })
+    }
+
+    fn parse_packet_table(
+        audio_desc: &CafAudioDescription,
+        chunk: &[u8],
+        data_size: u64,
+    ) -> Result<Vec<PacketLocation>, ()> {
+        // The header also contains the number of valid, priming and remainder
+        // frames, which we currently ignore.
+        let (header, mut entries) = (chunk.get(..24).ok_or(())?, &chunk[24..]);
+        let packet_count = i64::from_be_bytes(header[..8].try_into().unwrap());
+        let packet_count: u64 = packet_count.try_into().map_err(|_| ())?;
+
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        for _ in 0..packet_count {
+            let size = if audio_desc.bytes_per_packet == 0 {
+                read_variable_length_int(&mut entries)?
+            } else {
+                audio_desc.bytes_per_packet.into()
+            };
+            let frames = if audio_desc.frames_per_packet == 0 {
+                read_variable_length_int(&mut entries)?
+            } else {
+                0
+            };
+            let size: u32 = size.try_into().map_err(|_| ())?;
+            if offset + u64::from(size) > data_size {
+                return Err(());
+            }
+            packets.push(PacketLocation {
+                offset,
+                size,
+                frames: frames.try_into().map_err(|_| ())?,
+            });
+            offset += u64::from(size);
+        }
+        Ok(packets)
+    }
+
+    /// Size of the audio data in bytes.
+    pub fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    pub fn packet_count(&self) -> u64 {
+        match self.packet_table {
+            Some(ref packets) => packets.len() as u64,
+            None => self.data_size / u64::from(self.audio_desc.bytes_per_packet),
+        }
+    }
+
+    pub fn packet_size_upper_bound(&self) -> u32 {
+        match self.packet_table {
+            Some(ref packets) => packets.iter().map(|packet| packet.size).max().unwrap_or(0),
+            None => self.audio_desc.bytes_per_packet,
+        }
+    }
+
+    /// Read audio data starting from byte offset `offset` within it. Returns
+    /// the number of bytes read, which is less than `buffer.len()` only if the
+    /// end of the data was reached.
+    pub fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, ()> {
+        let available = self.data_size.saturating_sub(offset);
+        let to_read = buffer.len().min(available.try_into().unwrap_or(usize::MAX));
+        self.reader
+            .seek(SeekFrom::Start(self.data_offset + offset))
+            .map_err(|_| ())?;
+        self.reader
+            .read_exact(&mut buffer[..to_read])
+            .map_err(|_| ())?;
+        Ok(to_read)
+    }
+
+    /// Returns [true] if the packets have variable sizes or frame counts, as
+    /// described by the packet table.
+    pub fn has_packet_table(&self) -> bool {
+        self.packet_table.is_some()
+    }
+
+    /// Read up to `packet_count` packets starting from packet `first_packet`,
+    /// as many as fit into `buffer`. Only for files with a packet table.
+    pub fn read_packets(
+        &mut self,
+        first_packet: u64,
+        packet_count: u32,
+        buffer: &mut [u8],
+    ) -> Result<Vec<PacketDescription>, ()> {
+        let packets = self.packet_table.as_ref().unwrap();
+        let first_packet = usize::try_from(first_packet).unwrap_or(usize::MAX);
+        let packets = packets.get(first_packet..).unwrap_or(&[]);
+        let data_offset = packets.first().map_or(0, |packet| packet.offset);
+        let mut descriptions = Vec::new();
+        let mut byte_count: u64 = 0;
+        for packet in packets.iter().take(packet_count as usize) {
+            if byte_count + u64::from(packet.size) > buffer.len() as u64 {
+                break;
+            }
+            descriptions.push(PacketDescription {
+                offset: byte_count,
+                size: packet.size,
+                frames: packet.frames,
+            });
+            byte_count += u64::from(packet.size);
+        }
+
+        // Packets are stored contiguously, so they can be read all at once.
+        let byte_count = byte_count as usize;
+        if self.read_bytes(data_offset, &mut buffer[..byte_count])? != byte_count {
+            return Err(());
+        }
+        Ok(descriptions)
+    }
+}
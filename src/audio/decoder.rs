@@ -0,0 +1,161 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Decoding of compressed audio packets (AAC and Apple Lossless) to PCM.
+//!
+//! The decoders themselves are provided by [symphonia_codec_aac] and
+//! [symphonia_codec_alac]. This module deals with Apple's magic cookie formats.
+//!
+//! Resources:
+//! - ISO/IEC 14496-1 (MPEG-4 Systems), for the ES descriptor
+//! - Apple's [ALAC magic cookie description](https://github.com/macosforge/alac/blob/master/ALACMagicCookieDescription.txt)
+
+use super::AudioFormat;
+use symphonia_core::audio::SampleBuffer;
+use symphonia_core::codecs::{
+    CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_AAC, CODEC_TYPE_ALAC,
+};
+use symphonia_core::formats::Packet;
+
+/// Read the length of an MPEG-4 descriptor, which is encoded with 7 bits per
+/// byte, most significant first, with the high bit set on all bytes but the
+/// last.
+fn read_descriptor_length(bytes: &mut &[u8]) -> Option<usize> {
+    let mut length = 0;
+    for _ in 0..4 {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        length = (length << 7) | usize::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Some(length);
+        }
+    }
+    None
+}
+
+/// Find the descriptor with tag `tag` at the start of `bytes` and return its
+/// contents.
+fn read_descriptor<'a>(bytes: &mut &'a [u8], tag: u8) -> Option<&'a [u8]> {
+    let (&found_tag, rest) = bytes.split_first()?;
+    *bytes = rest;
+    let length = read_descriptor_length(bytes)?;
+    let contents = bytes.get(..length)?;
+    *bytes = &bytes[length..];
+    (found_tag == tag).then_some(contents)
+}
+
+/// Extract the AudioSpecificConfig from an AAC magic cookie, which is an ES
+/// descriptor (the contents of an `esds` box, minus the version and flags).
+fn audio_specific_config(magic_cookie: &[u8]) -> Option<&[u8]> {
+    let mut bytes = magic_cookie;
+    let mut es_descriptor = read_descriptor(&mut bytes, 0x03)?;
+    let (_es_id, rest) = es_descriptor.split_at(2.min(es_descriptor.len()));
+    let (&flags, mut rest) = rest.split_first()?;
+    if flags & 0x80 != 0 {
+        rest = rest.get(2..)?; // dependsOn_ES_ID
+    }
+    if flags & 0x40 != 0 {
+        let (&url_length, after) = rest.split_first()?;
+        rest = after.get(usize::from(url_length)..)?; // URLstring
+    }
+    if flags & 0x20 != 0 {
+        rest = rest.get(2..)?; // OCR_ES_Id
+    }
+    es_descriptor = rest;
+
+    let mut decoder_config = read_descriptor(&mut es_descriptor, 0x04)?;
+    // objectTypeIndication, streamType, bufferSizeDB, maxBitrate, avgBitrate
+    decoder_config = decoder_config.get(13..)?;
+    read_descriptor(&mut decoder_config, 0x05)
+}
+
+/// Extract the ALACSpecificConfig (and channel layout info, if present) from
+/// an Apple Lossless magic cookie, which may be wrapped in `frma` and `alac`
+/// atoms.
+fn alac_specific_config(magic_cookie: &[u8]) -> Option<&[u8]> {
+    let mut bytes = magic_cookie;
+    if bytes.get(4..8) == Some(b"frma") {
+        bytes = bytes.get(12..)?;
+    }
+    if bytes.get(4..8) == Some(b"alac") {
+        bytes = bytes.get(12..)?; // atom header, version and flags
+    }
+    // The channel layout info is an atom following the config.
+    match bytes.get(24..48) {
+        Some(layout) if &layout[4..8] == b"chan" => Some(&bytes[..48]),
+        _ => bytes.get(..24),
+    }
+}
+
+/// Decoder for a stream of compressed packets.
+pub struct AudioDecoder(Box<dyn Decoder>);
+
+impl AudioDecoder {
+    /// Create a decoder for the given format. `sample_rate` and `channels` are
+    /// used if the magic cookie doesn't specify them.
+    pub fn new(
+        format: &AudioFormat,
+        sample_rate: u32,
+        channels: u32,
+        magic_cookie: Option<&[u8]>,
+    ) -> Result<AudioDecoder, String> {
+        let mut params = CodecParameters::new();
+        params
+            .with_sample_rate(sample_rate)
+            .with_channels(match channels {
+                1 => symphonia_core::audio::Channels::FRONT_LEFT,
+                2 => {
+                    symphonia_core::audio::Channels::FRONT_LEFT
+                        | symphonia_core::audio::Channels::FRONT_RIGHT
+                }
+                _ => return Err(format!("Unsupported channel count {}", channels)),
+            });
+        let options = DecoderOptions::default();
+        let decoder: Box<dyn Decoder> = match format {
+            AudioFormat::Aac => {
+                params.for_codec(CODEC_TYPE_AAC);
+                if let Some(magic_cookie) = magic_cookie {
+                    let config = audio_specific_config(magic_cookie)
+                        .ok_or_else(|| "Invalid AAC magic cookie".to_string())?;
+                    params.with_extra_data(config.into());
+                }
+                Box::new(
+                    symphonia_codec_aac::AacDecoder::try_new(&params, &options)
+                        .map_err(|e| e.to_string())?,
+                )
+            }
+            AudioFormat::AppleLossless => {
+                params.for_codec(CODEC_TYPE_ALAC);
+                let config = magic_cookie
+                    .and_then(alac_specific_config)
+                    .ok_or_else(|| "Missing or invalid ALAC magic cookie".to_string())?;
+                params.with_extra_data(config.into());
+                Box::new(
+                    symphonia_codec_alac::AlacDecoder::try_new(&params, &options)
+                        .map_err(|e| e.to_string())?,
+                )
+            }
+            _ => return Err(format!("{:?} is not a compressed format", format)),
+        };
+        Ok(AudioDecoder(decoder))
+    }
+
+    /// Decode a packet and append the resulting interleaved 16-bit samples to
+    /// `out`. Returns the number of channels.
+    pub fn decode_packet(&mut self, packet: &[u8], out: &mut Vec<i16>) -> Result<u32, String> {
+        let packet = Packet::new_from_slice(0, 0, 0, packet);
+        let decoded = self.0.decode(&packet).map_err(|e| e.to_string())?;
+        let spec = *decoded.spec();
+        let mut sample_buffer = SampleBuffer::new(decoded.capacity() as u64, spec);
+        sample_buffer.copy_interleaved_ref(decoded);
+        out.extend_from_slice(sample_buffer.samples());
+        Ok(spec.channels.count() as u32)
+    }
+
+    /// Reset the decoder's state, e.g. after seeking.
+    pub fn reset(&mut self) {
+        self.0.reset();
+    }
+}
@@ -10,21 +10,24 @@
 #include <stdint.h>
 #include <stdlib.h>
 
-int16_t *touchHLE_decode_mp3_to_pcm(const uint8_t *data, size_t data_size,
-                                    uint32_t *channels,
-                                    uint32_t *sample_rate,
-                                    uint64_t *frame_count) {
-  drmp3_config config;
-  int16_t *samples = drmp3_open_memory_and_read_pcm_frames_s16(
-      data, data_size, &config, frame_count,
-      /* pAllocationCallbacks: */ NULL);
-  if (samples) {
-    *channels = config.channels;
-    *sample_rate = config.sampleRate;
+drmp3dec *touchHLE_mp3_decoder_new(void) {
+  drmp3dec *decoder = malloc(sizeof(drmp3dec));
+  if (decoder) {
+    drmp3dec_init(decoder);
   }
-  return samples;
+  return decoder;
 }
 
-void touchHLE_free_decoded_mp3_pcm(int16_t *samples) {
-  drmp3_free(samples, /* pAllocationCallbacks: */ NULL);
+int touchHLE_mp3_decoder_decode_frame(drmp3dec *decoder, const uint8_t *data,
+                                      size_t data_size, int16_t *samples,
+                                      uint32_t *channels,
+                                      uint32_t *sample_rate) {
+  drmp3dec_frame_info info;
+  int sample_count =
+      drmp3dec_decode_frame(decoder, data, (int)data_size, samples, &info);
+  *channels = (uint32_t)info.channels;
+  *sample_rate = (uint32_t)info.hz;
+  return sample_count;
 }
+
+void touchHLE_mp3_decoder_free(drmp3dec *decoder) { free(decoder); }
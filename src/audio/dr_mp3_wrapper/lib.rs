@@ -10,59 +10,86 @@
 // This also allows items in the crate to have non-snake-case names.
 #![allow(non_snake_case)]
 
+use std::ffi::c_void;
+
 // See build.rs and lib.c
 extern "C" {
-    fn touchHLE_decode_mp3_to_pcm(
+    fn touchHLE_mp3_decoder_new() -> *mut c_void;
+    fn touchHLE_mp3_decoder_decode_frame(
+        decoder: *mut c_void,
         data: *const u8,
         data_size: usize,
+        samples: *mut i16,
         channels: *mut u32,
         sample_rate: *mut u32,
-        frame_count: *mut u64,
-    ) -> *mut i16;
-    fn touchHLE_free_decoded_mp3_pcm(samples: *mut i16);
+    ) -> i32;
+    fn touchHLE_mp3_decoder_free(decoder: *mut c_void);
 }
 
-/// PCM data decoded from an MP3 file.
-pub struct Mp3DecodedToPcm {
-    /// 16-bit little-endian PCM samples, grouped in frames (one sample per
-    /// channel in each frame).
-    pub bytes: Vec<u8>,
+/// Maximum number of samples (not frames) a single MP3 frame can decode to.
+pub const MAX_SAMPLES_PER_FRAME: usize = 1152 * 2;
+
+/// Information about a decoded MP3 frame.
+pub struct DecodedFrame {
+    /// Number of samples per channel. This is zero if the frame could not be
+    /// decoded, e.g. because the data it depends on from previous frames is
+    /// missing.
+    pub sample_count: usize,
     /// Sample rate in Hz.
     pub sample_rate: u32,
     /// Channel count.
     pub channels: u32,
 }
 
-#[allow(clippy::result_unit_err)]
-pub fn decode_mp3_to_pcm(data: &[u8]) -> Result<Mp3DecodedToPcm, ()> {
-    let mut channels = 0;
-    let mut sample_rate = 0;
-    let mut frame_count = 0;
-    let samples_ptr = unsafe {
-        touchHLE_decode_mp3_to_pcm(
-            data.as_ptr(),
-            data.len(),
-            &mut channels,
-            &mut sample_rate,
-            &mut frame_count,
-        )
-    };
-    if samples_ptr.is_null() {
-        return Err(());
+/// Frame-by-frame MP3 decoder. The decoder keeps state between frames (the
+/// bit reservoir and the overlap of the synthesis filter), so frames should be
+/// decoded in order.
+pub struct Mp3Decoder(*mut c_void);
+
+impl Mp3Decoder {
+    pub fn new() -> Mp3Decoder {
+        let decoder = unsafe { touchHLE_mp3_decoder_new() };
+        assert!(!decoder.is_null());
+        Mp3Decoder(decoder)
+    }
+
+    /// Decode the frame at the start of `data` into `samples`, which are
+    /// 16-bit and interleaved. `data` should include the header of the
+    /// following frame, if there is one, so the decoder can check it is
+    /// synchronized.
+    pub fn decode_frame(
+        &mut self,
+        data: &[u8],
+        samples: &mut [i16; MAX_SAMPLES_PER_FRAME],
+    ) -> DecodedFrame {
+        let mut channels = 0;
+        let mut sample_rate = 0;
+        let sample_count = unsafe {
+            touchHLE_mp3_decoder_decode_frame(
+                self.0,
+                data.as_ptr(),
+                data.len(),
+                samples.as_mut_ptr(),
+                &mut channels,
+                &mut sample_rate,
+            )
+        };
+        DecodedFrame {
+            sample_count: sample_count.max(0) as usize,
+            sample_rate,
+            channels,
+        }
     }
+}
 
-    let bytes = unsafe {
-        std::slice::from_raw_parts(
-            samples_ptr as *const _,
-            std::mem::size_of::<i16>() * (frame_count as usize),
-        )
+impl Default for Mp3Decoder {
+    fn default() -> Self {
+        Self::new()
     }
-    .to_vec();
-    unsafe { touchHLE_free_decoded_mp3_pcm(samples_ptr) };
+}
 
-    Ok(Mp3DecodedToPcm {
-        bytes,
-        sample_rate,
-        channels,
-    })
+impl Drop for Mp3Decoder {
+    fn drop(&mut self) {
+        unsafe { touchHLE_mp3_decoder_free(self.0) }
+    }
 }
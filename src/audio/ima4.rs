@@ -75,3 +75,21 @@ pub fn decode_ima4(in_packet: &[u8; 34]) -> [i16; 64] {
 
     out_packet
 }
+
+/// Decode a sequence of IMA4 ADPCM packets for `channels` channels to
+/// interleaved 16-bit signed integer PCM. Any incomplete packets at the end
+/// are ignored.
+pub fn decode_ima4_interleaved(data: &[u8], channels: usize) -> Vec<i16> {
+    let channels = channels.max(1);
+    let mut samples = Vec::with_capacity((data.len() / 34) * 64);
+    for frame_packets in data.chunks_exact(34 * channels) {
+        let decoded: Vec<[i16; 64]> = frame_packets
+            .chunks_exact(34)
+            .map(|packet| decode_ima4(packet.try_into().unwrap()))
+            .collect();
+        for i in 0..64 {
+            samples.extend(decoded.iter().map(|packet| packet[i]));
+        }
+    }
+    samples
+}
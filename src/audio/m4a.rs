@@ -0,0 +1,179 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! MPEG-4 audio (`.m4a`) file reading.
+//!
+//! Only the `moov` box is loaded into memory, the audio data is read on
+//! demand. The box parsing itself is shared with the movie player, see
+//! [crate::mp4].
+
+use super::PacketDescription;
+use crate::mp4::{self, Sample, TrackFormat};
+use std::io::{Read, Seek, SeekFrom};
+
+pub struct M4aReader<R: Read + Seek> {
+    reader: R,
+    /// `mp4a` or `alac`.
+    pub format_id: [u8; 4],
+    pub sample_rate: f64,
+    pub channels: u32,
+    pub magic_cookie: Option<Vec<u8>>,
+    packets: Vec<Sample>,
+    /// Offset of each packet within the audio data, i.e. the sum of the sizes
+    /// of the packets before it.
+    data_offsets: Vec<u64>,
+    data_size: u64,
+}
+
+impl<R: Read + Seek> M4aReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, ()> {
+        // Find the `moov` box.
+        let file_size = reader.seek(SeekFrom::End(0)).map_err(|_| ())?;
+        let mut offset = 0;
+        let moov = loop {
+            if offset + 8 > file_size {
+                return Err(());
+            }
+            reader.seek(SeekFrom::Start(offset)).map_err(|_| ())?;
+            let mut header = [0u8; 8];
+            reader.read_exact(&mut header).map_err(|_| ())?;
+            let box_type: [u8; 4] = header[4..].try_into().unwrap();
+            let (size, header_size) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+                0 => (file_size - offset, 8),
+                1 => {
+                    let mut size = [0u8; 8];
+                    reader.read_exact(&mut size).map_err(|_| ())?;
+                    (u64::from_be_bytes(size), 16)
+                }
+                size => (u64::from(size), 8),
+            };
+            if size < header_size || offset + size > file_size {
+                return Err(());
+            }
+            if &box_type == b"moov" {
+                let mut moov = vec![0u8; (size - header_size).try_into().map_err(|_| ())?];
+                reader.read_exact(&mut moov).map_err(|_| ())?;
+                break moov;
+            }
+            offset += size;
+        };
+
+        let tracks = mp4::parse_movie_box(&moov).map_err(|e| {
+            log!("Warning: couldn't parse MPEG-4 file: {}", e);
+        })?;
+        let track = tracks
+            .into_iter()
+            .find(|track| matches!(track.format, TrackFormat::Audio { .. }))
+            .ok_or(())?;
+        let TrackFormat::Audio {
+            format_id,
+            channels,
+            sample_rate,
+            magic_cookie,
+            ..
+        } = track.format
+        else {
+            unreachable!();
+        };
+        if &format_id != b"mp4a" && &format_id != b"alac" {
+            log!(
+                "Warning: unsupported MPEG-4 audio format {:?}",
+                String::from_utf8_lossy(&format_id)
+            );
+            return Err(());
+        }
+
+        let mut data_offsets = Vec::with_capacity(track.samples.len());
+        let mut data_size = 0;
+        for sample in &track.samples {
+            data_offsets.push(data_size);
+            data_size += u64::from(sample.size);
+        }
+
+        Ok(M4aReader {
+            reader,
+            format_id,
+            sample_rate,
+            channels: channels.into(),
+            magic_cookie,
+            packets: track.samples,
+            data_offsets,
+            data_size,
+        })
+    }
+
+    /// Size of the audio data in bytes.
+    pub fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    pub fn packet_count(&self) -> u64 {
+        self.packets.len() as u64
+    }
+
+    pub fn packet_size_upper_bound(&self) -> u32 {
+        self.packets
+            .iter()
+            .map(|packet| packet.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Read audio data starting from byte offset `offset` within it, as if the
+    /// packets were stored contiguously. Returns the number of bytes read,
+    /// which is less than `buffer.len()` only if the end of the data was
+    /// reached.
+    pub fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, ()> {
+        let mut bytes_read = 0;
+        let mut index = self.data_offsets.partition_point(|&start| start <= offset);
+        index = index.saturating_sub(1);
+        while bytes_read < buffer.len() {
+            let Some(packet) = self.packets.get(index) else {
+                break;
+            };
+            let within = (offset + bytes_read as u64).saturating_sub(self.data_offsets[index]);
+            let count = (u64::from(packet.size) - within).min((buffer.len() - bytes_read) as u64);
+            self.reader
+                .seek(SeekFrom::Start(packet.offset + within))
+                .map_err(|_| ())?;
+            self.reader
+                .read_exact(&mut buffer[bytes_read..][..count as usize])
+                .map_err(|_| ())?;
+            bytes_read += count as usize;
+            index += 1;
+        }
+        Ok(bytes_read)
+    }
+
+    /// Read up to `packet_count` packets starting from packet `first_packet`,
+    /// as many as fit into `buffer`.
+    pub fn read_packets(
+        &mut self,
+        first_packet: u64,
+        packet_count: u32,
+        buffer: &mut [u8],
+    ) -> Result<Vec<PacketDescription>, ()> {
+        let first_packet = usize::try_from(first_packet).unwrap_or(usize::MAX);
+        let mut descriptions = Vec::new();
+        let mut byte_count = 0;
+        for index in first_packet..first_packet.saturating_add(packet_count as usize) {
+            let Some(&Sample { offset, size, .. }) = self.packets.get(index) else {
+                break;
+            };
+            let Some(packet_buffer) = buffer.get_mut(byte_count..byte_count + size as usize) else {
+                break;
+            };
+            self.reader.seek(SeekFrom::Start(offset)).map_err(|_| ())?;
+            self.reader.read_exact(packet_buffer).map_err(|_| ())?;
+            descriptions.push(PacketDescription {
+                offset: byte_count as u64,
+                size,
+                frames: 0,
+            });
+            byte_count += size as usize;
+        }
+        Ok(descriptions)
+    }
+}
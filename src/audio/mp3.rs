@@ -0,0 +1,293 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! MPEG audio (`.mp3`) file reading.
+//!
+//! The file is scanned for frame headers when opened, which gives a table of
+//! frames without decoding anything. Frames are then decoded on demand and
+//! presented as 16-bit linear PCM.
+//!
+//! Resources:
+//! - [MPEG Audio Frame Header](http://www.mp3-tech.org/programmer/frame_header.html)
+
+use super::dr_mp3;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+/// Number of frames to decode before the requested frame after a seek. Layer
+/// III frames can depend on data from previous frames (the bit reservoir), and
+/// the synthesis filter needs the previous frame too.
+const PRIMING_FRAMES: usize = 10;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct FrameHeader {
+    /// The MPEG version, layer and sample rate, which must be the same for all
+    /// frames in a stream.
+    stream_id: (u8, u8, u32),
+    size: u32,
+    samples_per_frame: u32,
+    channels: u32,
+}
+
+fn parse_frame_header(header: [u8; 4]) -> Option<FrameHeader> {
+    if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+    // 0 = MPEG 2.5, 2 = MPEG 2, 3 = MPEG 1
+    let version = (header[1] >> 3) & 3;
+    // 1 = Layer III, 2 = Layer II, 3 = Layer I
+    let layer = (header[1] >> 1) & 3;
+    let bitrate_index = usize::from(header[2] >> 4);
+    let sample_rate_index = usize::from((header[2] >> 2) & 3);
+    let padding = u32::from((header[2] >> 1) & 1);
+    let channel_mode = header[3] >> 6;
+    // Free-format bitrates (index 0) are not supported.
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 {
+        return None;
+    }
+
+    const BITRATES_V1: [[u32; 15]; 3] = [
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+    ];
+    const BITRATES_V2: [[u32; 15]; 3] = [
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+    ];
+    const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+    let sample_rate = *SAMPLE_RATES.get(sample_rate_index)?
+        >> match version {
+            3 => 0,
+            2 => 1,
+            _ => 2,
+        };
+    let bitrates = if version == 3 {
+        &BITRATES_V1
+    } else {
+        &BITRATES_V2
+    };
+    let bitrate = bitrates[usize::from(layer - 1)][bitrate_index] * 1000;
+
+    let (size, samples_per_frame) = match layer {
+        3 => ((12 * bitrate / sample_rate + padding) * 4, 384),
+        2 => (144 * bitrate / sample_rate + padding, 1152),
+        1 if version == 3 => (144 * bitrate / sample_rate + padding, 1152),
+        _ => (72 * bitrate / sample_rate + padding, 576),
+    };
+
+    Some(FrameHeader {
+        stream_id: (version, layer, sample_rate),
+        size,
+        samples_per_frame,
+        channels: if channel_mode == 3 { 1 } else { 2 },
+    })
+}
+
+/// Location of a frame within the file.
+struct FrameLocation {
+    offset: u64,
+    size: u32,
+}
+
+pub struct Mp3Reader<R: Read + Seek> {
+    reader: BufReader<R>,
+    frames: Vec<FrameLocation>,
+    pub sample_rate: u32,
+    pub channels: u32,
+    samples_per_frame: u32,
+    decoder: dr_mp3::Mp3Decoder,
+    /// The frame the decoder expects to decode next.
+    next_frame: usize,
+    /// The most recently decoded frame and its PCM data, as 16-bit
+    /// little-endian samples.
+    decoded_frame: Option<(usize, Vec<u8>)>,
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Option<[u8; 4]> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header).ok()?;
+    Some(header)
+}
+
+impl<R: Read + Seek> Mp3Reader<R> {
+    pub fn new(reader: R) -> Result<Self, ()> {
+        let mut reader = BufReader::new(reader);
+
+        // Skip the ID3v2 tag, if any.
+        let mut offset: u64 = 0;
+        let mut id3_header = [0u8; 10];
+        if reader.read_exact(&mut id3_header).is_ok() && &id3_header[..3] == b"ID3" {
+            let size = id3_header[6..]
+                .iter()
+                .fold(0u64, |acc, &byte| (acc << 7) | u64::from(byte & 0x7f));
+            let has_footer = id3_header[5] & 0x10 != 0;
+            offset = 10 + size + if has_footer { 10 } else { 0 };
+        }
+
+        // Scan for frames, so they can be found by packet index later. This
+        // reads through the whole file once, but only the frame locations are
+        // kept in memory.
+        let mut frames = Vec::new();
+        let mut first_header: Option<FrameHeader> = None;
+        reader.seek(SeekFrom::Start(offset)).map_err(|_| ())?;
+        while let Some(header_bytes) = read_header(&mut reader) {
+            let header = parse_frame_header(header_bytes).filter(|header| {
+                first_header.map_or(true, |first| first.stream_id == header.stream_id)
+            });
+            let Some(header) = header else {
+                // An ID3v1 tag may follow the last frame.
+                if &header_bytes[..3] == b"TAG" {
+                    break;
+                }
+                // Otherwise, this is garbage. Try to resynchronize.
+                offset += 1;
+                reader.seek_relative(-3).map_err(|_| ())?;
+                continue;
+            };
+
+            if first_header.is_none() {
+                // Make sure this wasn't a false sync by checking that another
+                // frame follows.
+                reader
+                    .seek_relative(i64::from(header.size) - 4)
+                    .map_err(|_| ())?;
+                let next = read_header(&mut reader).and_then(parse_frame_header);
+                if next.map_or(true, |next| next.stream_id != header.stream_id) {
+                    offset += 1;
+                    reader.seek(SeekFrom::Start(offset)).map_err(|_| ())?;
+                    continue;
+                }
+                reader
+                    .seek_relative(-i64::from(header.size))
+                    .map_err(|_| ())?;
+                first_header = Some(header);
+            }
+
+            frames.push(FrameLocation {
+                offset,
+                size: header.size,
+            });
+            offset += u64::from(header.size);
+            reader
+                .seek_relative(i64::from(header.size) - 4)
+                .map_err(|_| ())?;
+        }
+
+        let first_header = first_header.ok_or(())?;
+        // The last frame may be truncated.
+        let file_size = reader.seek(SeekFrom::End(0)).map_err(|_| ())?;
+        if frames
+            .last()
+            .map_or(false, |last| last.offset + u64::from(last.size) > file_size)
+        {
+            frames.pop();
+        }
+
+        Ok(Mp3Reader {
+            reader,
+            frames,
+            sample_rate: first_header.stream_id.2,
+            channels: first_header.channels,
+            samples_per_frame: first_header.samples_per_frame,
+            decoder: dr_mp3::Mp3Decoder::new(),
+            next_frame: 0,
+            decoded_frame: None,
+        })
+    }
+
+    fn bytes_per_frame(&self) -> u64 {
+        u64::from(self.samples_per_frame) * u64::from(self.channels) * 2
+    }
+
+    /// Size of the decoded PCM data in bytes.
+    pub fn byte_count(&self) -> u64 {
+        self.frames.len() as u64 * self.bytes_per_frame()
+    }
+
+    /// Decode the frame at index `index`, which must be in bounds. Frames
+    /// that can't be decoded become silence, so that every frame has the same
+    /// size.
+    fn decode_frame(&mut self, index: usize) -> Result<(), ()> {
+        if self
+            .decoded_frame
+            .as_ref()
+            .map_or(false, |&(decoded, _)| decoded == index)
+        {
+            return Ok(());
+        }
+
+        let mut next_frame = self.next_frame;
+        if index != next_frame {
+            self.decoder = dr_mp3::Mp3Decoder::new();
+            next_frame = index.saturating_sub(PRIMING_FRAMES);
+        }
+
+        let mut samples = [0i16; dr_mp3::MAX_SAMPLES_PER_FRAME];
+        let mut data = Vec::new();
+        while next_frame <= index {
+            // The header of the following frame is included so the decoder
+            // can check it is synchronized.
+            let FrameLocation { offset, size } = self.frames[next_frame];
+            data.resize(size as usize, 0);
+            self.reader.seek(SeekFrom::Start(offset)).map_err(|_| ())?;
+            self.reader.read_exact(&mut data).map_err(|_| ())?;
+            if let Some(next) = self.frames.get(next_frame + 1) {
+                self.reader
+                    .seek(SeekFrom::Start(next.offset))
+                    .map_err(|_| ())?;
+                data.extend_from_slice(&read_header(&mut self.reader).ok_or(())?);
+            }
+            let decoded = self.decoder.decode_frame(&data, &mut samples);
+            next_frame += 1;
+
+            if next_frame <= index {
+                continue;
+            }
+            let mut pcm = vec![0u8; self.bytes_per_frame() as usize];
+            if decoded.channels == self.channels
+                && decoded.sample_count == self.samples_per_frame as usize
+            {
+                for (bytes, sample) in pcm.chunks_exact_mut(2).zip(samples.iter()) {
+                    bytes.copy_from_slice(&sample.to_le_bytes());
+                }
+            }
+            self.decoded_frame = Some((index, pcm));
+        }
+        self.next_frame = next_frame;
+        Ok(())
+    }
+
+    /// Read decoded PCM data starting from byte offset `offset`. Returns the
+    /// number of bytes read, which is less than `buffer.len()` only if the end
+    /// of the data was reached.
+    pub fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, ()> {
+        let bytes_per_frame = self.bytes_per_frame();
+        let mut bytes_read = 0;
+        while bytes_read < buffer.len() {
+            let offset = offset + bytes_read as u64;
+            let index = usize::try_from(offset / bytes_per_frame).unwrap_or(usize::MAX);
+            if index >= self.frames.len() {
+                break;
+            }
+            self.decode_frame(index)?;
+            let pcm = &self.decoded_frame.as_ref().unwrap().1;
+            let pcm = &pcm[(offset % bytes_per_frame) as usize..];
+            let count = pcm.len().min(buffer.len() - bytes_read);
+            buffer[bytes_read..][..count].copy_from_slice(&pcm[..count]);
+            bytes_read += count;
+        }
+        Ok(bytes_read)
+    }
+}
@@ -48,6 +48,11 @@ pub const ALC_FALSE: ALCboolean = 0;
 #[allow(dead_code)]
 pub const ALC_TRUE: ALCboolean = 1;
 
+pub const ALC_DEVICE_SPECIFIER: ALCenum = 0x1005;
+pub const ALC_CAPTURE_DEVICE_SPECIFIER: ALCenum = 0x310;
+pub const ALC_ALL_DEVICES_SPECIFIER: ALCenum = 0x1013;
+pub const ALC_EXTENSIONS: ALCenum = 0x1006;
+
 extern "C" {
     pub fn alcOpenDevice(devicename: *const ALCchar) -> *mut ALCdevice;
     pub fn alcCloseDevice(device: *mut ALCdevice) -> ALCboolean;
@@ -56,9 +61,23 @@ extern "C" {
     pub fn alcDestroyContext(context: *mut ALCcontext);
 
     pub fn alcMakeContextCurrent(context: *mut ALCcontext) -> ALCboolean;
+    pub fn alcProcessContext(context: *mut ALCcontext);
+    pub fn alcSuspendContext(context: *mut ALCcontext);
     pub fn alcGetCurrentContext() -> *mut ALCcontext;
+    pub fn alcGetContextsDevice(context: *mut ALCcontext) -> *mut ALCdevice;
 
     pub fn alcGetError(device: *mut ALCdevice) -> ALCenum;
+
+    pub fn alcIsExtensionPresent(device: *mut ALCdevice, extname: *const ALCchar) -> ALCboolean;
+    pub fn alcGetEnumValue(device: *mut ALCdevice, enumname: *const ALCchar) -> ALCenum;
+
+    pub fn alcGetString(device: *mut ALCdevice, param: ALCenum) -> *const ALCchar;
+    pub fn alcGetIntegerv(
+        device: *mut ALCdevice,
+        param: ALCenum,
+        size: ALCsizei,
+        values: *mut ALCint,
+    );
 }
 
 // === al.h ===
@@ -85,8 +104,18 @@ use al_types::*;
 
 pub const AL_NO_ERROR: ALenum = 0;
 
+pub const AL_EXTENSIONS: ALenum = 0xB004;
+
+pub const AL_POSITION: ALenum = 0x1004;
+pub const AL_DIRECTION: ALenum = 0x1005;
+pub const AL_VELOCITY: ALenum = 0x1006;
+
+pub const AL_BUFFER: ALenum = 0x1009;
+
 pub const AL_MAX_GAIN: ALenum = 0x100E;
 
+pub const AL_ORIENTATION: ALenum = 0x100F;
+
 pub const AL_SOURCE_STATE: ALenum = 0x1010;
 
 pub const AL_INITIAL: ALenum = 0x1011;
@@ -103,32 +132,102 @@ pub const AL_FORMAT_STEREO8: ALenum = 0x1102;
 pub const AL_FORMAT_STEREO16: ALenum = 0x1103;
 
 extern "C" {
+    pub fn alEnable(capability: ALenum);
+    pub fn alDisable(capability: ALenum);
+    pub fn alIsEnabled(capability: ALenum) -> ALboolean;
+
+    pub fn alGetString(param: ALenum) -> *const ALchar;
+    pub fn alGetBooleanv(param: ALenum, values: *mut ALboolean);
+    pub fn alGetIntegerv(param: ALenum, values: *mut ALint);
+    pub fn alGetFloatv(param: ALenum, values: *mut ALfloat);
+    pub fn alGetDoublev(param: ALenum, values: *mut ALdouble);
+    pub fn alGetBoolean(param: ALenum) -> ALboolean;
+    pub fn alGetInteger(param: ALenum) -> ALint;
+    pub fn alGetFloat(param: ALenum) -> ALfloat;
+    pub fn alGetDouble(param: ALenum) -> ALdouble;
+
     pub fn alGetError() -> ALenum;
 
+    pub fn alIsExtensionPresent(extname: *const ALchar) -> ALboolean;
+    pub fn alGetEnumValue(ename: *const ALchar) -> ALenum;
+
     pub fn alDistanceModel(value: ALenum);
+    pub fn alSpeedOfSound(value: ALfloat);
 
     pub fn alListenerf(param: ALenum, value: ALfloat);
-    pub fn alListenerfv(param: ALenum, values: *const ALfloat);
     pub fn alListener3f(param: ALenum, value1: ALfloat, value2: ALfloat, value3: ALfloat);
+    pub fn alListenerfv(param: ALenum, values: *const ALfloat);
+    pub fn alListeneri(param: ALenum, value: ALint);
+    pub fn alListener3i(param: ALenum, value1: ALint, value2: ALint, value3: ALint);
+    pub fn alListeneriv(param: ALenum, values: *const ALint);
+    pub fn alGetListenerf(param: ALenum, value: *mut ALfloat);
+    pub fn alGetListener3f(
+        param: ALenum,
+        value1: *mut ALfloat,
+        value2: *mut ALfloat,
+        value3: *mut ALfloat,
+    );
+    pub fn alGetListenerfv(param: ALenum, values: *mut ALfloat);
+    pub fn alGetListeneri(param: ALenum, value: *mut ALint);
+    pub fn alGetListener3i(
+        param: ALenum,
+        value1: *mut ALint,
+        value2: *mut ALint,
+        value3: *mut ALint,
+    );
+    pub fn alGetListeneriv(param: ALenum, values: *mut ALint);
 
     pub fn alGenSources(n: ALsizei, sources: *mut ALuint);
     pub fn alDeleteSources(n: ALsizei, sources: *const ALuint);
+    pub fn alIsSource(source: ALuint) -> ALboolean;
 
     pub fn alSourcef(source: ALuint, param: ALenum, value: ALfloat);
-    pub fn alSourcei(source: ALuint, param: ALenum, value: ALint);
+    pub fn alSource3f(
+        source: ALuint,
+        param: ALenum,
+        value1: ALfloat,
+        value2: ALfloat,
+        value3: ALfloat,
+    );
     pub fn alSourcefv(source: ALuint, param: ALenum, values: *const ALfloat);
+    pub fn alSourcei(source: ALuint, param: ALenum, value: ALint);
+    pub fn alSource3i(source: ALuint, param: ALenum, value1: ALint, value2: ALint, value3: ALint);
+    pub fn alSourceiv(source: ALuint, param: ALenum, values: *const ALint);
     pub fn alGetSourcef(source: ALuint, param: ALenum, value: *mut ALfloat);
+    pub fn alGetSource3f(
+        source: ALuint,
+        param: ALenum,
+        value1: *mut ALfloat,
+        value2: *mut ALfloat,
+        value3: *mut ALfloat,
+    );
+    pub fn alGetSourcefv(source: ALuint, param: ALenum, values: *mut ALfloat);
     pub fn alGetSourcei(source: ALuint, param: ALenum, value: *mut ALint);
+    pub fn alGetSource3i(
+        source: ALuint,
+        param: ALenum,
+        value1: *mut ALint,
+        value2: *mut ALint,
+        value3: *mut ALint,
+    );
+    pub fn alGetSourceiv(source: ALuint, param: ALenum, values: *mut ALint);
+
+    pub fn alSourcePlayv(n: ALsizei, sources: *const ALuint);
+    pub fn alSourceStopv(n: ALsizei, sources: *const ALuint);
+    pub fn alSourceRewindv(n: ALsizei, sources: *const ALuint);
+    pub fn alSourcePausev(n: ALsizei, sources: *const ALuint);
 
     pub fn alSourcePlay(source: ALuint);
     pub fn alSourcePause(source: ALuint);
     pub fn alSourceStop(source: ALuint);
+    pub fn alSourceRewind(source: ALuint);
 
     pub fn alSourceQueueBuffers(source: ALuint, nb: ALsizei, buffers: *const ALuint);
     pub fn alSourceUnqueueBuffers(source: ALuint, nb: ALsizei, buffers: *mut ALuint);
 
     pub fn alGenBuffers(n: ALsizei, buffers: *mut ALuint);
     pub fn alDeleteBuffers(n: ALsizei, buffers: *const ALuint);
+    pub fn alIsBuffer(buffer: ALuint) -> ALboolean;
 
     pub fn alBufferData(
         buffer: ALuint,
@@ -138,6 +237,37 @@ extern "C" {
         samplerate: ALsizei,
     );
 
+    pub fn alBufferf(buffer: ALuint, param: ALenum, value: ALfloat);
+    pub fn alBuffer3f(
+        buffer: ALuint,
+        param: ALenum,
+        value1: ALfloat,
+        value2: ALfloat,
+        value3: ALfloat,
+    );
+    pub fn alBufferfv(buffer: ALuint, param: ALenum, values: *const ALfloat);
+    pub fn alBufferi(buffer: ALuint, param: ALenum, value: ALint);
+    pub fn alBuffer3i(buffer: ALuint, param: ALenum, value1: ALint, value2: ALint, value3: ALint);
+    pub fn alBufferiv(buffer: ALuint, param: ALenum, values: *const ALint);
+    pub fn alGetBufferf(buffer: ALuint, param: ALenum, value: *mut ALfloat);
+    pub fn alGetBuffer3f(
+        buffer: ALuint,
+        param: ALenum,
+        value1: *mut ALfloat,
+        value2: *mut ALfloat,
+        value3: *mut ALfloat,
+    );
+    pub fn alGetBufferfv(buffer: ALuint, param: ALenum, values: *mut ALfloat);
+    pub fn alGetBufferi(buffer: ALuint, param: ALenum, value: *mut ALint);
+    pub fn alGetBuffer3i(
+        buffer: ALuint,
+        param: ALenum,
+        value1: *mut ALint,
+        value2: *mut ALint,
+        value3: *mut ALint,
+    );
+    pub fn alGetBufferiv(buffer: ALuint, param: ALenum, values: *mut ALint);
+
     pub fn alDopplerFactor(dopplerFactor: ALfloat);
     pub fn alDopplerVelocity(dopplerVelocity: ALfloat);
 }
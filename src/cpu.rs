@@ -11,7 +11,9 @@
 //! For the moment, only ARMv6 has been tested.
 
 use crate::abi::GuestFunction;
-use crate::mem::{ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead, SafeWrite};
+use crate::mem::{
+    guest_size_of, ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead, SafeWrite, Watchpoint,
+};
 
 // Import functions from C++
 use touchHLE_dynarmic_wrapper::*;
@@ -38,11 +40,15 @@ fn touchHLE_cpu_read_impl<T: SafeRead + Default>(
     // the emulator will crash anyway, maybe this is okay.
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let mem = unsafe { &mut *mem.cast::<Mem>() };
+        if mem.check_watchpoints(addr, guest_size_of::<T>(), false) {
+            return None;
+        }
         let ptr: ConstPtr<T> = Ptr::from_bits(addr);
-        mem.read(ptr)
+        Some(mem.read(ptr))
     }));
+    let res = res.ok().flatten();
     unsafe {
-        error.write(res.is_err());
+        error.write(res.is_none());
     }
     res.unwrap_or_default()
 }
@@ -51,10 +57,14 @@ fn touchHLE_cpu_write_impl<T: SafeWrite>(mem: *mut touchHLE_Mem, addr: VAddr, va
     // See comments above about catch_unwind
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let mem = unsafe { &mut *mem.cast::<Mem>() };
+        if mem.check_watchpoints(addr, guest_size_of::<T>(), true) {
+            return false;
+        }
         let ptr: MutPtr<T> = Ptr::from_bits(addr);
-        mem.write(ptr, value)
+        mem.write(ptr, value);
+        true
     }));
-    res.is_err()
+    !res.unwrap_or(false)
 }
 
 // Export functions for use by C++
@@ -142,6 +152,9 @@ pub enum CpuError {
     UndefinedInstruction,
     /// Breakpoint (`bkpt` instruction).
     Breakpoint,
+    /// A debugger watchpoint was triggered by an access to the given address.
+    /// The access has not been performed.
+    Watchpoint(Watchpoint, GuestUSize),
 }
 
 impl Cpu {
@@ -220,6 +233,29 @@ impl Cpu {
         unsafe { touchHLE_DynarmicWrapper_set_cpsr(self.dynarmic_wrapper, cpsr) }
     }
 
+    /// Get the VFP/NEON extension registers as 32-bit words. Word `n` is
+    /// register `sn`, and register `dn` is words `2n` (low) and `2n + 1`
+    /// (high).
+    pub fn ext_regs(&self) -> &[u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_ext_regs_const(self.dynarmic_wrapper);
+            &*(ptr as *const [u32; 64])
+        }
+    }
+    pub fn ext_regs_mut(&mut self) -> &mut [u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_ext_regs_mut(self.dynarmic_wrapper);
+            &mut *(ptr as *mut [u32; 64])
+        }
+    }
+
+    pub fn fpscr(&self) -> u32 {
+        unsafe { touchHLE_DynarmicWrapper_fpscr(self.dynarmic_wrapper) }
+    }
+    pub fn set_fpscr(&mut self, fpscr: u32) {
+        unsafe { touchHLE_DynarmicWrapper_set_fpscr(self.dynarmic_wrapper, fpscr) }
+    }
+
     /// Swap the current state of the CPU (registers etc) with the state stored
     /// in the context object.
     pub fn swap_context(&mut self, context: &mut CpuContext) {
@@ -264,6 +300,18 @@ impl Cpu {
         }
     }
 
+    /// Enable or disable direct memory access for the page with index `page`
+    /// (i.e. the page containing the address `page << 12`), so that accesses to
+    /// it go through the memory callbacks, like they do when direct memory
+    /// access is disabled entirely. This is used for debugger watchpoints. It
+    /// does nothing if direct memory access is not in use. The null page can
+    /// never have direct memory access.
+    pub fn set_page_direct_access(&mut self, page: GuestUSize, enabled: bool) {
+        unsafe {
+            touchHLE_DynarmicWrapper_set_page_direct_access(self.dynarmic_wrapper, page, enabled)
+        }
+    }
+
     /// Start CPU execution.
     ///
     /// If `ticks` is [Some], it is used as an abstract time limit. The value
@@ -290,7 +338,10 @@ impl Cpu {
         };
         match res {
             -1 => CpuState::Normal,
-            -2 => CpuState::Error(CpuError::MemoryError),
+            -2 => CpuState::Error(match mem.take_watchpoint_hit() {
+                Some((watchpoint, addr)) => CpuError::Watchpoint(watchpoint, addr),
+                None => CpuError::MemoryError,
+            }),
             -3 => CpuState::Error(CpuError::UndefinedInstruction),
             -4 => CpuState::Error(CpuError::Breakpoint),
             _ if res < -4 => panic!("Unexpected CPU execution result"),
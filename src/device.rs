@@ -0,0 +1,97 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Profiles of the devices touchHLE can pretend to be (`--device=`).
+//!
+//! Apps can find out what device they are running on via `sysctl` (see
+//! [crate::libc::sysctl]) or `UIDevice`, and some use this to choose quality
+//! settings or to enable features. The OS version is the earliest one
+//! touchHLE supports on that device: iPhone OS 2.0, or the version the device
+//! shipped with if that is later.
+
+/// Hardware and OS details reported to the app for a particular device.
+#[derive(Debug)]
+pub struct DeviceProfile {
+    /// Name used for the `--device=` option.
+    pub short_name: &'static str,
+    /// `hw.machine`, e.g. `iPhone1,1`.
+    pub machine: &'static str,
+    /// `hw.model`, the internal board name, e.g. `M68AP`.
+    pub model: &'static str,
+    /// `-[UIDevice model]`, e.g. `iPhone`.
+    pub ui_device_model: &'static str,
+    /// `-[UIDevice systemVersion]`, e.g. `2.0`.
+    pub system_version: &'static str,
+    /// `kern.osversion`, the OS build number, e.g. `5A347`.
+    pub os_build: &'static str,
+    /// `kern.osrelease`, the Darwin kernel version, e.g. `9.3.1`.
+    pub darwin_version: &'static str,
+    /// `hw.ncpu`.
+    pub cpu_count: u32,
+    /// `hw.cpufrequency`, in Hz.
+    pub cpu_frequency: u32,
+    /// `hw.physmem`, in bytes.
+    pub physical_memory: u32,
+}
+
+/// The supported devices. The first one, the original iPhone, is used if
+/// `--device=` isn't specified.
+pub const DEVICES: &[DeviceProfile] = &[
+    DeviceProfile {
+        short_name: "iphone",
+        machine: "iPhone1,1",
+        model: "M68AP",
+        ui_device_model: "iPhone",
+        system_version: "2.0",
+        os_build: "5A347",
+        darwin_version: "9.3.1",
+        cpu_count: 1,
+        cpu_frequency: 412_000_000,
+        physical_memory: 128 * 1024 * 1024,
+    },
+    DeviceProfile {
+        short_name: "ipod-touch",
+        machine: "iPod1,1",
+        model: "N45AP",
+        ui_device_model: "iPod touch",
+        system_version: "2.0",
+        os_build: "5A347",
+        darwin_version: "9.3.1",
+        cpu_count: 1,
+        cpu_frequency: 412_000_000,
+        physical_memory: 128 * 1024 * 1024,
+    },
+    DeviceProfile {
+        short_name: "iphone-3g",
+        machine: "iPhone1,2",
+        model: "N82AP",
+        ui_device_model: "iPhone",
+        system_version: "2.0",
+        os_build: "5A347",
+        darwin_version: "9.3.1",
+        cpu_count: 1,
+        cpu_frequency: 412_000_000,
+        physical_memory: 128 * 1024 * 1024,
+    },
+    DeviceProfile {
+        short_name: "ipod-touch-2g",
+        machine: "iPod2,1",
+        model: "N72AP",
+        ui_device_model: "iPod touch",
+        system_version: "2.1.1",
+        os_build: "5F138",
+        darwin_version: "9.4.1",
+        cpu_count: 1,
+        cpu_frequency: 532_000_000,
+        physical_memory: 128 * 1024 * 1024,
+    },
+];
+
+impl DeviceProfile {
+    /// Look up a device by the name used for the `--device=` option.
+    pub fn from_short_name(name: &str) -> Option<&'static DeviceProfile> {
+        DEVICES.iter().find(|device| device.short_name == name)
+    }
+}
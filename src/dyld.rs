@@ -22,15 +22,22 @@
 
 mod constant_lists;
 mod function_lists;
+mod scan_imports;
+
+pub use scan_imports::{scan_imports, ScanImportsFormat};
 
 use crate::abi::{CallFromGuest, GuestFunction};
+use crate::bundle::Bundle;
 use crate::cpu::Cpu;
 use crate::frameworks::foundation::ns_string;
+use crate::fs::{Fs, GuestPath};
 use crate::mach_o::{MachO, SectionType};
 use crate::mem::{ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
 use crate::objc::{nil, ObjC};
+use crate::options::{glob_matches, Options};
 use crate::Environment;
 use std::collections::HashMap;
+use std::time::Duration;
 
 type HostFunction = &'static dyn CallFromGuest;
 
@@ -94,7 +101,7 @@ pub use crate::export_c_func; // #[macro_export] is weird...
 pub enum HostConstant {
     NSString(&'static str),
     NullPtr,
-    Custom(fn(&mut Mem) -> ConstVoidPtr),
+    Custom(fn(&mut Environment) -> ConstVoidPtr),
 }
 
 /// Type for lists of constants exported by host implementations of frameworks.
@@ -137,6 +144,43 @@ fn encode_a32_trap() -> u32 {
     0xe7ffdefe
 }
 
+/// Load the app's executable and the bundled dynamic libraries it depends on.
+/// The executable is always the first binary in the result.
+pub fn load_binaries(bundle: &Bundle, fs: &Fs, mem: &mut Mem) -> Result<Vec<MachO>, String> {
+    let executable = MachO::load_from_file(bundle.executable_path(), fs, mem)
+        .map_err(|e| format!("Could not load executable: {}", e))?;
+
+    let mut dylibs = Vec::new();
+    for dylib in &executable.dynamic_libraries {
+        if dylib == "/usr/lib/libSystem.B.dylib" || dylib == "/usr/lib/libobjc.A.dylib" {
+            // We have host implementations of these
+            continue;
+        }
+
+        // There are some Free Software libraries bundled with touchHLE and
+        // exposed via the guest file system (see Fs::new()).
+        if fs.is_file(GuestPath::new(dylib)) {
+            let dylib = MachO::load_from_file(GuestPath::new(dylib), fs, mem)
+                .map_err(|e| format!("Could not load bundled dylib: {}", e))?;
+            dylibs.push(dylib);
+        } else {
+            // System frameworks will have host implementations.
+            // TODO: warn about unimplemented frameworks?
+            if !dylib.starts_with("/System/Library/Frameworks/") {
+                log!(
+                    "Warning: app binary depends on unexpected dylib \"{}\"",
+                    dylib
+                );
+            }
+            continue;
+        };
+    }
+
+    let mut bins = dylibs;
+    bins.insert(0, executable);
+    Ok(bins)
+}
+
 pub struct Dyld {
     /// List of host functions that have been "linked" and had SVCs assigned.
     ///
@@ -145,6 +189,11 @@ pub struct Dyld {
     linked_host_functions: Vec<(&'static str, HostFunction)>,
     return_to_host_routine: Option<GuestFunction>,
     constants_to_link_later: Vec<(MutPtr<ConstVoidPtr>, &'static HostConstant)>,
+    /// Patterns from `--trace-calls=`.
+    trace_calls_patterns: Vec<String>,
+    /// Number of calls and total time spent for each host function, if
+    /// `--call-stats` is enabled.
+    call_stats: Option<HashMap<&'static str, (u64, Duration)>>,
 }
 
 impl Dyld {
@@ -159,14 +208,69 @@ impl Dyld {
     const SYMBOL_STUB_INSTRUCTIONS: [u32; 2] = [0xe59fc000, 0xe59cf000];
     const PIC_SYMBOL_STUB_INSTRUCTIONS: [u32; 3] = [0xe59fc004, 0xe08fc00c, 0xe59cf000];
 
-    pub fn new() -> Dyld {
+    pub fn new(options: &Options) -> Dyld {
         Dyld {
             linked_host_functions: Vec::new(),
             return_to_host_routine: None,
             constants_to_link_later: Vec::new(),
+            trace_calls_patterns: options.trace_calls.clone(),
+            call_stats: options.call_stats.then(HashMap::new),
+        }
+    }
+
+    /// Returns [true] if calls to the host function with symbol name `symbol`
+    /// should be logged (see `--trace-calls=`).
+    pub fn should_trace_call(&self, symbol: &str) -> bool {
+        if self.trace_calls_patterns.is_empty() {
+            return false;
+        }
+        // Patterns use C function names.
+        let name = symbol.strip_prefix('_').unwrap_or(symbol);
+        self.trace_calls_patterns
+            .iter()
+            .any(|pattern| glob_matches(pattern, name))
+    }
+
+    /// Returns [true] if `--call-stats` is enabled.
+    pub fn call_stats_enabled(&self) -> bool {
+        self.call_stats.is_some()
+    }
+
+    /// Record a call to a host function for `--call-stats`.
+    pub fn record_call(&mut self, symbol: &'static str, duration: Duration) {
+        if let Some(ref mut call_stats) = self.call_stats {
+            let (count, total) = call_stats.entry(symbol).or_insert((0, Duration::ZERO));
+            *count += 1;
+            *total += duration;
+        }
+    }
+
+    /// Print the statistics collected for `--call-stats`, if enabled. The
+    /// functions with the most time spent in them come first.
+    pub fn print_call_stats(&self) {
+        let Some(ref call_stats) = self.call_stats else {
+            return;
+        };
+        let mut call_stats: Vec<_> = call_stats.iter().collect();
+        call_stats.sort_by_key(|&(_, &(_, total))| std::cmp::Reverse(total));
+        echo!("Host function call statistics:");
+        for (symbol, &(count, total)) in call_stats {
+            echo!(
+                "{}: {} calls, {:.3}ms total, {:.3}µs per call",
+                symbol,
+                count,
+                total.as_secs_f64() * 1e3,
+                total.as_secs_f64() * 1e6 / count as f64
+            );
         }
     }
 
+    /// Names of the host functions that have been linked so far, in the order
+    /// they were linked.
+    pub fn linked_host_function_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.linked_host_functions.iter().map(|&(name, _)| name)
+    }
+
     pub fn return_to_host_routine(&self) -> GuestFunction {
         self.return_to_host_routine.unwrap()
     }
@@ -360,15 +464,15 @@ impl Dyld {
                     let null_ptr_ptr = env.mem.alloc_and_write(null_ptr);
                     null_ptr_ptr.cast().cast_const()
                 }
-                HostConstant::Custom(f) => f(&mut env.mem),
+                HostConstant::Custom(f) => f(env),
             };
             env.mem.write(symbol_ptr_ptr, symbol_ptr.cast());
         }
     }
 
-    /// Return a host function that can be called to handle an SVC instruction
-    /// encountered during CPU emulation. If `None` is returned, the execution
-    /// needs to resume at `svc_pc`.
+    /// Return a host function, and its symbol name, that can be called to
+    /// handle an SVC instruction encountered during CPU emulation. If `None` is
+    /// returned, the execution needs to resume at `svc_pc`.
     pub fn get_svc_handler(
         &mut self,
         bins: &[MachO],
@@ -376,7 +480,7 @@ impl Dyld {
         cpu: &mut Cpu,
         svc_pc: u32,
         svc: u32,
-    ) -> Option<HostFunction> {
+    ) -> Option<(&'static str, HostFunction)> {
         match svc {
             Self::SVC_LAZY_LINK => self.do_lazy_link(bins, mem, cpu, svc_pc),
             Self::SVC_RETURN_TO_HOST => unreachable!(), // don't handle here
@@ -388,7 +492,7 @@ impl Dyld {
                     panic!("Unexpected SVC #{} at {:#x}", svc, svc_pc);
                 };
                 log_dbg!("Call to host function, already linked: {}", symbol);
-                Some(f)
+                Some((symbol, f))
             }
         }
     }
@@ -399,7 +503,7 @@ impl Dyld {
         mem: &mut Mem,
         cpu: &mut Cpu,
         svc_pc: u32,
-    ) -> Option<HostFunction> {
+    ) -> Option<(&'static str, HostFunction)> {
         let stubs = bins
             .iter()
             .flat_map(|bin| bin.get_section(SectionType::SymbolStubs))
@@ -435,7 +539,7 @@ impl Dyld {
 
             // Return the host function so that we can call it now that we're
             // done.
-            return Some(f);
+            return Some((symbol, f));
         }
 
         for dylib in &bins[1..] {
@@ -499,7 +603,20 @@ impl Dyld {
         symbol: &str,
     ) -> Result<GuestFunction, ()> {
         let &(symbol, f) = search_lists(function_lists::FUNCTION_LISTS, symbol).ok_or(())?;
+        Ok(self.create_guest_function(mem, cpu, symbol, f))
+    }
 
+    /// Creates a guest function that will call the host function `f`. The
+    /// symbol name is only used for debugging. Like with
+    /// [Self::create_proc_address], no attempt is made to deduplicate or
+    /// deallocate these.
+    pub fn create_guest_function(
+        &mut self,
+        mem: &mut Mem,
+        cpu: &mut Cpu,
+        symbol: &'static str,
+        f: HostFunction,
+    ) -> GuestFunction {
         // Allocate an SVC ID for this host function
         let idx: u32 = self.linked_host_functions.len().try_into().unwrap();
         let svc = idx + Self::SVC_LINKED_FUNCTIONS_BASE;
@@ -514,8 +631,6 @@ impl Dyld {
         // Just in case
         cpu.invalidate_cache_range(function_ptr.to_bits(), 4);
 
-        Ok(GuestFunction::from_addr_with_thumb_bit(
-            function_ptr.to_bits(),
-        ))
+        GuestFunction::from_addr_with_thumb_bit(function_ptr.to_bits())
     }
 }
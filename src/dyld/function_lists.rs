@@ -23,10 +23,13 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::math::FUNCTIONS,
     libc::posix_io::FUNCTIONS,
     libc::posix_io::stat::FUNCTIONS,
+    libc::pthread::cond::FUNCTIONS,
     libc::pthread::key::FUNCTIONS,
     libc::pthread::mutex::FUNCTIONS,
     libc::pthread::once::FUNCTIONS,
+    libc::pthread::rwlock::FUNCTIONS,
     libc::pthread::thread::FUNCTIONS,
+    libc::semaphore::FUNCTIONS,
     libc::setjmp::FUNCTIONS,
     libc::stdio::FUNCTIONS,
     libc::stdio::printf::FUNCTIONS,
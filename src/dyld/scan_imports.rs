@@ -0,0 +1,235 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `--scan-imports`: reporting which of an app's imports touchHLE doesn't
+//! implement, without running the app.
+//!
+//! The checks mirror what the linker does when the app is run, so a missing
+//! function here is one that would cause a "Call to unimplemented function"
+//! panic if the app called it. Selectors are an approximation: which class a
+//! message is sent to can't be known without running the app, so a selector is
+//! only reported if no host class and no class in the app has a method for it.
+
+use super::{constant_lists, function_lists, load_binaries, search_lists, Dyld};
+use crate::bundle::Bundle;
+use crate::fs::Fs;
+use crate::mach_o::{MachO, SectionType};
+use crate::mem::{ConstPtr, Mem, Ptr};
+use crate::objc::ObjC;
+use crate::options::Options;
+use std::collections::{BTreeSet, HashSet};
+
+/// Output format for [scan_imports].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScanImportsFormat {
+    Text,
+    Json,
+}
+
+/// The imports of one kind that a binary has, and which of them are missing.
+struct ImportList {
+    referenced: usize,
+    missing: Vec<String>,
+}
+
+impl ImportList {
+    fn new(referenced: BTreeSet<&str>, is_implemented: impl Fn(&str) -> bool) -> ImportList {
+        ImportList {
+            referenced: referenced.len(),
+            missing: referenced
+                .into_iter()
+                .filter(|&name| !is_implemented(name))
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+struct BinaryReport {
+    name: String,
+    functions: ImportList,
+    other_symbols: ImportList,
+    classes: ImportList,
+    selectors: ImportList,
+}
+
+impl BinaryReport {
+    /// Returns the import lists with their JSON keys and human-readable
+    /// names.
+    fn lists(&self) -> [(&'static str, &'static str, &ImportList); 4] {
+        [
+            ("functions", "Functions", &self.functions),
+            ("other_symbols", "Other symbols", &self.other_symbols),
+            ("classes", "Classes", &self.classes),
+            ("selectors", "Selectors", &self.selectors),
+        ]
+    }
+}
+
+fn scan_binary(
+    bin: &MachO,
+    bins: &[MachO],
+    mem: &Mem,
+    implemented_selectors: &HashSet<String>,
+) -> BinaryReport {
+    let indirect_symbols = |type_: SectionType| -> BTreeSet<&str> {
+        bin.sections
+            .iter()
+            .filter(|section| section.type_ == type_)
+            .flat_map(|section| {
+                let info = section.dyld_indirect_symbol_info.as_ref().unwrap();
+                info.indirect_undef_symbols.iter().flatten()
+            })
+            .map(|symbol| symbol.as_str())
+            .collect()
+    };
+
+    // See Dyld::do_lazy_link
+    let functions = ImportList::new(indirect_symbols(SectionType::SymbolStubs), |symbol| {
+        search_lists(function_lists::FUNCTION_LISTS, symbol).is_some()
+            || bins[1..]
+                .iter()
+                .any(|dylib| dylib.exported_symbols.contains_key(symbol))
+    });
+
+    // See Dyld::do_non_lazy_linking
+    let mut class_names = BTreeSet::new();
+    let mut relocation_symbols = BTreeSet::new();
+    for (_addr, name) in &bin.external_relocations {
+        if let Some(class_name) = name
+            .strip_prefix("_OBJC_CLASS_$_")
+            .or_else(|| name.strip_prefix("_OBJC_METACLASS_$_"))
+        {
+            class_names.insert(class_name);
+        } else if name != "___CFConstantStringClassReference" {
+            relocation_symbols.insert(name.as_str());
+        }
+    }
+    let non_lazy_symbols = indirect_symbols(SectionType::NonLazySymbolPointers);
+    let mut other_symbols = ImportList::new(non_lazy_symbols, |symbol| {
+        bins.iter()
+            .any(|bin| bin.exported_symbols.contains_key(symbol))
+            || search_lists(constant_lists::CONSTANT_LISTS, symbol).is_some()
+    });
+    // External relocations other than classes are never handled currently.
+    other_symbols.referenced += relocation_symbols.len();
+    other_symbols.missing.extend(
+        relocation_symbols
+            .into_iter()
+            .map(|symbol| symbol.to_string()),
+    );
+    other_symbols.missing.sort();
+    let classes = ImportList::new(class_names, ObjC::has_host_class);
+
+    let mut selector_names = BTreeSet::new();
+    if let Some(selrefs) = bin.get_section("__objc_selrefs") {
+        let base: ConstPtr<ConstPtr<u8>> = Ptr::from_bits(selrefs.addr);
+        for i in 0..(selrefs.size / 4) {
+            if let Ok(name) = mem.cstr_at_utf8(mem.read(base + i)) {
+                selector_names.insert(name);
+            }
+        }
+    }
+    let selectors = ImportList::new(selector_names, |name| implemented_selectors.contains(name));
+
+    BinaryReport {
+        name: bin.name.clone(),
+        functions,
+        other_symbols,
+        classes,
+        selectors,
+    }
+}
+
+/// Escape a string for use in JSON output.
+fn json_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c < ' ' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// The JSON report is printed to stdout rather than stderr like other output,
+/// so that it can be redirected to a file.
+fn print_json(reports: &[BinaryReport]) {
+    let binaries: Vec<String> = reports
+        .iter()
+        .map(|report| {
+            let lists: Vec<String> = report
+                .lists()
+                .iter()
+                .map(|&(key, _name, list)| {
+                    let missing: Vec<String> =
+                        list.missing.iter().map(|name| json_string(name)).collect();
+                    format!(
+                        "{}:{{\"referenced\":{},\"missing\":[{}]}}",
+                        json_string(key),
+                        list.referenced,
+                        missing.join(",")
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"name\":{},{}}}",
+                json_string(&report.name),
+                lists.join(",")
+            )
+        })
+        .collect();
+    println!("{{\"binaries\":[{}]}}", binaries.join(","));
+}
+
+fn print_text(reports: &[BinaryReport]) {
+    echo!("Import scan results:");
+    for report in reports {
+        echo!();
+        echo!("{}:", report.name);
+        for (_key, name, list) in report.lists() {
+            echo!(
+                "- {}: {} referenced, {} missing",
+                name,
+                list.referenced,
+                list.missing.len()
+            );
+            for name in &list.missing {
+                echo!("  - {}", name);
+            }
+        }
+    }
+    echo!();
+    echo!("Missing selectors are ones that no host class or class in the app has a method for. Other messages may still fail at runtime if they are sent to a class that doesn't have that method.");
+}
+
+/// Load the app's binaries, link them as if the app were about to be run, and
+/// print a report of the functions, symbols, classes and selectors they
+/// reference that touchHLE doesn't implement.
+pub fn scan_imports(bundle: &Bundle, fs: &Fs, format: ScanImportsFormat) -> Result<(), String> {
+    let options = Options::default();
+    let mut mem = Mem::new();
+    let bins = load_binaries(bundle, fs, &mut mem)?;
+    let mut objc = ObjC::new(&options);
+    let mut dyld = Dyld::new(&options);
+    dyld.do_initial_linking(&bins, &mut mem, &mut objc);
+
+    let implemented_selectors = objc.implemented_selectors(&mem);
+    let reports: Vec<BinaryReport> = bins
+        .iter()
+        .map(|bin| scan_binary(bin, &bins, &mem, &implemented_selectors))
+        .collect();
+
+    match format {
+        ScanImportsFormat::Text => print_text(&reports),
+        ScanImportsFormat::Json => print_json(&reports),
+    }
+    Ok(())
+}
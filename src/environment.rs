@@ -9,21 +9,55 @@
 //! via the re-exports one level up.
 
 use crate::{
-    abi, bundle, cpu, dyld, frameworks, fs, gdb, image, libc, mach_o, mem, objc, options, stack,
-    window,
+    abi, bundle, cpu, dyld, frameworks, fs, gdb, image, libc, mach_o, mem, objc, options, paths,
+    stack, window,
 };
 use std::net::TcpListener;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 /// Index into the [Vec] of threads. Thread 0 is always the main thread.
 pub type ThreadID = usize;
 
+/// Something a thread can be blocked on, see [Environment::block]. Guest
+/// objects are identified by their addresses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ThreadBlock {
+    /// Waiting to lock a mutex (see [libc::pthread::mutex]).
+    Mutex(mem::MutVoidPtr),
+    /// Waiting for a condition variable to be signalled (see
+    /// [libc::pthread::cond]). Once it is, the thread waits to re-lock the
+    /// mutex.
+    Condition {
+        cond: mem::MutVoidPtr,
+        mutex: mem::MutVoidPtr,
+    },
+    /// Waiting to lock a read-write lock for reading (see
+    /// [libc::pthread::rwlock]).
+    RwLockRead(mem::MutVoidPtr),
+    /// Waiting to lock a read-write lock for writing.
+    RwLockWrite(mem::MutVoidPtr),
+    /// Waiting to decrement a semaphore (see [libc::semaphore]).
+    Semaphore(u32),
+    /// Waiting for a thread to finish (`pthread_join`). Its exit value is
+    /// written to the pointer, unless it is null.
+    Joining(ThreadID, mem::MutPtr<mem::MutVoidPtr>),
+}
+
 /// Bookkeeping for a thread.
 pub struct Thread {
     /// Once a thread finishes, this is set to false.
     pub active: bool,
     /// If this is not [None], the thread is sleeping until the specified time.
     sleeping_until: Option<Instant>,
+    /// If this is not [None], the thread is blocked until the scheduler can
+    /// resolve the block, see [Environment::block].
+    blocked_by: Option<ThreadBlock>,
+    /// Time at which [Self::blocked_by] is abandoned, and the value the
+    /// blocked host function should then return instead.
+    block_timeout: Option<(Instant, u32)>,
+    /// Return value of the thread's startup routine, once it has finished.
+    exit_value: mem::MutVoidPtr,
     /// Set to [true] when a thread is running its startup routine (i.e. the
     /// function pointer passed to `pthread_create`). When it returns to the
     /// host, it should become inactive.
@@ -44,6 +78,10 @@ pub struct Thread {
     /// frame" of the thread is a host function, not whether there are any host
     /// functions at all.
     in_host_function: bool,
+    /// Number of host-to-guest function calls ([Environment::run_call])
+    /// currently in progress on this thread. `longjmp` uses this to detect
+    /// jumps that would have to unwind host stack frames.
+    guest_call_depth: u32,
     /// Context object containing the CPU state for this thread.
     ///
     /// There should always be `(threads.len() - 1)` contexts in existence.
@@ -56,6 +94,40 @@ pub struct Thread {
     stack: Option<std::ops::RangeInclusive<u32>>,
 }
 
+impl Thread {
+    /// Returns [true] if the thread could be switched to and run guest code,
+    /// i.e. it is active, not sleeping, not blocked and not waiting for a host
+    /// function.
+    pub fn is_runnable(&self) -> bool {
+        self.active
+            && self.sleeping_until.is_none()
+            && self.blocked_by.is_none()
+            && !self.in_host_function
+    }
+
+    /// Short description of the thread's state, for debugging.
+    pub fn state_description(&self, is_current: bool) -> String {
+        if !self.active {
+            "finished".to_string()
+        } else if is_current {
+            "running".to_string()
+        } else if self.in_host_function {
+            "waiting for host function".to_string()
+        } else if let Some(block) = self.blocked_by {
+            format!("blocked on {:?}", block)
+        } else if let Some(until) = self.sleeping_until {
+            format!(
+                "sleeping for {:.3}s",
+                until
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+            )
+        } else {
+            "runnable".to_string()
+        }
+    }
+}
+
 /// The struct containing the entire emulator state. Methods are provided for
 /// execution and management of threads.
 pub struct Environment {
@@ -76,6 +148,11 @@ pub struct Environment {
     pub libc_state: libc::State,
     pub framework_state: frameworks::State,
     pub options: options::Options,
+    /// Name of the host function or method about to be called, if its
+    /// arguments and return value should be logged (see `--trace-calls=` and
+    /// `--trace-objc=`). This is taken by the [abi::CallFromGuest]
+    /// implementation.
+    pub traced_host_call: Option<String>,
     gdb_server: Option<gdb::GdbServer>,
 }
 
@@ -137,36 +214,9 @@ impl Environment {
 
         let mut mem = mem::Mem::new();
 
-        let executable = mach_o::MachO::load_from_file(bundle.executable_path(), &fs, &mut mem)
-            .map_err(|e| format!("Could not load executable: {}", e))?;
-
-        let mut dylibs = Vec::new();
-        for dylib in &executable.dynamic_libraries {
-            if dylib == "/usr/lib/libSystem.B.dylib" || dylib == "/usr/lib/libobjc.A.dylib" {
-                // We have host implementations of these
-                continue;
-            }
-
-            // There are some Free Software libraries bundled with touchHLE and
-            // exposed via the guest file system (see Fs::new()).
-            if fs.is_file(fs::GuestPath::new(dylib)) {
-                let dylib = mach_o::MachO::load_from_file(fs::GuestPath::new(dylib), &fs, &mut mem)
-                    .map_err(|e| format!("Could not load bundled dylib: {}", e))?;
-                dylibs.push(dylib);
-            } else {
-                // System frameworks will have host implementations.
-                // TODO: warn about unimplemented frameworks?
-                if !dylib.starts_with("/System/Library/Frameworks/") {
-                    log!(
-                        "Warning: app binary depends on unexpected dylib \"{}\"",
-                        dylib
-                    );
-                }
-                continue;
-            };
-        }
+        let bins = dyld::load_binaries(&bundle, &fs, &mut mem)?;
 
-        let entry_point_addr = executable.entry_point_pc.ok_or_else(|| {
+        let entry_point_addr = bins[0].entry_point_pc.ok_or_else(|| {
             "Mach-O file does not specify an entry point PC, perhaps it is not an executable?"
                 .to_string()
         })?;
@@ -174,12 +224,9 @@ impl Environment {
 
         log_dbg!("Address of start function: {:?}", entry_point_addr);
 
-        let mut bins = dylibs;
-        bins.insert(0, executable);
-
-        let mut objc = objc::ObjC::new();
+        let mut objc = objc::ObjC::new(&options);
 
-        let mut dyld = dyld::Dyld::new();
+        let mut dyld = dyld::Dyld::new(&options);
         dyld.do_initial_linking(&bins, &mut mem, &mut objc);
 
         let cpu = cpu::Cpu::new(match options.direct_memory_access {
@@ -190,8 +237,12 @@ impl Environment {
         let main_thread = Thread {
             active: true,
             sleeping_until: None,
+            blocked_by: None,
+            block_timeout: None,
+            exit_value: mem::Ptr::null(),
             in_start_routine: false, // main thread never terminates
             in_host_function: false,
+            guest_call_depth: 0,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
         };
@@ -211,6 +262,7 @@ impl Environment {
             libc_state: Default::default(),
             framework_state: Default::default(),
             options,
+            traced_host_call: None,
             gdb_server: None,
         };
 
@@ -243,9 +295,8 @@ impl Environment {
                 .accept()
                 .map_err(|e| format!("Could not accept connection: {}", e))?;
             echo!("Debugger client connected on {}.", client_addr);
-            let mut gdb_server = gdb::GdbServer::new(client);
-            gdb_server.wait_for_debugger(None, &mut env.cpu, &mut env.mem);
-            env.gdb_server = Some(gdb_server);
+            env.gdb_server = Some(gdb::GdbServer::new(client));
+            env.wait_for_debugger(None);
         }
 
         echo!("CPU emulation begins now.");
@@ -279,7 +330,48 @@ impl Environment {
         Ok(env)
     }
 
-    fn stack_trace(&self) {
+    /// Print the call statistics requested by `--call-stats` and the summary
+    /// of unrecognized messages, if any. Call this before exiting.
+    pub fn print_exit_summaries(&self) {
+        self.dyld.print_call_stats();
+        self.objc.print_unrecognized_selectors();
+    }
+
+    /// Flush the app's output, print the exit summaries and exit touchHLE with
+    /// `exit_code`. All exits after the app has started should go through
+    /// this.
+    pub fn exit(&mut self, exit_code: i32) -> ! {
+        libc::stdio::flush_all(self);
+        self.print_exit_summaries();
+        std::process::exit(exit_code);
+    }
+
+    /// Describe a guest address for debugging output, including the symbol
+    /// and binary it belongs to if known, e.g.
+    /// `0x2f04 -[AppDelegate applicationDidFinishLaunching:]+0x10 (MyApp)`.
+    pub fn describe_addr(&self, addr: u32) -> String {
+        // Ignore the Thumb bit.
+        let lookup_addr = addr & !abi::GuestFunction::THUMB_BIT;
+        for bin in &self.bins {
+            if let Some((symbol, offset)) = bin.symbolicate(lookup_addr) {
+                return format!("{:#x} {}+{:#x} ({})", addr, symbol, offset, bin.name);
+            } else if bin.contains_addr(lookup_addr) {
+                return format!("{:#x} ({})", addr, bin.name);
+            }
+        }
+        format!("{:#x}", addr)
+    }
+
+    /// Print the registers, plus symbols for PC and LR.
+    fn dump_regs(&self) {
+        self.cpu.dump_regs();
+        let regs = self.cpu.regs();
+        echo!("PC: {}", self.describe_addr(regs[cpu::Cpu::PC]));
+        echo!("LR: {}", self.describe_addr(regs[cpu::Cpu::LR]));
+    }
+
+    /// Print a stack trace for the current thread.
+    pub fn stack_trace(&self) {
         if self.current_thread == 0 {
             echo!("Attempting to produce stack trace for main thread:");
         } else {
@@ -288,35 +380,182 @@ impl Environment {
                 self.current_thread
             );
         }
-        let stack_range = self.threads[self.current_thread].stack.clone().unwrap();
-        echo!(
-            " 0. {:#x} (PC)",
-            self.cpu.pc_with_thumb_bit().addr_with_thumb_bit()
-        );
+        for line in self.stack_trace_lines(self.current_thread) {
+            echo!("{}", line);
+        }
+    }
+
+    /// Produce a stack trace for `thread`, whose state must currently be in
+    /// the CPU. The result is empty if the thread has no stack.
+    fn stack_trace_lines(&self, thread: ThreadID) -> Vec<String> {
+        let mut lines = Vec::new();
+        let Some(stack_range) = self.threads[thread].stack.clone() else {
+            return lines;
+        };
+        lines.push(format!(
+            " 0. {} (PC)",
+            self.describe_addr(self.cpu.pc_with_thumb_bit().addr_with_thumb_bit())
+        ));
         let regs = self.cpu.regs();
         let mut lr = regs[cpu::Cpu::LR];
         let return_to_host_routine_addr = self.dyld.return_to_host_routine().addr_with_thumb_bit();
         if lr == return_to_host_routine_addr {
-            echo!(" 1. [host function] (LR)");
+            lines.push(" 1. [host function] (LR)".to_string());
         } else {
-            echo!(" 1. {:#x} (LR)", lr);
+            lines.push(format!(" 1. {} (LR)", self.describe_addr(lr)));
         }
         let mut i = 2;
         let mut fp: mem::ConstPtr<u8> = mem::Ptr::from_bits(regs[abi::FRAME_POINTER]);
         loop {
             if !stack_range.contains(&fp.to_bits()) {
-                echo!("Next FP ({:?}) is outside the stack.", fp);
+                lines.push(format!("Next FP ({:?}) is outside the stack.", fp));
                 break;
             }
             lr = self.mem.read((fp + 4).cast());
             fp = self.mem.read(fp.cast());
             if lr == return_to_host_routine_addr {
-                echo!("{:2}. [host function]", i);
+                lines.push(format!("{:2}. [host function]", i));
             } else {
-                echo!("{:2}. {:#x}", i, lr);
+                lines.push(format!("{:2}. {}", i, self.describe_addr(lr)));
             }
             i += 1;
         }
+        lines
+    }
+
+    #[cold]
+    /// Print what each thread is waiting for, with a stack trace, and then
+    /// panic. This is for when no thread can run and none will wake up.
+    fn report_deadlock(&mut self) -> ! {
+        echo!("Deadlock: no thread can run. Thread states:");
+        for thread in 0..self.threads.len() {
+            if !self.threads[thread].active {
+                continue;
+            }
+            echo!();
+            echo!(
+                "Thread {}{}: {}",
+                thread,
+                if thread == 0 { " (main thread)" } else { "" },
+                self.threads[thread].state_description(false)
+            );
+            match self.threads[thread].blocked_by {
+                Some(ThreadBlock::Mutex(mutex)) => {
+                    if let Some(owner) = libc::pthread::mutex::mutex_owner(self, mutex) {
+                        echo!("Mutex {:?} is held by thread {}.", mutex, owner);
+                    }
+                }
+                Some(ThreadBlock::Joining(other, _)) => {
+                    echo!(
+                        "Thread {} is {}.",
+                        other,
+                        self.threads[other].state_description(false)
+                    );
+                }
+                _ => (),
+            }
+            let held = libc::pthread::mutex::mutexes_held_by(self, thread);
+            if !held.is_empty() {
+                echo!("Holds mutexes: {:?}", held);
+            }
+
+            // Temporarily swap the thread's state into the CPU, like in
+            // Self::write_crash_report.
+            if thread != self.current_thread {
+                let context = self.threads[thread].context.as_mut().unwrap();
+                self.cpu.swap_context(context);
+            }
+            for line in self.stack_trace_lines(thread) {
+                echo!("{}", line);
+            }
+            if thread != self.current_thread {
+                let context = self.threads[thread].context.as_mut().unwrap();
+                self.cpu.swap_context(context);
+            }
+        }
+        echo!();
+        panic!("Deadlock: all threads are blocked!");
+    }
+
+    /// Write a crash report file to the user data directory, so that the
+    /// information needed for a bug report is in one place. Returns the path
+    /// of the file.
+    fn write_crash_report(&mut self, panic_message: &str) -> Result<PathBuf, String> {
+        use std::fmt::Write;
+
+        let mut report = String::new();
+        writeln!(report, "touchHLE {} crash report", super::VERSION).unwrap();
+        writeln!(
+            report,
+            "App: {} ({}), version {}",
+            self.bundle.display_name(),
+            self.bundle.bundle_identifier(),
+            self.bundle.bundle_version()
+        )
+        .unwrap();
+        writeln!(report, "Panic: {}", panic_message).unwrap();
+        writeln!(report).unwrap();
+        writeln!(report, "Options: {:#?}", self.options).unwrap();
+
+        for thread in 0..self.threads.len() {
+            if !self.threads[thread].active {
+                continue;
+            }
+            writeln!(report).unwrap();
+            writeln!(
+                report,
+                "Thread {}{} ({}):",
+                thread,
+                if thread == 0 { " (main thread)" } else { "" },
+                self.threads[thread].state_description(thread == self.current_thread)
+            )
+            .unwrap();
+
+            // Temporarily swap the thread's state into the CPU, so it can be
+            // read the same way as the current thread's.
+            if thread != self.current_thread {
+                let context = self.threads[thread].context.as_mut().unwrap();
+                self.cpu.swap_context(context);
+            }
+            let regs = self.cpu.regs();
+            for row in regs.chunks(4) {
+                let row: Vec<String> = row.iter().map(|reg| format!("{:#010x}", reg)).collect();
+                writeln!(report, "    {}", row.join(" ")).unwrap();
+            }
+            writeln!(report, "    CPSR: {:#010x}", self.cpu.cpsr()).unwrap();
+            for line in self.stack_trace_lines(thread) {
+                writeln!(report, "    {}", line).unwrap();
+            }
+            if thread != self.current_thread {
+                let context = self.threads[thread].context.as_mut().unwrap();
+                self.cpu.swap_context(context);
+            }
+        }
+
+        writeln!(report).unwrap();
+        writeln!(report, "Linked host functions:").unwrap();
+        for name in self.dyld.linked_host_function_names() {
+            writeln!(report, "    {}", name).unwrap();
+        }
+
+        writeln!(report).unwrap();
+        writeln!(report, "Recent output:").unwrap();
+        for line in crate::log::recent_lines() {
+            writeln!(report, "    {}", line).unwrap();
+        }
+
+        let dir = paths::user_data_base_path().join(paths::CRASH_REPORTS_DIR);
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let path = dir.join(format!(
+            "{}_{}.txt",
+            self.bundle.bundle_identifier(),
+            timestamp
+        ));
+        std::fs::write(&path, report).map_err(|e| e.to_string())?;
+        Ok(path)
     }
 
     /// Create a new thread and return its ID. The `start_routine` and
@@ -335,8 +574,12 @@ impl Environment {
         self.threads.push(Thread {
             active: true,
             sleeping_until: None,
+            blocked_by: None,
+            block_timeout: None,
+            exit_value: mem::Ptr::null(),
             in_start_routine: true,
             in_host_function: false,
+            guest_call_depth: 0,
             context: Some(cpu::CpuContext::new()),
             stack: Some(stack_alloc.to_bits()..=(stack_high_addr - 1)),
         });
@@ -382,6 +625,123 @@ impl Environment {
         self.cpu.branch(old_pc);
     }
 
+    /// Block the current thread until the scheduler can resolve `block`, e.g.
+    /// until it can lock a mutex on the thread's behalf.
+    ///
+    /// This must only be used by host functions called directly from guest
+    /// code: the host function returns as normal, but the thread executes no
+    /// more guest code until the block is resolved. Its return value should be
+    /// the one for success. If `timeout` is not [None], the block is abandoned
+    /// at the specified time and the return value is replaced with the one
+    /// given (a timed-out condition variable wait still re-locks the mutex).
+    pub fn block(&mut self, block: ThreadBlock, timeout: Option<(Instant, u32)>) {
+        let thread = &mut self.threads[self.current_thread];
+        assert!(thread.blocked_by.is_none());
+        log_dbg!("Thread {} is blocking on {:?}.", self.current_thread, block);
+        thread.blocked_by = Some(block);
+        thread.block_timeout = timeout;
+    }
+
+    /// Wake up threads waiting for the condition variable `cond`: either one
+    /// of them, or all of them if `broadcast` is [true]. They will then wait
+    /// to re-lock their mutex.
+    pub fn signal_condition(&mut self, cond: mem::MutVoidPtr, broadcast: bool) {
+        for (i, thread) in self.threads.iter_mut().enumerate() {
+            let Some(ThreadBlock::Condition {
+                cond: waiting_cond,
+                mutex,
+            }) = thread.blocked_by
+            else {
+                continue;
+            };
+            if waiting_cond != cond {
+                continue;
+            }
+            log_dbg!("Thread {} was signalled by condition {:?}.", i, cond);
+            thread.blocked_by = Some(ThreadBlock::Mutex(mutex));
+            thread.block_timeout = None;
+            if !broadcast {
+                break;
+            }
+        }
+    }
+
+    /// Wake up all threads blocked on `block` without resolving it, e.g.
+    /// because the object they're waiting for was destroyed. The blocked host
+    /// function's return value is replaced with `result`.
+    pub fn abandon_blocks(&mut self, block: ThreadBlock, result: u32) {
+        for thread in 0..self.threads.len() {
+            if self.threads[thread].blocked_by != Some(block) {
+                continue;
+            }
+            log_dbg!("Thread {} is no longer blocked on {:?}.", thread, block);
+            self.with_thread_cpu(thread, |cpu| cpu.regs_mut()[0] = result);
+            self.threads[thread].blocked_by = None;
+            self.threads[thread].block_timeout = None;
+        }
+    }
+
+    /// Count the threads blocked on `block`.
+    pub fn count_blocked_threads(&self, block: ThreadBlock) -> usize {
+        self.threads
+            .iter()
+            .filter(|thread| thread.blocked_by == Some(block))
+            .count()
+    }
+
+    /// Try to resolve a thread's block (see [Self::block]), or abandon it if
+    /// it has timed out. Returns [true] if the thread is no longer blocked.
+    fn try_unblock(&mut self, thread: ThreadID) -> bool {
+        let Some(block) = self.threads[thread].blocked_by else {
+            return true;
+        };
+        let resolved = match block {
+            ThreadBlock::Mutex(mutex) => {
+                libc::pthread::mutex::try_lock_for_thread(self, mutex.cast(), thread)
+            }
+            // Only signalling or a timeout can end this.
+            ThreadBlock::Condition { .. } => false,
+            ThreadBlock::RwLockRead(rwlock) => {
+                libc::pthread::rwlock::try_lock_for_thread(self, rwlock.cast(), thread, false)
+            }
+            ThreadBlock::RwLockWrite(rwlock) => {
+                libc::pthread::rwlock::try_lock_for_thread(self, rwlock.cast(), thread, true)
+            }
+            ThreadBlock::Semaphore(semaphore) => libc::semaphore::try_decrement(self, semaphore),
+            ThreadBlock::Joining(other, value_ptr) => {
+                let finished = !self.threads[other].active;
+                if finished && !value_ptr.is_null() {
+                    let exit_value = self.threads[other].exit_value;
+                    self.mem.write(value_ptr, exit_value);
+                }
+                finished
+            }
+        };
+        if resolved {
+            log_dbg!("Thread {} is no longer blocked on {:?}.", thread, block);
+            self.threads[thread].blocked_by = None;
+            self.threads[thread].block_timeout = None;
+            return true;
+        }
+
+        let Some((deadline, timeout_result)) = self.threads[thread].block_timeout else {
+            return false;
+        };
+        if deadline > Instant::now() {
+            return false;
+        }
+        log_dbg!("Thread {} timed out while blocked on {:?}.", thread, block);
+        self.with_thread_cpu(thread, |cpu| cpu.regs_mut()[0] = timeout_result);
+        self.threads[thread].block_timeout = None;
+        if let ThreadBlock::Condition { mutex, .. } = block {
+            self.threads[thread].blocked_by = Some(ThreadBlock::Mutex(mutex));
+            self.try_unblock(thread)
+        } else {
+            self.threads[thread].blocked_by = None;
+            true
+        }
+    }
+
     /// Run the emulator. This is the main loop and won't return until app exit.
     /// Only `main.rs` should call this.
     pub fn run(&mut self) {
@@ -390,8 +750,23 @@ impl Environment {
         let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.run_inner(true)));
         if let Err(e) = res {
             echo!("Register state immediately after panic:");
-            self.cpu.dump_regs();
+            self.dump_regs();
             self.stack_trace();
+            let panic_message = if let Some(s) = e.downcast_ref::<&str>() {
+                s
+            } else if let Some(s) = e.downcast_ref::<String>() {
+                s
+            } else {
+                "(non-string payload)"
+            };
+            match self.write_crash_report(panic_message) {
+                Ok(path) => echo!("Crash report written to {}.", path.display()),
+                Err(e) => echo!("Warning: couldn't write crash report: {}", e),
+            }
+            // Don't lose the app's buffered output, it may explain the crash.
+            libc::stdio::flush_all(self);
+            // These can be useful for diagnosing the crash too.
+            self.print_exit_summaries();
             std::panic::resume_unwind(e);
         }
     }
@@ -404,11 +779,22 @@ impl Environment {
     pub fn run_call(&mut self) {
         let was_in_host_function = self.threads[self.current_thread].in_host_function;
         self.threads[self.current_thread].in_host_function = false;
+        self.threads[self.current_thread].guest_call_depth += 1;
         self.run_inner(false);
+        self.threads[self.current_thread].guest_call_depth -= 1;
         self.threads[self.current_thread].in_host_function = was_in_host_function;
     }
 
-    fn switch_thread(&mut self, new_thread: ThreadID) {
+    /// Number of host-to-guest function calls ([Self::run_call]) currently in
+    /// progress on the current thread. If this differs between two points in
+    /// the execution of the thread, there are host stack frames between them.
+    pub fn guest_call_depth(&self) -> u32 {
+        self.threads[self.current_thread].guest_call_depth
+    }
+
+    /// Switch the CPU to executing a different thread. This doesn't check
+    /// whether the thread is runnable, see [Thread::is_runnable].
+    pub fn switch_thread(&mut self, new_thread: ThreadID) {
         assert!(new_thread != self.current_thread);
 
         log_dbg!(
@@ -424,6 +810,36 @@ impl Environment {
         self.current_thread = new_thread;
     }
 
+    /// Run `f` with the CPU state of thread `thread`, even if it is not the
+    /// current thread. This is for the debugger: the current thread doesn't
+    /// change, the CPU state is swapped in and out only temporarily.
+    pub fn with_thread_cpu<R>(
+        &mut self,
+        thread: ThreadID,
+        f: impl FnOnce(&mut cpu::Cpu) -> R,
+    ) -> R {
+        if thread == self.current_thread {
+            return f(&mut self.cpu);
+        }
+        let context = self.threads[thread].context.as_mut().unwrap();
+        self.cpu.swap_context(context);
+        let result = f(&mut self.cpu);
+        self.cpu.swap_context(context);
+        result
+    }
+
+    #[cold]
+    /// Hand control to the debugger. Returns [true] if the CPU should step and
+    /// then resume debugging, or [false] if it should resume normal execution.
+    fn wait_for_debugger(&mut self, stop_reason: Option<cpu::CpuError>) -> bool {
+        // The server is taken out temporarily so that it can access the rest
+        // of the environment, e.g. to inspect other threads.
+        let mut gdb_server = self.gdb_server.take().unwrap();
+        let step = gdb_server.wait_for_debugger(stop_reason, self);
+        self.gdb_server = Some(gdb_server);
+        step
+    }
+
     #[cold]
     /// Let the debugger handle a CPU error, or panic if there's no debugger
     /// connected. Returns [true] if the CPU should step and then resume
@@ -444,19 +860,20 @@ impl Environment {
             self.cpu.regs_mut()[cpu::Cpu::PC] -= instruction_len;
         }
 
+        let pc = self.describe_addr(self.cpu.pc_with_thumb_bit().addr_with_thumb_bit());
         if self.gdb_server.is_none() {
-            panic!("Error during CPU execution: {:?}", error);
+            panic!("Error during CPU execution at {}: {:?}", pc, error);
         }
 
-        echo!("Debuggable error during CPU execution: {:?}.", error);
+        echo!(
+            "Debuggable error during CPU execution at {}: {:?}.",
+            pc,
+            error
+        );
         // GDB doesn't seem to manage to produce a useful stack trace, so
         // let's print our own.
         self.stack_trace();
-        self.gdb_server.as_mut().unwrap().wait_for_debugger(
-            Some(error),
-            &mut self.cpu,
-            &mut self.mem,
-        )
+        self.wait_for_debugger(Some(error))
     }
 
     #[inline(always)]
@@ -492,6 +909,8 @@ impl Environment {
                             self.current_thread
                         );
                         self.threads[self.current_thread].active = false;
+                        self.threads[self.current_thread].exit_value =
+                            mem::Ptr::from_bits(self.cpu.regs()[0]);
                         let stack = self.threads[self.current_thread].stack.take().unwrap();
                         let stack: mem::MutVoidPtr = mem::Ptr::from_bits(*stack.start());
                         log_dbg!("Freeing thread {} stack {:?}", self.current_thread, stack);
@@ -502,15 +921,28 @@ impl Environment {
                     }
                 }
 
-                if let Some(f) =
+                if let Some((symbol, f)) =
                     self.dyld
                         .get_svc_handler(&self.bins, &mut self.mem, &mut self.cpu, svc_pc, svc)
                 {
                     let was_in_host_function = self.threads[self.current_thread].in_host_function;
                     self.threads[self.current_thread].in_host_function = true;
+                    self.traced_host_call = self
+                        .dyld
+                        .should_trace_call(symbol)
+                        .then(|| symbol.to_string());
+                    let start = self.dyld.call_stats_enabled().then(Instant::now);
                     f.call_from_guest(self);
+                    if let Some(start) = start {
+                        self.dyld.record_call(symbol, start.elapsed());
+                    }
                     self.threads[self.current_thread].in_host_function = was_in_host_function;
-                    ThreadNextAction::Continue
+                    if self.threads[self.current_thread].blocked_by.is_some() {
+                        // The host function blocked the thread, see Self::block
+                        ThreadNextAction::Yield
+                    } else {
+                        ThreadNextAction::Continue
+                    }
                 } else {
                     self.cpu.regs_mut()[cpu::Cpu::PC] = svc_pc;
                     ThreadNextAction::Continue
@@ -551,11 +983,7 @@ impl Environment {
                 match self.handle_cpu_state(state, initial_thread, root) {
                     ThreadNextAction::Continue => {
                         if step_and_debug {
-                            step_and_debug = self.gdb_server.as_mut().unwrap().wait_for_debugger(
-                                None,
-                                &mut self.cpu,
-                                &mut self.mem,
-                            );
+                            step_and_debug = self.wait_for_debugger(None);
                         }
                     }
                     ThreadNextAction::Yield => break,
@@ -573,6 +1001,10 @@ impl Environment {
             // this until after we've done some amount of work on the guest
             // thread, lest every single callback call pay this cost.
             self.window.poll_for_events(&self.options);
+            if self.window.forced_exit_due() {
+                echo!("App didn't terminate in time, exiting anyway.");
+                self.exit(1);
+            }
 
             loop {
                 // Try to find a new thread to execute, starting with the thread
@@ -581,12 +1013,23 @@ impl Environment {
                 let mut next_awakening: Option<Instant> = None;
                 for i in 0..self.threads.len() {
                     let i = (self.current_thread + 1 + i) % self.threads.len();
-                    let candidate = &mut self.threads[i];
 
-                    if !candidate.active || candidate.in_host_function {
+                    if !self.threads[i].active || self.threads[i].in_host_function {
                         continue;
                     }
 
+                    if !self.try_unblock(i) {
+                        if let Some((deadline, _)) = self.threads[i].block_timeout {
+                            next_awakening = match next_awakening {
+                                None => Some(deadline),
+                                Some(other) => Some(other.min(deadline)),
+                            };
+                        }
+                        continue;
+                    }
+
+                    let candidate = &mut self.threads[i];
+
                     if let Some(sleeping_until) = candidate.sleeping_until {
                         if sleeping_until <= Instant::now() {
                             log_dbg!("Thread {} finished sleeping.", i);
@@ -610,8 +1053,8 @@ impl Environment {
                         self.switch_thread(suitable_thread);
                     }
                     break;
-                // All suitable threads are asleep. Sleep until one of them
-                // wakes up.
+                // All suitable threads are asleep or waiting for a timeout.
+                // Sleep until one of them wakes up.
                 } else if let Some(next_awakening) = next_awakening {
                     let duration = next_awakening.duration_since(Instant::now());
                     log_dbg!("All threads asleep, sleeping for {:?}.", duration);
@@ -619,6 +1062,8 @@ impl Environment {
                     // Try again, there should be some thread awake now (or
                     // there will be soon, since timing is approximate).
                     continue;
+                } else if self.threads.iter().any(|thread| thread.active) {
+                    self.report_deadlock();
                 } else {
                     // This should never happen!
                     panic!("No active threads?!");
@@ -9,9 +9,10 @@ use crate::audio; // Keep this module namespaced to avoid confusion
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::{
-    debug_fourcc, fourcc, kAudioFormatAppleIMA4, kAudioFormatFlagIsBigEndian,
-    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatFlagIsSignedInteger,
-    kAudioFormatLinearPCM, AudioStreamBasicDescription,
+    debug_fourcc, fourcc, kAudioFormatAppleIMA4, kAudioFormatAppleLossless,
+    kAudioFormatFlagIsBigEndian, kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked,
+    kAudioFormatFlagIsSignedInteger, kAudioFormatLinearPCM, kAudioFormatMPEG4AAC,
+    AudioStreamBasicDescription, AudioStreamPacketDescription,
 };
 use crate::frameworks::core_foundation::cf_url::CFURLRef;
 use crate::frameworks::foundation::ns_url::to_rust_path;
@@ -41,6 +42,7 @@ unsafe impl SafeRead for OpaqueAudioFileID {}
 
 type AudioFileID = MutPtr<OpaqueAudioFileID>;
 
+const kAudioFileEndOfFileError: OSStatus = -39;
 const kAudioFileFileNotFoundError: OSStatus = -43;
 const kAudioFileBadPropertySizeError: OSStatus = fourcc(b"!siz") as _;
 const kAudioFileUnsupportedProperty: OSStatus = fourcc(b"pty?") as _;
@@ -98,14 +100,24 @@ fn AudioFileOpenURL(
     0 // success
 }
 
-fn property_size(property_id: AudioFilePropertyID) -> GuestUSize {
-    match property_id {
+/// Returns [None] if the property isn't available for this file.
+fn property_size(
+    audio_file: &audio::AudioFile,
+    property_id: AudioFilePropertyID,
+) -> Option<GuestUSize> {
+    Some(match property_id {
         kAudioFilePropertyDataFormat => guest_size_of::<AudioStreamBasicDescription>(),
         kAudioFilePropertyAudioDataByteCount => guest_size_of::<u64>(),
         kAudioFilePropertyAudioDataPacketCount => guest_size_of::<u64>(),
         kAudioFilePropertyPacketSizeUpperBound => guest_size_of::<u32>(),
+        // Only compressed formats have a magic cookie.
+        kAudioFilePropertyMagicCookieData => audio_file.magic_cookie()?.len().try_into().unwrap(),
+        // Our currently supported formats probably don't use this property.
+        // Not sure if this is correct, but it skips some code we don't want to
+        // run in Touch & Go.
+        kAudioFilePropertyChannelLayout => return None,
         _ => unimplemented!("Unimplemented property ID: {}", debug_fourcc(property_id)),
-    }
+    })
 }
 
 fn AudioFileGetPropertyInfo(
@@ -117,12 +129,12 @@ fn AudioFileGetPropertyInfo(
 ) -> OSStatus {
     return_if_null!(in_audio_file);
 
-    if in_property_id == kAudioFilePropertyMagicCookieData
-        || in_property_id == kAudioFilePropertyChannelLayout
-    {
-        // Our currently supported formats probably don't use these properties.
-        // Not sure if this is correct, but it skips some code we don't want to
-        // run in Touch & Go.
+    let host_object = State::get(&mut env.framework_state)
+        .audio_files
+        .get_mut(&in_audio_file)
+        .unwrap();
+
+    let Some(size) = property_size(&host_object.audio_file, in_property_id) else {
         if !out_data_size.is_null() {
             env.mem.write(out_data_size, 0);
         }
@@ -130,9 +142,9 @@ fn AudioFileGetPropertyInfo(
             env.mem.write(is_writable, 0);
         }
         return kAudioFileUnsupportedProperty;
-    }
+    };
     if !out_data_size.is_null() {
-        env.mem.write(out_data_size, property_size(in_property_id));
+        env.mem.write(out_data_size, size);
     }
     if !is_writable.is_null() {
         env.mem.write(is_writable, 0); // TODO: probably not always correct
@@ -149,17 +161,24 @@ fn AudioFileGetProperty(
 ) -> OSStatus {
     return_if_null!(in_audio_file);
 
-    let required_size = property_size(in_property_id);
-    if env.mem.read(io_data_size) != required_size {
-        log!("Warning: AudioFileGetProperty() failed");
-        return kAudioFileBadPropertySizeError;
-    }
-
     let host_object = State::get(&mut env.framework_state)
         .audio_files
         .get_mut(&in_audio_file)
         .unwrap();
 
+    let Some(required_size) = property_size(&host_object.audio_file, in_property_id) else {
+        log!(
+            "Warning: AudioFileGetProperty() for unsupported property {}",
+            debug_fourcc(in_property_id)
+        );
+        return kAudioFileUnsupportedProperty;
+    };
+    if env.mem.read(io_data_size) < required_size {
+        log!("Warning: AudioFileGetProperty() failed");
+        return kAudioFileBadPropertySizeError;
+    }
+    env.mem.write(io_data_size, required_size);
+
     match in_property_id {
         kAudioFilePropertyDataFormat => {
             let audio::AudioDescription {
@@ -194,10 +213,16 @@ fn AudioFileGetProperty(
                         _reserved: 0,
                     }
                 }
-                audio::AudioFormat::AppleIma4 => {
+                audio::AudioFormat::AppleIma4
+                | audio::AudioFormat::Aac
+                | audio::AudioFormat::AppleLossless => {
                     AudioStreamBasicDescription {
                         sample_rate,
-                        format_id: kAudioFormatAppleIMA4,
+                        format_id: match format {
+                            audio::AudioFormat::AppleIma4 => kAudioFormatAppleIMA4,
+                            audio::AudioFormat::Aac => kAudioFormatMPEG4AAC,
+                            _ => kAudioFormatAppleLossless,
+                        },
                         format_flags: 0,
                         bytes_per_packet,
                         frames_per_packet,
@@ -223,6 +248,12 @@ fn AudioFileGetProperty(
             env.mem
                 .write(out_property_data.cast(), packet_size_upper_bound);
         }
+        kAudioFilePropertyMagicCookieData => {
+            let magic_cookie = host_object.audio_file.magic_cookie().unwrap();
+            env.mem
+                .bytes_at_mut(out_property_data.cast(), required_size)
+                .copy_from_slice(magic_cookie);
+        }
         _ => unreachable!(),
     }
 
@@ -251,56 +282,69 @@ fn AudioFileReadBytes(
         .audio_file
         .read_bytes(in_starting_byte.try_into().unwrap(), buffer_slice)
         .unwrap(); // TODO: handle seek error?
-    assert!((bytes_read as u64) == (bytes_to_read as u64)); // TODO: return eofErr
     env.mem.write(io_num_bytes, bytes_read.try_into().unwrap());
 
-    0 // success
+    if (bytes_read as u64) < (bytes_to_read as u64) {
+        kAudioFileEndOfFileError
+    } else {
+        0 // success
+    }
 }
 
 fn AudioFileReadPackets(
     env: &mut Environment,
     in_audio_file: AudioFileID,
-    in_use_cache: bool,
+    _in_use_cache: bool,
     out_num_bytes: MutPtr<u32>,
-    out_packet_descriptions: MutVoidPtr, // unimplemented
+    out_packet_descriptions: MutPtr<AudioStreamPacketDescription>,
     in_starting_packet: i64,
     io_num_packets: MutPtr<u32>,
     out_buffer: MutVoidPtr,
 ) -> OSStatus {
     return_if_null!(in_audio_file);
 
-    // Variable-size packets are not implemented currently. When they are,
-    // this parameter should be a `MutPtr<AudioStreamPacketDescription>`.
-    assert!(out_packet_descriptions.is_null());
-
     let host_object = State::get(&mut env.framework_state)
         .audio_files
         .get_mut(&in_audio_file)
         .unwrap();
-    let packet_size = host_object.audio_file.packet_size_fixed();
 
     let packets_to_read = env.mem.read(io_num_packets);
 
-    let starting_byte = i64::from(packet_size)
-        .checked_mul(in_starting_packet)
+    // The buffer must be large enough for the requested number of packets.
+    let buffer_size = packets_to_read
+        .checked_mul(host_object.audio_file.packet_size_upper_bound())
         .unwrap();
-    let bytes_to_read = packets_to_read.checked_mul(packet_size).unwrap();
-
-    env.mem.write(out_num_bytes, bytes_to_read);
-    let res = AudioFileReadBytes(
-        env,
-        in_audio_file,
-        in_use_cache,
-        starting_byte,
-        out_num_bytes,
-        out_buffer,
-    );
+    let buffer_slice = env.mem.bytes_at_mut(out_buffer.cast(), buffer_size);
+
+    let packets = host_object
+        .audio_file
+        .read_packets(
+            in_starting_packet.try_into().unwrap(),
+            packets_to_read,
+            buffer_slice,
+        )
+        .unwrap(); // TODO: handle seek error?
 
-    let bytes_read = env.mem.read(out_num_bytes);
-    let packets_read = bytes_read / packet_size;
-    env.mem.write(io_num_packets, packets_read);
+    let bytes_read = packets
+        .last()
+        .map_or(0, |packet| packet.offset + u64::from(packet.size));
+    env.mem.write(out_num_bytes, bytes_read.try_into().unwrap());
+    env.mem
+        .write(io_num_packets, packets.len().try_into().unwrap());
+    if !out_packet_descriptions.is_null() {
+        for (i, packet) in packets.into_iter().enumerate() {
+            env.mem.write(
+                out_packet_descriptions + i.try_into().unwrap(),
+                AudioStreamPacketDescription {
+                    start_offset: packet.offset.try_into().unwrap(),
+                    variable_frames_in_packet: packet.frames,
+                    data_byte_size: packet.size,
+                },
+            );
+        }
+    }
 
-    res
+    0 // success
 }
 
 fn AudioFileClose(env: &mut Environment, in_audio_file: AudioFileID) -> OSStatus {
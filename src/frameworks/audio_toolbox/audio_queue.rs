@@ -9,22 +9,26 @@
 //! Apple's implementation probably uses Core Audio instead.
 
 use crate::abi::{CallFromHost, GuestFunction};
-use crate::audio::decode_ima4;
 use crate::audio::openal as al;
 use crate::audio::openal::al_types::*;
 use crate::audio::openal::alc_types::*;
+use crate::audio::{decode_ima4_interleaved, pcm_to_i16, AudioDecoder, AudioFormat};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::{
-    kAudioFormatAppleIMA4, kAudioFormatFlagIsBigEndian, kAudioFormatFlagIsFloat,
-    kAudioFormatFlagIsPacked, kAudioFormatLinearPCM, AudioStreamBasicDescription,
+    debug_fourcc, fourcc, kAudioFormatAppleIMA4, kAudioFormatAppleLossless,
+    kAudioFormatFlagIsAlignedHigh, kAudioFormatFlagIsBigEndian, kAudioFormatFlagIsFloat,
+    kAudioFormatFlagIsNonInterleaved, kAudioFormatFlagIsSignedInteger, kAudioFormatLinearPCM,
+    kAudioFormatMPEG4AAC, AudioStreamBasicDescription, AudioStreamPacketDescription,
 };
 use crate::frameworks::core_foundation::cf_run_loop::{
     kCFRunLoopCommonModes, CFRunLoopGetMain, CFRunLoopMode, CFRunLoopRef,
 };
 use crate::frameworks::foundation::ns_run_loop;
 use crate::frameworks::foundation::ns_string::get_static_str;
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::mem::{
+    guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead,
+};
 use crate::objc::msg;
 use crate::Environment;
 use std::collections::{HashMap, VecDeque};
@@ -60,8 +64,15 @@ impl State {
     }
 }
 
+/// Make the internal OpenAL context current, e.g. so that the movie player can
+/// play audio. The guest app's context is restored when the returned object is
+/// dropped.
+pub fn make_internal_al_context_current(env: &mut Environment) -> ContextManager {
+    State::get(&mut env.framework_state).make_al_context_current()
+}
+
 #[must_use]
-struct ContextManager(*mut ALCcontext);
+pub struct ContextManager(*mut ALCcontext);
 impl ContextManager {
     pub fn make_active(new_context: *mut ALCcontext) -> ContextManager {
         let old_context = unsafe { al::alcGetCurrentContext() };
@@ -82,7 +93,14 @@ struct AudioQueueHostObject {
     /// Weak reference
     run_loop: CFRunLoopRef,
     volume: f32,
+    /// Codec configuration for compressed formats, set by the app.
+    magic_cookie: Option<Vec<u8>>,
+    /// Created when the first buffer in a compressed format is decoded.
+    decoder: Option<AudioDecoder>,
     buffers: Vec<AudioQueueBufferRef>,
+    /// Packet descriptions passed when enqueueing each buffer, needed for
+    /// formats with variable packet sizes.
+    packet_descriptions: HashMap<AudioQueueBufferRef, Vec<AudioStreamPacketDescription>>,
     /// There is also a queue of OpenAL buffers, which must be kept in sync:
     /// the nth item in this queue must also be the nth item in the OpenAL
     /// queue, though the OpenAL queue may be shorter.
@@ -90,6 +108,20 @@ struct AudioQueueHostObject {
     /// Tracks whether this audio queue has been started, so we can restart the
     /// OpenAL source if it automatically stops due to running out of data.
     is_running: bool,
+    /// Set by a non-immediate [AudioQueueStop]: the queue stops once the
+    /// buffers already enqueued have been played.
+    is_stopping: bool,
+    /// Value of `kAudioQueueProperty_IsRunning`. Unlike `is_running`, this
+    /// isn't affected by pausing, and a stopping queue is still running.
+    is_running_property: bool,
+    /// Set when `kAudioQueueProperty_IsRunning` changes, so the listeners can
+    /// be called from the run loop.
+    is_running_changed: bool,
+    property_listeners: Vec<(
+        AudioQueuePropertyID,
+        AudioQueuePropertyListenerProc,
+        MutVoidPtr,
+    )>,
     al_source: Option<ALuint>,
     al_unused_buffers: Vec<ALuint>,
 }
@@ -108,11 +140,9 @@ struct AudioQueueBuffer {
     audio_data: MutVoidPtr,
     audio_data_byte_size: u32,
     user_data: MutVoidPtr,
-    _packet_description_capacity: u32,
-    /// Should be a `MutPtr<AudioStreamPacketDescription>`, but that's not
-    /// implemented yet.
-    _packet_descriptions: MutVoidPtr,
-    _packet_description_count: u32,
+    packet_description_capacity: u32,
+    packet_descriptions: MutPtr<AudioStreamPacketDescription>,
+    packet_description_count: u32,
 }
 unsafe impl SafeRead for AudioQueueBuffer {}
 
@@ -126,6 +156,17 @@ const kAudioQueueParam_Volume: AudioQueueParameterID = 1;
 
 type AudioQueueParameterValue = f32;
 
+type AudioQueuePropertyID = u32;
+const kAudioQueueProperty_IsRunning: AudioQueuePropertyID = fourcc(b"aqrn");
+const kAudioQueueProperty_StreamDescription: AudioQueuePropertyID = fourcc(b"aqft");
+const kAudioQueueProperty_MagicCookie: AudioQueuePropertyID = fourcc(b"aqmc");
+
+/// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueuePropertyID in_id)
+type AudioQueuePropertyListenerProc = GuestFunction;
+
+const kAudioQueueErr_InvalidProperty: OSStatus = -66684;
+const kAudioQueueErr_InvalidPropertySize: OSStatus = -66683;
+
 fn AudioQueueNewOutput(
     env: &mut Environment,
     in_format: ConstPtr<AudioStreamBasicDescription>,
@@ -163,9 +204,16 @@ fn AudioQueueNewOutput(
         callback_user_data: in_user_data,
         run_loop: in_callback_run_loop,
         volume: 1.0,
+        magic_cookie: None,
+        decoder: None,
         buffers: Vec::new(),
+        packet_descriptions: HashMap::new(),
         buffer_queue: VecDeque::new(),
         is_running: false,
+        is_stopping: false,
+        is_running_property: false,
+        is_running_changed: false,
+        property_listeners: Vec::new(),
         al_source: None,
         al_unused_buffers: Vec::new(),
     };
@@ -221,6 +269,16 @@ fn AudioQueueAllocateBuffer(
     in_aq: AudioQueueRef,
     in_buffer_byte_size: GuestUSize,
     out_buffer: MutPtr<AudioQueueBufferRef>,
+) -> OSStatus {
+    AudioQueueAllocateBufferWithPacketDescriptions(env, in_aq, in_buffer_byte_size, 0, out_buffer)
+}
+
+fn AudioQueueAllocateBufferWithPacketDescriptions(
+    env: &mut Environment,
+    in_aq: AudioQueueRef,
+    in_buffer_byte_size: GuestUSize,
+    in_number_packet_descriptions: u32,
+    out_buffer: MutPtr<AudioQueueBufferRef>,
 ) -> OSStatus {
     return_if_null!(in_aq);
 
@@ -230,14 +288,21 @@ fn AudioQueueAllocateBuffer(
         .unwrap();
 
     let audio_data = env.mem.alloc(in_buffer_byte_size);
+    let packet_descriptions = if in_number_packet_descriptions == 0 {
+        Ptr::null()
+    } else {
+        env.mem
+            .alloc(in_number_packet_descriptions * guest_size_of::<AudioStreamPacketDescription>())
+            .cast()
+    };
     let buffer_ptr = env.mem.alloc_and_write(AudioQueueBuffer {
         audio_data_bytes_capacity: in_buffer_byte_size,
         audio_data,
         audio_data_byte_size: 0,
         user_data: Ptr::null(),
-        _packet_description_capacity: 0,
-        _packet_descriptions: Ptr::null(),
-        _packet_description_count: 0,
+        packet_description_capacity: in_number_packet_descriptions,
+        packet_descriptions,
+        packet_description_count: 0,
     });
     host_object.buffers.push(buffer_ptr);
     env.mem.write(out_buffer, buffer_ptr);
@@ -249,14 +314,28 @@ fn AudioQueueEnqueueBuffer(
     env: &mut Environment,
     in_aq: AudioQueueRef,
     in_buffer: AudioQueueBufferRef,
-    _in_num_packet_descs: u32,
-    _in_packet_descs: MutVoidPtr,
+    in_num_packet_descs: u32,
+    in_packet_descs: ConstPtr<AudioStreamPacketDescription>,
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    // Variable packet size unimplemented (no formats supported that need it).
-    // We don't assert the count is 0 because we might get a useless one even
-    // for formats that don't need it.
+    // Packet descriptions are only needed for variable packet sizes. They can
+    // either be passed here or be stored in the buffer. We don't assert the
+    // count is 0 for other formats because we might get a useless one.
+    let buffer = env.mem.read(in_buffer);
+    let (count, descs) = if in_num_packet_descs != 0 && !in_packet_descs.is_null() {
+        (in_num_packet_descs, in_packet_descs)
+    } else {
+        (
+            buffer.packet_description_count,
+            buffer.packet_descriptions.cast_const(),
+        )
+    };
+    let packet_descriptions: Vec<_> = if descs.is_null() {
+        Vec::new()
+    } else {
+        (0..count).map(|i| env.mem.read(descs + i)).collect()
+    };
 
     let host_object = State::get(&mut env.framework_state)
         .audio_queues
@@ -267,70 +346,261 @@ fn AudioQueueEnqueueBuffer(
     assert!(host_object.buffers.contains(&in_buffer));
 
     host_object.buffer_queue.push_back(in_buffer);
+    host_object
+        .packet_descriptions
+        .insert(in_buffer, packet_descriptions);
     log_dbg!("New buffer enqueued: {:?}", in_buffer);
 
     0 // success
 }
 
+fn AudioQueueSetProperty(
+    env: &mut Environment,
+    in_aq: AudioQueueRef,
+    in_id: AudioQueuePropertyID,
+    in_data: ConstVoidPtr,
+    in_data_size: u32,
+) -> OSStatus {
+    return_if_null!(in_aq);
+
+    let data = env.mem.bytes_at(in_data.cast(), in_data_size).to_vec();
+
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get_mut(&in_aq)
+        .unwrap();
+
+    match in_id {
+        kAudioQueueProperty_MagicCookie => {
+            host_object.magic_cookie = Some(data);
+            host_object.decoder = None;
+        }
+        _ => {
+            log!(
+                "TODO: AudioQueueSetProperty({:?}, {}, {:?}, {})",
+                in_aq,
+                debug_fourcc(in_id),
+                in_data,
+                in_data_size
+            );
+        }
+    }
+
+    0 // success
+}
+
+fn property_size(
+    host_object: &AudioQueueHostObject,
+    in_id: AudioQueuePropertyID,
+) -> Option<GuestUSize> {
+    Some(match in_id {
+        kAudioQueueProperty_IsRunning => guest_size_of::<u32>(),
+        kAudioQueueProperty_StreamDescription => guest_size_of::<AudioStreamBasicDescription>(),
+        kAudioQueueProperty_MagicCookie => host_object.magic_cookie.as_ref()?.len() as GuestUSize,
+        _ => return None,
+    })
+}
+
+fn AudioQueueGetPropertySize(
+    env: &mut Environment,
+    in_aq: AudioQueueRef,
+    in_id: AudioQueuePropertyID,
+    out_data_size: MutPtr<u32>,
+) -> OSStatus {
+    return_if_null!(in_aq);
+
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get(&in_aq)
+        .unwrap();
+
+    let Some(size) = property_size(host_object, in_id) else {
+        log!(
+            "Warning: AudioQueueGetPropertySize() for unsupported property {}",
+            debug_fourcc(in_id)
+        );
+        return kAudioQueueErr_InvalidProperty;
+    };
+    env.mem.write(out_data_size, size);
+
+    0 // success
+}
+
+fn AudioQueueGetProperty(
+    env: &mut Environment,
+    in_aq: AudioQueueRef,
+    in_id: AudioQueuePropertyID,
+    out_data: MutVoidPtr,
+    io_data_size: MutPtr<u32>,
+) -> OSStatus {
+    return_if_null!(in_aq);
+
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get(&in_aq)
+        .unwrap();
+
+    let Some(required_size) = property_size(host_object, in_id) else {
+        log!(
+            "Warning: AudioQueueGetProperty() for unsupported property {}",
+            debug_fourcc(in_id)
+        );
+        return kAudioQueueErr_InvalidProperty;
+    };
+    if env.mem.read(io_data_size) < required_size {
+        log!("Warning: AudioQueueGetProperty() failed");
+        return kAudioQueueErr_InvalidPropertySize;
+    }
+    env.mem.write(io_data_size, required_size);
+
+    match in_id {
+        kAudioQueueProperty_IsRunning => {
+            let value = u32::from(host_object.is_running_property);
+            env.mem.write(out_data.cast(), value);
+        }
+        kAudioQueueProperty_StreamDescription => {
+            let format = host_object.format;
+            env.mem.write(out_data.cast(), format);
+        }
+        kAudioQueueProperty_MagicCookie => {
+            let magic_cookie = host_object.magic_cookie.as_ref().unwrap();
+            env.mem
+                .bytes_at_mut(out_data.cast(), required_size)
+                .copy_from_slice(magic_cookie);
+        }
+        _ => unreachable!(),
+    }
+
+    0 // success
+}
+
 fn AudioQueueAddPropertyListener(
-    _env: &mut Environment,
+    env: &mut Environment,
     in_aq: AudioQueueRef,
-    in_id: u32,             // TODO: should be AudioQueuePropertyID
-    in_proc: GuestFunction, // TODO: should be AudioQueuePropertyListenerProc
+    in_id: AudioQueuePropertyID,
+    in_proc: AudioQueuePropertyListenerProc,
     in_user_data: MutVoidPtr,
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    log!(
-        "TODO: AudioQueueAddPropertyListener({:?}, {:?}, {:?}, {:?})",
-        in_aq,
-        in_id,
-        in_proc,
-        in_user_data
-    );
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get_mut(&in_aq)
+        .unwrap();
+
+    // Only changes to kAudioQueueProperty_IsRunning are currently reported.
+    if in_id != kAudioQueueProperty_IsRunning {
+        log!(
+            "TODO: AudioQueueAddPropertyListener() for property {}, listener will never be called",
+            debug_fourcc(in_id)
+        );
+    }
+
+    host_object
+        .property_listeners
+        .push((in_id, in_proc, in_user_data));
     0 // success
 }
 fn AudioQueueRemovePropertyListener(
-    _env: &mut Environment,
+    env: &mut Environment,
     in_aq: AudioQueueRef,
-    in_id: u32,             // TODO: should be AudioQueuePropertyID
-    in_proc: GuestFunction, // TODO: should be AudioQueuePropertyListenerProc
+    in_id: AudioQueuePropertyID,
+    in_proc: AudioQueuePropertyListenerProc,
     in_user_data: MutVoidPtr,
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    log!(
-        "TODO: AudioQueueRemovePropertyListener({:?}, {:?}, {:?}, {:?})",
-        in_aq,
-        in_id,
-        in_proc,
-        in_user_data
-    );
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get_mut(&in_aq)
+        .unwrap();
+
+    if let Some(idx) =
+        host_object
+            .property_listeners
+            .iter()
+            .position(|&(id, callback, user_data)| {
+                id == in_id
+                    && callback.addr_with_thumb_bit() == in_proc.addr_with_thumb_bit()
+                    && user_data == in_user_data
+            })
+    {
+        host_object.property_listeners.remove(idx);
+    }
     0 // success
 }
 
+/// Update `kAudioQueueProperty_IsRunning`. The listeners are called later by
+/// [handle_audio_queue], as they would be called asynchronously on iPhone OS.
+fn set_is_running_property(host_object: &mut AudioQueueHostObject, value: bool) {
+    if host_object.is_running_property != value {
+        host_object.is_running_property = value;
+        host_object.is_running_changed = true;
+    }
+}
+
+/// Call the `kAudioQueueProperty_IsRunning` listeners, if it has changed.
+fn notify_property_listeners(env: &mut Environment, in_aq: AudioQueueRef) {
+    let host_object = State::get(&mut env.framework_state)
+        .audio_queues
+        .get_mut(&in_aq)
+        .unwrap();
+    if !std::mem::take(&mut host_object.is_running_changed) {
+        return;
+    }
+
+    let listeners: Vec<_> = host_object
+        .property_listeners
+        .iter()
+        .filter(|&&(id, _, _)| id == kAudioQueueProperty_IsRunning)
+        .map(|&(_, callback, user_data)| (callback, user_data))
+        .collect();
+    for (callback, user_data) in listeners {
+        log_dbg!(
+            "Calling property listener {:?} with user data {:?} for queue {:?}",
+            callback,
+            user_data,
+            in_aq
+        );
+        let () = callback.call_from_host(env, (user_data, in_aq, kAudioQueueProperty_IsRunning));
+    }
+}
+
 /// Check if the format of an audio queue is one we currently support.
 /// If not, we should skip trying to play it rather than crash.
 fn is_supported_audio_format(format: &AudioStreamBasicDescription) -> bool {
     let &AudioStreamBasicDescription {
         format_id,
         format_flags,
+        bytes_per_frame,
         channels_per_frame,
         bits_per_channel,
         ..
     } = format;
     match format_id {
-        kAudioFormatAppleIMA4 => {
-            // TODO: stereo (requires interleaving)
-            channels_per_frame == 1
-        }
+        kAudioFormatAppleIMA4 => channels_per_frame == 1 || channels_per_frame == 2,
         kAudioFormatLinearPCM => {
-            // TODO: support more PCM formats
-            (channels_per_frame == 1 || channels_per_frame == 2)
-                && (bits_per_channel == 8 || bits_per_channel == 16)
-                && (format_flags & kAudioFormatFlagIsPacked) != 0
-                && (format_flags & kAudioFormatFlagIsBigEndian) == 0
-                && (format_flags & kAudioFormatFlagIsFloat) == 0
+            if !(channels_per_frame == 1 || channels_per_frame == 2)
+                || (format_flags & kAudioFormatFlagIsNonInterleaved) != 0
+                || bytes_per_frame % channels_per_frame != 0
+            {
+                return false;
+            }
+            let container_bits = (bytes_per_frame / channels_per_frame) * 8;
+            // Samples that don't fill their container must be in the high
+            // bits, because the low bits are discarded.
+            let is_valid_size = bits_per_channel == container_bits
+                || (bits_per_channel < container_bits
+                    && (format_flags & kAudioFormatFlagIsAlignedHigh) != 0);
+            let is_supported_container = if (format_flags & kAudioFormatFlagIsFloat) != 0 {
+                container_bits == 32 || container_bits == 64
+            } else {
+                matches!(container_bits, 8 | 16 | 24 | 32)
+            };
+            is_valid_size && is_supported_container
+        }
+        kAudioFormatMPEG4AAC | kAudioFormatAppleLossless => {
+            channels_per_frame == 1 || channels_per_frame == 2
         }
         _ => false,
     }
@@ -340,26 +610,29 @@ fn is_supported_audio_format(format: &AudioStreamBasicDescription) -> bool {
 /// buffer.
 fn decode_buffer(
     mem: &Mem,
-    format: &AudioStreamBasicDescription,
-    buffer: &AudioQueueBuffer,
+    host_object: &mut AudioQueueHostObject,
+    buffer_ref: AudioQueueBufferRef,
 ) -> (ALenum, ALsizei, Vec<u8>) {
+    let format = &host_object.format;
+    let buffer = mem.read(buffer_ref);
     let data_slice = mem.bytes_at(buffer.audio_data.cast(), buffer.audio_data_byte_size);
 
     assert!(is_supported_audio_format(format));
 
     match format.format_id {
         kAudioFormatAppleIMA4 => {
-            assert!(data_slice.len() % 34 == 0);
-            let mut out_pcm = Vec::<u8>::with_capacity((data_slice.len() / 34) * 64 * 2);
-
-            for packet in data_slice.chunks(34) {
-                let pcm_packet: [i16; 64] = decode_ima4(packet.try_into().unwrap());
-                let pcm_bytes: &[u8] =
-                    unsafe { std::slice::from_raw_parts(pcm_packet.as_ptr() as *const u8, 128) };
-                out_pcm.extend_from_slice(pcm_bytes);
-            }
-
-            (al::AL_FORMAT_MONO16, format.sample_rate as ALsizei, out_pcm)
+            let channels = format.channels_per_frame as usize;
+            assert!(data_slice.len() % (34 * channels) == 0);
+            let out_pcm: Vec<u8> = decode_ima4_interleaved(data_slice, channels)
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect();
+            let f = if channels == 2 {
+                al::AL_FORMAT_STEREO16
+            } else {
+                al::AL_FORMAT_MONO16
+            };
+            (f, format.sample_rate as ALsizei, out_pcm)
         }
         kAudioFormatLinearPCM => {
             // The end of the data might be misaligned (this happens in Crash
@@ -371,14 +644,118 @@ fn decode_buffer(
                 data_slice
             };
 
-            let f = match (format.channels_per_frame, format.bits_per_channel) {
-                (1, 8) => al::AL_FORMAT_MONO8,
-                (1, 16) => al::AL_FORMAT_MONO16,
-                (2, 8) => al::AL_FORMAT_STEREO8,
-                (2, 16) => al::AL_FORMAT_STEREO16,
-                _ => unreachable!(),
+            let is_stereo = format.channels_per_frame == 2;
+            let bytes_per_sample = (format.bytes_per_frame / format.channels_per_frame) as usize;
+            let is_float = (format.format_flags & kAudioFormatFlagIsFloat) != 0;
+            let is_big_endian = (format.format_flags & kAudioFormatFlagIsBigEndian) != 0;
+            // Apps don't always set kAudioFormatFlagIsSignedInteger, so only
+            // 8-bit samples are treated as unsigned without it.
+            let is_signed = bytes_per_sample != 1
+                || (format.format_flags & kAudioFormatFlagIsSignedInteger) != 0;
+
+            // OpenAL supports unsigned 8-bit and signed 16-bit native-endian
+            // samples, anything else must be converted.
+            match (bytes_per_sample, is_float, is_signed, is_big_endian) {
+                (1, false, false, _) => {
+                    let f = if is_stereo {
+                        al::AL_FORMAT_STEREO8
+                    } else {
+                        al::AL_FORMAT_MONO8
+                    };
+                    (f, format.sample_rate as ALsizei, data_slice.to_owned())
+                }
+                (2, false, true, false) => {
+                    let f = if is_stereo {
+                        al::AL_FORMAT_STEREO16
+                    } else {
+                        al::AL_FORMAT_MONO16
+                    };
+                    (f, format.sample_rate as ALsizei, data_slice.to_owned())
+                }
+                _ => {
+                    let out_pcm = pcm_to_i16(
+                        data_slice,
+                        bytes_per_sample,
+                        is_float,
+                        is_signed,
+                        is_big_endian,
+                    )
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes())
+                    .collect();
+                    let f = if is_stereo {
+                        al::AL_FORMAT_STEREO16
+                    } else {
+                        al::AL_FORMAT_MONO16
+                    };
+                    (f, format.sample_rate as ALsizei, out_pcm)
+                }
+            }
+        }
+        kAudioFormatMPEG4AAC | kAudioFormatAppleLossless => {
+            if host_object.decoder.is_none() {
+                let audio_format = if format.format_id == kAudioFormatMPEG4AAC {
+                    AudioFormat::Aac
+                } else {
+                    AudioFormat::AppleLossless
+                };
+                match AudioDecoder::new(
+                    &audio_format,
+                    format.sample_rate as u32,
+                    format.channels_per_frame,
+                    host_object.magic_cookie.as_deref(),
+                ) {
+                    Ok(decoder) => host_object.decoder = Some(decoder),
+                    Err(e) => {
+                        log!("Warning: couldn't create {:?} decoder: {}", audio_format, e);
+                    }
+                }
+            }
+
+            // Without packet descriptions, assume the buffer is one packet.
+            let whole_buffer = [AudioStreamPacketDescription {
+                start_offset: 0,
+                variable_frames_in_packet: 0,
+                data_byte_size: buffer.audio_data_byte_size,
+            }];
+            let packet_descriptions = match host_object.packet_descriptions.get(&buffer_ref) {
+                Some(descriptions) if !descriptions.is_empty() => &descriptions[..],
+                _ => &whole_buffer[..],
+            };
+
+            let mut channels = format.channels_per_frame;
+            let mut out_pcm = Vec::<i16>::new();
+            if let Some(decoder) = host_object.decoder.as_mut() {
+                for &AudioStreamPacketDescription {
+                    start_offset,
+                    data_byte_size,
+                    ..
+                } in packet_descriptions
+                {
+                    let Some(packet) = usize::try_from(start_offset)
+                        .ok()
+                        .and_then(|start| data_slice.get(start..)?.get(..data_byte_size as usize))
+                    else {
+                        log!("Warning: packet description is out of bounds, skipping packet");
+                        continue;
+                    };
+                    match decoder.decode_packet(packet, &mut out_pcm) {
+                        Ok(decoded_channels) => channels = decoded_channels,
+                        Err(e) => log_dbg!("Couldn't decode packet: {}", e),
+                    }
+                }
+            }
+
+            let f = if channels == 2 {
+                al::AL_FORMAT_STEREO16
+            } else {
+                al::AL_FORMAT_MONO16
             };
-            (f, format.sample_rate as ALsizei, data_slice.to_owned())
+            let out_pcm = out_pcm
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect();
+            (f, format.sample_rate as ALsizei, out_pcm)
         }
         _ => unreachable!(),
     }
@@ -435,7 +812,6 @@ fn prime_audio_queue(
 
         let next_buffer_idx = al_buffers_queued;
         let next_buffer_ref = host_object.buffer_queue[next_buffer_idx];
-        let next_buffer = env.mem.read(next_buffer_ref);
 
         log_dbg!(
             "Decoding buffer {:?} for queue {:?}",
@@ -450,8 +826,7 @@ fn prime_audio_queue(
             al_buffer
         });
 
-        let (al_format, al_frequency, data) =
-            decode_buffer(&env.mem, &host_object.format, &next_buffer);
+        let (al_format, al_frequency, data) = decode_buffer(&env.mem, host_object, next_buffer_ref);
         unsafe {
             al::alBufferData(
                 next_al_buffer,
@@ -496,6 +871,8 @@ fn unqueue_buffers<F: FnMut(ALuint)>(al_source: ALuint, mut callback: F) {
 /// For use by `NSRunLoop`: check the status of an audio queue, recycle buffers,
 /// call callbacks, push new buffers etc.
 pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
+    notify_property_listeners(env, in_aq);
+
     // Collect used buffers and call the user callback so the app can provide
     // new buffers.
 
@@ -503,7 +880,10 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
 
     let context_manager = state.make_al_context_current();
 
-    let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
+    // A property listener might have disposed of the queue.
+    let Some(host_object) = state.audio_queues.get_mut(&in_aq) else {
+        return;
+    };
     let Some(al_source) = host_object.al_source else {
         return;
     };
@@ -543,20 +923,36 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
     let _context_manager = prime_audio_queue(env, in_aq, Some(context_manager));
 
     if is_running {
+        let mut al_source_state = 0;
         unsafe {
-            let mut al_source_state = 0;
             al::alGetSourcei(al_source, al::AL_SOURCE_STATE, &mut al_source_state);
             assert!(al::alGetError() == 0);
-            // Source probably ran out data and needs restarting
-            // TODO: We currently have to do this even when touchHLE is not
-            // lagging, because we're not ensuring OpenAL always has at least
-            // one buffer it hasn't processed yet. We need to change our queue
-            // handling.
-            if al_source_state == al::AL_STOPPED {
-                al::alSourcePlay(al_source);
-                log_dbg!("Restarted OpenAL source for queue {:?}", in_aq);
-            }
         }
+        if al_source_state != al::AL_STOPPED {
+            return;
+        }
+
+        let host_object = State::get(&mut env.framework_state)
+            .audio_queues
+            .get_mut(&in_aq)
+            .unwrap();
+        if host_object.is_stopping && host_object.buffer_queue.is_empty() {
+            // All the buffers enqueued before stopping have been played.
+            host_object.is_running = false;
+            host_object.is_stopping = false;
+            set_is_running_property(host_object, false);
+            log_dbg!("Queue {:?} has finished stopping", in_aq);
+            return;
+        }
+
+        // Source probably ran out data and needs restarting
+        // TODO: We currently have to do this even when touchHLE is not
+        // lagging, because we're not ensuring OpenAL always has at least
+        // one buffer it hasn't processed yet. We need to change our queue
+        // handling.
+        unsafe { al::alSourcePlay(al_source) };
+        assert!(unsafe { al::alGetError() } == 0);
+        log_dbg!("Restarted OpenAL source for queue {:?}", in_aq);
     }
 }
 
@@ -590,6 +986,8 @@ fn AudioQueueStart(
         .unwrap();
 
     host_object.is_running = true;
+    host_object.is_stopping = false;
+    set_is_running_property(host_object, true);
 
     if is_supported_audio_format(&host_object.format) {
         let al_source = host_object.al_source.unwrap();
@@ -625,13 +1023,23 @@ fn AudioQueueStop(env: &mut Environment, in_aq: AudioQueueRef, in_immediate: boo
     let _context_manager = state.make_al_context_current();
 
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
-    host_object.is_running = false;
 
-    if in_immediate {
+    if !in_immediate && host_object.is_running && host_object.al_source.is_some() {
+        // Keep playing until the enqueued buffers run out, see
+        // handle_audio_queue().
+        host_object.is_stopping = true;
+    } else {
+        host_object.is_running = false;
+        host_object.is_stopping = false;
+        set_is_running_property(host_object, false);
         if let Some(al_source) = host_object.al_source {
             unsafe { al::alSourceStop(al_source) };
             assert!(unsafe { al::alGetError() } == 0);
         }
+        // The app may be about to seek.
+        if let Some(decoder) = host_object.decoder.as_mut() {
+            decoder.reset();
+        }
     }
 
     0 // success
@@ -652,6 +1060,9 @@ fn AudioQueueDispose(env: &mut Environment, in_aq: AudioQueueRef, in_immediate:
     for buffer_ptr in host_object.buffers {
         let buffer = env.mem.read(buffer_ptr);
         env.mem.free(buffer.audio_data);
+        if !buffer.packet_descriptions.is_null() {
+            env.mem.free(buffer.packet_descriptions.cast());
+        }
         env.mem.free(buffer_ptr.cast());
     }
 
@@ -685,7 +1096,11 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(AudioQueueNewOutput(_, _, _, _, _, _, _)),
     export_c_func!(AudioQueueSetParameter(_, _, _)),
     export_c_func!(AudioQueueAllocateBuffer(_, _, _)),
+    export_c_func!(AudioQueueAllocateBufferWithPacketDescriptions(_, _, _, _)),
     export_c_func!(AudioQueueEnqueueBuffer(_, _, _, _)),
+    export_c_func!(AudioQueueGetPropertySize(_, _, _)),
+    export_c_func!(AudioQueueGetProperty(_, _, _, _)),
+    export_c_func!(AudioQueueSetProperty(_, _, _, _)),
     export_c_func!(AudioQueueAddPropertyListener(_, _, _, _)),
     export_c_func!(AudioQueueRemovePropertyListener(_, _, _, _)),
     export_c_func!(AudioQueuePrime(_, _, _)),
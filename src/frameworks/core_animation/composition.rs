@@ -12,6 +12,7 @@
 use super::ca_eagl_layer::find_fullscreen_eagl_layer;
 use super::ca_layer::CALayerHostObject;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::media_player::movie_player;
 use crate::frameworks::uikit::ui_color;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
@@ -42,6 +43,11 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         return None;
     };
 
+    if movie_player::is_playing(env) {
+        log_dbg!("Movie is playing, skipping composition");
+        return None;
+    }
+
     if find_fullscreen_eagl_layer(env) != nil {
         // No composition done, EAGLContext will present directly.
         log_dbg!("Using CAEAGLLayer fast path, skipping composition");
@@ -68,6 +68,12 @@ impl std::fmt::Debug for AudioStreamBasicDescription {
                 if (format_flags & kAudioFormatFlagIsPacked) != 0 {
                     flags.push("kAudioFormatFlagIsPacked");
                 }
+                if (format_flags & kAudioFormatFlagIsAlignedHigh) != 0 {
+                    flags.push("kAudioFormatFlagIsAlignedHigh");
+                }
+                if (format_flags & kAudioFormatFlagIsNonInterleaved) != 0 {
+                    flags.push("kAudioFormatFlagIsNonInterleaved");
+                }
                 flags
             })
             .field("bytes_per_packet", &bytes_per_packet)
@@ -79,13 +85,26 @@ impl std::fmt::Debug for AudioStreamBasicDescription {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct AudioStreamPacketDescription {
+    pub start_offset: i64,
+    pub variable_frames_in_packet: u32,
+    pub data_byte_size: u32,
+}
+unsafe impl SafeRead for AudioStreamPacketDescription {}
+
 /// Usually a FourCC.
 pub type AudioFormatID = u32;
 pub const kAudioFormatLinearPCM: AudioFormatID = fourcc(b"lpcm");
 pub const kAudioFormatAppleIMA4: AudioFormatID = fourcc(b"ima4");
+pub const kAudioFormatMPEG4AAC: AudioFormatID = fourcc(b"aac ");
+pub const kAudioFormatAppleLossless: AudioFormatID = fourcc(b"alac");
 
 pub type AudioFormatFlags = u32;
 pub const kAudioFormatFlagIsFloat: AudioFormatFlags = 1 << 0;
 pub const kAudioFormatFlagIsBigEndian: AudioFormatFlags = 1 << 1;
 pub const kAudioFormatFlagIsSignedInteger: AudioFormatFlags = 1 << 2;
 pub const kAudioFormatFlagIsPacked: AudioFormatFlags = 1 << 3;
+pub const kAudioFormatFlagIsAlignedHigh: AudioFormatFlags = 1 << 4;
+pub const kAudioFormatFlagIsNonInterleaved: AudioFormatFlags = 1 << 5;
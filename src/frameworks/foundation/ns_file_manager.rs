@@ -15,31 +15,111 @@ use crate::Environment;
 
 type NSSearchPathDirectory = NSUInteger;
 const NSApplicationDirectory: NSSearchPathDirectory = 1;
+const NSDemoApplicationDirectory: NSSearchPathDirectory = 2;
+const NSDeveloperApplicationDirectory: NSSearchPathDirectory = 3;
+const NSAdminApplicationDirectory: NSSearchPathDirectory = 4;
+const NSLibraryDirectory: NSSearchPathDirectory = 5;
+const NSDeveloperDirectory: NSSearchPathDirectory = 6;
+const NSUserDirectory: NSSearchPathDirectory = 7;
+const NSDocumentationDirectory: NSSearchPathDirectory = 8;
 const NSDocumentDirectory: NSSearchPathDirectory = 9;
+const NSCoreServiceDirectory: NSSearchPathDirectory = 10;
+const NSAutosavedInformationDirectory: NSSearchPathDirectory = 11;
+const NSDesktopDirectory: NSSearchPathDirectory = 12;
+const NSCachesDirectory: NSSearchPathDirectory = 13;
+const NSApplicationSupportDirectory: NSSearchPathDirectory = 14;
+const NSDownloadsDirectory: NSSearchPathDirectory = 15;
+const NSInputMethodsDirectory: NSSearchPathDirectory = 16;
+const NSMoviesDirectory: NSSearchPathDirectory = 17;
+const NSMusicDirectory: NSSearchPathDirectory = 18;
+const NSPicturesDirectory: NSSearchPathDirectory = 19;
+const NSPrinterDescriptionDirectory: NSSearchPathDirectory = 20;
+const NSSharedPublicDirectory: NSSearchPathDirectory = 21;
+const NSPreferencePanesDirectory: NSSearchPathDirectory = 22;
+const NSItemReplacementDirectory: NSSearchPathDirectory = 99;
+const NSAllApplicationsDirectory: NSSearchPathDirectory = 100;
+const NSAllLibrariesDirectory: NSSearchPathDirectory = 101;
 
 type NSSearchPathDomainMask = NSUInteger;
 const NSUserDomainMask: NSSearchPathDomainMask = 1;
 
+/// Get the paths, relative to the home directory, that a directory constant
+/// maps to in the user domain. Like on a real device, the directories don't
+/// necessarily exist.
+fn user_domain_directories(directory: NSSearchPathDirectory) -> &'static [&'static str] {
+    match directory {
+        NSApplicationDirectory => &["Applications"],
+        NSDemoApplicationDirectory => &["Applications/Demos"],
+        NSDeveloperApplicationDirectory => &["Developer/Applications"],
+        NSAdminApplicationDirectory => &["Applications/Utilities"],
+        NSLibraryDirectory => &["Library"],
+        NSDeveloperDirectory => &["Developer"],
+        NSDocumentationDirectory => &["Library/Documentation"],
+        NSDocumentDirectory => &["Documents"],
+        NSAutosavedInformationDirectory => &["Library/Autosave Information"],
+        NSDesktopDirectory => &["Desktop"],
+        NSCachesDirectory => &["Library/Caches"],
+        NSApplicationSupportDirectory => &["Library/Application Support"],
+        NSDownloadsDirectory => &["Downloads"],
+        NSInputMethodsDirectory => &["Library/Input Methods"],
+        NSMoviesDirectory => &["Movies"],
+        NSMusicDirectory => &["Music"],
+        NSPicturesDirectory => &["Pictures"],
+        NSPrinterDescriptionDirectory => &[],
+        NSSharedPublicDirectory => &["Public"],
+        NSPreferencePanesDirectory => &["Library/PreferencePanes"],
+        NSAllApplicationsDirectory => &[
+            "Applications",
+            "Applications/Utilities",
+            "Developer/Applications",
+            "Applications/Demos",
+        ],
+        NSAllLibrariesDirectory => &["Library", "Developer"],
+        // These only exist in other domains.
+        NSUserDirectory | NSCoreServiceDirectory => &[],
+        // This is only meaningful for
+        // URLForDirectory:inDomain:appropriateForURL:create:error:.
+        NSItemReplacementDirectory => &[],
+        _ => {
+            log!(
+                "Warning: unknown NSSearchPathDirectory {}, returning no paths",
+                directory
+            );
+            &[]
+        }
+    }
+}
+
 fn NSSearchPathForDirectoriesInDomains(
     env: &mut Environment,
     directory: NSSearchPathDirectory,
     domain_mask: NSSearchPathDomainMask,
     expand_tilde: bool,
 ) -> id {
-    // TODO: other cases not implemented
-    assert!(domain_mask == NSUserDomainMask);
-    assert!(expand_tilde);
-
-    let dir = match directory {
-        // This might not actually be correct. I haven't bothered to test it
-        // because I can't think of a good reason an iPhone OS app would have to
-        // request this; Wolfenstein 3D requests it but never uses it.
-        NSApplicationDirectory => GuestPath::new("/User/Applications").to_owned(),
-        NSDocumentDirectory => env.fs.home_directory().join("Documents"),
-        _ => todo!("NSSearchPathDirectory {}", directory),
+    // Apps can only see their own sandbox, so the other domains (local,
+    // network, system) never have anything in them.
+    let dirs: &[&str] = if domain_mask & NSUserDomainMask != 0 {
+        user_domain_directories(directory)
+    } else {
+        log!(
+            "TODO: NSSearchPathForDirectoriesInDomains() for domain mask {:#x}, returning no paths",
+            domain_mask
+        );
+        &[]
     };
-    let dir = ns_string::from_rust_string(env, String::from(dir));
-    let dir_list = ns_array::from_vec(env, vec![dir]);
+
+    let dirs = dirs
+        .iter()
+        .map(|&dir| {
+            let dir = if expand_tilde {
+                env.fs.home_directory().join(dir)
+            } else {
+                GuestPath::new("~").join(dir)
+            };
+            ns_string::from_rust_string(env, String::from(dir))
+        })
+        .collect();
+    let dir_list = ns_array::from_vec(env, dirs);
     autorelease(env, dir_list)
 }
 
@@ -49,9 +129,17 @@ fn NSHomeDirectory(env: &mut Environment) -> id {
     autorelease(env, dir)
 }
 
+fn NSTemporaryDirectory(env: &mut Environment) -> id {
+    // The trailing slash matches what iPhone OS returns.
+    let dir = format!("{}/", env.fs.home_directory().join("tmp").as_str());
+    let dir = ns_string::from_rust_string(env, dir);
+    autorelease(env, dir)
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(NSHomeDirectory()),
     export_c_func!(NSSearchPathForDirectoriesInDomains(_, _, _)),
+    export_c_func!(NSTemporaryDirectory()),
 ];
 
 #[derive(Default)]
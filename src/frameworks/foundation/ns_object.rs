@@ -18,8 +18,8 @@ use super::ns_string::to_rust_string;
 use super::NSUInteger;
 use crate::mem::MutVoidPtr;
 use crate::objc::{
-    id, msg, msg_class, msg_send, objc_classes, Class, ClassExports, NSZonePtr, ObjC,
-    TrivialHostObject,
+    id, msg, msg_class, msg_send, objc_classes, release_associated_objects_of_deallocated, Class,
+    ClassExports, NSZonePtr, ObjC, TrivialHostObject,
 };
 
 pub const CLASSES: ClassExports = objc_classes! {
@@ -70,6 +70,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     log_dbg!("[{:?} release]", this);
     if env.objc.decrement_refcount(this) {
         () = msg![env; this dealloc];
+        release_associated_objects_of_deallocated(env);
     }
 }
 - (id)autorelease {
@@ -171,6 +171,10 @@ fn run_run_loop(env: &mut Environment, run_loop: id) {
         let mut sleep_until = None;
 
         env.window.poll_for_events(&env.options);
+        if env.window.forced_exit_due() {
+            echo!("App didn't terminate in time, exiting anyway.");
+            env.exit(1);
+        }
 
         let next_due = uikit::handle_events(env);
         limit_sleep_time(&mut sleep_until, next_due);
@@ -197,7 +201,8 @@ fn run_run_loop(env: &mut Environment, run_loop: id) {
             handle_audio_queue(env, audio_queue);
         }
 
-        media_player::handle_players(env);
+        let next_due = media_player::handle_players(env);
+        limit_sleep_time(&mut sleep_until, next_due);
 
         // Unfortunately, touchHLE has to poll for certain things repeatedly;
         // it can't just wait until the next event appears.
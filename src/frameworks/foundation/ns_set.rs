@@ -13,6 +13,7 @@ use crate::mem::MutPtr;
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, retain, ClassExports, HostObject, NSZonePtr,
 };
+use crate::Environment;
 
 /// Belongs to _touchHLE_NSSet
 struct SetHostObject {
@@ -83,7 +84,11 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // TODO: more init methods, etc
 
-// TODO: accessors
+// TODO: more accessors
+
+- (NSUInteger)count {
+    env.objc.borrow::<SetHostObject>(this).dict.count
+}
 
 - (id)anyObject {
     let object_or_none = env.objc.borrow_mut::<SetHostObject>(this).dict.iter_keys().next();
@@ -94,8 +99,12 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)allObjects {
-    let objects = env.objc.borrow_mut::<SetHostObject>(this).dict.iter_keys().collect();
-    ns_array::from_vec(env, objects)
+    let objects: Vec<id> = env.objc.borrow_mut::<SetHostObject>(this).dict.iter_keys().collect();
+    for &object in &objects {
+        retain(env, object);
+    }
+    let array = ns_array::from_vec(env, objects);
+    autorelease(env, array)
 }
 
 // NSFastEnumeration implementation
@@ -108,36 +117,52 @@ pub const CLASSES: ClassExports = objc_classes! {
         return 0;
     }
 
-    // TODO: handle size > 1
-    assert!(host_object.dict.count == 1);
-    assert!(len >= host_object.dict.count);
-
+    // The state is the number of objects returned so far. This relies on the
+    // iteration order being stable, which it is as long as the set isn't
+    // mutated, and mutating a set while enumerating it isn't allowed anyway.
     let NSFastEnumerationState {
-        state: is_first_round,
+        state: start_index,
         ..
     } = env.mem.read(state);
+    assert!(start_index <= host_object.dict.count); // app failed to initialize the buffer?
 
-    match is_first_round {
-        0 => {
-            let object = host_object.dict.iter_keys().next().unwrap();
-            env.mem.write(stackbuf, object);
-            env.mem.write(state, NSFastEnumerationState {
-                state: 1,
-                items_ptr: stackbuf,
-                // can be anything as long as it's dereferenceable and the same
-                // each iteration
-                mutations_ptr: stackbuf.cast(),
-                extra: Default::default(),
-            });
-            1 // returned object count
-        },
-        1 => {
-            0 // end of iteration
-        },
-        _ => panic!(), // app failed to initialize the buffer?
+    let mut count = 0;
+    for object in host_object.dict.iter_keys().skip(start_index as usize).take(len as usize) {
+        env.mem.write(stackbuf + count, object);
+        count += 1;
     }
+
+    if count == 0 {
+        return 0; // end of iteration
+    }
+
+    env.mem.write(state, NSFastEnumerationState {
+        state: start_index + count,
+        items_ptr: stackbuf,
+        // can be anything as long as it's dereferenceable and the same
+        // each iteration
+        mutations_ptr: stackbuf.cast(),
+        extra: Default::default(),
+    });
+    count // returned object count
 }
 
 @end
 
 };
+
+/// Shortcut for host code, roughly equivalent to
+/// `[[NSSet alloc] initWithObjects:count:]`. Unlike [ns_array::from_vec], the
+/// objects are retained by the set, so the caller keeps its own references.
+pub fn from_vec(env: &mut Environment, objects: Vec<id>) -> id {
+    let set: id = msg_class![env; _touchHLE_NSSet alloc];
+
+    let null: id = msg_class![env; NSNull null];
+    let mut dict = <DictionaryHostObject as Default>::default();
+    for object in objects {
+        dict.insert(env, object, null, /* copy_key: */ false);
+    }
+    env.objc.borrow_mut::<SetHostObject>(set).dict = dict;
+
+    set
+}
@@ -5,17 +5,29 @@
  */
 //! `NSUserDefaults`.
 //!
+//! The defaults are persisted in the app's sandbox as
+//! `Library/Preferences/<bundle identifier>.plist`, in the same binary plist
+//! format iPhone OS uses, so they can be moved to and from a real device.
+//!
 //! References:
 //! - Apple's [Preferences and Settings Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/UserDefaults/AboutPreferenceDomains/AboutPreferenceDomains.html).
 
-use super::ns_dictionary::dict_from_keys_and_objects;
-use super::ns_string;
-use crate::objc::{id, msg_class, objc_classes, ClassExports};
+use super::ns_dictionary::{dict_from_keys_and_objects, DictionaryHostObject};
+use super::ns_string::{from_rust_string, to_rust_string};
+use super::ns_value::NSNumberHostObject;
+use super::{ns_array, NSInteger, NSUInteger};
+use crate::fs::GuestPathBuf;
+use crate::mem::{ConstVoidPtr, MutVoidPtr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, Class, ClassExports, HostObject,
+};
 use crate::Environment;
+use plist::{Dictionary, Value};
+use std::io::Cursor;
 
 #[derive(Default)]
 pub struct State {
-    /// `NSDictionary*`
+    /// `NSUserDefaults*`
     standard_defaults: Option<id>,
 }
 impl State {
@@ -24,6 +36,24 @@ impl State {
     }
 }
 
+struct NSUserDefaultsHostObject {
+    /// The application domain: values set by the app, which are persisted.
+    app_domain: Dictionary,
+    /// The registration domain: values provided by `registerDefaults:` (and a
+    /// few system-provided values), which are not persisted.
+    registration_domain: Dictionary,
+    /// Whether `app_domain` has changed since it was last written to disk.
+    dirty: bool,
+}
+impl HostObject for NSUserDefaultsHostObject {}
+impl NSUserDefaultsHostObject {
+    fn lookup(&self, key: &str) -> Option<&Value> {
+        self.app_domain
+            .get(key)
+            .or_else(|| self.registration_domain.get(key))
+    }
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -34,17 +64,384 @@ pub const CLASSES: ClassExports = objc_classes! {
     if let Some(existing) = State::get(env).standard_defaults {
         existing
     } else {
+        let app_domain = read_app_domain(env);
+
         // TODO: Are there other default keys we need to set?
-        let langs_value: id = msg_class![env; NSLocale preferredLanguages];
-        let langs_key: id = ns_string::get_static_str(env, "AppleLanguages");
-        let new = dict_from_keys_and_objects(env, &[(langs_key, langs_value)]);
+        let mut registration_domain = Dictionary::new();
+        let langs: id = msg_class![env; NSLocale preferredLanguages];
+        let langs = id_to_plist_value(env, langs).unwrap();
+        registration_domain.insert("AppleLanguages".to_string(), langs);
+
+        let host_object = NSUserDefaultsHostObject {
+            app_domain,
+            registration_domain,
+            dirty: false,
+        };
+        let new = env.objc.alloc_object(this, Box::new(host_object), &mut env.mem);
         State::get(env).standard_defaults = Some(new);
         new
     }
 }
 
-// TODO: plist methods etc
+- (())registerDefaults:(id)dict { // NSDictionary*
+    let Some(Value::Dictionary(dict)) = id_to_plist_value(env, dict) else {
+        log!(
+            "Warning: [{:?} registerDefaults:{:?}] argument is not a property list dictionary, ignoring",
+            this,
+            dict
+        );
+        return;
+    };
+    let host_object = env.objc.borrow_mut::<NSUserDefaultsHostObject>(this);
+    for (key, value) in dict {
+        host_object.registration_domain.insert(key, value);
+    }
+}
+
+- (bool)synchronize {
+    let host_object = env.objc.borrow_mut::<NSUserDefaultsHostObject>(this);
+    if !host_object.dirty {
+        return true;
+    }
+    let mut bytes = Vec::new();
+    Value::Dictionary(host_object.app_domain.clone())
+        .to_writer_binary(&mut bytes)
+        .unwrap();
+    let path = plist_path(env);
+    log_dbg!("Writing user defaults to {:?}", path);
+    if env.fs.write(&path, &bytes).is_err() {
+        log!("Warning: couldn't write user defaults to {:?}", path);
+        return false;
+    }
+    env.objc.borrow_mut::<NSUserDefaultsHostObject>(this).dirty = false;
+    true
+}
+
+- (id)objectForKey:(id)key { // NSString*
+    let Some(value) = lookup(env, this, key) else {
+        return nil;
+    };
+    let object = plist_value_to_id(env, &value);
+    autorelease(env, object)
+}
+- (())setObject:(id)object
+         forKey:(id)key { // NSString*
+    if object == nil {
+        return msg![env; this removeObjectForKey:key];
+    }
+    let Some(value) = id_to_plist_value(env, object) else {
+        // Real iPhone OS raises an exception in this case.
+        log!(
+            "Warning: attempt to set non-property-list object {:?} for key {:?} in NSUserDefaults, ignoring",
+            object,
+            key
+        );
+        return;
+    };
+    set_value(env, this, key, value);
+}
+- (())removeObjectForKey:(id)key { // NSString*
+    let key = to_rust_string(env, key);
+    let host_object = env.objc.borrow_mut::<NSUserDefaultsHostObject>(this);
+    if host_object.app_domain.remove(&key).is_some() {
+        host_object.dirty = true;
+    }
+}
+
+- (id)stringForKey:(id)key { // NSString*
+    let string = match lookup(env, this, key) {
+        Some(Value::String(string)) => string,
+        Some(value @ (Value::Integer(_) | Value::Real(_))) => {
+            plist_value_to_f64(&value).to_string()
+        },
+        _ => return nil,
+    };
+    let string = from_rust_string(env, string);
+    autorelease(env, string)
+}
+- (id)arrayForKey:(id)key { // NSString*
+    match lookup(env, this, key) {
+        Some(value @ Value::Array(_)) => {
+            let array = plist_value_to_id(env, &value);
+            autorelease(env, array)
+        },
+        _ => nil,
+    }
+}
+- (id)dictionaryForKey:(id)key { // NSString*
+    match lookup(env, this, key) {
+        Some(value @ Value::Dictionary(_)) => {
+            let dict = plist_value_to_id(env, &value);
+            autorelease(env, dict)
+        },
+        _ => nil,
+    }
+}
+- (id)dataForKey:(id)key { // NSString*
+    match lookup(env, this, key) {
+        Some(value @ Value::Data(_)) => {
+            let data = plist_value_to_id(env, &value);
+            autorelease(env, data)
+        },
+        _ => nil,
+    }
+}
+
+- (NSInteger)integerForKey:(id)key { // NSString*
+    lookup(env, this, key).map_or(0, |value| plist_value_to_f64(&value) as NSInteger)
+}
+- (f32)floatForKey:(id)key { // NSString*
+    lookup(env, this, key).map_or(0.0, |value| plist_value_to_f64(&value) as f32)
+}
+- (f64)doubleForKey:(id)key { // NSString*
+    lookup(env, this, key).map_or(0.0, |value| plist_value_to_f64(&value))
+}
+- (bool)boolForKey:(id)key { // NSString*
+    lookup(env, this, key).map_or(false, |value| match value {
+        Value::String(string) => {
+            let string = string.trim().to_ascii_lowercase();
+            string == "yes" || string == "true" || string.parse().map_or(false, |n: f64| n != 0.0)
+        },
+        value => plist_value_to_f64(&value) != 0.0,
+    })
+}
+
+- (())setInteger:(NSInteger)value
+          forKey:(id)key { // NSString*
+    set_value(env, this, key, Value::from(i64::from(value)));
+}
+- (())setFloat:(f32)value
+        forKey:(id)key { // NSString*
+    set_value(env, this, key, Value::from(f64::from(value)));
+}
+- (())setDouble:(f64)value
+         forKey:(id)key { // NSString*
+    set_value(env, this, key, Value::from(value));
+}
+- (())setBool:(bool)value
+       forKey:(id)key { // NSString*
+    set_value(env, this, key, Value::from(value));
+}
+
+- (id)dictionaryRepresentation {
+    let host_object = env.objc.borrow::<NSUserDefaultsHostObject>(this);
+    let mut dict = host_object.registration_domain.clone();
+    for (key, value) in host_object.app_domain.iter() {
+        dict.insert(key.clone(), value.clone());
+    }
+    let dict = plist_value_to_id(env, &Value::Dictionary(dict));
+    autorelease(env, dict)
+}
 
 @end
 
 };
+
+/// Guest path of the plist file the app's defaults are persisted in.
+fn plist_path(env: &mut Environment) -> GuestPathBuf {
+    env.fs.home_directory().join(format!(
+        "Library/Preferences/{}.plist",
+        env.bundle.bundle_identifier()
+    ))
+}
+
+fn read_app_domain(env: &mut Environment) -> Dictionary {
+    let path = plist_path(env);
+    let Ok(bytes) = env.fs.read(&path) else {
+        log_dbg!("No user defaults found at {:?}", path);
+        return Dictionary::new();
+    };
+    // plist::Value::from_reader() accepts both the binary and XML formats.
+    match Value::from_reader(Cursor::new(bytes)).map(Value::into_dictionary) {
+        Ok(Some(dict)) => {
+            log_dbg!("Read user defaults from {:?}", path);
+            dict
+        }
+        _ => {
+            log!(
+                "Warning: user defaults at {:?} could not be parsed, ignoring them",
+                path
+            );
+            Dictionary::new()
+        }
+    }
+}
+
+fn lookup(env: &mut Environment, defaults: id, key: id) -> Option<Value> {
+    let key = to_rust_string(env, key);
+    env.objc
+        .borrow::<NSUserDefaultsHostObject>(defaults)
+        .lookup(&key)
+        .cloned()
+}
+
+fn set_value(env: &mut Environment, defaults: id, key: id, value: Value) {
+    let key = to_rust_string(env, key).into_owned();
+    log_dbg!(
+        "[(NSUserDefaults*){:?} set {:?} for {:?}]",
+        defaults,
+        value,
+        key
+    );
+    let host_object = env.objc.borrow_mut::<NSUserDefaultsHostObject>(defaults);
+    host_object.app_domain.insert(key, value);
+    host_object.dirty = true;
+}
+
+/// Interpret a property list value as a number, the way the `integerForKey:`
+/// family of methods does. Values that aren't numbers are treated as zero.
+fn plist_value_to_f64(value: &Value) -> f64 {
+    match value {
+        Value::Boolean(boolean) => *boolean as i32 as f64,
+        Value::Integer(integer) => integer
+            .as_signed()
+            .map(|i| i as f64)
+            .or_else(|| integer.as_unsigned().map(|u| u as f64))
+            .unwrap(),
+        Value::Real(real) => *real,
+        Value::String(string) => string.trim().parse().unwrap_or(0.0),
+        _ => 0.0,
+    }
+}
+
+/// Convert a property list object (`NSString`, `NSNumber`, `NSData`, `NSArray`
+/// or `NSDictionary`) to a [Value]. Returns [None] if the object or one of its
+/// children is not a property list object.
+fn id_to_plist_value(env: &mut Environment, object: id) -> Option<Value> {
+    let is_kind_of = |env: &mut Environment, class_name: &str| -> bool {
+        let class: Class = env.objc.get_known_class(class_name, &mut env.mem);
+        msg![env; object isKindOfClass:class]
+    };
+
+    if object == nil {
+        None
+    } else if is_kind_of(env, "NSString") {
+        Some(Value::String(to_rust_string(env, object).into_owned()))
+    } else if is_kind_of(env, "NSNumber") {
+        Some(match *env.objc.borrow::<NSNumberHostObject>(object) {
+            NSNumberHostObject::Bool(value) => Value::from(value),
+            NSNumberHostObject::Int(value) => Value::from(i64::from(value)),
+            NSNumberHostObject::LongLong(value) => Value::from(value),
+            NSNumberHostObject::Float(value) => Value::from(f64::from(value)),
+            NSNumberHostObject::Double(value) => Value::from(value),
+        })
+    } else if is_kind_of(env, "NSData") {
+        let length: NSUInteger = msg![env; object length];
+        let bytes: ConstVoidPtr = msg![env; object bytes];
+        let bytes = if length == 0 {
+            Vec::new()
+        } else {
+            env.mem.bytes_at(bytes.cast(), length).to_vec()
+        };
+        Some(Value::Data(bytes))
+    } else if is_kind_of(env, "NSArray") {
+        let count: NSUInteger = msg![env; object count];
+        let mut array = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let item: id = msg![env; object objectAtIndex:i];
+            array.push(id_to_plist_value(env, item)?);
+        }
+        Some(Value::Array(array))
+    } else if is_kind_of(env, "NSDictionary") {
+        // TODO: handle foreign subclasses of NSDictionary
+        let keys: Vec<id> = env
+            .objc
+            .borrow::<DictionaryHostObject>(object)
+            .iter_keys()
+            .collect();
+        let mut dict = Dictionary::new();
+        for key in keys {
+            let Some(Value::String(key_string)) = id_to_plist_value(env, key) else {
+                // Property list dictionaries can only have string keys.
+                return None;
+            };
+            let value: id = msg![env; object objectForKey:key];
+            dict.insert(key_string, id_to_plist_value(env, value)?);
+        }
+        Some(Value::Dictionary(dict))
+    } else {
+        None
+    }
+}
+
+/// Convert a [Value] to the corresponding property list object. The result is
+/// retained. Values of types we don't have classes for yet are skipped, and
+/// converted to `nil` at the top level.
+fn plist_value_to_id(env: &mut Environment, value: &Value) -> id {
+    match value {
+        Value::String(string) => from_rust_string(env, string.clone()),
+        Value::Boolean(boolean) => {
+            let number: id = msg_class![env; NSNumber alloc];
+            msg![env; number initWithBool:(*boolean)]
+        }
+        Value::Integer(integer) => {
+            let number: id = msg_class![env; NSNumber alloc];
+            let integer = integer
+                .as_signed()
+                .unwrap_or_else(|| integer.as_unsigned().unwrap() as i64);
+            if let Ok(integer) = i32::try_from(integer) {
+                msg![env; number initWithInt:integer]
+            } else {
+                msg![env; number initWithLongLong:integer]
+            }
+        }
+        Value::Real(real) => {
+            let number: id = msg_class![env; NSNumber alloc];
+            msg![env; number initWithDouble:(*real)]
+        }
+        Value::Data(bytes) => {
+            let data: id = msg_class![env; NSData alloc];
+            let length: NSUInteger = bytes.len().try_into().unwrap();
+            if length == 0 {
+                return msg![env; data init];
+            }
+            let alloc: MutVoidPtr = env.mem.alloc(length);
+            env.mem
+                .bytes_at_mut(alloc.cast(), length)
+                .copy_from_slice(bytes);
+            msg![env; data initWithBytesNoCopy:alloc length:length]
+        }
+        Value::Array(array) => {
+            let objects = array
+                .iter()
+                .map(|item| plist_value_to_id(env, item))
+                .filter(|&object| object != nil)
+                .collect();
+            ns_array::from_vec(env, objects)
+        }
+        Value::Dictionary(dict) => {
+            let keys_and_objects: Vec<(id, id)> = dict
+                .iter()
+                .filter_map(|(key, value)| {
+                    let object = plist_value_to_id(env, value);
+                    if object == nil {
+                        return None;
+                    }
+                    Some((from_rust_string(env, key.clone()), object))
+                })
+                .collect();
+            let new = dict_from_keys_and_objects(env, &keys_and_objects);
+            for (key, object) in keys_and_objects {
+                release(env, key);
+                release(env, object);
+            }
+            new
+        }
+        // TODO: NSDate
+        _ => {
+            log!(
+                "Warning: can't convert {:?} to an Objective-C object yet, skipping it",
+                value
+            );
+            nil
+        }
+    }
+}
+
+/// For use by [crate::frameworks::uikit::ui_application::exit]: write the
+/// standard user defaults to disk, if they've been used.
+pub fn synchronize_standard_defaults(env: &mut Environment) {
+    if let Some(defaults) = State::get(env).standard_defaults {
+        let _: bool = msg![env; defaults synchronize];
+    }
+}
@@ -5,16 +5,57 @@
  */
 //! The `NSValue` class cluster, including `NSNumber`.
 
-use super::NSUInteger;
+use super::ns_string::from_rust_string;
+use super::{NSInteger, NSUInteger};
 use crate::objc::{
     autorelease, id, msg, msg_class, objc_classes, retain, Class, ClassExports, HostObject,
     NSZonePtr,
 };
 
-enum NSNumberHostObject {
+#[derive(Copy, Clone, Debug)]
+pub(super) enum NSNumberHostObject {
     Bool(bool),
+    Int(i32),
+    LongLong(i64),
+    Float(f32),
+    Double(f64),
 }
 impl HostObject for NSNumberHostObject {}
+impl NSNumberHostObject {
+    pub(super) fn as_bool(self) -> bool {
+        match self {
+            NSNumberHostObject::Bool(value) => value,
+            NSNumberHostObject::Int(value) => value != 0,
+            NSNumberHostObject::LongLong(value) => value != 0,
+            NSNumberHostObject::Float(value) => value != 0.0,
+            NSNumberHostObject::Double(value) => value != 0.0,
+        }
+    }
+    pub(super) fn as_i64(self) -> i64 {
+        match self {
+            NSNumberHostObject::Bool(value) => value as i64,
+            NSNumberHostObject::Int(value) => value as i64,
+            NSNumberHostObject::LongLong(value) => value,
+            NSNumberHostObject::Float(value) => value as i64,
+            NSNumberHostObject::Double(value) => value as i64,
+        }
+    }
+    pub(super) fn as_f64(self) -> f64 {
+        match self {
+            NSNumberHostObject::Bool(value) => value as i32 as f64,
+            NSNumberHostObject::Int(value) => value as f64,
+            NSNumberHostObject::LongLong(value) => value as f64,
+            NSNumberHostObject::Float(value) => value as f64,
+            NSNumberHostObject::Double(value) => value,
+        }
+    }
+    fn is_floating_point(self) -> bool {
+        matches!(
+            self,
+            NSNumberHostObject::Float(_) | NSNumberHostObject::Double(_)
+        )
+    }
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -46,8 +87,36 @@ pub const CLASSES: ClassExports = objc_classes! {
     let new: id = msg![env; new initWithBool:value];
     autorelease(env, new)
 }
-
-// TODO: types other than booleans
++ (id)numberWithInt:(i32)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithInt:value];
+    autorelease(env, new)
+}
++ (id)numberWithInteger:(NSInteger)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithInteger:value];
+    autorelease(env, new)
+}
++ (id)numberWithUnsignedInt:(u32)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithUnsignedInt:value];
+    autorelease(env, new)
+}
++ (id)numberWithLongLong:(i64)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithLongLong:value];
+    autorelease(env, new)
+}
++ (id)numberWithFloat:(f32)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithFloat:value];
+    autorelease(env, new)
+}
++ (id)numberWithDouble:(f64)value {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithDouble:value];
+    autorelease(env, new)
+}
 
 - (id)initWithBool:(bool)value {
     *env.objc.borrow_mut::<NSNumberHostObject>(this) = NSNumberHostObject::Bool(
@@ -55,10 +124,45 @@ pub const CLASSES: ClassExports = objc_classes! {
     );
     this
 }
+- (id)initWithInt:(i32)value {
+    *env.objc.borrow_mut::<NSNumberHostObject>(this) = NSNumberHostObject::Int(
+        value,
+    );
+    this
+}
+- (id)initWithInteger:(NSInteger)value {
+    msg![env; this initWithInt:value]
+}
+- (id)initWithUnsignedInt:(u32)value {
+    *env.objc.borrow_mut::<NSNumberHostObject>(this) = NSNumberHostObject::LongLong(
+        value.into(),
+    );
+    this
+}
+- (id)initWithLongLong:(i64)value {
+    *env.objc.borrow_mut::<NSNumberHostObject>(this) = NSNumberHostObject::LongLong(
+        value,
+    );
+    this
+}
+- (id)initWithFloat:(f32)value {
+    *env.objc.borrow_mut::<NSNumberHostObject>(this) = NSNumberHostObject::Float(
+        value,
+    );
+    this
+}
+- (id)initWithDouble:(f64)value {
+    *env.objc.borrow_mut::<NSNumberHostObject>(this) = NSNumberHostObject::Double(
+        value,
+    );
+    this
+}
 
 - (NSUInteger)hash {
-    let &NSNumberHostObject::Bool(value) = env.objc.borrow(this);
-    super::hash_helper(&value)
+    // Numbers that compare equal must have the same hash, regardless of their
+    // type, so the hash is always based on the floating-point value.
+    let value = env.objc.borrow::<NSNumberHostObject>(this).as_f64();
+    super::hash_helper(&value.to_bits())
 }
 - (bool)isEqualTo:(id)other {
     if this == other {
@@ -68,12 +172,53 @@ pub const CLASSES: ClassExports = objc_classes! {
     if !msg![env; other isKindOfClass:class] {
         return false;
     }
-    let &NSNumberHostObject::Bool(a) = env.objc.borrow(this);
-    let &NSNumberHostObject::Bool(b) = env.objc.borrow(other);
-    a == b
+    let a = *env.objc.borrow::<NSNumberHostObject>(this);
+    let b = *env.objc.borrow::<NSNumberHostObject>(other);
+    if a.is_floating_point() || b.is_floating_point() {
+        a.as_f64() == b.as_f64()
+    } else {
+        a.as_i64() == b.as_i64()
+    }
+}
+
+- (bool)boolValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_bool()
+}
+- (i32)intValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as i32
+}
+- (NSInteger)integerValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as NSInteger
+}
+- (u32)unsignedIntValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64() as u32
+}
+- (i64)longLongValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_i64()
+}
+- (f32)floatValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_f64() as f32
+}
+- (f64)doubleValue {
+    env.objc.borrow::<NSNumberHostObject>(this).as_f64()
+}
+
+- (id)stringValue {
+    let string = match *env.objc.borrow::<NSNumberHostObject>(this) {
+        NSNumberHostObject::Bool(value) => (value as i32).to_string(),
+        NSNumberHostObject::Int(value) => value.to_string(),
+        NSNumberHostObject::LongLong(value) => value.to_string(),
+        NSNumberHostObject::Float(value) => value.to_string(),
+        NSNumberHostObject::Double(value) => value.to_string(),
+    };
+    let string = from_rust_string(env, string);
+    autorelease(env, string)
+}
+- (id)description {
+    msg![env; this stringValue]
 }
 
-// TODO: accessors etc
+// TODO: more types and accessors
 
 @end
 
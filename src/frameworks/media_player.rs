@@ -12,8 +12,10 @@ pub struct State {
     movie_player: movie_player::State,
 }
 
-/// For use by `NSRunLoop`: check media players' status, send notifications if
-/// necessary.
-pub fn handle_players(env: &mut crate::Environment) {
-    movie_player::handle_players(env);
+/// For use by `NSRunLoop`: check media players' status, display frames and
+/// send notifications if necessary.
+///
+/// Returns the time the next frame is due, if any.
+pub fn handle_players(env: &mut crate::Environment) -> Option<std::time::Instant> {
+    movie_player::handle_players(env)
 }
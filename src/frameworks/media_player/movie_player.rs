@@ -5,20 +5,32 @@
  */
 //! `MPMoviePlayerController` etc.
 
+use crate::audio::openal as al;
+use crate::audio::openal::al_types::*;
 use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::audio_toolbox::audio_queue::make_internal_al_context_current;
 use crate::frameworks::foundation::{ns_string, ns_url, NSInteger};
-use crate::objc::{id, msg, msg_class, objc_classes, release, retain, ClassExports};
+use crate::objc::{
+    id, msg, msg_class, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::video::Movie;
+use crate::window::Event;
 use crate::Environment;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
 pub struct State {
     active_player: Option<id>,
+    /// Playback state of the active player, if it has a movie that could be
+    /// loaded.
+    playback: Option<Playback>,
     /// Various apps (e.g. Crash Bandicoot Nitro Kart 3D and Spore Origins)
     /// create or start a player and await some kind of notification, but can't
     /// handle it if that notification happens immediately. This queue lets us
     /// delay such notifications until the app next returns to the run loop,
-    /// which seems to be late enough.
+    /// which seems to be late enough. The players are retained until then (see
+    /// [queue_notification]).
     pending_notifications: VecDeque<(&'static str, id)>,
 }
 impl State {
@@ -27,7 +39,36 @@ impl State {
     }
 }
 
+struct Playback {
+    started_at: Instant,
+    /// OpenAL source for the audio track, if there is one. The audio is
+    /// decoded and queued on it a chunk at a time (see [queue_audio]).
+    al_source: Option<ALuint>,
+    /// [true] until all of the audio has been decoded and queued.
+    audio_remaining: bool,
+}
+
+/// Duration of audio decoded at once, in seconds.
+const AUDIO_CHUNK_SECONDS: f64 = 0.25;
+/// Number of chunks of audio to keep queued ahead of the playback position.
+const AUDIO_CHUNKS_QUEUED: ALint = 4;
+
+struct MPMoviePlayerControllerHostObject {
+    /// [None] if the movie couldn't be loaded.
+    movie: Option<Movie>,
+    scaling_mode: MPMovieScalingMode,
+    control_mode: MPMovieControlMode,
+}
+impl HostObject for MPMoviePlayerControllerHostObject {}
+
 type MPMovieScalingMode = NSInteger;
+const MPMovieScalingModeNone: MPMovieScalingMode = 0;
+const MPMovieScalingModeAspectFit: MPMovieScalingMode = 1;
+const MPMovieScalingModeAspectFill: MPMovieScalingMode = 2;
+const MPMovieScalingModeFill: MPMovieScalingMode = 3;
+
+type MPMovieControlMode = NSInteger;
+const MPMovieControlModeDefault: MPMovieControlMode = 0;
 
 // Values might not be correct, but as these are linked symbol constants, it
 // shouldn't matter.
@@ -56,30 +97,64 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation MPMoviePlayerController: NSObject
 
-// TODO: actual playback
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(MPMoviePlayerControllerHostObject {
+        movie: None,
+        scaling_mode: MPMovieScalingModeAspectFit,
+        control_mode: MPMovieControlModeDefault,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
 
 - (id)initWithContentURL:(id)url { // NSURL*
-    log!(
-        "TODO: [(MPMoviePlayerController*){:?} initWithContentURL:{:?} ({:?})]",
+    let path = ns_url::to_rust_path(env, url);
+    log_dbg!(
+        "[(MPMoviePlayerController*){:?} initWithContentURL:{:?} ({:?})]",
         this,
         url,
-        ns_url::to_rust_path(env, url),
+        path,
     );
 
+    let movie = env
+        .fs
+        .read(&path)
+        .map_err(|()| "Couldn't read file".to_string())
+        .and_then(Movie::open);
+    match movie {
+        Ok(movie) => {
+            env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).movie = Some(movie);
+        }
+        Err(e) => {
+            log!(
+                "Warning: couldn't load movie {:?} ({}), playback will finish immediately",
+                path,
+                e
+            );
+        }
+    }
+
     // Act as if loading immediately completed (Spore Origins waits for this).
-    State::get(env).pending_notifications.push_back(
-        (MPMoviePlayerContentPreloadDidFinishNotification, this)
-    );
+    queue_notification(env, MPMoviePlayerContentPreloadDidFinishNotification, this);
 
     this
 }
 
-- (())setScalingMode:(MPMovieScalingMode)_mode {
-    // TODO
+- (())dealloc {
+    assert!(State::get(env).active_player != Some(this));
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())setScalingMode:(MPMovieScalingMode)mode {
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).scaling_mode = mode;
+}
+- (MPMovieScalingMode)scalingMode {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).scaling_mode
 }
 
 // Apparently an undocumented, private API, but Spore Origins uses it.
-- (())setMovieControlMode:(NSInteger)_mode {
+- (())setMovieControlMode:(MPMovieControlMode)mode {
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).control_mode = mode;
+
     // Game-specific hack :(
     // Spore Origins subscribes to the playback finished notification 0.2s after
     // starting playback, so it misses the notification we send. When it
@@ -87,34 +162,42 @@ pub const CLASSES: ClassExports = objc_classes! {
     // the notification again.
     if env.bundle.bundle_identifier().starts_with("com.ea.spore") {
         log!("Applying game-specific hack for Spore Origins: sending MPMoviePlayerPlaybackDidFinishNotification again.");
-        State::get(env).pending_notifications.push_back(
-            (MPMoviePlayerPlaybackDidFinishNotification, this)
-        );
+        queue_notification(env, MPMoviePlayerPlaybackDidFinishNotification, this);
     }
-    // As this is undocumented and we don't have real video playback yet, let's
-    // ignore it otherwise.
+}
+- (MPMovieControlMode)movieControlMode {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).control_mode
 }
 
 // MPMediaPlayback implementation
 - (())play {
-    log!("TODO: [(MPMoviePlayerController*){:?} play]", this);
-    if let Some(old) = env.framework_state.media_player.movie_player.active_player {
+    log_dbg!("[(MPMoviePlayerController*){:?} play]", this);
+    if let Some(old) = State::get(env).active_player {
         let _: () = msg![env; old stop];
     }
-    assert!(env.framework_state.media_player.movie_player.active_player.is_none());
+    assert!(State::get(env).active_player.is_none());
     // Movie player is retained by the runtime until it is stopped
     retain(env, this);
-    env.framework_state.media_player.movie_player.active_player = Some(this);
+    State::get(env).active_player = Some(this);
 
-    // Act as if playback immediately completed (various apps wait for this).
-    State::get(env).pending_notifications.push_back(
-        (MPMoviePlayerPlaybackDidFinishNotification, this)
-    );
+    let Some(playback) = start_playback(env, this) else {
+        // Act as if playback immediately completed (various apps wait for
+        // this).
+        queue_notification(env, MPMoviePlayerPlaybackDidFinishNotification, this);
+        return;
+    };
+    State::get(env).playback = Some(playback);
+    queue_audio(env);
 }
 
 - (())stop {
-    log!("TODO: [(MPMoviePlayerController*){:?} stop]", this);
-    assert!(this == env.framework_state.media_player.movie_player.active_player.take().unwrap());
+    log_dbg!("[(MPMoviePlayerController*){:?} stop]", this);
+    if State::get(env).active_player != Some(this) {
+        // Already stopped, e.g. because playback finished.
+        return;
+    }
+    stop_playback(env);
+    State::get(env).active_player = None;
     release(env, this);
 }
 
@@ -122,14 +205,295 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Rewind the player's movie and start its audio. Returns [None] if there is no
+/// movie to play.
+fn start_playback(env: &mut Environment, player: id) -> Option<Playback> {
+    let host_object = env
+        .objc
+        .borrow_mut::<MPMoviePlayerControllerHostObject>(player);
+    let movie = host_object.movie.as_mut()?;
+    if let Err(e) = movie.rewind() {
+        log!("Warning: couldn't start movie playback: {}", e);
+        return None;
+    }
+
+    let has_audio = movie.has_audio();
+
+    let al_source = has_audio.then(|| {
+        let _context_manager = make_internal_al_context_current(env);
+        let mut al_source = 0;
+        unsafe {
+            al::alGenSources(1, &mut al_source);
+            assert!(al::alGetError() == 0);
+        }
+        al_source
+    });
+
+    Some(Playback {
+        started_at: Instant::now(),
+        al_source,
+        audio_remaining: has_audio,
+    })
+}
+
+/// Decode more of the active player's audio and queue it, so that a few
+/// chunks are always queued ahead of the playback position. Buffers that have
+/// been played are deleted.
+fn queue_audio(env: &mut Environment) {
+    let Some(playback) = State::get(env).playback.as_ref() else {
+        return;
+    };
+    let Some(al_source) = playback.al_source else {
+        return;
+    };
+    if !playback.audio_remaining {
+        return;
+    }
+    let player = State::get(env).active_player.unwrap();
+
+    let _context_manager = make_internal_al_context_current(env);
+    let mut al_buffers_queued = 0;
+    unsafe {
+        let mut al_buffers_processed = 0;
+        al::alGetSourcei(
+            al_source,
+            al::AL_BUFFERS_PROCESSED,
+            &mut al_buffers_processed,
+        );
+        for _ in 0..al_buffers_processed {
+            let mut al_buffer = 0;
+            al::alSourceUnqueueBuffers(al_source, 1, &mut al_buffer);
+            al::alDeleteBuffers(1, &al_buffer);
+        }
+        al::alGetSourcei(al_source, al::AL_BUFFERS_QUEUED, &mut al_buffers_queued);
+        assert!(al::alGetError() == 0);
+    }
+
+    while al_buffers_queued < AUDIO_CHUNKS_QUEUED {
+        let movie = env
+            .objc
+            .borrow_mut::<MPMoviePlayerControllerHostObject>(player)
+            .movie
+            .as_mut()
+            .unwrap();
+        let audio = match movie.decode_audio_chunk(AUDIO_CHUNK_SECONDS) {
+            Ok(Some(audio)) => audio,
+            Ok(None) => {
+                State::get(env).playback.as_mut().unwrap().audio_remaining = false;
+                break;
+            }
+            Err(e) => {
+                log!(
+                    "Warning: couldn't decode movie audio ({}), continuing without sound",
+                    e
+                );
+                State::get(env).playback.as_mut().unwrap().audio_remaining = false;
+                break;
+            }
+        };
+        let format = match audio.channels {
+            1 => al::AL_FORMAT_MONO16,
+            _ => al::AL_FORMAT_STEREO16,
+        };
+        unsafe {
+            let mut al_buffer = 0;
+            al::alGenBuffers(1, &mut al_buffer);
+            al::alBufferData(
+                al_buffer,
+                format,
+                audio.samples.as_ptr() as *const ALvoid,
+                (audio.samples.len() * 2) as ALsizei,
+                audio.sample_rate as ALsizei,
+            );
+            al::alSourceQueueBuffers(al_source, 1, &al_buffer);
+            assert!(al::alGetError() == 0);
+        }
+        al_buffers_queued += 1;
+    }
+
+    // The source stops if decoding doesn't keep up, so it might need to be
+    // restarted.
+    unsafe {
+        let mut al_source_state = 0;
+        al::alGetSourcei(al_source, al::AL_SOURCE_STATE, &mut al_source_state);
+        if al_source_state != al::AL_PLAYING && al_buffers_queued > 0 {
+            al::alSourcePlay(al_source);
+        }
+        assert!(al::alGetError() == 0);
+    }
+}
+
+/// Stop the audio of the active player, if any.
+fn stop_playback(env: &mut Environment) {
+    let Some(playback) = State::get(env).playback.take() else {
+        return;
+    };
+    if let Some(al_source) = playback.al_source {
+        let _context_manager = make_internal_al_context_current(env);
+        unsafe {
+            al::alSourceStop(al_source);
+            // Stopping the source marks all its buffers as processed.
+            let mut al_buffers_processed = 0;
+            al::alGetSourcei(
+                al_source,
+                al::AL_BUFFERS_PROCESSED,
+                &mut al_buffers_processed,
+            );
+            for _ in 0..al_buffers_processed {
+                let mut al_buffer = 0;
+                al::alSourceUnqueueBuffers(al_source, 1, &mut al_buffer);
+                al::alDeleteBuffers(1, &al_buffer);
+            }
+            al::alDeleteSources(1, &al_source);
+            assert!(al::alGetError() == 0);
+        }
+    }
+}
+
+/// Queue a notification to be posted when the app next returns to the run
+/// loop. The player is retained until then.
+fn queue_notification(env: &mut Environment, name: &'static str, player: id) {
+    retain(env, player);
+    State::get(env)
+        .pending_notifications
+        .push_back((name, player));
+}
+
+/// End playback of the active player and notify the app.
+fn finish_playback(env: &mut Environment) {
+    let player = State::get(env).active_player.unwrap();
+    // The notification must be queued first, because stopping releases the
+    // player.
+    queue_notification(env, MPMoviePlayerPlaybackDidFinishNotification, player);
+    let _: () = msg![env; player stop];
+}
+
+/// Returns [true] while a movie is being displayed, in which case the app's
+/// own rendering should not be presented.
+pub fn is_playing(env: &mut Environment) -> bool {
+    State::get(env).playback.is_some()
+}
+
+/// For use by UIKit's event handling: lets the movie player intercept touch
+/// events while a movie is being displayed. Returns [true] if the event was
+/// consumed.
+pub fn intercept_touch(env: &mut Environment, event: &Event) -> bool {
+    if !is_playing(env) {
+        return false;
+    }
+    let player = State::get(env).active_player.unwrap();
+    let control_mode = env
+        .objc
+        .borrow::<MPMoviePlayerControllerHostObject>(player)
+        .control_mode;
+    // With the default controls, tapping the screen skips the movie.
+    // TODO: Display the controls instead, like the real thing?
+    if control_mode == MPMovieControlModeDefault && matches!(event, Event::TouchUp(..)) {
+        log!("Movie skipped by tapping the screen");
+        finish_playback(env);
+    }
+    true
+}
+
+/// Display the frames of the active player's movie that are due, and finish
+/// playback when the end is reached. Returns the time the next frame is due.
+fn present_frames(env: &mut Environment) -> Option<Instant> {
+    let started_at = State::get(env).playback.as_ref()?.started_at;
+    let player = State::get(env).active_player.unwrap();
+
+    queue_audio(env);
+    let elapsed = Instant::now().duration_since(started_at).as_secs_f64();
+
+    let host_object = env
+        .objc
+        .borrow_mut::<MPMoviePlayerControllerHostObject>(player);
+    let scaling_mode = host_object.scaling_mode;
+    let movie = host_object.movie.as_mut().unwrap();
+
+    // If touchHLE can't decode fast enough, frames are dropped so that the
+    // video stays in sync with the audio.
+    let mut frame = None;
+    let mut error = None;
+    while movie
+        .next_frame_time()
+        .map_or(false, |time| time <= elapsed)
+    {
+        match movie.decode_next_frame() {
+            Ok(Some(new_frame)) => frame = Some(new_frame),
+            Ok(None) => (),
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    let next_frame_time = movie.next_frame_time();
+    let next_due = next_frame_time.unwrap_or(movie.duration());
+
+    if let Some(e) = error {
+        log!(
+            "Warning: error while decoding movie, stopping playback: {}",
+            e
+        );
+        finish_playback(env);
+        return None;
+    }
+
+    if let Some(frame) = frame {
+        // TODO: Rotate the app's UI to landscape orientation while the movie
+        // is playing, like iPhone OS does.
+        let (width, height) = (frame.width as f32, frame.height as f32);
+        env.window.display_movie_frame(
+            &frame.pixels,
+            (frame.width, frame.height),
+            |(viewport_width, viewport_height)| {
+                let fit_scale = (viewport_width / width).min(viewport_height / height);
+                let fill_scale = (viewport_width / width).max(viewport_height / height);
+                match scaling_mode {
+                    MPMovieScalingModeNone => {
+                        // The movie is displayed at its natural size in
+                        // points.
+                        let scale = viewport_height / 320.0;
+                        (width * scale, height * scale)
+                    }
+                    MPMovieScalingModeAspectFill => (width * fill_scale, height * fill_scale),
+                    MPMovieScalingModeFill => (viewport_width, viewport_height),
+                    _ => (width * fit_scale, height * fit_scale),
+                }
+            },
+        );
+    }
+
+    if next_frame_time.is_none() && elapsed >= next_due {
+        finish_playback(env);
+        return None;
+    }
+
+    let next_due = started_at + Duration::from_secs_f64(next_due);
+    if State::get(env).playback.as_ref().unwrap().audio_remaining {
+        // More audio will need to be queued before the current chunks run out.
+        let audio_due = Instant::now() + Duration::from_secs_f64(AUDIO_CHUNK_SECONDS);
+        Some(next_due.min(audio_due))
+    } else {
+        Some(next_due)
+    }
+}
+
 /// For use by `NSRunLoop` via [super::handle_players]: check movie players'
-/// status, send notifications if necessary.
-pub(super) fn handle_players(env: &mut Environment) {
+/// status, display frames and send notifications if necessary.
+///
+/// Returns the time the next frame is due, if any.
+pub(super) fn handle_players(env: &mut Environment) -> Option<Instant> {
+    let next_due = present_frames(env);
+
     while let Some(notif) = State::get(env).pending_notifications.pop_front() {
         let (name, object) = notif;
         let name = ns_string::get_static_str(env, name);
         let center: id = msg_class![env; NSNotificationCenter defaultCenter];
         // TODO: should there be some user info attached?
         let _: () = msg![env; center postNotificationName:name object:object];
+        release(env, object);
     }
+
+    next_due
 }
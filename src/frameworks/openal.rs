@@ -15,14 +15,18 @@ use crate::audio::openal as al;
 use crate::audio::openal::al_types::*;
 use crate::audio::openal::alc_types::*;
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeWrite};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, Ptr, SafeWrite};
 use crate::Environment;
 use std::collections::HashMap;
+use std::ffi::CString;
 
 #[derive(Default)]
 pub struct State {
     devices: HashMap<MutPtr<GuestALCdevice>, *mut ALCdevice>,
     contexts: HashMap<MutPtr<GuestALCcontext>, *mut ALCcontext>,
+    /// Strings returned by `alGetString` and `alcGetString`, see
+    /// [guest_string].
+    strings: HashMap<StringKey, (MutPtr<u8>, Vec<u8>)>,
 }
 impl State {
     fn get(env: &mut Environment) -> &mut Self {
@@ -30,6 +34,9 @@ impl State {
     }
 }
 
+/// Device (`NULL` for `alGetString`) and parameter.
+type StringKey = (MutPtr<GuestALCdevice>, ALenum);
+
 /// Opaque type in guest memory standing in for [ALCdevice] in host memory.
 struct GuestALCdevice {
     _filler: u8,
@@ -125,22 +132,183 @@ fn alcGetProcAddress(
     func_name: ConstPtr<u8>,
 ) -> ConstVoidPtr {
     let mangled_func_name = format!("_{}", env.mem.cstr_at_utf8(func_name).unwrap());
-    assert!(mangled_func_name.starts_with("_al"));
 
+    if !mangled_func_name.starts_with("_al") {
+        log!(
+            "Warning: alcGetProcAddress() for non-OpenAL function {}, returning NULL",
+            mangled_func_name
+        );
+        return Ptr::null();
+    }
     if let Ok(ptr) = env
         .dyld
         .create_proc_address(&mut env.mem, &mut env.cpu, &mangled_func_name)
     {
         Ptr::from_bits(ptr.addr_with_thumb_bit())
     } else {
-        panic!(
-            "Request for procedure address for unimplemented OpenAL function {}",
+        log!(
+            "Warning: alcGetProcAddress() for unimplemented function {}, returning NULL",
             mangled_func_name
         );
+        Ptr::null()
     }
 }
 
-// TODO: more functions
+fn alcProcessContext(env: &mut Environment, context: MutPtr<GuestALCcontext>) {
+    let &host_context = State::get(env).contexts.get(&context).unwrap();
+    unsafe { al::alcProcessContext(host_context) };
+}
+fn alcSuspendContext(env: &mut Environment, context: MutPtr<GuestALCcontext>) {
+    let &host_context = State::get(env).contexts.get(&context).unwrap();
+    unsafe { al::alcSuspendContext(host_context) };
+}
+
+fn alcGetCurrentContext(env: &mut Environment) -> MutPtr<GuestALCcontext> {
+    let host_context = unsafe { al::alcGetCurrentContext() };
+    // The current context could also be one used internally by touchHLE, but
+    // that should never be observable by the app.
+    let res = State::get(env)
+        .contexts
+        .iter()
+        .find(|&(_, &host)| host == host_context)
+        .map_or(Ptr::null(), |(&guest, _)| guest);
+    log_dbg!("alcGetCurrentContext() => {:?}", res);
+    res
+}
+fn alcGetContextsDevice(
+    env: &mut Environment,
+    context: MutPtr<GuestALCcontext>,
+) -> MutPtr<GuestALCdevice> {
+    let &host_context = State::get(env).contexts.get(&context).unwrap();
+    let host_device = unsafe { al::alcGetContextsDevice(host_context) };
+    let res = State::get(env)
+        .devices
+        .iter()
+        .find(|&(_, &host)| host == host_device)
+        .map(|(&guest, _)| guest)
+        .unwrap();
+    log_dbg!("alcGetContextsDevice({:?}) => {:?}", context, res);
+    res
+}
+
+fn alcIsExtensionPresent(
+    _env: &mut Environment,
+    _device: MutPtr<GuestALCdevice>,
+    _ext_name: ConstPtr<u8>,
+) -> ALCboolean {
+    // ALC extensions (capture, EFX etc) aren't exposed to the app, since their
+    // functions aren't available through alcGetProcAddress.
+    0
+}
+fn alcGetEnumValue(
+    env: &mut Environment,
+    device: MutPtr<GuestALCdevice>,
+    enum_name: ConstPtr<u8>,
+) -> ALCenum {
+    let host_device = host_device(env, device);
+    let enum_name = CString::new(env.mem.cstr_at(enum_name)).unwrap();
+    let res = unsafe { al::alcGetEnumValue(host_device, enum_name.as_ptr()) };
+    log_dbg!(
+        "alcGetEnumValue({:?}, {:?}) => {:#x}",
+        device,
+        enum_name,
+        res
+    );
+    res
+}
+
+fn alcGetString(
+    env: &mut Environment,
+    device: MutPtr<GuestALCdevice>,
+    param: ALCenum,
+) -> ConstPtr<u8> {
+    let host_device = host_device(env, device);
+    let res = if param == al::ALC_EXTENSIONS {
+        // See alcIsExtensionPresent.
+        NO_EXTENSIONS.as_ptr().cast()
+    } else {
+        unsafe { al::alcGetString(host_device, param) }
+    };
+    let res = guest_string(env, Some(device), param, res);
+    log_dbg!("alcGetString({:?}, {:#x}) => {:?}", device, param, res);
+    res
+}
+fn alcGetIntegerv(
+    env: &mut Environment,
+    device: MutPtr<GuestALCdevice>,
+    param: ALCenum,
+    size: ALCsizei,
+    values: MutPtr<ALCint>,
+) {
+    let host_device = host_device(env, device);
+    // An invalid size is passed on without the pointer, so that OpenAL records
+    // ALC_INVALID_VALUE.
+    let values = match GuestUSize::try_from(size) {
+        Ok(0) | Err(_) => std::ptr::null_mut(),
+        Ok(size_usize) => env.mem.ptr_at_mut(values, size_usize),
+    };
+    unsafe { al::alcGetIntegerv(host_device, param, size, values) };
+}
+
+/// Extension list reported by `alGetString` and `alcGetString`.
+const NO_EXTENSIONS: &[u8] = b"\0";
+
+/// Look up the host device for a guest device, which may be `NULL` for the
+/// functions that can be used without a device.
+fn host_device(env: &mut Environment, device: MutPtr<GuestALCdevice>) -> *mut ALCdevice {
+    if device.is_null() {
+        std::ptr::null_mut()
+    } else {
+        State::get(env).devices.get(&device).copied().unwrap()
+    }
+}
+
+/// Copy a string returned by `alGetString` or `alcGetString` (if `device` is
+/// not [None]) to guest memory. The copy is kept around and re-used, because
+/// the app won't free it.
+fn guest_string(
+    env: &mut Environment,
+    device: Option<MutPtr<GuestALCdevice>>,
+    param: ALenum,
+    host_string: *const std::ffi::c_char,
+) -> ConstPtr<u8> {
+    if host_string.is_null() {
+        return Ptr::null();
+    }
+
+    // Device lists (requested with a NULL device) are terminated by two NULs
+    // rather than one.
+    let is_list = device == Some(Ptr::null())
+        && matches!(
+            param,
+            al::ALC_DEVICE_SPECIFIER
+                | al::ALC_CAPTURE_DEVICE_SPECIFIER
+                | al::ALC_ALL_DEVICES_SPECIFIER
+        );
+    let mut bytes = Vec::new();
+    unsafe {
+        let mut ptr = host_string as *const u8;
+        loop {
+            let byte = *ptr;
+            if byte == b'\0' && (!is_list || bytes.last().map_or(true, |&last| last == b'\0')) {
+                break;
+            }
+            bytes.push(byte);
+            ptr = ptr.add(1);
+        }
+    }
+
+    let key = (device.unwrap_or_default(), param);
+    if let Some(&(existing, ref existing_bytes)) = State::get(env).strings.get(&key) {
+        if *existing_bytes == bytes {
+            return existing.cast_const();
+        }
+    }
+    // alloc_and_write_cstr() adds the (final) NUL terminator.
+    let new = env.mem.alloc_and_write_cstr(&bytes);
+    State::get(env).strings.insert(key, (new, bytes));
+    new.cast_const()
+}
 
 // === al.h ===
 
@@ -192,6 +360,21 @@ fn alSourcef(_env: &mut Environment, source: ALuint, param: ALenum, value: ALflo
     unsafe { al::alSourcef(source, param, value) };
 }
 fn alSourcei(_env: &mut Environment, source: ALuint, param: ALenum, value: ALint) {
+    if param == al::AL_BUFFER {
+        // Apple's implementation lets apps change the buffer of a source that
+        // is playing or paused, stopping it implicitly, which some apps seem to
+        // rely on. OpenAL Soft doesn't allow this (AL_INVALID_OPERATION).
+        let mut state = 0;
+        unsafe { al::alGetSourcei(source, al::AL_SOURCE_STATE, &mut state) };
+        if state == al::AL_PLAYING || state == al::AL_PAUSED {
+            log_dbg!(
+                "Stopping source {} before setting AL_BUFFER to {}",
+                source,
+                value
+            );
+            unsafe { al::alSourceStop(source) };
+        }
+    }
     unsafe { al::alSourcei(source, param, value) };
 }
 fn alGetSourcef(env: &mut Environment, source: ALuint, param: ALenum, value: MutPtr<ALfloat>) {
@@ -311,12 +494,6 @@ fn alcMacOSXMixerOutputRate(_env: &mut Environment, value: ALdouble) {
     log!("App wants to set mixer output sample rate to {} Hz", value);
 }
 
-fn alListenerfv(env: &mut Environment, param: ALenum, values: ConstPtr<ALfloat>) {
-    // we assume that at least 1 parameter should be passed
-    let values = env.mem.ptr_at(values, 1);
-    unsafe { al::alListenerfv(param, values) };
-}
-
 fn alDopplerFactor(_env: &mut Environment, value: ALfloat) {
     unsafe { al::alDopplerFactor(value) };
 }
@@ -335,182 +512,320 @@ fn alDopplerVelocity(env: &mut Environment, value: ALfloat) {
     unsafe { al::alDopplerVelocity(value) };
 }
 
-fn alSourcefv(env: &mut Environment, source: ALuint, param: ALenum, values: ConstPtr<ALfloat>) {
-    // we assume that at least 1 parameter should be passed
-    let values = env.mem.ptr_at(values, 1);
-    unsafe { al::alSourcefv(source, param, values) };
+fn alSpeedOfSound(_env: &mut Environment, value: ALfloat) {
+    unsafe { al::alSpeedOfSound(value) };
 }
 
-// TODO: more functions
-
-// Note: For some reasons Wolf3d registers many OpenAl functions, but actually uses only few ones.
-// To workaround this, we just provide stubs
-
-fn alcGetContextsDevice(
-    _env: &mut Environment,
-    _context: MutPtr<GuestALCcontext>,
-) -> MutPtr<GuestALCdevice> {
-    todo!();
+fn alEnable(_env: &mut Environment, capability: ALenum) {
+    unsafe { al::alEnable(capability) };
 }
-fn alcGetCurrentContext(_env: &mut Environment) -> MutPtr<GuestALCcontext> {
-    todo!();
+fn alDisable(_env: &mut Environment, capability: ALenum) {
+    unsafe { al::alDisable(capability) };
 }
-fn alcGetEnumValue(
-    _env: &mut Environment,
-    _device: MutPtr<GuestALCdevice>,
-    _enumName: ConstPtr<u8>,
-) -> ALenum {
-    todo!();
+fn alIsEnabled(_env: &mut Environment, capability: ALenum) -> ALboolean {
+    unsafe { al::alIsEnabled(capability) }
 }
-fn alcGetIntegerv(
-    _env: &mut Environment,
-    _device: MutPtr<GuestALCdevice>,
-    _param: ALenum,
-    _size: ALCsizei,
-    _values: MutPtr<ALCint>,
-) {
-    todo!();
+
+// All the state queryable with these functions is scalar.
+fn alGetBoolean(_env: &mut Environment, param: ALenum) -> ALboolean {
+    unsafe { al::alGetBoolean(param) }
 }
-fn alcGetString(
-    _env: &mut Environment,
-    _device: MutPtr<GuestALCdevice>,
-    _param: ALenum,
-) -> ConstPtr<u8> {
-    todo!();
+fn alGetBooleanv(env: &mut Environment, param: ALenum, values: MutPtr<ALboolean>) {
+    unsafe { al::alGetBooleanv(param, env.mem.ptr_at_mut(values, 1)) };
 }
-fn alcIsExtensionPresent(
-    _env: &mut Environment,
-    _device: MutPtr<GuestALCdevice>,
-    _extName: ConstPtr<u8>,
-) -> ALCboolean {
-    0
+fn alGetDouble(_env: &mut Environment, param: ALenum) -> ALdouble {
+    unsafe { al::alGetDouble(param) }
 }
-fn alcProcessContext(_env: &mut Environment, _context: MutPtr<GuestALCcontext>) {
-    todo!();
+fn alGetDoublev(env: &mut Environment, param: ALenum, values: MutPtr<ALdouble>) {
+    unsafe { al::alGetDoublev(param, env.mem.ptr_at_mut(values, 1)) };
 }
-fn alcSuspendContext(_env: &mut Environment, _context: MutPtr<GuestALCcontext>) {
-    todo!();
+fn alGetFloat(_env: &mut Environment, param: ALenum) -> ALfloat {
+    unsafe { al::alGetFloat(param) }
 }
-fn alIsBuffer(_env: &mut Environment, _buffer: ALuint) -> ALboolean {
-    todo!();
+fn alGetFloatv(env: &mut Environment, param: ALenum, values: MutPtr<ALfloat>) {
+    unsafe { al::alGetFloatv(param, env.mem.ptr_at_mut(values, 1)) };
 }
-fn alGetBufferf(_env: &mut Environment, _buffer: ALuint, _param: ALenum, _value: MutPtr<ALfloat>) {
-    todo!();
+fn alGetInteger(_env: &mut Environment, param: ALenum) -> ALint {
+    unsafe { al::alGetInteger(param) }
 }
-fn alGetBufferi(_env: &mut Environment, _buffer: ALuint, _param: ALenum, _value: MutPtr<ALint>) {
-    todo!();
+fn alGetIntegerv(env: &mut Environment, param: ALenum, values: MutPtr<ALint>) {
+    unsafe { al::alGetIntegerv(param, env.mem.ptr_at_mut(values, 1)) };
 }
-fn alEnable(_env: &mut Environment, _capability: ALenum) {
-    todo!();
+
+fn alGetString(env: &mut Environment, param: ALenum) -> ConstPtr<u8> {
+    let res = if param == al::AL_EXTENSIONS {
+        // See alIsExtensionPresent.
+        NO_EXTENSIONS.as_ptr().cast()
+    } else {
+        unsafe { al::alGetString(param) }
+    };
+    let res = guest_string(env, None, param, res);
+    log_dbg!("alGetString({:#x}) => {:?}", param, res);
+    res
 }
-fn alDisable(_env: &mut Environment, _capability: ALenum) {
-    todo!();
+
+fn alIsExtensionPresent(env: &mut Environment, ext_name: ConstPtr<u8>) -> ALboolean {
+    // Like the ALC extensions, the AL extensions OpenAL Soft provides aren't
+    // exposed to the app, since their functions and enums aren't available.
+    log_dbg!(
+        "alIsExtensionPresent({:?}) => 0",
+        env.mem.cstr_at_utf8(ext_name)
+    );
+    0
 }
-fn alGetBoolean(_env: &mut Environment, _param: ALenum) -> ALboolean {
-    todo!();
+fn alGetEnumValue(env: &mut Environment, enum_name: ConstPtr<u8>) -> ALenum {
+    let enum_name = CString::new(env.mem.cstr_at(enum_name)).unwrap();
+    let res = unsafe { al::alGetEnumValue(enum_name.as_ptr()) };
+    log_dbg!("alGetEnumValue({:?}) => {:#x}", enum_name, res);
+    res
 }
-fn alGetBooleanv(_env: &mut Environment, _param: ALenum, _values: MutPtr<ALboolean>) {
-    todo!();
+fn alGetProcAddress(env: &mut Environment, func_name: ConstPtr<u8>) -> ConstVoidPtr {
+    alcGetProcAddress(env, Ptr::null(), func_name)
 }
-fn alGetDouble(_env: &mut Environment, _param: ALenum) -> ALdouble {
-    todo!();
+
+fn alListeneri(_env: &mut Environment, param: ALenum, value: ALint) {
+    unsafe { al::alListeneri(param, value) };
 }
-fn alGetDoublev(_env: &mut Environment, _param: ALenum, _values: MutPtr<ALdouble>) {
-    todo!();
+fn alListener3i(
+    _env: &mut Environment,
+    param: ALenum,
+    value1: ALint,
+    value2: ALint,
+    value3: ALint,
+) {
+    unsafe { al::alListener3i(param, value1, value2, value3) };
 }
-fn alGetFloat(_env: &mut Environment, _param: ALenum) -> ALfloat {
-    todo!();
+fn alListenerfv(env: &mut Environment, param: ALenum, values: ConstPtr<ALfloat>) {
+    let values = env.mem.ptr_at(values, value_count(param));
+    unsafe { al::alListenerfv(param, values) };
 }
-fn alGetFloatv(_env: &mut Environment, _param: ALenum, _values: MutPtr<ALfloat>) {
-    todo!();
+fn alListeneriv(env: &mut Environment, param: ALenum, values: ConstPtr<ALint>) {
+    let values = env.mem.ptr_at(values, value_count(param));
+    unsafe { al::alListeneriv(param, values) };
 }
-fn alGetInteger(_env: &mut Environment, _param: ALenum) -> ALint {
-    todo!();
+fn alGetListenerf(env: &mut Environment, param: ALenum, value: MutPtr<ALfloat>) {
+    unsafe { al::alGetListenerf(param, env.mem.ptr_at_mut(value, 1)) };
 }
-fn alGetIntegerv(_env: &mut Environment, _param: ALenum, _values: MutPtr<ALint>) {
-    todo!();
+fn alGetListener3f(
+    env: &mut Environment,
+    param: ALenum,
+    value1: MutPtr<ALfloat>,
+    value2: MutPtr<ALfloat>,
+    value3: MutPtr<ALfloat>,
+) {
+    let value1 = env.mem.ptr_at_mut(value1, 1);
+    let value2 = env.mem.ptr_at_mut(value2, 1);
+    let value3 = env.mem.ptr_at_mut(value3, 1);
+    unsafe { al::alGetListener3f(param, value1, value2, value3) };
 }
-fn alGetEnumValue(_env: &mut Environment, _enumName: ConstPtr<u8>) -> ALenum {
-    todo!();
+fn alGetListenerfv(env: &mut Environment, param: ALenum, values: MutPtr<ALfloat>) {
+    let values = env.mem.ptr_at_mut(values, value_count(param));
+    unsafe { al::alGetListenerfv(param, values) };
 }
-fn alGetProcAddress(_env: &mut Environment, _funcName: ConstPtr<u8>) -> MutVoidPtr {
-    todo!();
+fn alGetListeneri(env: &mut Environment, param: ALenum, value: MutPtr<ALint>) {
+    unsafe { al::alGetListeneri(param, env.mem.ptr_at_mut(value, 1)) };
 }
-fn alGetString(_env: &mut Environment, _param: ALenum) -> ConstPtr<u8> {
-    todo!();
+fn alGetListener3i(
+    env: &mut Environment,
+    param: ALenum,
+    value1: MutPtr<ALint>,
+    value2: MutPtr<ALint>,
+    value3: MutPtr<ALint>,
+) {
+    let value1 = env.mem.ptr_at_mut(value1, 1);
+    let value2 = env.mem.ptr_at_mut(value2, 1);
+    let value3 = env.mem.ptr_at_mut(value3, 1);
+    unsafe { al::alGetListener3i(param, value1, value2, value3) };
 }
-fn alIsExtensionPresent(_env: &mut Environment, _extName: ConstPtr<u8>) -> ALboolean {
-    todo!();
+fn alGetListeneriv(env: &mut Environment, param: ALenum, values: MutPtr<ALint>) {
+    let values = env.mem.ptr_at_mut(values, value_count(param));
+    unsafe { al::alGetListeneriv(param, values) };
 }
-fn alIsEnabled(_env: &mut Environment, _capability: ALenum) -> ALboolean {
-    todo!();
+
+fn alIsSource(_env: &mut Environment, source: ALuint) -> ALboolean {
+    unsafe { al::alIsSource(source) }
 }
-fn alListeneri(_env: &mut Environment, _param: ALenum, _value: ALint) {
-    todo!();
+
+fn alSource3f(
+    _env: &mut Environment,
+    source: ALuint,
+    param: ALenum,
+    value1: ALfloat,
+    value2: ALfloat,
+    value3: ALfloat,
+) {
+    unsafe { al::alSource3f(source, param, value1, value2, value3) };
 }
-fn alGetListenerf(_env: &mut Environment, _param: ALenum, _value: MutPtr<ALfloat>) {
-    todo!();
+fn alSourcefv(env: &mut Environment, source: ALuint, param: ALenum, values: ConstPtr<ALfloat>) {
+    let values = env.mem.ptr_at(values, value_count(param));
+    unsafe { al::alSourcefv(source, param, values) };
 }
-fn alGetListener3f(
+fn alSource3i(
     _env: &mut Environment,
-    _param: ALenum,
-    _value1: MutPtr<ALfloat>,
-    _value2: MutPtr<ALfloat>,
-    _value3: MutPtr<ALfloat>,
+    source: ALuint,
+    param: ALenum,
+    value1: ALint,
+    value2: ALint,
+    value3: ALint,
 ) {
-    todo!();
+    unsafe { al::alSource3i(source, param, value1, value2, value3) };
 }
-fn alGetListenerfv(_env: &mut Environment, _param: ALenum, _values: MutPtr<ALfloat>) {
-    todo!();
+fn alSourceiv(env: &mut Environment, source: ALuint, param: ALenum, values: ConstPtr<ALint>) {
+    if param == al::AL_BUFFER {
+        let value = env.mem.read(values);
+        alSourcei(env, source, param, value);
+        return;
+    }
+    let values = env.mem.ptr_at(values, value_count(param));
+    unsafe { al::alSourceiv(source, param, values) };
 }
-fn alGetListeneri(_env: &mut Environment, _param: ALenum, _value: MutPtr<ALint>) {
-    todo!();
+fn alGetSource3f(
+    env: &mut Environment,
+    source: ALuint,
+    param: ALenum,
+    value1: MutPtr<ALfloat>,
+    value2: MutPtr<ALfloat>,
+    value3: MutPtr<ALfloat>,
+) {
+    let value1 = env.mem.ptr_at_mut(value1, 1);
+    let value2 = env.mem.ptr_at_mut(value2, 1);
+    let value3 = env.mem.ptr_at_mut(value3, 1);
+    unsafe { al::alGetSource3f(source, param, value1, value2, value3) };
 }
-fn alIsSource(_env: &mut Environment, _source: ALuint) -> ALboolean {
-    todo!();
+fn alGetSourcefv(env: &mut Environment, source: ALuint, param: ALenum, values: MutPtr<ALfloat>) {
+    let values = env.mem.ptr_at_mut(values, value_count(param));
+    unsafe { al::alGetSourcefv(source, param, values) };
 }
-fn alSource3f(
-    _env: &mut Environment,
-    _source: ALuint,
-    _param: ALenum,
-    _value1: ALfloat,
-    _value2: ALfloat,
-    _value3: ALfloat,
+fn alGetSource3i(
+    env: &mut Environment,
+    source: ALuint,
+    param: ALenum,
+    value1: MutPtr<ALint>,
+    value2: MutPtr<ALint>,
+    value3: MutPtr<ALint>,
 ) {
-    todo!();
+    let value1 = env.mem.ptr_at_mut(value1, 1);
+    let value2 = env.mem.ptr_at_mut(value2, 1);
+    let value3 = env.mem.ptr_at_mut(value3, 1);
+    unsafe { al::alGetSource3i(source, param, value1, value2, value3) };
 }
-fn alGetSource3f(
+fn alGetSourceiv(env: &mut Environment, source: ALuint, param: ALenum, values: MutPtr<ALint>) {
+    let values = env.mem.ptr_at_mut(values, value_count(param));
+    unsafe { al::alGetSourceiv(source, param, values) };
+}
+
+fn alSourcePlayv(env: &mut Environment, n: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = n.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourcePlayv(n, sources) };
+}
+fn alSourcePausev(env: &mut Environment, n: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = n.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourcePausev(n, sources) };
+}
+fn alSourceStopv(env: &mut Environment, n: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = n.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourceStopv(n, sources) };
+}
+fn alSourceRewind(_env: &mut Environment, source: ALuint) {
+    unsafe { al::alSourceRewind(source) };
+}
+fn alSourceRewindv(env: &mut Environment, n: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = n.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourceRewindv(n, sources) };
+}
+
+fn alIsBuffer(_env: &mut Environment, buffer: ALuint) -> ALboolean {
+    unsafe { al::alIsBuffer(buffer) }
+}
+
+fn alBufferf(_env: &mut Environment, buffer: ALuint, param: ALenum, value: ALfloat) {
+    unsafe { al::alBufferf(buffer, param, value) };
+}
+fn alBuffer3f(
     _env: &mut Environment,
-    _source: ALuint,
-    _param: ALenum,
-    _value1: MutPtr<ALfloat>,
-    _value2: MutPtr<ALfloat>,
-    _value3: MutPtr<ALfloat>,
+    buffer: ALuint,
+    param: ALenum,
+    value1: ALfloat,
+    value2: ALfloat,
+    value3: ALfloat,
 ) {
-    todo!();
+    unsafe { al::alBuffer3f(buffer, param, value1, value2, value3) };
+}
+fn alBufferfv(env: &mut Environment, buffer: ALuint, param: ALenum, values: ConstPtr<ALfloat>) {
+    let values = env.mem.ptr_at(values, value_count(param));
+    unsafe { al::alBufferfv(buffer, param, values) };
 }
-fn alGetSourcefv(
+fn alBufferi(_env: &mut Environment, buffer: ALuint, param: ALenum, value: ALint) {
+    unsafe { al::alBufferi(buffer, param, value) };
+}
+fn alBuffer3i(
     _env: &mut Environment,
-    _source: ALuint,
-    _param: ALenum,
-    _values: MutPtr<ALfloat>,
+    buffer: ALuint,
+    param: ALenum,
+    value1: ALint,
+    value2: ALint,
+    value3: ALint,
 ) {
-    todo!();
+    unsafe { al::alBuffer3i(buffer, param, value1, value2, value3) };
 }
-fn alSourcePlayv(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alBufferiv(env: &mut Environment, buffer: ALuint, param: ALenum, values: ConstPtr<ALint>) {
+    let values = env.mem.ptr_at(values, value_count(param));
+    unsafe { al::alBufferiv(buffer, param, values) };
 }
-fn alSourcePausev(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alGetBufferf(env: &mut Environment, buffer: ALuint, param: ALenum, value: MutPtr<ALfloat>) {
+    unsafe { al::alGetBufferf(buffer, param, env.mem.ptr_at_mut(value, 1)) };
 }
-fn alSourceStopv(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alGetBuffer3f(
+    env: &mut Environment,
+    buffer: ALuint,
+    param: ALenum,
+    value1: MutPtr<ALfloat>,
+    value2: MutPtr<ALfloat>,
+    value3: MutPtr<ALfloat>,
+) {
+    let value1 = env.mem.ptr_at_mut(value1, 1);
+    let value2 = env.mem.ptr_at_mut(value2, 1);
+    let value3 = env.mem.ptr_at_mut(value3, 1);
+    unsafe { al::alGetBuffer3f(buffer, param, value1, value2, value3) };
 }
-fn alSourceRewind(_env: &mut Environment, _source: ALuint) {
-    todo!();
+fn alGetBufferfv(env: &mut Environment, buffer: ALuint, param: ALenum, values: MutPtr<ALfloat>) {
+    let values = env.mem.ptr_at_mut(values, value_count(param));
+    unsafe { al::alGetBufferfv(buffer, param, values) };
 }
-fn alSourceRewindv(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alGetBufferi(env: &mut Environment, buffer: ALuint, param: ALenum, value: MutPtr<ALint>) {
+    unsafe { al::alGetBufferi(buffer, param, env.mem.ptr_at_mut(value, 1)) };
+}
+fn alGetBuffer3i(
+    env: &mut Environment,
+    buffer: ALuint,
+    param: ALenum,
+    value1: MutPtr<ALint>,
+    value2: MutPtr<ALint>,
+    value3: MutPtr<ALint>,
+) {
+    let value1 = env.mem.ptr_at_mut(value1, 1);
+    let value2 = env.mem.ptr_at_mut(value2, 1);
+    let value3 = env.mem.ptr_at_mut(value3, 1);
+    unsafe { al::alGetBuffer3i(buffer, param, value1, value2, value3) };
+}
+fn alGetBufferiv(env: &mut Environment, buffer: ALuint, param: ALenum, values: MutPtr<ALint>) {
+    let values = env.mem.ptr_at_mut(values, value_count(param));
+    unsafe { al::alGetBufferiv(buffer, param, values) };
+}
+
+/// Number of values used by a vector variant (e.g. `alSourcefv`) of a
+/// function for a parameter.
+fn value_count(param: ALenum) -> GuestUSize {
+    match param {
+        al::AL_POSITION | al::AL_VELOCITY | al::AL_DIRECTION => 3,
+        // "at" vector followed by "up" vector
+        al::AL_ORIENTATION => 6,
+        _ => 1,
+    }
 }
 
 pub const FUNCTIONS: FunctionExports = &[
@@ -521,6 +836,14 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(alcDestroyContext(_)),
     export_c_func!(alcMakeContextCurrent(_)),
     export_c_func!(alcGetProcAddress(_, _)),
+    export_c_func!(alcProcessContext(_)),
+    export_c_func!(alcSuspendContext(_)),
+    export_c_func!(alcGetCurrentContext()),
+    export_c_func!(alcGetContextsDevice(_)),
+    export_c_func!(alcIsExtensionPresent(_, _)),
+    export_c_func!(alcGetEnumValue(_, _)),
+    export_c_func!(alcGetString(_, _)),
+    export_c_func!(alcGetIntegerv(_, _, _, _)),
     export_c_func!(alGetError()),
     export_c_func!(alDistanceModel(_)),
     export_c_func!(alListenerf(_, _)),
@@ -541,21 +864,12 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(alBufferData(_, _, _, _, _)),
     export_c_func!(alBufferDataStatic(_, _, _, _, _)),
     export_c_func!(alcMacOSXMixerOutputRate(_)),
-    export_c_func!(alcGetContextsDevice(_)),
-    export_c_func!(alcGetCurrentContext()),
-    export_c_func!(alcGetEnumValue(_, _)),
-    export_c_func!(alcGetIntegerv(_, _, _, _)),
-    export_c_func!(alcGetString(_, _)),
-    export_c_func!(alcIsExtensionPresent(_, _)),
-    export_c_func!(alcProcessContext(_)),
-    export_c_func!(alcSuspendContext(_)),
-    export_c_func!(alIsBuffer(_)),
-    export_c_func!(alGetBufferf(_, _, _)),
-    export_c_func!(alGetBufferi(_, _, _)),
-    export_c_func!(alEnable(_)),
-    export_c_func!(alDisable(_)),
     export_c_func!(alDopplerFactor(_)),
     export_c_func!(alDopplerVelocity(_)),
+    export_c_func!(alSpeedOfSound(_)),
+    export_c_func!(alEnable(_)),
+    export_c_func!(alDisable(_)),
+    export_c_func!(alIsEnabled(_)),
     export_c_func!(alGetBoolean(_)),
     export_c_func!(alGetBooleanv(_, _)),
     export_c_func!(alGetDouble(_)),
@@ -564,26 +878,45 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(alGetFloatv(_, _)),
     export_c_func!(alGetInteger(_)),
     export_c_func!(alGetIntegerv(_, _)),
-    export_c_func!(alGetEnumValue(_)),
-    export_c_func!(alGetProcAddress(_)),
     export_c_func!(alGetString(_)),
     export_c_func!(alIsExtensionPresent(_)),
-    export_c_func!(alIsEnabled(_)),
-    export_c_func!(alListenerfv(_, _)),
+    export_c_func!(alGetEnumValue(_)),
+    export_c_func!(alGetProcAddress(_)),
     export_c_func!(alListeneri(_, _)),
+    export_c_func!(alListener3i(_, _, _, _)),
+    export_c_func!(alListenerfv(_, _)),
+    export_c_func!(alListeneriv(_, _)),
     export_c_func!(alGetListenerf(_, _)),
     export_c_func!(alGetListener3f(_, _, _, _)),
     export_c_func!(alGetListenerfv(_, _)),
     export_c_func!(alGetListeneri(_, _)),
+    export_c_func!(alGetListener3i(_, _, _, _)),
+    export_c_func!(alGetListeneriv(_, _)),
     export_c_func!(alIsSource(_)),
-    export_c_func!(alSourcefv(_, _, _)),
     export_c_func!(alSource3f(_, _, _, _, _)),
+    export_c_func!(alSourcefv(_, _, _)),
+    export_c_func!(alSource3i(_, _, _, _, _)),
+    export_c_func!(alSourceiv(_, _, _)),
     export_c_func!(alGetSource3f(_, _, _, _, _)),
     export_c_func!(alGetSourcefv(_, _, _)),
+    export_c_func!(alGetSource3i(_, _, _, _, _)),
+    export_c_func!(alGetSourceiv(_, _, _)),
     export_c_func!(alSourcePlayv(_, _)),
-    export_c_func!(alSourcePause(_)),
     export_c_func!(alSourcePausev(_, _)),
     export_c_func!(alSourceStopv(_, _)),
     export_c_func!(alSourceRewind(_)),
     export_c_func!(alSourceRewindv(_, _)),
+    export_c_func!(alIsBuffer(_)),
+    export_c_func!(alBufferf(_, _, _)),
+    export_c_func!(alBuffer3f(_, _, _, _, _)),
+    export_c_func!(alBufferfv(_, _, _)),
+    export_c_func!(alBufferi(_, _, _)),
+    export_c_func!(alBuffer3i(_, _, _, _, _)),
+    export_c_func!(alBufferiv(_, _, _)),
+    export_c_func!(alGetBufferf(_, _, _)),
+    export_c_func!(alGetBuffer3f(_, _, _, _, _)),
+    export_c_func!(alGetBufferfv(_, _, _)),
+    export_c_func!(alGetBufferi(_, _, _)),
+    export_c_func!(alGetBuffer3i(_, _, _, _, _)),
+    export_c_func!(alGetBufferiv(_, _, _)),
 ];
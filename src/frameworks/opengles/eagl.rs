@@ -11,6 +11,7 @@ use crate::frameworks::core_animation::ca_eagl_layer::{
 };
 use crate::frameworks::foundation::ns_string::get_static_str;
 use crate::frameworks::foundation::NSUInteger;
+use crate::frameworks::media_player::movie_player;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
 use crate::gles::present::present_frame;
@@ -206,6 +207,15 @@ pub const CLASSES: ClassExports = objc_classes! {
         .get(&renderbuffer)
         .expect("Can't present a renderbuffer not bound to a drawable!");
 
+    // While a movie is playing, it covers the screen.
+    if movie_player::is_playing(env) {
+        log_dbg!(
+            "Movie is playing, skipping presentation of renderbuffer {:?}",
+            renderbuffer,
+        );
+        return true;
+    }
+
     // We're presenting to the opaque CAEAGLLayer that covers the screen.
     // We can use the fast path where we skip composition and present directly.
     if drawable == fullscreen_layer {
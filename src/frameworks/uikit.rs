@@ -9,6 +9,7 @@
 //! likely to use UIKit in very simple and limited ways, so this implementation
 //! will probably take a lot of shortcuts.
 
+use crate::frameworks::media_player::movie_player;
 use crate::Environment;
 use std::time::Instant;
 
@@ -65,7 +66,9 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
                 ui_application::exit(env);
             }
             Event::TouchDown(..) | Event::TouchMove(..) | Event::TouchUp(..) => {
-                ui_touch::handle_event(env, event)
+                if !movie_player::intercept_touch(env, &event) {
+                    ui_touch::handle_event(env, event)
+                }
             }
             Event::AppWillResignActive => {
                 // Getting this event means touchHLE is becoming inactive, e.g.
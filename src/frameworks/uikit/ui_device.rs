@@ -53,14 +53,16 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (())endGeneratingDeviceOrientationNotifications {
     log!("TODO: endGeneratingDeviceOrientationNotifications");
 }
+// NSString
 - (id)model {
-    // TODO: Hardcoded to iPhone for now
-    ns_string::get_static_str(env, "iPhone")
+    let model = env.options.device.ui_device_model;
+    ns_string::get_static_str(env, model)
 }
 
 // NSString
 - (id)systemVersion {
-    ns_string::get_static_str(env, "2.0")
+    let version = env.options.device.system_version;
+    ns_string::get_static_str(env, version)
 }
 
 - (bool)isMultitaskingSupported {
@@ -6,8 +6,10 @@
 //! `UIEvent`.
 
 use crate::frameworks::core_graphics::CGPoint;
+use crate::frameworks::foundation::{ns_set, NSUInteger};
 use crate::objc::{
-    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
 };
 use crate::Environment;
 
@@ -41,12 +43,22 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (id)touchesForView:(id)view {
     let &UIEventHostObject { touches, .. } = env.objc.borrow(this);
-    // TODO: broken for multi-touch
-    // FIXME: this will be wrong sometimes. locationInView: currently panics
-    // if it would be, at least.
-    let touch: id = msg![env; touches anyObject];
-    let _: CGPoint = msg![env; touch locationInView:view];
-    touches
+    let touches_array: id = msg![env; touches allObjects];
+    let count: NSUInteger = msg![env; touches_array count];
+    let mut touches_for_view = Vec::new();
+    for i in 0..count {
+        let touch: id = msg![env; touches_array objectAtIndex:i];
+        let touch_view: id = msg![env; touch view];
+        if touch_view != view {
+            continue;
+        }
+        // FIXME: this will be wrong sometimes. locationInView: currently
+        // panics if it would be, at least.
+        let _: CGPoint = msg![env; touch locationInView:view];
+        touches_for_view.push(touch);
+    }
+    let touches_for_view = ns_set::from_vec(env, touches_for_view);
+    autorelease(env, touches_for_view)
 }
 
 - (id)allTouches {
@@ -60,8 +72,8 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
-/// For use by [super::ui_touch]: create a `UIEvent` with a set of `UITouch*`
-/// and the view it was originally sent to.
+/// For use by [super::ui_touch]: create a `UIEvent` with the set of all current
+/// `UITouch*`s and the view it was originally sent to.
 pub(super) fn new_event(env: &mut Environment, touches: id, view: id) -> id {
     let event: id = msg_class![env; UIEvent alloc];
     retain(env, touches);
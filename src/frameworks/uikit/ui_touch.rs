@@ -7,13 +7,14 @@
 
 use super::ui_event;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect};
-use crate::frameworks::foundation::{NSInteger, NSTimeInterval, NSUInteger};
+use crate::frameworks::foundation::{ns_set, NSInteger, NSTimeInterval, NSUInteger};
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
     NSZonePtr,
 };
-use crate::window::Event;
+use crate::window::{Event, FingerId};
 use crate::Environment;
+use std::collections::{HashMap, HashSet};
 
 pub type UITouchPhase = NSInteger;
 pub const UITouchPhaseBegan: UITouchPhase = 0;
@@ -22,7 +23,12 @@ pub const UITouchPhaseEnded: UITouchPhase = 3;
 
 #[derive(Default)]
 pub struct State {
-    current_touch: Option<id>,
+    /// Touches that began but haven't ended yet, one per finger. Each
+    /// `UITouch*` is retained by this map.
+    current_touches: HashMap<FingerId, id>,
+    /// Fingers whose touches aren't delivered because the view already has a
+    /// touch and doesn't have `multipleTouchEnabled` set.
+    ignored_fingers: HashSet<FingerId>,
 }
 
 struct UITouchHostObject {
@@ -152,16 +158,37 @@ fn find_view_for_touch(env: &mut Environment, point: CGPoint) -> Option<id> {
     None
 }
 
+/// Create an autoreleased `NSSet` of all the current `UITouch*`s, for use as
+/// the `allTouches` of a `UIEvent`.
+fn all_touches(env: &mut Environment) -> id {
+    let touches = env
+        .framework_state
+        .uikit
+        .ui_touch
+        .current_touches
+        .values()
+        .copied()
+        .collect();
+    let touches = ns_set::from_vec(env, touches);
+    autorelease(env, touches)
+}
+
 /// [super::handle_events] will forward touch events to this function.
 pub fn handle_event(env: &mut Environment, event: Event) {
     match event {
-        Event::TouchDown(coords) => {
-            if env.framework_state.uikit.ui_touch.current_touch.is_some() {
-                log!("Warning: New touch initiated but current touch did not end yet, treating as movement.");
-                return handle_event(env, Event::TouchMove(coords));
+        Event::TouchDown(finger, coords) => {
+            if env
+                .framework_state
+                .uikit
+                .ui_touch
+                .current_touches
+                .contains_key(&finger)
+            {
+                log!("Warning: New touch initiated by {:?} but its current touch did not end yet, treating as movement.", finger);
+                return handle_event(env, Event::TouchMove(finger, coords));
             }
 
-            log_dbg!("Touch down: {:?}", coords);
+            log_dbg!("Touch down ({:?}): {:?}", finger, coords);
 
             let location = CGPoint {
                 x: coords.0,
@@ -172,6 +199,28 @@ pub fn handle_event(env: &mut Environment, event: Event) {
                 return;
             };
 
+            let multiple_touch_enabled: bool = msg![env; view isMultipleTouchEnabled];
+            if !multiple_touch_enabled {
+                let state = &env.framework_state.uikit.ui_touch;
+                let view_has_touch = state
+                    .current_touches
+                    .values()
+                    .any(|&touch| env.objc.borrow::<UITouchHostObject>(touch).view == view);
+                if view_has_touch {
+                    log_dbg!(
+                        "Ignoring touch by {:?}, view {:?} doesn't accept multiple touches",
+                        finger,
+                        view
+                    );
+                    env.framework_state
+                        .uikit
+                        .ui_touch
+                        .ignored_fingers
+                        .insert(finger);
+                    return;
+                }
+            }
+
             // UIKit creates and drains autorelease pools when handling events.
             let pool: id = msg_class![env; NSAutoreleasePool new];
 
@@ -192,11 +241,16 @@ pub fn handle_event(env: &mut Environment, event: Event) {
             };
             autorelease(env, new_touch);
 
-            env.framework_state.uikit.ui_touch.current_touch = Some(new_touch);
+            env.framework_state
+                .uikit
+                .ui_touch
+                .current_touches
+                .insert(finger, new_touch);
             retain(env, new_touch);
 
             let touches: id = msg_class![env; NSSet setWithObject:new_touch];
-            let event = ui_event::new_event(env, touches, view);
+            let all_touches = all_touches(env);
+            let event = ui_event::new_event(env, all_touches, view);
             autorelease(env, event);
 
             log_dbg!(
@@ -209,13 +263,17 @@ pub fn handle_event(env: &mut Environment, event: Event) {
 
             release(env, pool);
         }
-        Event::TouchMove(coords) => {
-            let Some(touch) = env.framework_state.uikit.ui_touch.current_touch else {
-                log!("Warning: Touch move event received but no current touch, ignoring.");
+        Event::TouchMove(finger, coords) => {
+            let state = &env.framework_state.uikit.ui_touch;
+            if state.ignored_fingers.contains(&finger) {
+                return;
+            }
+            let Some(&touch) = state.current_touches.get(&finger) else {
+                log!("Warning: Touch move event received for {:?} but it has no current touch, ignoring.", finger);
                 return;
             };
 
-            log_dbg!("Touch move: {:?}", coords);
+            log_dbg!("Touch move ({:?}): {:?}", finger, coords);
 
             let location = CGPoint {
                 x: coords.0,
@@ -234,7 +292,8 @@ pub fn handle_event(env: &mut Environment, event: Event) {
             let pool: id = msg_class![env; NSAutoreleasePool new];
 
             let touches: id = msg_class![env; NSSet setWithObject:touch];
-            let event = ui_event::new_event(env, touches, view);
+            let all_touches = all_touches(env);
+            let event = ui_event::new_event(env, all_touches, view);
             autorelease(env, event);
 
             log_dbg!(
@@ -247,13 +306,17 @@ pub fn handle_event(env: &mut Environment, event: Event) {
 
             release(env, pool);
         }
-        Event::TouchUp(coords) => {
-            let Some(touch) = env.framework_state.uikit.ui_touch.current_touch else {
-                log!("Warning: Touch up event received but no current touch, ignoring.");
+        Event::TouchUp(finger, coords) => {
+            let state = &mut env.framework_state.uikit.ui_touch;
+            if state.ignored_fingers.remove(&finger) {
+                return;
+            }
+            let Some(&touch) = state.current_touches.get(&finger) else {
+                log!("Warning: Touch up event received for {:?} but it has no current touch, ignoring.", finger);
                 return;
             };
 
-            log_dbg!("Touch up: {:?}", coords);
+            log_dbg!("Touch up ({:?}): {:?}", finger, coords);
 
             let location = CGPoint {
                 x: coords.0,
@@ -272,11 +335,17 @@ pub fn handle_event(env: &mut Environment, event: Event) {
             let pool: id = msg_class![env; NSAutoreleasePool new];
 
             let touches: id = msg_class![env; NSSet setWithObject:touch];
-            let event = ui_event::new_event(env, touches, view);
+            // The ending touch is still part of the event's touches.
+            let all_touches = all_touches(env);
+            let event = ui_event::new_event(env, all_touches, view);
             autorelease(env, event);
 
-            env.framework_state.uikit.ui_touch.current_touch = None;
-            release(env, touch); // only owner now should be the NSSet
+            env.framework_state
+                .uikit
+                .ui_touch
+                .current_touches
+                .remove(&finger);
+            release(env, touch); // only owners now should be the NSSets
 
             log_dbg!(
                 "Sending [{:?} touchesEnded:{:?} withEvent:{:?}]",
@@ -26,6 +26,9 @@ pub(super) struct UIViewHostObject {
     subviews: Vec<id>,
     /// The superview. This is a weak reference.
     superview: id,
+    /// If this is [false], the view only receives the first of several
+    /// simultaneous touches.
+    multiple_touch_enabled: bool,
 }
 impl HostObject for UIViewHostObject {}
 
@@ -128,9 +131,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     // TODO: enable user interaction
 }
 
-// TODO: setMultipleTouchEnabled
-- (())setMultipleTouchEnabled:(bool)_enabled {
-    // TODO: enable multitouch
+- (bool)isMultipleTouchEnabled {
+    env.objc.borrow::<UIViewHostObject>(this).multiple_touch_enabled
+}
+- (())setMultipleTouchEnabled:(bool)enabled {
+    env.objc.borrow_mut::<UIViewHostObject>(this).multiple_touch_enabled = enabled;
 }
 
 - (())layoutSubviews {
@@ -199,6 +204,7 @@ pub const CLASSES: ClassExports = objc_classes! {
         layer,
         superview,
         subviews,
+        multiple_touch_enabled: _,
     } = std::mem::take(env.objc.borrow_mut(this));
 
     release(env, layer);
@@ -412,8 +412,8 @@ pub struct Fs {
 }
 impl Fs {
     /// Construct a filesystem containing a home directory for the app, its
-    /// bundle and documents, and the bundled shared libraries. Returns the new
-    /// filesystem and the guest path of the bundle.
+    /// bundle and sandbox directories, and the bundled shared libraries.
+    /// Returns the new filesystem and the guest path of the bundle.
     ///
     /// The `bundle_dir_name` argument will be used as the name of the bundle
     /// directory in the guest filesystem, and must end in `.app`.
@@ -423,8 +423,9 @@ impl Fs {
     ///
     /// The `bundle_id` argument should be some value that uniquely identifies
     /// the app. This will be used to construct the host path for the app's
-    /// sandbox directory, where documents can be stored. A directory will be
-    /// created at that path if it does not already exist.
+    /// sandbox directory, which contains the `Documents`, `Library` and `tmp`
+    /// directories the app can write to. Directories will be created at that
+    /// path if they do not already exist.
     pub fn new(
         app_bundle: BundleData,
         bundle_dir_name: String,
@@ -437,15 +438,26 @@ impl Fs {
 
         let bundle_guest_path = home_directory.join(&bundle_dir_name);
 
-        let documents_host_path = paths::user_data_base_path()
+        let sandbox_host_path = paths::user_data_base_path()
             .join(paths::SANDBOX_DIR)
-            .join(bundle_id)
-            .join("Documents");
-        if let Err(e) = std::fs::create_dir_all(&documents_host_path) {
-            panic!(
-                "Could not create documents directory for app at {:?}: {:?}",
-                documents_host_path, e
-            );
+            .join(bundle_id);
+        let documents_host_path = sandbox_host_path.join("Documents");
+        let library_host_path = sandbox_host_path.join("Library");
+        let tmp_host_path = sandbox_host_path.join("tmp");
+        // This is the layout an app gets on a real device. Library/Preferences
+        // is where NSUserDefaults stores its plist.
+        for host_path in [
+            &documents_host_path,
+            &library_host_path.join("Caches"),
+            &library_host_path.join("Preferences"),
+            &tmp_host_path,
+        ] {
+            if let Err(e) = std::fs::create_dir_all(host_path) {
+                panic!(
+                    "Could not create sandbox directory for app at {:?}: {:?}",
+                    host_path, e
+                );
+            }
         }
 
         // Some Free Software libraries are bundled with touchHLE.
@@ -482,6 +494,20 @@ impl Fs {
                                         /* writeable: */ true,
                                     ),
                                 ),
+                                (
+                                    "Library".to_string(),
+                                    FsNode::from_host_dir(
+                                        &library_host_path,
+                                        /* writeable: */ true,
+                                    ),
+                                ),
+                                (
+                                    "tmp".to_string(),
+                                    FsNode::from_host_dir(
+                                        &tmp_host_path,
+                                        /* writeable: */ true,
+                                    ),
+                                ),
                             ]),
                             writeable: None,
                         },
@@ -11,26 +11,241 @@
 //! - The GDB source code:
 //!   - `include/gdb/signals.def` for the meanings of signal numbers
 //!   - `gdb/arch/arm.h` for ARMv6 register numbers
+//!   - `gdb/features/arm/` for the target description features
+//!
+//! Threads are identified to the debugger by their [ThreadID] plus one, since
+//! GDB reserves 0 to mean "any thread".
 
+use crate::abi::GuestFunction;
 use crate::cpu::{Cpu, CpuError};
-use crate::mem::{GuestUSize, Mem, Ptr};
+use crate::mem::{GuestUSize, Mem, Ptr, WatchKind, Watchpoint};
+use crate::{Environment, ThreadID};
 use std::fmt::Write as _;
 use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::net::TcpStream;
 use std::time::Duration;
 
-/// GDB target description XML.
-const TARGET_XML: &str = r##"
+/// GDB register number of CPSR. Numbers 16 to 24 are the legacy FPA
+/// registers, which we don't have.
+const REG_CPSR: usize = 25;
+/// GDB register number of `d0`. `d1` to `d15` follow it. ARMv6 has VFPv2,
+/// which only has 16 `d` registers.
+const REG_D0: usize = 26;
+/// GDB register number of FPSCR.
+const REG_FPSCR: usize = REG_D0 + 16;
+
+/// The registers in the order they appear in `g` and `G` packets.
+fn g_packet_registers() -> impl Iterator<Item = usize> {
+    (0..16).chain(REG_CPSR..=REG_FPSCR)
+}
+
+/// GDB target description XML. The VFP feature makes GDB show the `s` and `d`
+/// registers, the former being pseudo-registers derived from the latter.
+/// There's no NEON feature, because ARMv6 doesn't have NEON.
+fn target_xml() -> String {
+    let mut xml = String::from(
+        r##"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
 <target version="1.0">
     <architecture>armv6</architecture>
     <osabi>Darwin</osabi>
+    <feature name="org.gnu.gdb.arm.core">
+"##,
+    );
+    for i in 0..13 {
+        writeln!(
+            xml,
+            r#"        <reg name="r{}" bitsize="32" type="uint32"/>"#,
+            i
+        )
+        .unwrap();
+    }
+    xml.push_str(
+        r##"        <reg name="sp" bitsize="32" type="data_ptr"/>
+        <reg name="lr" bitsize="32"/>
+        <reg name="pc" bitsize="32" type="code_ptr"/>
+"##,
+    );
+    writeln!(
+        xml,
+        r#"        <reg name="cpsr" bitsize="32" regnum="{}"/>"#,
+        REG_CPSR
+    )
+    .unwrap();
+    xml.push_str(
+        r##"    </feature>
+    <feature name="org.gnu.gdb.arm.vfp">
+"##,
+    );
+    for i in 0..16 {
+        writeln!(
+            xml,
+            r#"        <reg name="d{}" bitsize="64" type="ieee_double" regnum="{}"/>"#,
+            i,
+            REG_D0 + i
+        )
+        .unwrap();
+    }
+    writeln!(
+        xml,
+        r#"        <reg name="fpscr" bitsize="32" type="int" group="float" regnum="{}"/>"#,
+        REG_FPSCR
+    )
+    .unwrap();
+    xml.push_str(
+        r##"    </feature>
 </target>
-"##;
+"##,
+    );
+    xml
+}
+
+/// Read a register by GDB register number. The result is in target
+/// (little-endian) byte order.
+fn read_register(cpu: &Cpu, num: usize) -> Option<Vec<u8>> {
+    if num < 16 {
+        Some(cpu.regs()[num].to_le_bytes().to_vec())
+    } else if num == REG_CPSR {
+        Some(cpu.cpsr().to_le_bytes().to_vec())
+    } else if (REG_D0..REG_FPSCR).contains(&num) {
+        let i = (num - REG_D0) * 2;
+        let ext_regs = cpu.ext_regs();
+        let mut bytes = ext_regs[i].to_le_bytes().to_vec();
+        bytes.extend_from_slice(&ext_regs[i + 1].to_le_bytes());
+        Some(bytes)
+    } else if num == REG_FPSCR {
+        Some(cpu.fpscr().to_le_bytes().to_vec())
+    } else {
+        None
+    }
+}
+
+/// Write a register by GDB register number. The value is in target
+/// (little-endian) byte order. Returns [false] if there is no such register or
+/// the value has the wrong size.
+fn write_register(cpu: &mut Cpu, num: usize, bytes: &[u8]) -> bool {
+    let word = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+    if (REG_D0..REG_FPSCR).contains(&num) {
+        if bytes.len() != 8 {
+            return false;
+        }
+        let i = (num - REG_D0) * 2;
+        let ext_regs = cpu.ext_regs_mut();
+        ext_regs[i] = word(&bytes[..4]);
+        ext_regs[i + 1] = word(&bytes[4..]);
+        return true;
+    }
+    if bytes.len() != 4 {
+        return false;
+    }
+    if num < 16 {
+        cpu.regs_mut()[num] = word(bytes);
+    } else if num == REG_CPSR {
+        cpu.set_cpsr(word(bytes));
+    } else if num == REG_FPSCR {
+        cpu.set_fpscr(word(bytes));
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Returns [true] if the thread can be resumed by the debugger. Only threads
+/// the scheduler could switch to can be resumed. [None] means the current
+/// thread.
+fn can_resume(env: &Environment, thread: Option<ThreadID>) -> bool {
+    thread.map_or(true, |thread| {
+        thread == env.current_thread
+            || env
+                .threads
+                .get(thread)
+                .map_or(false, |thread| thread.is_runnable())
+    })
+}
+
+/// Parse the actions of a `vCont` packet. Only one thread runs at a time, so
+/// the actions are reduced to whether to step and which thread to resume
+/// ([None] meaning the current thread). Stepping takes precedence over
+/// continuing, otherwise the leftmost action does. Signals are ignored.
+fn parse_vcont_actions(actions: &str) -> Option<(bool, Option<ThreadID>)> {
+    let mut result: Option<(bool, Option<ThreadID>)> = None;
+    for action in actions.split(';') {
+        let (action, thread) = match action.split_once(':') {
+            Some((action, thread)) => (action, parse_thread_id(thread).ok()?),
+            None => (action, None),
+        };
+        let step = match action.as_bytes().first()? {
+            b'c' | b'C' => false,
+            b's' | b'S' => true,
+            _ => return None,
+        };
+        if result.is_none() || (step && !result.unwrap().0) {
+            result = Some((step, thread));
+        }
+    }
+    result
+}
+
+/// Update whether the pages covered by `changed` can be accessed directly by
+/// the CPU, after adding or removing that watchpoint. Watched pages must go
+/// through the memory callbacks, which check the watchpoints.
+fn update_watched_pages(cpu: &mut Cpu, mem: &Mem, changed: Watchpoint) {
+    const PAGE_SIZE: u64 = 0x1000;
+    let pages = |w: &Watchpoint| {
+        let start = u64::from(w.addr) / PAGE_SIZE;
+        let end = (u64::from(w.addr) + u64::from(w.size.max(1)) - 1) / PAGE_SIZE;
+        start..=end.min(u64::from(u32::MAX) / PAGE_SIZE)
+    };
+    for page in pages(&changed) {
+        let watched = mem.watchpoints().iter().any(|w| pages(w).contains(&page));
+        cpu.set_page_direct_access(page as GuestUSize, !watched);
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
 
 /// GDB Remote Serial Protocol handler, implementing a server.
 pub struct GdbServer {
     reader: BufReader<TcpStream>,
     first_halt: bool,
+    /// Thread selected with `Hg` for register access. [None] means the current
+    /// thread.
+    register_thread: Option<ThreadID>,
+    /// Thread selected with `Hc` to be resumed. [None] means the current
+    /// thread.
+    resume_thread: Option<ThreadID>,
+}
+
+/// Parse a GDB thread ID. Returns [Ok] with [None] for "any thread" (`0`) and
+/// "all threads" (`-1`).
+fn parse_thread_id(id: &str) -> Result<Option<ThreadID>, ()> {
+    if id == "-1" {
+        return Ok(None);
+    }
+    match usize::from_str_radix(id, 16).map_err(|_| ())? {
+        0 => Ok(None),
+        id => Ok(Some(id - 1)),
+    }
+}
+
+/// Parse the `addr,length` part of an `m` or `M` packet.
+fn parse_addr_and_length(params: &str) -> Option<(GuestUSize, GuestUSize)> {
+    let (addr, length) = params.split_once(',')?;
+    let addr = GuestUSize::from_str_radix(addr, 16).ok()?;
+    let length = GuestUSize::from_str_radix(length, 16).ok()?;
+    Some((addr, length))
+}
+
+fn format_thread_id(thread: ThreadID) -> String {
+    format!("{:x}", thread + 1)
 }
 
 impl GdbServer {
@@ -54,6 +269,8 @@ impl GdbServer {
         GdbServer {
             reader: BufReader::with_capacity(4096, connection),
             first_halt: true,
+            register_thread: None,
+            resume_thread: None,
         }
     }
 
@@ -126,43 +343,75 @@ impl GdbServer {
         log_dbg!("Sent packet: {:?}", body);
     }
 
+    /// Send a stop reply for signal `signal`, which includes the thread that
+    /// stopped and the watchpoint that was hit, if any.
+    fn send_stop_reply(
+        &mut self,
+        signal: u8,
+        thread: ThreadID,
+        watchpoint_hit: Option<(Watchpoint, GuestUSize)>,
+    ) {
+        let mut packet = format!("T{:02x}", signal);
+        if let Some((watchpoint, addr)) = watchpoint_hit {
+            let reason = match watchpoint.kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::Access => "awatch",
+            };
+            // The access may have started before the watched range, but GDB
+            // expects an address within it.
+            let last = watchpoint.addr.saturating_add(watchpoint.size - 1);
+            let addr = addr.clamp(watchpoint.addr, last);
+            write!(packet, "{}:{:x};", reason, addr).unwrap();
+        }
+        write!(packet, "thread:{};", format_thread_id(thread)).unwrap();
+        self.send_packet(&packet);
+    }
+
     /// Communciates with the debugger, returning only once it requests
     /// execution should continue. Returns [true] if the CPU should step and
     /// then resume debugging, or [false] if it should resume normal execution.
+    ///
+    /// The debugger may have switched the current thread when this returns.
     pub fn wait_for_debugger(
         &mut self,
         stop_reason: Option<CpuError>,
-        cpu: &mut Cpu,
-        mem: &mut Mem,
+        env: &mut Environment,
     ) -> bool {
         echo!("Waiting for debugger to continue.");
 
-        // Send reply to continue/step packet that gdb sent earlier, so it knows
-        // why execution was stopped.
-        match stop_reason {
-            None => {
-                if self.first_halt {
-                    // The debugger has just connected, it hasn't sent anything yet.
-                    self.first_halt = false;
-                } else {
-                    // The debugger previously requested stepping and no errors
-                    // occurred.
-                    self.send_packet("S05"); // SIGTRAP
-                }
-            }
+        // Thread selections don't persist across stops.
+        self.register_thread = None;
+        self.resume_thread = None;
+
+        let is_initial_halt = self.first_halt;
+        self.first_halt = false;
+
+        // Signal and watchpoint for the stop reply about the current thread.
+        let (stop_signal, watchpoint_hit) = match stop_reason {
+            // The debugger has just connected.
+            None if is_initial_halt => (0x00, None), // no signal
+            // The debugger previously requested stepping and no errors
+            // occurred.
+            None => (0x05, None), // SIGTRAP
             // GDB uses an undefined instruction for software breakpoints in
             // normal Arm code, and the BKPT instruction in Thumb code.
             // It apparently expects SIGTRAP instead of SIGILL even in the
             // former case.
-            Some(CpuError::UndefinedInstruction) | Some(CpuError::Breakpoint) => {
-                self.send_packet("S05"); // SIGTRAP
-            }
-            Some(CpuError::MemoryError) => {
-                self.send_packet("S0b"); // SIGSEGV
-            }
+            Some(CpuError::UndefinedInstruction) | Some(CpuError::Breakpoint) => (0x05, None),
+            Some(CpuError::Watchpoint(watchpoint, addr)) => (0x05, Some((watchpoint, addr))),
+            Some(CpuError::MemoryError) => (0x0b, None), // SIGSEGV
+        };
+
+        // Send reply to continue/step packet that gdb sent earlier, so it knows
+        // why execution was stopped. If the debugger has just connected, it
+        // hasn't sent anything yet.
+        let current_thread = env.current_thread;
+        if !is_initial_halt {
+            self.send_stop_reply(stop_signal, current_thread, watchpoint_hit);
         }
 
-        let do_step = loop {
+        let (do_step, resume_addr) = loop {
             let Some(p) = self.read_packet() else {
                 continue;
             };
@@ -171,54 +420,60 @@ impl GdbServer {
                 continue;
             };
 
+            let register_thread = self.register_thread.unwrap_or(env.current_thread);
+
             match p.as_bytes()[0] {
                 // Query for target halt reason when first connecting
                 b'?' => {
-                    assert!(stop_reason.is_none());
-                    self.send_packet("S00"); // no signal
+                    self.send_stop_reply(stop_signal, current_thread, watchpoint_hit);
                 }
                 // Read general registers
                 b'g' => {
-                    let mut packet = String::with_capacity(16 * 4 * 2);
-                    for reg in cpu.regs() {
-                        // Rust always prints in big-endian, but GDB expects
-                        // little-endian.
-                        let reg = u32::from_be_bytes(reg.to_le_bytes());
-                        write!(packet, "{:08x}", reg).unwrap();
+                    let bytes = env.with_thread_cpu(register_thread, |cpu| {
+                        g_packet_registers()
+                            .flat_map(|num| read_register(cpu, num).unwrap())
+                            .collect::<Vec<u8>>()
+                    });
+                    let mut packet = String::with_capacity(bytes.len() * 2);
+                    for byte in bytes {
+                        write!(packet, "{:02x}", byte).unwrap();
                     }
                     self.send_packet(&packet);
                 }
                 // Write general registers
                 b'G' => {
-                    let data = &p[1..];
-                    let regs = cpu.regs_mut();
-                    assert!(data.len() == regs.len() * 4 * 2);
-                    for (i, reg) in regs.iter_mut().enumerate() {
-                        let word = &data[i * 4 * 2..][..4 * 2];
-                        let word = u32::from_str_radix(word, 16).unwrap();
-                        // Rust decodes in big-endian, but GDB supplies
-                        // little-endian.
-                        let word = u32::from_le_bytes(word.to_be_bytes());
-                        *reg = word;
-                    }
+                    let Some(bytes) = decode_hex(&p[1..]) else {
+                        self.send_packet("E01");
+                        continue;
+                    };
+                    env.with_thread_cpu(register_thread, |cpu| {
+                        // GDB may leave off registers at the end it doesn't
+                        // know the value of.
+                        let mut bytes = &bytes[..];
+                        for num in g_packet_registers() {
+                            let size = read_register(cpu, num).unwrap().len();
+                            if bytes.len() < size {
+                                break;
+                            }
+                            assert!(write_register(cpu, num, &bytes[..size]));
+                            bytes = &bytes[size..];
+                        }
+                    });
                     self.send_packet("OK");
                 }
                 // Read single register by number
                 b'p' => {
-                    let num = usize::from_str_radix(&p[1..], 16).unwrap();
-                    let reg = if num < 16 {
-                        Some(cpu.regs()[num])
-                    } else if num == 25 {
-                        Some(cpu.cpsr())
-                    // TODO: FPSCR, VFP registers
-                    } else {
-                        None
+                    let Ok(num) = usize::from_str_radix(&p[1..], 16) else {
+                        self.send_packet("E01");
+                        continue;
                     };
-                    if let Some(reg) = reg {
-                        // Rust always prints in big-endian, but GDB expects
-                        // little-endian.
-                        let reg = u32::from_be_bytes(reg.to_le_bytes());
-                        self.send_packet(&format!("{:08x}", reg));
+                    let bytes = env.with_thread_cpu(register_thread, |cpu| read_register(cpu, num));
+                    if let Some(bytes) = bytes {
+                        let mut packet = String::with_capacity(bytes.len() * 2);
+                        for byte in bytes {
+                            write!(packet, "{:02x}", byte).unwrap();
+                        }
+                        self.send_packet(&packet);
                     } else {
                         // Error 0
                         self.send_packet("E00");
@@ -226,31 +481,70 @@ impl GdbServer {
                 }
                 // Write single register by number
                 b'P' => {
-                    let (num, word) = p[1..].split_once('=').unwrap();
-                    let num = usize::from_str_radix(num, 16).unwrap();
-                    let word = u32::from_str_radix(word, 16).unwrap();
-                    // Rust decodes in big-endian, but GDB supplies
-                    // little-endian.
-                    let word = u32::from_le_bytes(word.to_be_bytes());
-                    if num < 16 {
-                        cpu.regs_mut()[num] = word;
-                        self.send_packet("OK");
-                    } else if num == 25 {
-                        cpu.set_cpsr(word);
+                    let Some((Ok(num), Some(bytes))) = p[1..]
+                        .split_once('=')
+                        .map(|(num, bytes)| (usize::from_str_radix(num, 16), decode_hex(bytes)))
+                    else {
+                        self.send_packet("E01");
+                        continue;
+                    };
+                    let success = env
+                        .with_thread_cpu(register_thread, |cpu| write_register(cpu, num, &bytes));
+                    if success {
                         self.send_packet("OK");
-                    // TODO: FPSCR, VFP registers
                     } else {
                         // Error 0
                         self.send_packet("E00");
                     }
                 }
+                // Select thread for subsequent operations
+                b'H' => {
+                    // The operation is followed by a thread ID.
+                    let Some(id) = p.get(2..).filter(|id| !id.is_empty()) else {
+                        self.send_packet("E01");
+                        continue;
+                    };
+                    let Ok(thread) = parse_thread_id(id) else {
+                        self.send_packet("E00");
+                        continue;
+                    };
+                    let valid = match thread {
+                        None => true,
+                        Some(thread) => env.threads.get(thread).map_or(false, |t| t.active),
+                    };
+                    match p.as_bytes().get(1) {
+                        Some(b'g') if valid => {
+                            self.register_thread = thread;
+                            self.send_packet("OK");
+                        }
+                        Some(b'c') if valid => {
+                            // Only threads the scheduler could switch to can
+                            // be resumed.
+                            if can_resume(env, thread) {
+                                self.resume_thread = thread;
+                                self.send_packet("OK");
+                            } else {
+                                self.send_packet("E01");
+                            }
+                        }
+                        _ => self.send_packet("E00"),
+                    }
+                }
+                // Query whether a thread is alive
+                b'T' => match parse_thread_id(&p[1..]) {
+                    Ok(Some(thread)) if env.threads.get(thread).map_or(false, |t| t.active) => {
+                        self.send_packet("OK")
+                    }
+                    _ => self.send_packet("E01"),
+                },
                 // Read memory
                 b'm' => {
-                    let (addr, length) = p[1..].split_once(',').unwrap();
-                    let addr = GuestUSize::from_str_radix(addr, 16).unwrap();
-                    let length = GuestUSize::from_str_radix(length, 16).unwrap();
+                    let Some((addr, length)) = parse_addr_and_length(&p[1..]) else {
+                        self.send_packet("E01");
+                        continue;
+                    };
                     let mut packet = String::with_capacity(length as usize * 2);
-                    match mem.get_bytes_fallible(Ptr::from_bits(addr), length) {
+                    match env.mem.get_bytes_fallible(Ptr::from_bits(addr), length) {
                         Some(data) => {
                             for byte in data {
                                 write!(packet, "{:02x}", byte).unwrap();
@@ -265,21 +559,22 @@ impl GdbServer {
                 }
                 // Write memory
                 b'M' => {
-                    let (header, data) = p[1..].split_once(':').unwrap();
-                    let (addr, length) = header.split_once(',').unwrap();
-                    let addr = GuestUSize::from_str_radix(addr, 16).unwrap();
-                    let length = GuestUSize::from_str_radix(length, 16).unwrap();
-                    assert!(data.len() == length as usize * 2);
+                    let parsed = p[1..].split_once(':').and_then(|(header, data)| {
+                        let (addr, length) = parse_addr_and_length(header)?;
+                        let data = decode_hex(data)?;
+                        (data.len() == length as usize).then_some((addr, data))
+                    });
+                    let Some((addr, data)) = parsed else {
+                        self.send_packet("E01");
+                        continue;
+                    };
+                    let length = data.len() as GuestUSize;
 
-                    match mem.get_bytes_fallible_mut(Ptr::from_bits(addr), length) {
+                    match env.mem.get_bytes_fallible_mut(Ptr::from_bits(addr), length) {
                         Some(dest) => {
-                            for i in 0..(length as usize) {
-                                let byte = &data[i * 2..][..2];
-                                let byte = u8::from_str_radix(byte, 16).unwrap();
-                                dest[i] = byte;
-                            }
+                            dest.copy_from_slice(&data);
                             // Important for e.g. software breakpoints.
-                            cpu.invalidate_cache_range(addr, length);
+                            env.cpu.invalidate_cache_range(addr, length);
                             self.send_packet("OK");
                         }
                         None => {
@@ -288,22 +583,69 @@ impl GdbServer {
                         }
                     }
                 }
-                // Continue or Step
+                // Insert or remove watchpoint. Breakpoints ('Z0' and 'Z1')
+                // aren't supported, so GDB uses software breakpoints instead.
+                b'Z' | b'z' if matches!(p.as_bytes().get(1), Some(b'2'..=b'4')) => {
+                    let kind = match p.as_bytes()[1] {
+                        b'2' => WatchKind::Write,
+                        b'3' => WatchKind::Read,
+                        _ => WatchKind::Access,
+                    };
+                    let Some(params) = p.get(3..) else {
+                        self.send_packet("E01");
+                        continue;
+                    };
+                    let mut params = params.split(',');
+                    let addr = params
+                        .next()
+                        .and_then(|a| GuestUSize::from_str_radix(a, 16).ok());
+                    let size = params
+                        .next()
+                        .and_then(|s| GuestUSize::from_str_radix(s, 16).ok());
+                    let (Some(addr), Some(size @ 1..)) = (addr, size) else {
+                        self.send_packet("E00");
+                        continue;
+                    };
+                    let watchpoint = Watchpoint { addr, size, kind };
+                    if p.as_bytes()[0] == b'Z' {
+                        env.mem.add_watchpoint(watchpoint);
+                        update_watched_pages(&mut env.cpu, &env.mem, watchpoint);
+                        self.send_packet("OK");
+                    } else if env.mem.remove_watchpoint(watchpoint) {
+                        update_watched_pages(&mut env.cpu, &env.mem, watchpoint);
+                        self.send_packet("OK");
+                    } else {
+                        self.send_packet("E01");
+                    }
+                }
+                // Continue or Step, optionally at a new address
                 b'c' | b's' => {
                     let addr = &p[1..];
-                    if !addr.is_empty() {
-                        todo!("TODO: Resume at {}", addr);
-                    }
-                    break p.as_bytes()[0] == b's';
+                    let addr = if addr.is_empty() {
+                        None
+                    } else if let Ok(addr) = GuestUSize::from_str_radix(addr, 16) {
+                        Some(addr)
+                    } else {
+                        self.send_packet("E01");
+                        continue;
+                    };
+                    break (p.as_bytes()[0] == b's', addr);
                 }
                 // "Continue with signal" or "Step with signal".
                 // Presumably "with" means "ignoring"?
                 b'C' | b'S' => {
                     // Signal is just ignored for now (TODO?)
-                    if let Some((_signal, addr)) = p[1..].split_once(';') {
-                        todo!("TODO: Resume at {}", addr);
-                    }
-                    break p.as_bytes()[0] == b'S';
+                    let addr = match p[1..].split_once(';') {
+                        None => None,
+                        Some((_signal, addr)) => match GuestUSize::from_str_radix(addr, 16) {
+                            Ok(addr) => Some(addr),
+                            Err(_) => {
+                                self.send_packet("E01");
+                                continue;
+                            }
+                        },
+                    };
+                    break (p.as_bytes()[0] == b'S', addr);
                 }
                 // Kill
                 b'k' => {
@@ -314,6 +656,69 @@ impl GdbServer {
                     if p == "qAttached" {
                         // New process
                         self.send_packet("0");
+                    // Query supported resume actions
+                    } else if p == "vCont?" {
+                        self.send_packet("vCont;c;C;s;S");
+                    // Resume with per-thread actions
+                    } else if let Some(actions) = p.strip_prefix("vCont;") {
+                        match parse_vcont_actions(actions) {
+                            Some((step, thread)) if can_resume(env, thread) => {
+                                self.resume_thread = thread;
+                                break (step, None);
+                            }
+                            Some(_) => self.send_packet("E01"),
+                            None => self.send_packet("E00"),
+                        }
+                    // Stop reason for a particular thread (used by LLDB)
+                    } else if let Some(id) = p.strip_prefix("qThreadStopInfo") {
+                        match parse_thread_id(id) {
+                            Ok(Some(thread)) if thread == current_thread => {
+                                self.send_stop_reply(stop_signal, thread, watchpoint_hit);
+                            }
+                            Ok(Some(thread)) if thread < env.threads.len() => {
+                                self.send_stop_reply(0x00, thread, None); // no signal
+                            }
+                            _ => self.send_packet("E01"),
+                        }
+                    // Query current thread
+                    } else if p == "qC" {
+                        self.send_packet(&format!("QC{}", format_thread_id(env.current_thread)));
+                    // List threads. They all fit in the first reply.
+                    } else if p == "qfThreadInfo" {
+                        let ids: Vec<String> = env
+                            .threads
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, thread)| thread.active)
+                            .map(|(id, _)| format_thread_id(id))
+                            .collect();
+                        self.send_packet(&format!("m{}", ids.join(",")));
+                    } else if p == "qsThreadInfo" {
+                        // End of list
+                        self.send_packet("l");
+                    // Thread description shown by GDB's `info threads`
+                    } else if let Some(id) = p.strip_prefix("qThreadExtraInfo,") {
+                        match parse_thread_id(id) {
+                            Ok(Some(thread)) if thread < env.threads.len() => {
+                                let description = format!(
+                                    "{}{}",
+                                    if thread == 0 { "main thread, " } else { "" },
+                                    env.threads[thread]
+                                        .state_description(thread == env.current_thread)
+                                );
+                                let mut packet = String::with_capacity(description.len() * 2);
+                                for byte in description.bytes() {
+                                    write!(packet, "{:02x}", byte).unwrap();
+                                }
+                                self.send_packet(&packet);
+                            }
+                            _ => self.send_packet("E01"),
+                        }
+                    // GDB offers to look up symbols for us. touchHLE has its
+                    // own symbols from the binaries (see
+                    // [Environment::describe_addr]), so none are needed.
+                    } else if p == "qSymbol::" {
+                        self.send_packet("OK");
                     // Query for supported features
                     } else if p == "qSupported" || p.starts_with("qSupported:") {
                         // Tell GDB we can send it an XML target description.
@@ -324,7 +729,8 @@ impl GdbServer {
                         let (offset, length) = params.split_once(',').unwrap();
                         let offset = usize::from_str_radix(offset, 16).unwrap();
                         let length = usize::from_str_radix(length, 16).unwrap();
-                        let bytes = TARGET_XML.as_bytes();
+                        let target_xml = target_xml();
+                        let bytes = target_xml.as_bytes();
                         if annex == "target.xml" && offset <= bytes.len() {
                             let bytes = &bytes[offset..];
                             let length_read = length.min(bytes.len());
@@ -350,7 +756,7 @@ impl GdbServer {
                         log_dbg!("Unhandled packet.");
                         // Tell GDB we don't understand this packet.
                         // In some cases this causes convenient fallbacks:
-                        // Since we don't support 'Z', GDB will implement
+                        // Since we don't support 'Z0', GDB will implement
                         // software breakpoints for us with trap instructions.
                         self.send_packet("");
                     }
@@ -358,6 +764,22 @@ impl GdbServer {
             }
         };
 
+        if let Some(thread) = self.resume_thread {
+            if thread != env.current_thread {
+                echo!("Debugger requested switch to thread {}.", thread);
+                env.switch_thread(thread);
+            }
+        }
+
+        if let Some(addr) = resume_addr {
+            echo!("Debugger requested resuming at {:#x}.", addr);
+            // GDB usually sends the address with the Thumb bit cleared, so
+            // the thread stays in its current mode unless bit 0 is set.
+            let thumb = (addr & 1) != 0 || (env.cpu.cpsr() & Cpu::CPSR_THUMB) != 0;
+            env.cpu
+                .branch(GuestFunction::from_addr_and_thumb_flag(addr & !1, thumb));
+        }
+
         if do_step {
             echo!("Debugger requested step, resuming execution for one instruction only.");
         } else {
@@ -70,7 +70,7 @@ use gles1_on_gl2::GLES1OnGL2;
 pub use gles_generic::GLES;
 
 /// Labels for [GLES] implementations and an abstraction for constructing them.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum GLESImplementation {
     /// [GLES1Native].
     GLES1Native,
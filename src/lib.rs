@@ -31,6 +31,7 @@ mod abi;
 mod audio;
 mod bundle;
 mod cpu;
+mod device;
 mod dyld;
 mod environment;
 mod font;
@@ -44,17 +45,19 @@ mod licenses;
 mod mach_o;
 mod matrix;
 mod mem;
+mod mp4;
 mod objc;
 mod options;
 mod paths;
 mod stack;
+mod video;
 mod window;
 
 // These are very frequently used and used to be in this module, so they are
 // re-exported to avoid having to update lots of imports.
 // Unlike its siblings, this module should be considered private and only used
 // via re-exports.
-use environment::{Environment, ThreadID};
+use environment::{Environment, ThreadBlock, ThreadID};
 
 use std::ffi::OsStr;
 use std::path::PathBuf;
@@ -110,6 +113,13 @@ Special options:
 
     --info
         Print basic information about the app bundle without running the app.
+
+    --scan-imports
+    --scan-imports=json
+        Print basic information about the app bundle, then check which of the
+        functions, classes and other things the app uses aren't implemented by
+        touchHLE, without running the app. With =json, the report is printed
+        to standard output in JSON format.
 ";
 
 fn app_picker(title: &str) -> Result<PathBuf, String> {
@@ -224,6 +234,7 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
 
     let mut bundle_path: Option<PathBuf> = None;
     let mut just_info = false;
+    let mut scan_imports = None;
     let mut option_args = Vec::new();
 
     for arg in args {
@@ -236,6 +247,10 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
             return Ok(());
         } else if arg == "--info" {
             just_info = true;
+        } else if arg == "--scan-imports" {
+            scan_imports = Some(dyld::ScanImportsFormat::Text);
+        } else if arg == "--scan-imports=json" {
+            scan_imports = Some(dyld::ScanImportsFormat::Json);
         // Parse an option but discard the value, to test whether it's valid.
         // We don't want to apply it immediately, because then options loaded
         // from a file would take precedence over options from the command line.
@@ -252,6 +267,8 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
 
     let bundle_path = if let Some(bundle_path) = bundle_path {
         bundle_path
+    } else if option_args.iter().any(|arg| arg == "--headless") {
+        return Err("An app path must be specified when using --headless".to_string());
     } else {
         echo!(
             "No app specified, opening app picker. Use the --help flag to see command-line usage."
@@ -306,6 +323,10 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
         return Ok(());
     }
 
+    if let Some(format) = scan_imports {
+        return dyld::scan_imports(&bundle, &fs, format);
+    }
+
     let mut options = options::Options::default();
 
     // Apply options from files
@@ -363,6 +384,11 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
         assert!(parse_result == Ok(true));
     }
 
+    // Machines used for automated testing usually don't have an audio device.
+    if options.headless && std::env::var_os("ALSOFT_DRIVERS").is_none() {
+        std::env::set_var("ALSOFT_DRIVERS", "null");
+    }
+
     let mut env = Environment::new(bundle, fs, options)?;
     env.run();
     Ok(())
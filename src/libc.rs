@@ -21,6 +21,7 @@ pub mod mach_time;
 pub mod math;
 pub mod posix_io;
 pub mod pthread;
+pub mod semaphore;
 pub mod setjmp;
 pub mod stdio;
 pub mod stdlib;
@@ -36,6 +37,8 @@ pub struct State {
     keymgr: keymgr::State,
     posix_io: posix_io::State,
     pthread: pthread::State,
+    semaphore: semaphore::State,
+    stdio: stdio::State,
     stdlib: stdlib::State,
     string: string::State,
     time: time::State,
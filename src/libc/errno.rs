@@ -11,8 +11,11 @@ use crate::mem::MutPtr;
 use crate::Environment;
 
 pub const EPERM: i32 = 1;
+pub const ESRCH: i32 = 3;
 pub const EDEADLK: i32 = 11;
+pub const EBUSY: i32 = 16;
 pub const EINVAL: i32 = 22;
+pub const ETIMEDOUT: i32 = 60;
 
 #[derive(Default)]
 pub struct State {
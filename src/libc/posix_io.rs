@@ -18,6 +18,9 @@ use std::io::{Read, Seek, SeekFrom, Write};
 pub struct State {
     /// File descriptors _other than stdin, stdout, and stderr_
     files: Vec<Option<PosixFileHostObject>>,
+    /// Output to stdout and stderr that hasn't been logged yet because it
+    /// doesn't end in a newline.
+    partial_lines: [Vec<u8>; 2],
 }
 impl State {
     fn file_for_fd(&mut self, fd: FileDescriptor) -> Option<&mut PosixFileHostObject> {
@@ -25,13 +28,38 @@ impl State {
             .get_mut(fd_to_file_idx(fd))
             .and_then(|file_or_none| file_or_none.as_mut())
     }
+
+    /// Shared part of [read] and [read_direct]. There's no console to read
+    /// from, so stdin is always at its end.
+    fn read_host(&mut self, fd: FileDescriptor, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if fd == STDIN_FILENO {
+            return Ok(0);
+        }
+        // TODO: error handling for unknown fd?
+        self.file_for_fd(fd).unwrap().file.read(buffer)
+    }
+
+    /// Shared part of [write] and [write_direct]. Output to stdout and stderr
+    /// is sent to touchHLE's log one line at a time.
+    fn write_host(&mut self, fd: FileDescriptor, buffer: &[u8]) -> std::io::Result<usize> {
+        if fd == STDOUT_FILENO || fd == STDERR_FILENO {
+            let partial_line = &mut self.partial_lines[(fd - STDOUT_FILENO) as usize];
+            partial_line.extend_from_slice(buffer);
+            while let Some(idx) = partial_line.iter().position(|&c| c == b'\n') {
+                let line: Vec<u8> = partial_line.drain(..=idx).collect();
+                echo!("{}", String::from_utf8_lossy(&line[..idx]));
+            }
+            return Ok(buffer.len());
+        }
+        // TODO: error handling for unknown fd?
+        self.file_for_fd(fd).unwrap().file.write(buffer)
+    }
 }
 
 struct PosixFileHostObject {
     file: GuestFile,
 }
 
-// TODO: stdin/stdout/stderr handling somehow
 fn file_idx_to_fd(idx: usize) -> FileDescriptor {
     FileDescriptor::try_from(idx)
         .unwrap()
@@ -44,11 +72,9 @@ fn fd_to_file_idx(fd: FileDescriptor) -> usize {
 
 /// File descriptor type. This alias is for readability, POSIX just uses `int`.
 pub type FileDescriptor = i32;
-#[allow(dead_code)]
-const STDIN_FILENO: FileDescriptor = 0;
-#[allow(dead_code)]
-const STDOUT_FILENO: FileDescriptor = 1;
-const STDERR_FILENO: FileDescriptor = 2;
+pub const STDIN_FILENO: FileDescriptor = 0;
+pub const STDOUT_FILENO: FileDescriptor = 1;
+pub const STDERR_FILENO: FileDescriptor = 2;
 const NORMAL_FILENO_BASE: FileDescriptor = STDERR_FILENO + 1;
 
 /// Flags bitfield for `open`. This alias is for readability, POSIX just uses
@@ -140,11 +166,8 @@ pub fn read(
     buffer: MutVoidPtr,
     size: GuestUSize,
 ) -> GuestISize {
-    // TODO: error handling for unknown fd?
-    let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
-
     let buffer_slice = env.mem.bytes_at_mut(buffer.cast(), size);
-    match file.file.read(buffer_slice) {
+    match env.libc_state.posix_io.read_host(fd, buffer_slice) {
         Ok(bytes_read) => {
             if bytes_read < buffer_slice.len() {
                 log!(
@@ -185,11 +208,8 @@ pub fn write(
     buffer: ConstVoidPtr,
     size: GuestUSize,
 ) -> GuestISize {
-    // TODO: error handling for unknown fd?
-    let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
-
     let buffer_slice = env.mem.bytes_at(buffer.cast(), size);
-    match file.file.write(buffer_slice) {
+    match env.libc_state.posix_io.write_host(fd, buffer_slice) {
         Ok(bytes_written) => {
             if bytes_written < buffer_slice.len() {
                 log!(
@@ -224,12 +244,52 @@ pub fn write(
     }
 }
 
+/// Special extension for host code: [read] into a host buffer.
+pub fn read_direct(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: &mut [u8],
+) -> Result<usize, ()> {
+    env.libc_state
+        .posix_io
+        .read_host(fd, buffer)
+        .map_err(|e| {
+            log!("Warning: read({:?}) encountered error {:?}", fd, e);
+        })
+}
+
+/// Special extension for host code: [write] from a host buffer.
+pub fn write_direct(env: &mut Environment, fd: FileDescriptor, buffer: &[u8]) -> Result<usize, ()> {
+    env.libc_state
+        .posix_io
+        .write_host(fd, buffer)
+        .map_err(|e| {
+            log!("Warning: write({:?}) encountered error {:?}", fd, e);
+        })
+}
+
+/// Log any output to stdout and stderr that is still waiting for a newline.
+/// This should be done before exiting.
+pub fn flush_standard_output(env: &mut Environment) {
+    for partial_line in env.libc_state.posix_io.partial_lines.iter_mut() {
+        if !partial_line.is_empty() {
+            echo!("{}", String::from_utf8_lossy(partial_line));
+            partial_line.clear();
+        }
+    }
+}
+
 #[allow(non_camel_case_types)]
 pub type off_t = i64;
 pub const SEEK_SET: i32 = 0;
 pub const SEEK_CUR: i32 = 1;
 pub const SEEK_END: i32 = 2;
 pub fn lseek(env: &mut Environment, fd: FileDescriptor, offset: off_t, whence: i32) -> off_t {
+    if fd < NORMAL_FILENO_BASE {
+        log_dbg!("lseek({:?}, {:#x}, {}) => -1", fd, offset, whence);
+        return -1; // should also set errno to ESPIPE
+    }
+
     // TODO: error handling for unknown fd?
     let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
 
@@ -252,6 +312,14 @@ pub fn lseek(env: &mut Environment, fd: FileDescriptor, offset: off_t, whence: i
 }
 
 pub fn close(env: &mut Environment, fd: FileDescriptor) -> i32 {
+    if fd < NORMAL_FILENO_BASE {
+        // The standard streams can't really be closed, but this is a good time
+        // to log whatever is left.
+        flush_standard_output(env);
+        log_dbg!("close({:?}) => 0", fd);
+        return 0;
+    }
+
     // TODO: error handling for unknown fd?
     let file = env.libc_state.posix_io.files[fd_to_file_idx(fd)]
         .take()
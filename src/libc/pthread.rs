@@ -28,14 +28,18 @@ macro_rules! check_magic {
     }
 }
 
+pub mod cond;
 pub mod key;
+mod lock_checker;
 pub mod mutex;
 pub mod once;
+pub mod rwlock;
 pub mod thread;
 
 #[derive(Default)]
 pub struct State {
     key: key::State,
     mutex: mutex::State,
+    rwlock: rwlock::State,
     thread: thread::State,
 }
@@ -0,0 +1,146 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Condition variables.
+//!
+//! There's no host object for these: the threads waiting on a condition
+//! variable are tracked by the scheduler, see [ThreadBlock::Condition].
+
+use super::mutex::{pthread_mutex_t, unlock_for_condition_wait};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::ETIMEDOUT;
+use crate::mem::{ConstPtr, ConstVoidPtr, MutPtr, SafeRead};
+use crate::{Environment, ThreadBlock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Apple's implementation is a 4-byte magic number followed by a 24-byte opaque
+/// region. We only have to match the size theirs has.
+#[repr(C, packed)]
+struct pthread_cond_t {
+    /// Magic number (must be [MAGIC_COND])
+    magic: u32,
+    _unused: [u32; 6],
+}
+unsafe impl SafeRead for pthread_cond_t {}
+
+/// Arbitrarily-chosen magic number for `pthread_cond_t` (not Apple's).
+const MAGIC_COND: u32 = u32::from_be_bytes(*b"COND");
+/// Magic number used by `PTHREAD_COND_INITIALIZER`. This is part of the ABI!
+const MAGIC_COND_STATIC: u32 = 0x3CB0B1BB;
+
+#[repr(C, packed)]
+struct timespec {
+    tv_sec: i32,
+    tv_nsec: i32,
+}
+unsafe impl SafeRead for timespec {}
+
+fn pthread_cond_init(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    attr: ConstVoidPtr, // const pthread_condattr_t *
+) -> i32 {
+    assert!(attr.is_null()); // TODO: attributes
+    env.mem.write(
+        cond,
+        pthread_cond_t {
+            magic: MAGIC_COND,
+            _unused: Default::default(),
+        },
+    );
+    0 // success
+}
+
+fn check_or_register_cond(env: &mut Environment, cond: MutPtr<pthread_cond_t>) {
+    let magic: u32 = env.mem.read(cond.cast());
+    // This is a statically-initialized condition variable, we need to change
+    // the magic number.
+    if magic == MAGIC_COND_STATIC {
+        log_dbg!(
+            "Detected statically-initialized condition variable at {:?}.",
+            cond
+        );
+        pthread_cond_init(env, cond, ConstPtr::null());
+    } else {
+        // See check_or_register_mutex
+        assert_eq!(magic, MAGIC_COND);
+    }
+}
+
+fn pthread_cond_destroy(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> i32 {
+    check_or_register_cond(env, cond);
+    env.mem.write(
+        cond,
+        pthread_cond_t {
+            magic: 0,
+            _unused: Default::default(),
+        },
+    );
+    0 // success
+}
+
+fn wait(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    mutex: MutPtr<pthread_mutex_t>,
+    timeout: Option<Instant>,
+) -> i32 {
+    check_or_register_cond(env, cond);
+    // A recursive mutex is unlocked completely, as on a real system.
+    let res = unlock_for_condition_wait(env, mutex);
+    assert!(res == 0); // should be EPERM
+    env.block(
+        ThreadBlock::Condition {
+            cond: cond.cast(),
+            mutex: mutex.cast(),
+        },
+        timeout.map(|timeout| (timeout, ETIMEDOUT as u32)),
+    );
+    0 // success (once signalled and the mutex has been re-locked)
+}
+
+fn pthread_cond_wait(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    mutex: MutPtr<pthread_mutex_t>,
+) -> i32 {
+    wait(env, cond, mutex, None)
+}
+
+fn pthread_cond_timedwait(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    mutex: MutPtr<pthread_mutex_t>,
+    abstime: ConstPtr<timespec>,
+) -> i32 {
+    let timespec { tv_sec, tv_nsec } = env.mem.read(abstime);
+    assert!(tv_sec >= 0 && (0..1_000_000_000).contains(&tv_nsec)); // should be EINVAL
+    let abstime = SystemTime::UNIX_EPOCH + Duration::new(tv_sec as u64, tv_nsec as u32);
+    let duration = abstime
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    wait(env, cond, mutex, Some(Instant::now() + duration))
+}
+
+fn pthread_cond_signal(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> i32 {
+    check_or_register_cond(env, cond);
+    env.signal_condition(cond.cast(), /* broadcast: */ false);
+    0 // success
+}
+
+fn pthread_cond_broadcast(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> i32 {
+    check_or_register_cond(env, cond);
+    env.signal_condition(cond.cast(), /* broadcast: */ true);
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(pthread_cond_init(_, _)),
+    export_c_func!(pthread_cond_destroy(_)),
+    export_c_func!(pthread_cond_wait(_, _)),
+    export_c_func!(pthread_cond_timedwait(_, _, _)),
+    export_c_func!(pthread_cond_signal(_)),
+    export_c_func!(pthread_cond_broadcast(_)),
+];
@@ -0,0 +1,162 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Lock-order checking for mutexes (`--check-locks`).
+//!
+//! Whenever a thread locks a mutex while holding others, that ordering is
+//! recorded. Locking mutexes in an order that contradicts the recorded ones is
+//! reported, since two threads doing that at the same time can deadlock. This
+//! catches the problem even if the timing happens to work out.
+
+use crate::mem::MutVoidPtr;
+use crate::ThreadID;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Default)]
+pub struct LockChecker {
+    /// For each mutex, the mutexes that have been locked while it was held,
+    /// and the thread that first did so.
+    orderings: HashMap<MutVoidPtr, HashMap<MutVoidPtr, ThreadID>>,
+    /// Inversions that have already been reported, so each is only reported
+    /// once.
+    reported: HashSet<(MutVoidPtr, MutVoidPtr)>,
+}
+
+impl LockChecker {
+    /// Record that `thread` is locking `mutex` while holding the mutexes in
+    /// `held`. Returns a description of each new inversion this causes.
+    pub fn check_lock_order(
+        &mut self,
+        thread: ThreadID,
+        mutex: MutVoidPtr,
+        held: &[MutVoidPtr],
+    ) -> Vec<String> {
+        let mut inversions = Vec::new();
+        for &held_mutex in held {
+            if let Some(path) = self.find_ordering(mutex, held_mutex) {
+                if self.reported.insert((held_mutex, mutex)) {
+                    let path: Vec<String> = path
+                        .iter()
+                        .map(|(mutex, thread)| format!("{:?} (thread {})", mutex, thread))
+                        .collect();
+                    inversions.push(format!(
+                        "thread {} is locking mutex {:?} while holding mutex {:?}, but they were previously locked in the opposite order: {:?} then {}",
+                        thread,
+                        mutex,
+                        held_mutex,
+                        mutex,
+                        path.join(" then "),
+                    ));
+                }
+            }
+            self.orderings
+                .entry(held_mutex)
+                .or_default()
+                .entry(mutex)
+                .or_insert(thread);
+        }
+        inversions
+    }
+
+    /// Forget the orderings involving `mutex`, because it has been destroyed.
+    pub fn forget_mutex(&mut self, mutex: MutVoidPtr) {
+        self.orderings.remove(&mutex);
+        for later_mutexes in self.orderings.values_mut() {
+            later_mutexes.remove(&mutex);
+        }
+        self.orderings
+            .retain(|_, later_mutexes| !later_mutexes.is_empty());
+        self.reported
+            .retain(|&(first, second)| first != mutex && second != mutex);
+    }
+
+    /// Search for a chain of recorded orderings leading from `from` to `to`.
+    /// Returns each mutex after `from` in the chain, with the thread that
+    /// locked it.
+    fn find_ordering(
+        &self,
+        from: MutVoidPtr,
+        to: MutVoidPtr,
+    ) -> Option<Vec<(MutVoidPtr, ThreadID)>> {
+        let mut previous: HashMap<MutVoidPtr, (MutVoidPtr, ThreadID)> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(current) = queue.pop_front() {
+            let Some(next_mutexes) = self.orderings.get(&current) else {
+                continue;
+            };
+            for (&next, &thread) in next_mutexes {
+                if next == from || previous.contains_key(&next) {
+                    continue;
+                }
+                previous.insert(next, (current, thread));
+                if next == to {
+                    let mut path = Vec::new();
+                    let mut mutex = to;
+                    while mutex != from {
+                        let (prev, thread) = previous[&mutex];
+                        path.push((mutex, thread));
+                        mutex = prev;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LockChecker;
+    use crate::mem::{MutVoidPtr, Ptr};
+
+    fn mutex(addr: u32) -> MutVoidPtr {
+        Ptr::from_bits(addr)
+    }
+
+    #[test]
+    fn test_consistent_order() {
+        let mut checker = LockChecker::default();
+        let (a, b, c) = (mutex(0x1000), mutex(0x2000), mutex(0x3000));
+        assert!(checker.check_lock_order(0, b, &[a]).is_empty());
+        assert!(checker.check_lock_order(1, c, &[a, b]).is_empty());
+        assert!(checker.check_lock_order(0, c, &[b]).is_empty());
+    }
+
+    #[test]
+    fn test_direct_inversion() {
+        let mut checker = LockChecker::default();
+        let (a, b) = (mutex(0x1000), mutex(0x2000));
+        assert!(checker.check_lock_order(0, b, &[a]).is_empty());
+        assert_eq!(checker.check_lock_order(1, a, &[b]).len(), 1);
+        // Each inversion is only reported once.
+        assert!(checker.check_lock_order(1, a, &[b]).is_empty());
+    }
+
+    #[test]
+    fn test_indirect_inversion() {
+        let mut checker = LockChecker::default();
+        let (a, b, c) = (mutex(0x1000), mutex(0x2000), mutex(0x3000));
+        assert!(checker.check_lock_order(0, b, &[a]).is_empty());
+        assert!(checker.check_lock_order(1, c, &[b]).is_empty());
+        // a then b then c was seen, so c then a is a cycle.
+        let inversions = checker.check_lock_order(2, a, &[c]);
+        assert_eq!(inversions.len(), 1);
+        assert!(inversions[0].contains("thread 2"));
+    }
+
+    #[test]
+    fn test_forget_mutex() {
+        let mut checker = LockChecker::default();
+        let (a, b) = (mutex(0x1000), mutex(0x2000));
+        assert!(checker.check_lock_order(0, b, &[a]).is_empty());
+        // After b is destroyed, a new mutex at the same address has no
+        // recorded orderings.
+        checker.forget_mutex(b);
+        assert!(checker.check_lock_order(1, a, &[b]).is_empty());
+    }
+}
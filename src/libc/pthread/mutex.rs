@@ -5,16 +5,22 @@
  */
 //! Mutexes.
 
+use super::lock_checker::LockChecker;
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::libc::errno::{EDEADLK, EPERM};
-use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
-use crate::{Environment, ThreadID};
+use crate::libc::errno::{EBUSY, EDEADLK, EPERM};
+use crate::mem::{ConstPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::{Environment, ThreadBlock, ThreadID};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 
 #[derive(Default)]
 pub struct State {
     mutexes: HashMap<MutPtr<pthread_mutex_t>, MutexHostObject>,
+    /// Lock counts of recursive mutexes that a thread unlocked completely to
+    /// wait on a condition variable, see [unlock_for_condition_wait].
+    saved_lock_counts: HashMap<(MutPtr<pthread_mutex_t>, ThreadID), NonZeroU32>,
+    /// Only used with `--check-locks`.
+    lock_checker: LockChecker,
 }
 impl State {
     fn get(env: &mut Environment) -> &mut Self {
@@ -37,7 +43,7 @@ unsafe impl SafeRead for pthread_mutexattr_t {}
 /// Apple's implementation is a 4-byte magic number followed by a 56-byte opaque
 /// region. We will store the actual data on the host instead.
 #[repr(C, packed)]
-struct pthread_mutex_t {
+pub(super) struct pthread_mutex_t {
     /// Magic number (must be [MAGIC_MUTEX])
     magic: u32,
 }
@@ -153,9 +159,68 @@ fn check_or_register_mutex(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>
     }
 }
 
+/// Get the mutexes currently locked by `thread`.
+pub fn mutexes_held_by(env: &Environment, thread: ThreadID) -> Vec<MutVoidPtr> {
+    let mut mutexes: Vec<MutVoidPtr> = env
+        .libc_state
+        .pthread
+        .mutex
+        .mutexes
+        .iter()
+        .filter(|(_, host_object)| matches!(host_object.locked, Some((t, _)) if t == thread))
+        .map(|(&mutex, _)| mutex.cast())
+        .collect();
+    mutexes.sort_by_key(|mutex| mutex.to_bits());
+    mutexes
+}
+
+/// Get the thread that has locked a mutex, if any.
+pub fn mutex_owner(env: &Environment, mutex: MutVoidPtr) -> Option<ThreadID> {
+    let host_object = env.libc_state.pthread.mutex.mutexes.get(&mutex.cast())?;
+    host_object.locked.map(|(thread, _)| thread)
+}
+
+fn pthread_mutex_destroy(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -> i32 {
+    check_or_register_mutex(env, mutex);
+    let state = State::get(env);
+    if state.mutexes[&mutex].locked.is_some() {
+        return EBUSY;
+    }
+    state.mutexes.remove(&mutex);
+    state
+        .saved_lock_counts
+        .retain(|&(saved_mutex, _), _| saved_mutex != mutex);
+    // The address may be reused for an unrelated mutex.
+    state.lock_checker.forget_mutex(mutex.cast());
+    env.mem.write(mutex, pthread_mutex_t { magic: 0 });
+    0 // success
+}
+
+/// For `--check-locks`: check for lock-order inversions before the current
+/// thread locks `mutex`.
+fn check_lock_order(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) {
+    let current_thread = env.current_thread;
+    if mutex_owner(env, mutex.cast()) == Some(current_thread) {
+        return;
+    }
+    let held = mutexes_held_by(env, current_thread);
+    let checker = &mut State::get(env).lock_checker;
+    let inversions = checker.check_lock_order(current_thread, mutex.cast(), &held);
+    for inversion in &inversions {
+        log!("Warning: possible deadlock: {}.", inversion);
+    }
+    if !inversions.is_empty() {
+        env.stack_trace();
+    }
+}
+
 fn pthread_mutex_lock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -> i32 {
     check_or_register_mutex(env, mutex);
 
+    if env.options.check_locks {
+        check_lock_order(env, mutex);
+    }
+
     let current_thread = env.current_thread;
     let host_object: &mut _ = State::get(env).mutexes.get_mut(&mutex).unwrap();
 
@@ -168,13 +233,23 @@ fn pthread_mutex_lock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) ->
     if locking_thread == current_thread {
         match host_object.type_ {
             PTHREAD_MUTEX_NORMAL => {
-                // This case would be a deadlock, we may as well panic.
-                panic!(
-                    "Attempted to lock non-error-checking mutex {:?} for thread {}, already locked by same thread!",
-                    mutex, current_thread,
-                );
+                if !env.options.check_locks {
+                    // This case would be a deadlock, we may as well panic.
+                    panic!(
+                        "Attempted to lock non-error-checking mutex {:?} for thread {}, already locked by same thread!",
+                        mutex, current_thread,
+                    );
+                }
+                // Block like a real device would, so other threads can still
+                // run, and the deadlock gets reported if none of them can.
+                log!("Warning: thread {} attempted to lock non-error-checking mutex {:?}, which it already holds. It will deadlock.", current_thread, mutex);
+                env.stack_trace();
             }
             PTHREAD_MUTEX_ERRORCHECK => {
+                if env.options.check_locks {
+                    log!("Warning: thread {} attempted to lock error-checking mutex {:?}, which it already holds. Returning EDEADLK.", current_thread, mutex);
+                    env.stack_trace();
+                }
                 log_dbg!("Attempted to lock error-checking mutex {:?} for thread {}, already locked by same thread! Returning EDEADLK.", mutex, current_thread);
                 return EDEADLK;
             }
@@ -191,16 +266,57 @@ fn pthread_mutex_lock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) ->
         }
     }
 
-    // TODO: wait for unlock on other thread
-    unimplemented!(
-        "Attempted to lock mutex {:?} for thread {}, already locked by thread {}",
+    log_dbg!(
+        "Mutex {:?} is locked by thread {}, thread {} will wait for it.",
         mutex,
-        current_thread,
         locking_thread,
-    )
+        current_thread,
+    );
+    env.block(ThreadBlock::Mutex(mutex.cast()), None);
+    0 // success (once the mutex has been locked)
+}
+
+/// Lock a mutex on behalf of a thread blocked by [pthread_mutex_lock], if it
+/// is unlocked. Returns [true] on success.
+pub fn try_lock_for_thread(env: &mut Environment, mutex: MutVoidPtr, thread: ThreadID) -> bool {
+    let host_object = State::get(env).mutexes.get_mut(&mutex.cast()).unwrap();
+    if host_object.locked.is_some() {
+        return false;
+    }
+    log_dbg!("Locked mutex {:?} for thread {}.", mutex, thread);
+    let lock_count = State::get(env)
+        .saved_lock_counts
+        .remove(&(mutex.cast(), thread))
+        .unwrap_or(NonZeroU32::new(1).unwrap());
+    let host_object = State::get(env).mutexes.get_mut(&mutex.cast()).unwrap();
+    host_object.locked = Some((thread, lock_count));
+    true
 }
 
-fn pthread_mutex_unlock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -> i32 {
+/// For [super::cond]: unlock a mutex held by the current thread completely,
+/// even if it's a recursive mutex that has been locked several times. The lock
+/// count is restored when [try_lock_for_thread] re-locks it for this thread.
+pub(super) fn unlock_for_condition_wait(
+    env: &mut Environment,
+    mutex: MutPtr<pthread_mutex_t>,
+) -> i32 {
+    check_or_register_mutex(env, mutex);
+
+    let current_thread = env.current_thread;
+    let state = State::get(env);
+    let host_object = state.mutexes.get_mut(&mutex).unwrap();
+    if let Some((locking_thread, lock_count)) = host_object.locked {
+        if locking_thread == current_thread && lock_count.get() > 1 {
+            host_object.locked = Some((locking_thread, NonZeroU32::new(1).unwrap()));
+            state
+                .saved_lock_counts
+                .insert((mutex, current_thread), lock_count);
+        }
+    }
+    pthread_mutex_unlock(env, mutex)
+}
+
+pub(super) fn pthread_mutex_unlock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -> i32 {
     check_or_register_mutex(env, mutex);
 
     let current_thread = env.current_thread;
@@ -214,14 +330,14 @@ fn pthread_mutex_unlock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -
                     "Attempted to unlock non-error-checking mutex {:?} for thread {}, already unlocked!",
                     mutex, current_thread,
                 );
-            },
+            }
             PTHREAD_MUTEX_ERRORCHECK | PTHREAD_MUTEX_RECURSIVE => {
                 log_dbg!(
                     "Attempted to unlock error-checking or recursive mutex {:?} for thread {}, already unlocked! Returning EPERM.",
                     mutex, current_thread,
                 );
                 return EPERM;
-            },
+            }
             _ => unreachable!(),
         }
     };
@@ -236,6 +352,13 @@ fn pthread_mutex_unlock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -
                 );
             }
             PTHREAD_MUTEX_ERRORCHECK | PTHREAD_MUTEX_RECURSIVE => {
+                if env.options.check_locks {
+                    log!(
+                        "Warning: thread {} attempted to unlock mutex {:?}, which is held by thread {}. Returning EPERM.",
+                        current_thread, mutex, locking_thread,
+                    );
+                    env.stack_trace();
+                }
                 log_dbg!(
                     "Attempted to unlock error-checking or recursive mutex {:?} for thread {}, lobkced by different thread {}! Returning EPERM.",
                     mutex, current_thread, locking_thread,
@@ -269,6 +392,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(pthread_mutexattr_settype(_, _)),
     export_c_func!(pthread_mutexattr_destroy(_)),
     export_c_func!(pthread_mutex_init(_, _)),
+    export_c_func!(pthread_mutex_destroy(_)),
     export_c_func!(pthread_mutex_lock(_)),
     export_c_func!(pthread_mutex_unlock(_)),
 ];
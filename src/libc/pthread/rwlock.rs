@@ -0,0 +1,205 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Read-write locks.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{EBUSY, EDEADLK};
+use crate::mem::{ConstVoidPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::{Environment, ThreadBlock, ThreadID};
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct State {
+    rwlocks: HashMap<MutPtr<pthread_rwlock_t>, RwLockHostObject>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.pthread.rwlock
+    }
+}
+
+/// Apple's implementation is a 4-byte magic number followed by a 124-byte
+/// opaque region. We will store the actual data on the host instead.
+#[repr(C, packed)]
+struct pthread_rwlock_t {
+    /// Magic number (must be [MAGIC_RWLOCK])
+    magic: u32,
+}
+unsafe impl SafeRead for pthread_rwlock_t {}
+
+#[derive(Default)]
+struct RwLockHostObject {
+    /// Threads holding a read lock. A thread appears once for each lock it
+    /// holds.
+    readers: Vec<ThreadID>,
+    writer: Option<ThreadID>,
+}
+
+/// Arbitrarily-chosen magic number for `pthread_rwlock_t` (not Apple's).
+const MAGIC_RWLOCK: u32 = u32::from_be_bytes(*b"RWLK");
+/// Magic number used by `PTHREAD_RWLOCK_INITIALIZER`. This is part of the ABI!
+const MAGIC_RWLOCK_STATIC: u32 = 0x2DA8B3B4;
+
+fn pthread_rwlock_init(
+    env: &mut Environment,
+    rwlock: MutPtr<pthread_rwlock_t>,
+    attr: ConstVoidPtr, // const pthread_rwlockattr_t *
+) -> i32 {
+    assert!(attr.is_null()); // TODO: attributes
+    env.mem.write(
+        rwlock,
+        pthread_rwlock_t {
+            magic: MAGIC_RWLOCK,
+        },
+    );
+
+    assert!(!State::get(env).rwlocks.contains_key(&rwlock));
+    State::get(env)
+        .rwlocks
+        .insert(rwlock, RwLockHostObject::default());
+
+    0 // success
+}
+
+fn check_or_register_rwlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) {
+    let magic: u32 = env.mem.read(rwlock.cast());
+    // This is a statically-initialized rwlock, we need to register it, and
+    // change the magic number in the process.
+    if magic == MAGIC_RWLOCK_STATIC {
+        log_dbg!(
+            "Detected statically-initialized rwlock at {:?}, registering.",
+            rwlock
+        );
+        pthread_rwlock_init(env, rwlock, Ptr::null());
+    } else {
+        // See check_or_register_mutex
+        assert_eq!(magic, MAGIC_RWLOCK);
+    }
+}
+
+fn pthread_rwlock_destroy(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    check_or_register_rwlock(env, rwlock);
+    let host_object = State::get(env).rwlocks.remove(&rwlock).unwrap();
+    assert!(host_object.readers.is_empty() && host_object.writer.is_none()); // should be EBUSY
+    env.mem.write(rwlock, pthread_rwlock_t { magic: 0 });
+    0 // success
+}
+
+/// Lock a rwlock for `thread` if that's possible without waiting. Returns
+/// [true] on success. This is also used to resolve a [ThreadBlock::RwLockRead]
+/// or [ThreadBlock::RwLockWrite].
+pub fn try_lock_for_thread(
+    env: &mut Environment,
+    rwlock: MutVoidPtr,
+    thread: ThreadID,
+    write: bool,
+) -> bool {
+    let host_object = State::get(env).rwlocks.get_mut(&rwlock.cast()).unwrap();
+    if host_object.writer.is_some() || (write && !host_object.readers.is_empty()) {
+        return false;
+    }
+    log_dbg!(
+        "Locked rwlock {:?} for {} on thread {}.",
+        rwlock,
+        if write { "writing" } else { "reading" },
+        thread
+    );
+    if write {
+        host_object.writer = Some(thread);
+    } else {
+        host_object.readers.push(thread);
+    }
+    true
+}
+
+fn lock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>, write: bool, wait: bool) -> i32 {
+    check_or_register_rwlock(env, rwlock);
+
+    let current_thread = env.current_thread;
+    let host_object = State::get(env).rwlocks.get(&rwlock).unwrap();
+    if host_object.writer == Some(current_thread)
+        || (write && host_object.readers.contains(&current_thread))
+    {
+        log!(
+            "Warning: thread {} attempted to lock rwlock {:?} that it already holds, returning EDEADLK.",
+            current_thread,
+            rwlock
+        );
+        return EDEADLK;
+    }
+
+    if try_lock_for_thread(env, rwlock.cast(), current_thread, write) {
+        return 0; // success
+    }
+    if !wait {
+        return EBUSY;
+    }
+    log_dbg!(
+        "Rwlock {:?} is busy, thread {} will wait for it.",
+        rwlock,
+        current_thread
+    );
+    env.block(
+        if write {
+            ThreadBlock::RwLockWrite(rwlock.cast())
+        } else {
+            ThreadBlock::RwLockRead(rwlock.cast())
+        },
+        None,
+    );
+    0 // success (once the rwlock has been locked)
+}
+
+fn pthread_rwlock_rdlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock(env, rwlock, /* write: */ false, /* wait: */ true)
+}
+fn pthread_rwlock_tryrdlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock(env, rwlock, /* write: */ false, /* wait: */ false)
+}
+fn pthread_rwlock_wrlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock(env, rwlock, /* write: */ true, /* wait: */ true)
+}
+fn pthread_rwlock_trywrlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock(env, rwlock, /* write: */ true, /* wait: */ false)
+}
+
+fn pthread_rwlock_unlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    check_or_register_rwlock(env, rwlock);
+
+    let current_thread = env.current_thread;
+    let host_object = State::get(env).rwlocks.get_mut(&rwlock).unwrap();
+    if host_object.writer == Some(current_thread) {
+        log_dbg!(
+            "Unlocked rwlock {:?} for writing on thread {}.",
+            rwlock,
+            current_thread
+        );
+        host_object.writer = None;
+    } else {
+        let idx = host_object
+            .readers
+            .iter()
+            .position(|&reader| reader == current_thread)
+            .unwrap(); // should be EPERM
+        log_dbg!(
+            "Unlocked rwlock {:?} for reading on thread {}.",
+            rwlock,
+            current_thread
+        );
+        host_object.readers.swap_remove(idx);
+    }
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(pthread_rwlock_init(_, _)),
+    export_c_func!(pthread_rwlock_destroy(_)),
+    export_c_func!(pthread_rwlock_rdlock(_)),
+    export_c_func!(pthread_rwlock_tryrdlock(_)),
+    export_c_func!(pthread_rwlock_wrlock(_)),
+    export_c_func!(pthread_rwlock_trywrlock(_)),
+    export_c_func!(pthread_rwlock_unlock(_)),
+];
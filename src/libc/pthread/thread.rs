@@ -7,8 +7,9 @@
 
 use crate::abi::GuestFunction;
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{EDEADLK, ESRCH};
 use crate::mem::{ConstPtr, MutPtr, MutVoidPtr, SafeRead};
-use crate::{Environment, ThreadID};
+use crate::{Environment, ThreadBlock, ThreadID};
 use std::collections::HashMap;
 
 #[derive(Default)]
@@ -54,6 +55,9 @@ type pthread_t = MutPtr<OpaqueThread>;
 struct ThreadHostObject {
     thread_id: ThreadID,
     _attr: pthread_attr_t,
+    /// Set once the thread is detached or another thread is joining it, since
+    /// it can't then be joined again.
+    detached_or_joined: bool,
 }
 
 /// Arbitrarily-chosen magic number for `pthread_attr_t` (not Apple's).
@@ -122,6 +126,7 @@ fn pthread_create(
         ThreadHostObject {
             thread_id,
             _attr: attr,
+            detached_or_joined: attr.detachstate == PTHREAD_CREATE_DETACHED,
         },
     );
 
@@ -148,6 +153,7 @@ fn pthread_self(env: &mut Environment) -> pthread_t {
             ThreadHostObject {
                 thread_id: 0,
                 _attr: DEFAULT_ATTR,
+                detached_or_joined: false,
             },
         );
         log_dbg!(
@@ -164,6 +170,36 @@ fn pthread_self(env: &mut Environment) -> pthread_t {
     ptr
 }
 
+fn pthread_join(env: &mut Environment, thread: pthread_t, value_ptr: MutPtr<MutVoidPtr>) -> i32 {
+    let current_thread = env.current_thread;
+    let Some(host_object) = State::get(env).threads.get_mut(&thread) else {
+        return ESRCH;
+    };
+    let thread_id = host_object.thread_id;
+    if thread_id == current_thread {
+        return EDEADLK;
+    }
+    assert!(!host_object.detached_or_joined); // should be EINVAL
+    host_object.detached_or_joined = true;
+
+    log_dbg!(
+        "Thread {} will wait for thread {} to finish.",
+        current_thread,
+        thread_id
+    );
+    env.block(ThreadBlock::Joining(thread_id, value_ptr), None);
+    0 // success (once the thread has finished)
+}
+
+fn pthread_detach(env: &mut Environment, thread: pthread_t) -> i32 {
+    let Some(host_object) = State::get(env).threads.get_mut(&thread) else {
+        return ESRCH;
+    };
+    assert!(!host_object.detached_or_joined); // should be EINVAL
+    host_object.detached_or_joined = true;
+    0 // success
+}
+
 fn pthread_setcanceltype(_env: &mut Environment, _type: i32, _oldtype: MutPtr<i32>) -> i32 {
     // TODO
     0
@@ -184,6 +220,8 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(pthread_attr_destroy(_)),
     export_c_func!(pthread_create(_, _, _, _)),
     export_c_func!(pthread_self()),
+    export_c_func!(pthread_join(_, _)),
+    export_c_func!(pthread_detach(_)),
     export_c_func!(pthread_setcanceltype(_, _)),
     export_c_func!(pthread_mach_thread_np(_)),
 ];
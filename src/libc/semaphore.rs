@@ -0,0 +1,363 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `semaphore.h` and Mach semaphores (`mach/semaphore.h`).
+//!
+//! Both kinds share the same host objects. iPhone OS only supports named POSIX
+//! semaphores, so apps tend to use Mach semaphores instead.
+
+#![allow(non_camel_case_types)]
+
+use crate::abi::DotDotDot;
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::mem::{ConstPtr, ConstVoidPtr, Mem, MutPtr, Ptr};
+use crate::{Environment, ThreadBlock};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct State {
+    semaphores: HashMap<u32, SemaphoreHostObject>,
+    /// Number of semaphores created so far, used to give each one a new ID.
+    created_count: u32,
+    /// Named semaphores created by [sem_open].
+    named: HashMap<String, MutPtr<sem_t>>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.semaphore
+    }
+
+    /// Create a new semaphore and return its ID. The IDs are used as Mach port
+    /// names, so they start at an arbitrary non-zero value. They are never
+    /// reused, so that using a destroyed semaphore can't affect a newer one.
+    fn create(&mut self, value: u32) -> u32 {
+        let id = 0x5E0 + self.created_count;
+        self.created_count += 1;
+        self.semaphores.insert(id, SemaphoreHostObject { value });
+        id
+    }
+
+    /// Returns [false] if the semaphore doesn't exist.
+    fn destroy(&mut self, semaphore: u32) -> bool {
+        self.semaphores.remove(&semaphore).is_some()
+    }
+
+    /// Decrement a semaphore if it's greater than zero. Returns [true] on
+    /// success, or [false] if it's zero or doesn't exist.
+    fn try_decrement(&mut self, semaphore: u32) -> bool {
+        let Some(host_object) = self.semaphores.get_mut(&semaphore) else {
+            return false;
+        };
+        if host_object.value == 0 {
+            return false;
+        }
+        host_object.value -= 1;
+        true
+    }
+
+    /// Increase a semaphore by `count`. Returns [false] if it doesn't exist.
+    fn signal(&mut self, semaphore: u32, count: u32) -> bool {
+        let Some(host_object) = self.semaphores.get_mut(&semaphore) else {
+            return false;
+        };
+        host_object.value = host_object.value.checked_add(count).unwrap();
+        true
+    }
+}
+
+struct SemaphoreHostObject {
+    value: u32,
+}
+
+/// Apple's `sem_t` is an `int`. We store the ID of the host object in it.
+type sem_t = u32;
+/// `SEM_FAILED` is `(sem_t *)-1`.
+const SEM_FAILED: u32 = u32::MAX;
+
+type kern_return_t = i32;
+const KERN_SUCCESS: kern_return_t = 0;
+const KERN_INVALID_ARGUMENT: kern_return_t = 4;
+const KERN_TERMINATED: kern_return_t = 15;
+const KERN_INVALID_VALUE: kern_return_t = 18;
+const KERN_OPERATION_TIMED_OUT: kern_return_t = 49;
+
+type mach_port_t = u32;
+type task_t = mach_port_t;
+type semaphore_t = mach_port_t;
+
+/// Port name of the current task. touchHLE doesn't have tasks, but apps need
+/// something to pass to [semaphore_create]. This is the value usually seen on
+/// real devices.
+const MACH_TASK_SELF: task_t = 0x103;
+
+// From `fcntl.h`
+const O_CREAT: i32 = 0x200;
+const O_EXCL: i32 = 0x800;
+
+/// Create a new semaphore and return its ID, see [State::create].
+fn create(env: &mut Environment, value: u32) -> u32 {
+    let id = State::get(env).create(value);
+    log_dbg!("Created semaphore {:#x} with value {}.", id, value);
+    id
+}
+
+/// Check that a semaphore exists (it may have been destroyed, or the app may
+/// be passing garbage), logging a warning if it doesn't.
+fn is_valid(env: &mut Environment, semaphore: u32, function: &str) -> bool {
+    let valid = State::get(env).semaphores.contains_key(&semaphore);
+    if !valid {
+        log!(
+            "Warning: {}() with invalid semaphore {:#x}, returning an error",
+            function,
+            semaphore
+        );
+    }
+    valid
+}
+
+/// Decrement a semaphore if it's greater than zero. Returns [true] on success.
+/// This is also used to resolve a [ThreadBlock::Semaphore]. Threads waiting on
+/// a semaphore are woken when it's destroyed, so it should still exist then.
+pub fn try_decrement(env: &mut Environment, semaphore: u32) -> bool {
+    State::get(env).try_decrement(semaphore)
+}
+
+/// Decrement a semaphore, blocking the current thread if it's zero. See
+/// [Environment::block] for the meaning of `timeout`.
+fn wait(env: &mut Environment, semaphore: u32, timeout: Option<(Instant, u32)>) {
+    if !try_decrement(env, semaphore) {
+        log_dbg!(
+            "Semaphore {:#x} is zero, thread {} will wait for it.",
+            semaphore,
+            env.current_thread
+        );
+        env.block(ThreadBlock::Semaphore(semaphore), timeout);
+    }
+}
+
+fn sem_open(
+    env: &mut Environment,
+    name: ConstPtr<u8>,
+    oflag: i32,
+    args: DotDotDot,
+) -> MutPtr<sem_t> {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    if let Some(&sem) = State::get(env).named.get(&name) {
+        if oflag & (O_CREAT | O_EXCL) == (O_CREAT | O_EXCL) {
+            log!(
+                "Warning: sem_open() of existing semaphore {:?} with O_EXCL, returning SEM_FAILED",
+                name
+            );
+            return Ptr::from_bits(SEM_FAILED); // should also set errno to EEXIST
+        }
+        return sem;
+    }
+    if oflag & O_CREAT == 0 {
+        log!(
+            "Warning: sem_open() of non-existent semaphore {:?} without O_CREAT, returning SEM_FAILED",
+            name
+        );
+        return Ptr::from_bits(SEM_FAILED); // should also set errno to ENOENT
+    }
+
+    let mut args = args.start();
+    let _mode: u32 = args.next(env);
+    let value: u32 = args.next(env);
+    let id = create(env, value);
+    let sem = env.mem.alloc_and_write(id);
+    log_dbg!("sem_open({:?}, {:#x}) => {:?}", name, oflag, sem);
+    State::get(env).named.insert(name, sem);
+    sem
+}
+
+fn sem_close(_env: &mut Environment, _sem: MutPtr<sem_t>) -> i32 {
+    // The semaphore is kept alive in case it's opened again.
+    0 // success
+}
+
+fn sem_unlink(env: &mut Environment, name: ConstPtr<u8>) -> i32 {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    if State::get(env).named.remove(&name).is_some() {
+        0 // success
+    } else {
+        -1 // should also set errno to ENOENT
+    }
+}
+
+fn sem_init(_env: &mut Environment, _sem: MutPtr<sem_t>, _pshared: i32, _value: u32) -> i32 {
+    // Real iPhone OS doesn't support unnamed semaphores either.
+    log!("Warning: sem_init() is not supported, returning -1");
+    -1 // should also set errno to ENOSYS
+}
+
+fn sem_wait(env: &mut Environment, sem: MutPtr<sem_t>) -> i32 {
+    let semaphore = env.mem.read(sem);
+    if !is_valid(env, semaphore, "sem_wait") {
+        return -1; // should also set errno to EINVAL
+    }
+    wait(env, semaphore, None);
+    0 // success (once the semaphore has been decremented)
+}
+
+fn sem_trywait(env: &mut Environment, sem: MutPtr<sem_t>) -> i32 {
+    let semaphore = env.mem.read(sem);
+    if !is_valid(env, semaphore, "sem_trywait") {
+        return -1; // should also set errno to EINVAL
+    }
+    if try_decrement(env, semaphore) {
+        0 // success
+    } else {
+        -1 // should also set errno to EAGAIN
+    }
+}
+
+fn sem_post(env: &mut Environment, sem: MutPtr<sem_t>) -> i32 {
+    let semaphore = env.mem.read(sem);
+    if !is_valid(env, semaphore, "sem_post") {
+        return -1; // should also set errno to EINVAL
+    }
+    State::get(env).signal(semaphore, 1);
+    0 // success
+}
+
+fn semaphore_create(
+    env: &mut Environment,
+    _task: task_t,
+    semaphore: MutPtr<semaphore_t>,
+    _policy: i32,
+    value: i32,
+) -> kern_return_t {
+    let Ok(value) = value.try_into() else {
+        return KERN_INVALID_ARGUMENT;
+    };
+    let id = create(env, value);
+    env.mem.write(semaphore, id);
+    KERN_SUCCESS
+}
+
+fn semaphore_destroy(
+    env: &mut Environment,
+    _task: task_t,
+    semaphore: semaphore_t,
+) -> kern_return_t {
+    if !State::get(env).destroy(semaphore) {
+        return KERN_INVALID_ARGUMENT;
+    }
+    env.abandon_blocks(ThreadBlock::Semaphore(semaphore), KERN_TERMINATED as u32);
+    KERN_SUCCESS
+}
+
+fn semaphore_signal(env: &mut Environment, semaphore: semaphore_t) -> kern_return_t {
+    if !is_valid(env, semaphore, "semaphore_signal") {
+        return KERN_INVALID_ARGUMENT;
+    }
+    State::get(env).signal(semaphore, 1);
+    KERN_SUCCESS
+}
+
+fn semaphore_signal_all(env: &mut Environment, semaphore: semaphore_t) -> kern_return_t {
+    if !is_valid(env, semaphore, "semaphore_signal_all") {
+        return KERN_INVALID_ARGUMENT;
+    }
+    // Each waiting thread will consume one increment when it's woken.
+    let waiting: u32 = env
+        .count_blocked_threads(ThreadBlock::Semaphore(semaphore))
+        .try_into()
+        .unwrap();
+    State::get(env).signal(semaphore, waiting);
+    KERN_SUCCESS
+}
+
+fn semaphore_wait(env: &mut Environment, semaphore: semaphore_t) -> kern_return_t {
+    if !is_valid(env, semaphore, "semaphore_wait") {
+        return KERN_INVALID_ARGUMENT;
+    }
+    wait(env, semaphore, None);
+    KERN_SUCCESS // once the semaphore has been decremented, or KERN_TERMINATED
+}
+
+/// The `wait_time` argument is a `mach_timespec_t` passed by value, which
+/// occupies two registers.
+fn semaphore_timedwait(
+    env: &mut Environment,
+    semaphore: semaphore_t,
+    wait_time_sec: u32,
+    wait_time_nsec: i32,
+) -> kern_return_t {
+    if !is_valid(env, semaphore, "semaphore_timedwait") {
+        return KERN_INVALID_ARGUMENT;
+    }
+    let wait_time_nsec = match u32::try_from(wait_time_nsec) {
+        Ok(nsec) if nsec < 1_000_000_000 => nsec,
+        _ => return KERN_INVALID_VALUE,
+    };
+    let duration = Duration::new(wait_time_sec.into(), wait_time_nsec);
+    let timeout = Instant::now() + duration;
+    wait(
+        env,
+        semaphore,
+        Some((timeout, KERN_OPERATION_TIMED_OUT as u32)),
+    );
+    KERN_SUCCESS // unless the wait times out or the semaphore is destroyed
+}
+
+/// `mach_task_self()` is a macro that reads this variable.
+fn get_mach_task_self(mem: &mut Mem) -> ConstVoidPtr {
+    mem.alloc_and_write(MACH_TASK_SELF).cast().cast_const()
+}
+
+pub const CONSTANTS: ConstantExports = &[(
+    "_mach_task_self_",
+    HostConstant::Custom(|env| get_mach_task_self(&mut env.mem)),
+)];
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(sem_open(_, _, _)),
+    export_c_func!(sem_close(_)),
+    export_c_func!(sem_unlink(_)),
+    export_c_func!(sem_init(_, _, _)),
+    export_c_func!(sem_wait(_)),
+    export_c_func!(sem_trywait(_)),
+    export_c_func!(sem_post(_)),
+    export_c_func!(semaphore_create(_, _, _, _)),
+    export_c_func!(semaphore_destroy(_, _)),
+    export_c_func!(semaphore_signal(_)),
+    export_c_func!(semaphore_signal_all(_)),
+    export_c_func!(semaphore_wait(_)),
+    export_c_func!(semaphore_timedwait(_, _, _)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+
+    #[test]
+    fn test_semaphore_value() {
+        let mut state = State::default();
+        let sem = state.create(1);
+        assert!(state.try_decrement(sem));
+        assert!(!state.try_decrement(sem));
+        assert!(state.signal(sem, 2));
+        assert!(state.try_decrement(sem));
+        assert!(state.try_decrement(sem));
+        assert!(!state.try_decrement(sem));
+    }
+
+    #[test]
+    fn test_destroyed_semaphore() {
+        let mut state = State::default();
+        let sem1 = state.create(1);
+        assert!(state.destroy(sem1));
+        assert!(!state.destroy(sem1));
+        assert!(!state.try_decrement(sem1));
+        assert!(!state.signal(sem1, 1));
+        // IDs aren't reused.
+        let sem2 = state.create(1);
+        assert_ne!(sem1, sem2);
+        assert!(!state.try_decrement(sem1));
+        assert!(state.try_decrement(sem2));
+    }
+}
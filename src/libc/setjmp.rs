@@ -5,25 +5,110 @@
  */
 //! `setjmp.h`.
 //!
-//! We don't have a real implementation for this right now. It could be quite
-//! tricky to write one, considering that we would need to unwind through host
-//! code, and somehow do so selectively since we have a mix of stack frames from
-//! different guest threads. For the moment, we simply pray the app never throws
-//! exceptions.
-//!
 //! Note that `setjmp` and `longjmp` are defined as macros in the C standard,
 //! but it seems like the implementation of these on iPhone OS uses real
-//! functions, at least for the former.
+//! functions.
+//!
+//! Since these are host functions, they see the guest registers as they were
+//! at the call site, so saving and restoring them is enough to jump between
+//! guest stack frames. Unwinding host stack frames is not possible, so a
+//! `longjmp` that would need to do that (i.e. one that crosses a host function
+//! that called back into guest code, see [Environment::run_call]) causes a
+//! panic rather than corrupting the state of the emulator.
+//!
+//! There are no signals in touchHLE, so the signal mask variants behave like
+//! the plain ones.
 
+use crate::abi::GuestFunction;
+use crate::cpu::Cpu;
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{ConstPtr, MutPtr};
 use crate::Environment;
 
-/// The signature of this is incomplete because it's a stub (see module docs).
-fn setjmp(env: &mut Environment) {
-    log_dbg!(
-        "TODO: setjmp() at {:#x}",
-        env.cpu.regs()[crate::cpu::Cpu::LR]
-    );
+// Layout of a `jmp_buf` in our implementation, in 32-bit words. Apple's
+// `jmp_buf` is `int[10 + 16 + 2]` (`sigjmp_buf` has one more element), so
+// everything has to fit in 28 words.
+
+/// The callee-saved core registers `r4`–`r8`, `r10` and `r11`, and then `sp`
+/// and `lr`.
+const JB_CORE_REGS: u32 = 0;
+const CORE_REGS: [usize; 9] = [4, 5, 6, 7, 8, 10, 11, Cpu::SP, Cpu::LR];
+/// The [Environment::guest_call_depth] at the time of `setjmp`.
+const JB_GUEST_CALL_DEPTH: u32 = 9;
+/// The callee-saved VFP registers `d8`–`d15` (`s16`–`s31`).
+const JB_VFP_REGS: u32 = 10;
+const VFP_REGS: std::ops::Range<usize> = 16..32;
+const JB_FPSCR: u32 = 26;
+
+#[allow(non_camel_case_types)]
+type jmp_buf = MutPtr<u32>;
+
+fn setjmp(env: &mut Environment, buf: jmp_buf) -> i32 {
+    let regs = *env.cpu.regs();
+    for (i, &reg) in CORE_REGS.iter().enumerate() {
+        env.mem.write(buf + JB_CORE_REGS + i as u32, regs[reg]);
+    }
+    env.mem.write(buf + JB_GUEST_CALL_DEPTH, env.guest_call_depth());
+    let ext_regs = *env.cpu.ext_regs();
+    for (i, &reg) in ext_regs[VFP_REGS].iter().enumerate() {
+        env.mem.write(buf + JB_VFP_REGS + i as u32, reg);
+    }
+    env.mem.write(buf + JB_FPSCR, env.cpu.fpscr());
+    0
+}
+
+fn _setjmp(env: &mut Environment, buf: jmp_buf) -> i32 {
+    setjmp(env, buf)
+}
+
+fn sigsetjmp(env: &mut Environment, buf: jmp_buf, _savemask: i32) -> i32 {
+    setjmp(env, buf)
+}
+
+/// Returns a second time from the `setjmp` call that filled `buf`, with the
+/// return value `val` (or 1 if `val` is 0).
+///
+/// This doesn't return to its own caller. The registers are changed so that
+/// execution resumes after the `setjmp` call once this host function returns.
+fn longjmp(env: &mut Environment, buf: ConstPtr<u32>, val: i32) {
+    let depth = env.mem.read(buf + JB_GUEST_CALL_DEPTH);
+    if depth != env.guest_call_depth() {
+        panic!(
+            "longjmp() across host function stack frames is not supported (setjmp() was called at host-to-guest call depth {}, longjmp() at depth {})",
+            depth,
+            env.guest_call_depth(),
+        );
+    }
+
+    for (i, &reg) in CORE_REGS.iter().enumerate() {
+        env.cpu.regs_mut()[reg] = env.mem.read(buf + JB_CORE_REGS + i as u32);
+    }
+    for (i, reg) in VFP_REGS.enumerate() {
+        env.cpu.ext_regs_mut()[reg] = env.mem.read(buf + JB_VFP_REGS + i as u32);
+    }
+    let fpscr = env.mem.read(buf + JB_FPSCR);
+    env.cpu.set_fpscr(fpscr);
+
+    env.cpu.regs_mut()[0] = if val == 0 { 1 } else { val as u32 };
+    // The saved lr is the return address of the setjmp() call, including the
+    // Thumb bit if the caller was Thumb code.
+    let return_addr = env.cpu.regs()[Cpu::LR];
+    env.cpu.branch(GuestFunction::from_addr_with_thumb_bit(return_addr));
+}
+
+fn _longjmp(env: &mut Environment, buf: ConstPtr<u32>, val: i32) {
+    longjmp(env, buf, val)
+}
+
+fn siglongjmp(env: &mut Environment, buf: ConstPtr<u32>, val: i32) {
+    longjmp(env, buf, val)
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(setjmp())];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(setjmp(_)),
+    export_c_func!(_setjmp(_)),
+    export_c_func!(sigsetjmp(_, _)),
+    export_c_func!(longjmp(_, _)),
+    export_c_func!(_longjmp(_, _)),
+    export_c_func!(siglongjmp(_, _)),
+];
@@ -4,29 +4,297 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `stdio.h`
+//!
+//! `FILE` streams are implemented on top of the file descriptors from
+//! [posix_io], with their own buffering and end-of-file and error flags. The
+//! standard streams are unbuffered at this level, since [posix_io] already
+//! collects their output into lines for touchHLE's log.
 
-use super::posix_io::{self, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
-use crate::dyld::{export_c_func, FunctionExports};
-use crate::fs::GuestPath;
+use super::posix_io::{
+    self, FileDescriptor, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, STDERR_FILENO,
+    STDIN_FILENO, STDOUT_FILENO,
+};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::fs::{GuestPath, GuestPathBuf};
 use crate::libc::string::strlen;
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::mem::{
+    guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead,
+};
 use crate::Environment;
-use std::io::Write;
+use std::collections::{HashMap, VecDeque};
 
 // Standard C functions
 
 pub mod printf;
 
+#[derive(Default)]
+pub struct State {
+    files: HashMap<MutPtr<FILE>, FileHostObject>,
+    /// The `FILE`s for stdin, stdout and stderr, indexed by file descriptor.
+    /// These are shared by the guest (see [CONSTANTS]) and by host functions
+    /// like [puts] that implicitly use them. Allocated on first use.
+    standard_streams: Option<MutPtr<FILE>>,
+    /// Number of files created by [tmpfile] so far, used for their names.
+    tmpfile_count: u32,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.stdio
+    }
+}
+
 const EOF: i32 = -1;
+const BUFSIZ: usize = 1024;
+
+// Buffering modes for setvbuf()
+const _IOFBF: i32 = 0;
+const _IOLBF: i32 = 1;
+const _IONBF: i32 = 2;
+
+// Flags in FILE::flags that Apple's headers access directly
+const __SEOF: u16 = 0x20;
+const __SERR: u16 = 0x40;
 
 #[allow(clippy::upper_case_acronyms)]
-/// C `FILE` struct. This is an opaque type in C, so the definition here is our
-/// own.
-struct FILE {
-    fd: posix_io::FileDescriptor,
+/// C `FILE` struct. This is an opaque type in C, but macros in Apple's headers
+/// access some of its fields, and `__sF` is an array of them, so the size and
+/// those fields match Apple's. Everything else is stored on the host instead
+/// (see [FileHostObject]).
+#[repr(C, packed)]
+pub struct FILE {
+    /// Apple's `_p`, `_r` and `_w`: the position in the buffer and the number
+    /// of bytes that can be read from or written to it by the inline versions
+    /// of `getc_unlocked()` and `putc_unlocked()`. These are always zero, so
+    /// they call [__srget] and [__swbuf] instead.
+    _p: u32,
+    _r: i32,
+    _w: i32,
+    /// Apple's `_flags`. Only [__SEOF] and [__SERR] are kept up to date, for
+    /// the inline versions of `feof_unlocked()` and `ferror_unlocked()`.
+    flags: u16,
+    /// Apple's `_file`.
+    fd: i16,
+    /// Magic number (must be [MAGIC_FILE] or [MAGIC_FILE_STANDARD]). Apple's
+    /// `_bf._base` is here.
+    magic: u32,
+    _unused: [u32; 17],
 }
 unsafe impl SafeRead for FILE {}
 
+/// Arbitrarily-chosen magic number for `FILE` (not Apple's).
+const MAGIC_FILE: u32 = u32::from_be_bytes(*b"FILE");
+/// Arbitrarily-chosen magic number for the standard streams (see [CONSTANTS]),
+/// which are registered when they are first used.
+const MAGIC_FILE_STANDARD: u32 = u32::from_be_bytes(*b"STDF");
+
+struct FileHostObject {
+    fd: FileDescriptor,
+    /// [_IOFBF], [_IOLBF] or [_IONBF].
+    buffer_mode: i32,
+    /// Bytes read from the file descriptor ahead of time, preceded by any that
+    /// were pushed back with [ungetc].
+    read_buffer: VecDeque<u8>,
+    /// Bytes written that haven't been passed on to the file descriptor yet.
+    write_buffer: Vec<u8>,
+    eof: bool,
+    error: bool,
+    /// For [tmpfile]: the file to delete once it's closed.
+    delete_on_close: Option<GuestPathBuf>,
+}
+impl FileHostObject {
+    fn new(fd: FileDescriptor, buffer_mode: i32) -> Self {
+        FileHostObject {
+            fd,
+            buffer_mode,
+            read_buffer: VecDeque::new(),
+            write_buffer: Vec::new(),
+            eof: false,
+            error: false,
+            delete_on_close: None,
+        }
+    }
+}
+
+fn new_guest_file(fd: FileDescriptor, magic: u32) -> FILE {
+    FILE {
+        _p: 0,
+        _r: 0,
+        _w: 0,
+        flags: 0,
+        fd: fd.try_into().unwrap(),
+        magic,
+        _unused: [0; 17],
+    }
+}
+
+/// Create a new `FILE` for a file descriptor.
+fn new_file(env: &mut Environment, fd: FileDescriptor) -> MutPtr<FILE> {
+    let file = env.mem.alloc_and_write(new_guest_file(fd, MAGIC_FILE));
+    State::get(env)
+        .files
+        .insert(file, FileHostObject::new(fd, _IOFBF));
+    file
+}
+
+/// Get the host object for a `FILE`, registering it first if it's one of the
+/// standard streams and this is its first use.
+fn get_host_object(env: &mut Environment, file: MutPtr<FILE>) -> &mut FileHostObject {
+    if !State::get(env).files.contains_key(&file) {
+        let FILE { magic, fd, .. } = env.mem.read(file);
+        // Anything else is an invalid or closed FILE.
+        assert_eq!(magic, MAGIC_FILE_STANDARD);
+        log_dbg!("Registering standard stream {:?} (fd {}).", file, fd);
+        State::get(env)
+            .files
+            .insert(file, FileHostObject::new(fd.into(), _IONBF));
+    }
+    State::get(env).files.get_mut(&file).unwrap()
+}
+
+/// Get the array of `FILE`s for stdin, stdout and stderr, allocating it if
+/// this is the first use.
+fn standard_streams(env: &mut Environment) -> MutPtr<FILE> {
+    if let Some(files) = State::get(env).standard_streams {
+        return files;
+    }
+    let files: MutPtr<FILE> = env.mem.alloc(guest_size_of::<FILE>() * 3).cast();
+    for fd in [STDIN_FILENO, STDOUT_FILENO, STDERR_FILENO] {
+        env.mem.write(
+            files + fd as GuestUSize,
+            new_guest_file(fd, MAGIC_FILE_STANDARD),
+        );
+    }
+    State::get(env).standard_streams = Some(files);
+    files
+}
+
+/// Get the `FILE` for stdin, stdout or stderr.
+pub fn standard_stream(env: &mut Environment, fd: FileDescriptor) -> MutPtr<FILE> {
+    assert!((STDIN_FILENO..=STDERR_FILENO).contains(&fd));
+    standard_streams(env) + fd as GuestUSize
+}
+
+/// Copy the end-of-file and error flags to the guest `FILE`.
+fn update_flags(env: &mut Environment, file: MutPtr<FILE>) {
+    let host_object = get_host_object(env, file);
+    let mut flags = 0;
+    if host_object.eof {
+        flags |= __SEOF;
+    }
+    if host_object.error {
+        flags |= __SERR;
+    }
+    let mut guest_file = env.mem.read(file);
+    guest_file.flags = flags;
+    env.mem.write(file, guest_file);
+}
+
+/// Pass any buffered written bytes on to the file descriptor. Returns [false]
+/// on error.
+fn flush(env: &mut Environment, file: MutPtr<FILE>) -> bool {
+    let host_object = get_host_object(env, file);
+    if host_object.write_buffer.is_empty() {
+        return true;
+    }
+    let fd = host_object.fd;
+    let data = std::mem::take(&mut host_object.write_buffer);
+    if posix_io::write_direct(env, fd, &data) == Ok(data.len()) {
+        true
+    } else {
+        get_host_object(env, file).error = true;
+        update_flags(env, file);
+        false
+    }
+}
+
+/// Flush all open `FILE`s. This should be done before exiting.
+pub fn flush_all(env: &mut Environment) {
+    let files: Vec<_> = State::get(env).files.keys().copied().collect();
+    for file in files {
+        flush(env, file);
+    }
+    posix_io::flush_standard_output(env);
+}
+
+/// Throw away bytes that were read ahead, moving the file descriptor's
+/// position back to where the app thinks it is. This must be done before
+/// writing.
+fn discard_read_buffer(env: &mut Environment, file: MutPtr<FILE>) {
+    let host_object = get_host_object(env, file);
+    if host_object.read_buffer.is_empty() {
+        return;
+    }
+    let fd = host_object.fd;
+    let offset: i64 = host_object.read_buffer.len().try_into().unwrap();
+    host_object.read_buffer.clear();
+    // This fails for the standard streams, but there's no position to restore
+    // for those anyway.
+    posix_io::lseek(env, fd, -offset, posix_io::SEEK_CUR);
+}
+
+/// Read up to `count` bytes from a `FILE`. Fewer bytes are only returned at
+/// the end of the file or on error, which set the corresponding flag.
+fn read_from_file(env: &mut Environment, file: MutPtr<FILE>, count: usize) -> Vec<u8> {
+    let mut result = Vec::with_capacity(count);
+    if !flush(env, file) {
+        return result;
+    }
+    loop {
+        let host_object = get_host_object(env, file);
+        let from_buffer = host_object.read_buffer.len().min(count - result.len());
+        result.extend(host_object.read_buffer.drain(..from_buffer));
+        if result.len() == count {
+            break;
+        }
+
+        let fd = host_object.fd;
+        let mut buffer = vec![0u8; BUFSIZ.max(count - result.len())];
+        match posix_io::read_direct(env, fd, &mut buffer) {
+            Ok(0) => {
+                get_host_object(env, file).eof = true;
+                break;
+            }
+            Ok(bytes_read) => {
+                buffer.truncate(bytes_read);
+                get_host_object(env, file).read_buffer.extend(buffer);
+            }
+            Err(()) => {
+                get_host_object(env, file).error = true;
+                break;
+            }
+        }
+    }
+    update_flags(env, file);
+    result
+}
+
+/// Write bytes to a `FILE`. Returns [false] on error.
+pub fn write_to_file(env: &mut Environment, file: MutPtr<FILE>, data: &[u8]) -> bool {
+    discard_read_buffer(env, file);
+    let host_object = get_host_object(env, file);
+    host_object.write_buffer.extend_from_slice(data);
+    let must_flush = match host_object.buffer_mode {
+        _IONBF => true,
+        _IOLBF => data.contains(&b'\n') || host_object.write_buffer.len() >= BUFSIZ,
+        _ => host_object.write_buffer.len() >= BUFSIZ,
+    };
+    !must_flush || flush(env, file)
+}
+
+/// Read a single byte from a `FILE`, or [None] at the end of the file or on
+/// error.
+pub fn read_byte(env: &mut Environment, file: MutPtr<FILE>) -> Option<u8> {
+    read_from_file(env, file, 1).first().copied()
+}
+
+/// Push a byte back onto a `FILE`, so it will be the next one read.
+pub fn unread_byte(env: &mut Environment, file: MutPtr<FILE>, c: u8) {
+    let host_object = get_host_object(env, file);
+    host_object.read_buffer.push_front(c);
+    host_object.eof = false;
+    update_flags(env, file);
+}
+
 fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> MutPtr<FILE> {
     // all valid modes are UTF-8
     let flags = match env.mem.cstr_at_utf8(mode).unwrap() {
@@ -43,8 +311,33 @@ fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> M
 
     match posix_io::open_direct(env, filename, flags) {
         -1 => Ptr::null(),
-        fd => env.mem.alloc_and_write(FILE { fd }),
+        fd => new_file(env, fd),
+    }
+}
+
+fn tmpfile(env: &mut Environment) -> MutPtr<FILE> {
+    let tmp_dir = env.fs.home_directory().join("tmp");
+    let path = loop {
+        let state = State::get(env);
+        state.tmpfile_count += 1;
+        let path = tmp_dir.join(format!("tmpfile.{}", state.tmpfile_count));
+        if !env.fs.exists(&path) {
+            break path;
+        }
+    };
+
+    let path_ptr = env.mem.alloc_and_write_cstr(path.as_str().as_bytes());
+    let fd = posix_io::open_direct(env, path_ptr.cast_const(), O_RDWR | O_CREAT | O_TRUNC);
+    env.mem.free(path_ptr.cast());
+    if fd == -1 {
+        log!("Warning: tmpfile() failed to create {:?}", path);
+        return Ptr::null();
     }
+
+    let file = new_file(env, fd);
+    log_dbg!("tmpfile() => {:?} ({:?})", file, path);
+    get_host_object(env, file).delete_on_close = Some(path);
+    file
 }
 
 fn fread(
@@ -54,46 +347,107 @@ fn fread(
     n_items: GuestUSize,
     file_ptr: MutPtr<FILE>,
 ) -> GuestUSize {
-    let FILE { fd } = env.mem.read(file_ptr);
-
     // Yes, the item_size/n_items split doesn't mean anything. The C standard
     // really does expect you to just multiply and divide like this, with no
     // attempt being made to ensure a whole number are read or written!
     let total_size = item_size.checked_mul(n_items).unwrap();
-    match posix_io::read(env, fd, buffer, total_size) {
-        // TODO: ferror() support.
-        -1 => 0,
-        bytes_read => {
-            let bytes_read: GuestUSize = bytes_read.try_into().unwrap();
-            bytes_read / item_size
-        }
+    if total_size == 0 {
+        return 0;
     }
+    let data = read_from_file(env, file_ptr, total_size as usize);
+    let bytes_read: GuestUSize = data.len().try_into().unwrap();
+    env.mem
+        .bytes_at_mut(buffer.cast(), bytes_read)
+        .copy_from_slice(&data);
+    bytes_read / item_size
 }
 
 fn fgetc(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
-    let FILE { fd } = env.mem.read(file_ptr);
-    let buffer = env.mem.alloc(1);
-
-    match posix_io::read(env, fd, buffer, 1) {
-        -1 => EOF,
-        bytes_read => {
-            let bytes_read: GuestUSize = bytes_read.try_into().unwrap();
-            if bytes_read < 1 {
-                EOF
-            } else {
-                let buf: MutPtr<i32> = buffer.cast();
-                env.mem.read(buf)
-            }
+    match read_byte(env, file_ptr) {
+        Some(c) => c.into(),
+        None => EOF,
+    }
+}
+fn getc(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    fgetc(env, file_ptr)
+}
+fn getchar(env: &mut Environment) -> i32 {
+    let stdin = standard_stream(env, STDIN_FILENO);
+    fgetc(env, stdin)
+}
+/// Called by the inline version of `getc_unlocked()` when its buffer is empty.
+fn __srget(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    // The macro decrements this before calling, so reset it to stop it drifting.
+    let mut guest_file = env.mem.read(file_ptr);
+    guest_file._r = 0;
+    env.mem.write(file_ptr, guest_file);
+    fgetc(env, file_ptr)
+}
+
+fn ungetc(env: &mut Environment, c: i32, file_ptr: MutPtr<FILE>) -> i32 {
+    if c == EOF {
+        return EOF;
+    }
+    unread_byte(env, file_ptr, c as u8);
+    (c as u8).into()
+}
+
+fn fgets(env: &mut Environment, str: MutPtr<u8>, size: i32, file_ptr: MutPtr<FILE>) -> MutPtr<u8> {
+    if size <= 0 {
+        return Ptr::null(); // should also set errno to EINVAL
+    }
+    let mut line = Vec::new();
+    while line.len() < (size - 1) as usize {
+        let Some(c) = read_byte(env, file_ptr) else {
+            break;
+        };
+        line.push(c);
+        if c == b'\n' {
+            break;
         }
     }
+    // Nothing is stored if there was nothing to read.
+    if line.is_empty() && size > 1 {
+        return Ptr::null();
+    }
+    line.push(b'\0');
+    env.mem
+        .bytes_at_mut(str, line.len().try_into().unwrap())
+        .copy_from_slice(&line);
+    str
+}
+
+fn fputc(env: &mut Environment, c: i32, file_ptr: MutPtr<FILE>) -> i32 {
+    if write_to_file(env, file_ptr, &[c as u8]) {
+        (c as u8).into()
+    } else {
+        EOF
+    }
+}
+fn putc(env: &mut Environment, c: i32, file_ptr: MutPtr<FILE>) -> i32 {
+    fputc(env, c, file_ptr)
+}
+fn putchar(env: &mut Environment, c: i32) -> i32 {
+    let stdout = standard_stream(env, STDOUT_FILENO);
+    fputc(env, c, stdout)
+}
+/// Called by the inline version of `putc_unlocked()` when its buffer is full.
+fn __swbuf(env: &mut Environment, c: i32, file_ptr: MutPtr<FILE>) -> i32 {
+    // See __srget
+    let mut guest_file = env.mem.read(file_ptr);
+    guest_file._w = 0;
+    env.mem.write(file_ptr, guest_file);
+    fputc(env, c, file_ptr)
 }
 
 fn fputs(env: &mut Environment, str: ConstPtr<u8>, stream: MutPtr<FILE>) -> i32 {
-    // TODO: this function doesn't set errno or return EOF yet
     let str_len = strlen(env, str);
-    fwrite(env, str.cast(), str_len, 1, stream)
-        .try_into()
-        .unwrap()
+    let data = env.mem.bytes_at(str, str_len).to_vec();
+    if write_to_file(env, stream, &data) {
+        0
+    } else {
+        EOF
+    }
 }
 
 fn fwrite(
@@ -103,61 +457,154 @@ fn fwrite(
     n_items: GuestUSize,
     file_ptr: MutPtr<FILE>,
 ) -> GuestUSize {
-    let FILE { fd } = env.mem.read(file_ptr);
-
     // The comment about the item_size/n_items split in fread() applies here too
     let total_size = item_size.checked_mul(n_items).unwrap();
-    match posix_io::write(env, fd, buffer, total_size) {
-        // TODO: ferror() support.
-        -1 => 0,
-        bytes_written => {
-            let bytes_written: GuestUSize = bytes_written.try_into().unwrap();
-            bytes_written / item_size
-        }
+    if total_size == 0 {
+        return 0;
+    }
+    let data = env.mem.bytes_at(buffer.cast(), total_size).to_vec();
+    if write_to_file(env, file_ptr, &data) {
+        n_items
+    } else {
+        0
+    }
+}
+
+fn fflush(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    if file_ptr.is_null() {
+        flush_all(env);
+        return 0;
+    }
+    if flush(env, file_ptr) {
+        0
+    } else {
+        EOF
+    }
+}
+
+fn setvbuf(
+    env: &mut Environment,
+    file_ptr: MutPtr<FILE>,
+    buf: MutPtr<u8>,
+    mode: i32,
+    size: GuestUSize,
+) -> i32 {
+    log_dbg!("setvbuf({:?}, {:?}, {}, {:#x})", file_ptr, buf, mode, size);
+    if ![_IOFBF, _IOLBF, _IONBF].contains(&mode) {
+        return EOF; // should also set errno to EINVAL
     }
+    // The buffer is kept on the host, so the app's buffer and size are
+    // ignored.
+    if !flush(env, file_ptr) {
+        return EOF;
+    }
+    get_host_object(env, file_ptr).buffer_mode = mode;
+    0 // success
+}
+
+fn setbuf(env: &mut Environment, file_ptr: MutPtr<FILE>, buf: MutPtr<u8>) {
+    let mode = if buf.is_null() { _IONBF } else { _IOFBF };
+    setvbuf(env, file_ptr, buf, mode, BUFSIZ as GuestUSize);
 }
 
 const SEEK_SET: i32 = posix_io::SEEK_SET;
 const SEEK_CUR: i32 = posix_io::SEEK_CUR;
 const SEEK_END: i32 = posix_io::SEEK_END;
 fn fseek(env: &mut Environment, file_ptr: MutPtr<FILE>, offset: i32, whence: i32) -> i32 {
-    let FILE { fd } = env.mem.read(file_ptr);
+    if ![SEEK_SET, SEEK_CUR, SEEK_END].contains(&whence) {
+        log!("Warning: fseek() with invalid whence {}, returning -1", whence);
+        return -1; // should also set errno to EINVAL
+    }
+    if !flush(env, file_ptr) {
+        return -1;
+    }
+
+    let host_object = get_host_object(env, file_ptr);
+    let fd = host_object.fd;
+    let mut offset: i64 = offset.into();
+    if whence == SEEK_CUR {
+        // The file descriptor is ahead of the app's position by whatever has
+        // been read ahead.
+        offset -= i64::try_from(host_object.read_buffer.len()).unwrap();
+    }
+    host_object.read_buffer.clear();
+    host_object.eof = false;
+    update_flags(env, file_ptr);
 
-    assert!([SEEK_SET, SEEK_CUR, SEEK_END].contains(&whence));
-    match posix_io::lseek(env, fd, offset.into(), whence) {
+    match posix_io::lseek(env, fd, offset, whence) {
         -1 => -1,
         _cur_pos => 0,
     }
 }
 
 fn ftell(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
-    let FILE { fd } = env.mem.read(file_ptr);
-
+    let fd = get_host_object(env, file_ptr).fd;
     match posix_io::lseek(env, fd, 0, posix_io::SEEK_CUR) {
         -1 => -1,
-        // TODO: What's the correct behaviour if the position is beyond 2GiB?
-        cur_pos => cur_pos.try_into().unwrap(),
+        cur_pos => {
+            let host_object = get_host_object(env, file_ptr);
+            let cur_pos = cur_pos - i64::try_from(host_object.read_buffer.len()).unwrap()
+                + i64::try_from(host_object.write_buffer.len()).unwrap();
+            // TODO: What's the correct behaviour if the position is beyond 2GiB?
+            cur_pos.try_into().unwrap()
+        }
     }
 }
 
+fn rewind(env: &mut Environment, file_ptr: MutPtr<FILE>) {
+    fseek(env, file_ptr, 0, SEEK_SET);
+    clearerr(env, file_ptr);
+}
+
+fn feof(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    get_host_object(env, file_ptr).eof.into()
+}
+
+fn ferror(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    get_host_object(env, file_ptr).error.into()
+}
+
+fn clearerr(env: &mut Environment, file_ptr: MutPtr<FILE>) {
+    let host_object = get_host_object(env, file_ptr);
+    host_object.eof = false;
+    host_object.error = false;
+    update_flags(env, file_ptr);
+}
+
 fn fclose(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
-    let FILE { fd } = env.mem.read(file_ptr);
+    let flushed = flush(env, file_ptr);
+    let host_object = State::get(env).files.remove(&file_ptr).unwrap();
+
+    // The standard streams aren't allocated like other FILEs, so only their
+    // host objects are discarded.
+    let FILE { magic, .. } = env.mem.read(file_ptr);
+    if magic == MAGIC_FILE {
+        env.mem.write(file_ptr, new_guest_file(host_object.fd, 0));
+        env.mem.free(file_ptr.cast());
+    }
 
-    env.mem.free(file_ptr.cast());
+    let closed = posix_io::close(env, host_object.fd) == 0;
+    if let Some(path) = host_object.delete_on_close {
+        let _ = env.fs.remove(&path);
+    }
 
-    match posix_io::close(env, fd) {
-        0 => 0,
-        -1 => EOF,
-        _ => unreachable!(),
+    if flushed && closed {
+        0
+    } else {
+        EOF
     }
 }
 
 fn puts(env: &mut Environment, s: ConstPtr<u8>) -> i32 {
-    let _ = std::io::stdout().write_all(env.mem.cstr_at(s));
-    let _ = std::io::stdout().write_all(b"\n");
-    // TODO: I/O error handling
+    let mut data = env.mem.cstr_at(s).to_vec();
+    data.push(b'\n');
+    let stdout = standard_stream(env, STDOUT_FILENO);
     // TODO: is this the return value iPhone OS uses?
-    0
+    if write_to_file(env, stdout, &data) {
+        0
+    } else {
+        EOF
+    }
 }
 
 fn remove(env: &mut Environment, path: ConstPtr<u8>) -> i32 {
@@ -179,20 +626,65 @@ fn remove(env: &mut Environment, path: ConstPtr<u8>) -> i32 {
 
 // POSIX-specific functions
 
-fn fileno(env: &mut Environment, file_ptr: MutPtr<FILE>) -> posix_io::FileDescriptor {
-    let FILE { fd } = env.mem.read(file_ptr);
-    fd
+fn fileno(env: &mut Environment, file_ptr: MutPtr<FILE>) -> FileDescriptor {
+    get_host_object(env, file_ptr).fd
+}
+
+/// `__sF`, Apple's array of the three standard streams, used by the `stdin`,
+/// `stdout` and `stderr` macros in older SDKs.
+fn get_sf(env: &mut Environment) -> ConstVoidPtr {
+    standard_streams(env).cast().cast_const()
 }
 
+/// `__stdinp` etc are pointers to the standard streams, used by the `stdin`
+/// etc macros in newer SDKs.
+fn get_standard_stream_pointer(env: &mut Environment, fd: FileDescriptor) -> ConstVoidPtr {
+    let file = standard_stream(env, fd);
+    env.mem.alloc_and_write(file).cast().cast_const()
+}
+
+pub const CONSTANTS: ConstantExports = &[
+    ("___sF", HostConstant::Custom(get_sf)),
+    (
+        "___stdinp",
+        HostConstant::Custom(|env| get_standard_stream_pointer(env, STDIN_FILENO)),
+    ),
+    (
+        "___stdoutp",
+        HostConstant::Custom(|env| get_standard_stream_pointer(env, STDOUT_FILENO)),
+    ),
+    (
+        "___stderrp",
+        HostConstant::Custom(|env| get_standard_stream_pointer(env, STDERR_FILENO)),
+    ),
+];
+
 pub const FUNCTIONS: FunctionExports = &[
     // Standard C functions
     export_c_func!(fopen(_, _)),
+    export_c_func!(tmpfile()),
     export_c_func!(fread(_, _, _, _)),
     export_c_func!(fgetc(_)),
+    export_c_func!(getc(_)),
+    export_c_func!(getchar()),
+    export_c_func!(__srget(_)),
+    export_c_func!(ungetc(_, _)),
+    export_c_func!(fgets(_, _, _)),
+    export_c_func!(fputc(_, _)),
+    export_c_func!(putc(_, _)),
+    export_c_func!(putchar(_)),
+    export_c_func!(__swbuf(_, _)),
     export_c_func!(fputs(_, _)),
     export_c_func!(fwrite(_, _, _, _)),
+    export_c_func!(fflush(_)),
+    export_c_func!(setvbuf(_, _, _, _)),
+    export_c_func!(setbuf(_, _)),
     export_c_func!(fseek(_, _, _)),
     export_c_func!(ftell(_)),
+    export_c_func!(rewind(_)),
+    export_c_func!(feof(_)),
+    export_c_func!(ferror(_)),
+    export_c_func!(clearerr(_)),
     export_c_func!(fclose(_)),
     export_c_func!(puts(_)),
     export_c_func!(remove(_)),
@@ -8,6 +8,8 @@
 use crate::abi::{DotDotDot, VaList};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::foundation::ns_string;
+use crate::libc::posix_io::STDOUT_FILENO;
+use crate::libc::stdio::{self, FILE};
 use crate::mem::{ConstPtr, GuestUSize, Mem, MutPtr};
 use crate::objc::{id, msg};
 use crate::Environment;
@@ -217,42 +219,147 @@ fn printf(env: &mut Environment, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
         env.mem.cstr_at_utf8(format)
     );
 
-    let res = printf_inner::<false, _>(env, |mem, idx| mem.read(format + idx), args.start());
-    // TODO: I/O error handling
-    let _ = std::io::stdout().write_all(&res);
-    res.len().try_into().unwrap()
+    let stdout = stdio::standard_stream(env, STDOUT_FILENO);
+    fprintf_inner(env, stdout, format, args.start())
 }
 
-// TODO: more printf variants
+fn vprintf(env: &mut Environment, format: ConstPtr<u8>, arg: VaList) -> i32 {
+    log_dbg!(
+        "vprintf({:?} ({:?}), ...)",
+        format,
+        env.mem.cstr_at_utf8(format)
+    );
 
-fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
+    let stdout = stdio::standard_stream(env, STDOUT_FILENO);
+    fprintf_inner(env, stdout, format, arg)
+}
+
+fn fprintf(
+    env: &mut Environment,
+    file: MutPtr<FILE>,
+    format: ConstPtr<u8>,
+    args: DotDotDot,
+) -> i32 {
     log_dbg!(
-        "sscanf({:?}, {:?} ({:?}), ...)",
-        src,
+        "fprintf({:?}, {:?} ({:?}), ...)",
+        file,
         format,
         env.mem.cstr_at_utf8(format)
     );
 
-    let mut args = args.start();
+    fprintf_inner(env, file, format, args.start())
+}
+
+fn vfprintf(env: &mut Environment, file: MutPtr<FILE>, format: ConstPtr<u8>, arg: VaList) -> i32 {
+    log_dbg!(
+        "vfprintf({:?}, {:?} ({:?}), ...)",
+        file,
+        format,
+        env.mem.cstr_at_utf8(format)
+    );
+
+    fprintf_inner(env, file, format, arg)
+}
+
+/// Shared part of the `printf` variants that write to a `FILE`.
+fn fprintf_inner(
+    env: &mut Environment,
+    file: MutPtr<FILE>,
+    format: ConstPtr<u8>,
+    args: VaList,
+) -> i32 {
+    let res = printf_inner::<false, _>(env, |mem, idx| mem.read(format + idx), args);
+    if stdio::write_to_file(env, file, &res) {
+        res.len().try_into().unwrap()
+    } else {
+        -1
+    }
+}
+
+/// Why a `scanf` conversion failed, which decides what the function returns.
+#[derive(Debug, PartialEq, Eq)]
+enum ScanFailure {
+    /// The input ran out. `EOF` is returned if nothing was converted yet.
+    Input,
+    /// The input didn't match the format.
+    Matching,
+}
+
+/// Consume any whitespace at the start of the input. See [scanf_inner] for
+/// `get_char`. This and [scan_decimal] take a generic context rather than an
+/// [Environment] so they can be tested on their own.
+fn skip_whitespace<C, F: FnMut(&mut C, bool) -> u8>(ctx: &mut C, get_char: &mut F) {
+    while get_char(ctx, /* consume: */ false).is_ascii_whitespace() {
+        get_char(ctx, /* consume: */ true);
+    }
+}
+
+/// Read an optionally signed decimal integer, skipping leading whitespace, as
+/// `%d` does. Overflow wraps around.
+fn scan_decimal<C, F: FnMut(&mut C, bool) -> u8>(
+    ctx: &mut C,
+    get_char: &mut F,
+) -> Result<i32, ScanFailure> {
+    skip_whitespace(ctx, get_char);
+
+    let mut c = get_char(ctx, /* consume: */ false);
+    if c == b'\0' {
+        return Err(ScanFailure::Input);
+    }
+    let negative = c == b'-';
+    if c == b'-' || c == b'+' {
+        get_char(ctx, /* consume: */ true);
+        c = get_char(ctx, /* consume: */ false);
+    }
+    if !c.is_ascii_digit() {
+        return Err(ScanFailure::Matching);
+    }
 
-    let mut src_ptr = src.cast_mut();
+    let mut val: i32 = 0;
+    while let c @ b'0'..=b'9' = get_char(ctx, /* consume: */ false) {
+        val = val.wrapping_mul(10).wrapping_add((c - b'0') as i32);
+        get_char(ctx, /* consume: */ true);
+    }
+    Ok(if negative { val.wrapping_neg() } else { val })
+}
+
+/// `scanf` function family implementation.
+///
+/// `get_char` is a callback that returns the next byte of the input, or `'\0'`
+/// at the end of the input. The byte is only consumed if its second argument is
+/// [true].
+fn scanf_inner<F: FnMut(&mut Environment, bool) -> u8>(
+    env: &mut Environment,
+    format: ConstPtr<u8>,
+    mut get_char: F,
+    mut args: VaList,
+) -> i32 {
     let mut format_char_idx = 0;
 
     let mut matched_args = 0;
 
-    loop {
+    let failure = loop {
         let c = env.mem.read(format + format_char_idx);
         format_char_idx += 1;
 
         if c == b'\0' {
-            break;
+            break None;
+        }
+        if c.is_ascii_whitespace() {
+            // Whitespace in the format matches any amount of whitespace in the
+            // input, including none.
+            skip_whitespace(env, &mut get_char);
+            continue;
         }
         if c != b'%' {
-            let cc = env.mem.read(src_ptr);
+            let cc = get_char(env, /* consume: */ false);
+            if cc == b'\0' {
+                break Some(ScanFailure::Input);
+            }
             if c != cc {
-                return matched_args - 1;
+                break Some(ScanFailure::Matching);
             }
-            src_ptr += 1;
+            get_char(env, /* consume: */ true);
             continue;
         }
 
@@ -260,29 +367,109 @@ fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args:
         format_char_idx += 1;
 
         match specifier {
-            b'd' => {
-                let mut val: i32 = 0;
-                while let c @ b'0'..=b'9' = env.mem.read(src_ptr) {
-                    val = val * 10 + (c - b'0') as i32;
-                    src_ptr += 1;
+            b'd' => match scan_decimal(env, &mut get_char) {
+                Ok(val) => {
+                    let c_int_ptr: ConstPtr<i32> = args.next(env);
+                    env.mem.write(c_int_ptr.cast_mut(), val);
                 }
-                let c_int_ptr: ConstPtr<i32> = args.next(env);
-                env.mem.write(c_int_ptr.cast_mut(), val);
-            }
+                Err(failure) => break Some(failure),
+            },
             // TODO: more specifiers
-            _ => unimplemented!("Format character '{}'", specifier as char),
+            _ => {
+                log!(
+                    "TODO: scanf format character '{}', stopping after {} conversions",
+                    specifier as char,
+                    matched_args
+                );
+                break Some(ScanFailure::Matching);
+            }
         }
 
         matched_args += 1;
+    };
+
+    if failure == Some(ScanFailure::Input) && matched_args == 0 {
+        stdio::EOF
+    } else {
+        matched_args
     }
+}
 
-    matched_args
+fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
+    log_dbg!(
+        "sscanf({:?}, {:?} ({:?}), ...)",
+        src,
+        format,
+        env.mem.cstr_at_utf8(format)
+    );
+
+    let mut src_ptr = src;
+    let get_char = |env: &mut Environment, consume: bool| {
+        let c = env.mem.read(src_ptr);
+        if consume {
+            src_ptr += 1;
+        }
+        c
+    };
+    scanf_inner(env, format, get_char, args.start())
+}
+
+fn fscanf(env: &mut Environment, file: MutPtr<FILE>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
+    log_dbg!(
+        "fscanf({:?}, {:?} ({:?}), ...)",
+        file,
+        format,
+        env.mem.cstr_at_utf8(format)
+    );
+
+    let get_char = |env: &mut Environment, consume: bool| match stdio::read_byte(env, file) {
+        Some(c) => {
+            if !consume {
+                stdio::unread_byte(env, file, c);
+            }
+            c
+        }
+        None => b'\0',
+    };
+    scanf_inner(env, format, get_char, args.start())
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(sscanf(_, _, _)),
+    export_c_func!(fscanf(_, _, _)),
     export_c_func!(vsnprintf(_, _, _, _)),
     export_c_func!(vsprintf(_, _, _)),
     export_c_func!(sprintf(_, _, _)),
     export_c_func!(printf(_, _)),
+    export_c_func!(vprintf(_, _)),
+    export_c_func!(fprintf(_, _, _)),
+    export_c_func!(vfprintf(_, _, _)),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::{scan_decimal, ScanFailure};
+
+    fn scan_decimal_str(input: &[u8]) -> (Result<i32, ScanFailure>, usize) {
+        let mut idx = 0;
+        let res = scan_decimal(&mut idx, &mut |idx: &mut usize, consume| {
+            let c = input.get(*idx).copied().unwrap_or(b'\0');
+            if consume {
+                *idx += 1;
+            }
+            c
+        });
+        (res, idx)
+    }
+
+    #[test]
+    fn test_scan_decimal() {
+        assert_eq!(scan_decimal_str(b"123abc"), (Ok(123), 3));
+        assert_eq!(scan_decimal_str(b" \t\n-42,"), (Ok(-42), 6));
+        assert_eq!(scan_decimal_str(b"+7"), (Ok(7), 2));
+        assert_eq!(scan_decimal_str(b"abc"), (Err(ScanFailure::Matching), 0));
+        assert_eq!(scan_decimal_str(b"-x"), (Err(ScanFailure::Matching), 1));
+        assert_eq!(scan_decimal_str(b""), (Err(ScanFailure::Input), 0));
+        assert_eq!(scan_decimal_str(b"  "), (Err(ScanFailure::Input), 2));
+    }
+}
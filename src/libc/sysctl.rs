@@ -4,10 +4,137 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `sys/sysctl.h`
+//!
+//! Only a few read-only values are provided, mostly describing the device
+//! selected with `--device=` (see [crate::device]).
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{GuestUSize, MutPtr, MutVoidPtr};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr};
 use crate::Environment;
+use std::time::SystemTime;
+
+// Top-level identifiers
+const CTL_KERN: i32 = 1;
+const CTL_HW: i32 = 6;
+
+// Second-level identifiers for CTL_KERN
+const KERN_OSTYPE: i32 = 1;
+const KERN_OSRELEASE: i32 = 2;
+const KERN_BOOTTIME: i32 = 21;
+const KERN_OSVERSION: i32 = 65;
+
+// Second-level identifiers for CTL_HW
+const HW_MACHINE: i32 = 1;
+const HW_MODEL: i32 = 2;
+const HW_NCPU: i32 = 3;
+const HW_PHYSMEM: i32 = 5;
+const HW_USERMEM: i32 = 6;
+const HW_PAGESIZE: i32 = 7;
+const HW_CPU_FREQ: i32 = 15;
+const HW_MEMSIZE: i32 = 24;
+const HW_AVAILCPU: i32 = 25;
+
+/// The supported values: name, MIB (numeric name) and a function producing the
+/// value's bytes.
+type SysctlEntry = (&'static str, [i32; 2], fn(&Environment) -> Vec<u8>);
+
+fn string_value(value: &str) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(b'\0');
+    bytes
+}
+
+const ENTRIES: &[SysctlEntry] = &[
+    ("kern.ostype", [CTL_KERN, KERN_OSTYPE], |_| {
+        string_value("Darwin")
+    }),
+    ("kern.osrelease", [CTL_KERN, KERN_OSRELEASE], |env| {
+        string_value(env.options.device.darwin_version)
+    }),
+    ("kern.boottime", [CTL_KERN, KERN_BOOTTIME], |env| {
+        // struct timeval. The emulated device boots when touchHLE starts.
+        let boot_time = SystemTime::now() - env.startup_time.elapsed();
+        let since_epoch = boot_time.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        let tv_sec = since_epoch.as_secs() as i32;
+        let tv_usec = since_epoch.subsec_micros() as i32;
+        [tv_sec.to_le_bytes(), tv_usec.to_le_bytes()].concat()
+    }),
+    ("kern.osversion", [CTL_KERN, KERN_OSVERSION], |env| {
+        string_value(env.options.device.os_build)
+    }),
+    ("hw.machine", [CTL_HW, HW_MACHINE], |env| {
+        string_value(env.options.device.machine)
+    }),
+    ("hw.model", [CTL_HW, HW_MODEL], |env| {
+        string_value(env.options.device.model)
+    }),
+    ("hw.ncpu", [CTL_HW, HW_NCPU], |env| {
+        env.options.device.cpu_count.to_le_bytes().to_vec()
+    }),
+    ("hw.activecpu", [CTL_HW, HW_AVAILCPU], |env| {
+        env.options.device.cpu_count.to_le_bytes().to_vec()
+    }),
+    ("hw.physmem", [CTL_HW, HW_PHYSMEM], |env| {
+        env.options.device.physical_memory.to_le_bytes().to_vec()
+    }),
+    ("hw.usermem", [CTL_HW, HW_USERMEM], |env| {
+        env.options.device.physical_memory.to_le_bytes().to_vec()
+    }),
+    ("hw.memsize", [CTL_HW, HW_MEMSIZE], |env| {
+        u64::from(env.options.device.physical_memory)
+            .to_le_bytes()
+            .to_vec()
+    }),
+    ("hw.pagesize", [CTL_HW, HW_PAGESIZE], |_| {
+        4096u32.to_le_bytes().to_vec()
+    }),
+    ("hw.cpufrequency", [CTL_HW, HW_CPU_FREQ], |env| {
+        env.options.device.cpu_frequency.to_le_bytes().to_vec()
+    }),
+];
+
+/// Shared implementation of [sysctl] and [sysctlbyname] once the entry has
+/// been found.
+fn read_value(
+    env: &mut Environment,
+    entry: &SysctlEntry,
+    oldp: MutVoidPtr,
+    oldlenp: MutPtr<GuestUSize>,
+    newp: ConstVoidPtr,
+) -> i32 {
+    let (name, _, get_value) = entry;
+    if !newp.is_null() {
+        log!(
+            "Warning: attempt to set read-only sysctl {:?}, returning -1",
+            name
+        );
+        return -1; // should also set errno to EPERM
+    }
+
+    let value = get_value(env);
+    let value_len: GuestUSize = value.len().try_into().unwrap();
+    if oldlenp.is_null() {
+        return 0; // success
+    }
+    if oldp.is_null() {
+        // The app is asking for the size of the value.
+        env.mem.write(oldlenp, value_len);
+        return 0; // success
+    }
+    if env.mem.read(oldlenp) < value_len {
+        log!(
+            "Warning: buffer too small for sysctl {:?}, returning -1",
+            name
+        );
+        return -1; // should also set errno to ENOMEM
+    }
+    env.mem
+        .bytes_at_mut(oldp.cast(), value_len)
+        .copy_from_slice(&value);
+    env.mem.write(oldlenp, value_len);
+    log_dbg!("sysctl {:?} => {:?}", name, value);
+    0 // success
+}
 
 fn sysctl(
     env: &mut Environment,
@@ -16,21 +143,40 @@ fn sysctl(
     oldp: MutVoidPtr,
     oldlenp: MutPtr<GuestUSize>,
     newp: MutVoidPtr,
-    newlen: GuestUSize,
+    _newlen: GuestUSize,
 ) -> i32 {
-    log!(
-        "TODO: sysctl({:?}, {:#x}, {:?}, {:?}, {:?}, {:x})",
-        name,
-        name_len,
-        oldp,
-        oldlenp,
-        newp,
-        newlen
-    );
-    assert!(!oldp.is_null() && !oldlenp.is_null()); // TODO
-    assert!(newp.is_null()); // TODO
-    env.mem.write(oldlenp, 0);
-    0 // success
+    let mib: Vec<i32> = (0..name_len).map(|i| env.mem.read(name + i)).collect();
+    let Some(entry) = ENTRIES
+        .iter()
+        .find(|(_, entry_mib, _)| entry_mib[..] == mib)
+    else {
+        log!("Warning: unknown sysctl {:?}, returning -1", mib);
+        return -1; // should also set errno to ENOENT
+    };
+    read_value(env, entry, oldp, oldlenp, newp.cast_const())
+}
+
+fn sysctlbyname(
+    env: &mut Environment,
+    name: ConstPtr<u8>,
+    oldp: MutVoidPtr,
+    oldlenp: MutPtr<GuestUSize>,
+    newp: MutVoidPtr,
+    _newlen: GuestUSize,
+) -> i32 {
+    // A name that isn't valid UTF-8 can't match any entry.
+    let name = String::from_utf8_lossy(env.mem.cstr_at(name));
+    let Some(entry) = ENTRIES
+        .iter()
+        .find(|(entry_name, _, _)| *entry_name == name)
+    else {
+        log!("Warning: unknown sysctl {:?}, returning -1", name);
+        return -1; // should also set errno to ENOENT
+    };
+    read_value(env, entry, oldp, oldlenp, newp.cast_const())
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(sysctl(_, _, _, _, _, _))];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(sysctl(_, _, _, _, _, _)),
+    export_c_func!(sysctlbyname(_, _, _, _, _)),
+];
@@ -32,14 +32,17 @@ macro_rules! log_dbg {
 macro_rules! echo {
     ($($arg:tt)+) => {
         {
+            let line = format!($($arg)+);
+            $crate::log::remember_line(&line);
             #[cfg(target_os = "android")]
-            sdl2::log::log(&format!($($arg)+));
+            sdl2::log::log(&line);
             #[cfg(not(target_os = "android"))]
-            eprintln!($($arg)+);
+            eprintln!("{}", line);
         }
     };
     () => {
         {
+            $crate::log::remember_line("");
             #[cfg(target_os = "android")]
             sdl2::log::log("");
             #[cfg(not(target_os = "android"))]
@@ -51,3 +54,32 @@ macro_rules! echo {
 /// Put modules to enable [log_dbg] for here, e.g. "touchHLE::mem" to see when
 /// memory is allocated and freed.
 pub const ENABLED_MODULES: &[&str] = &[];
+
+/// Number of lines kept by [remember_line] for crash reports.
+const RECENT_LINES_LIMIT: usize = 200;
+
+static RECENT_LINES: std::sync::Mutex<std::collections::VecDeque<String>> =
+    std::sync::Mutex::new(std::collections::VecDeque::new());
+
+/// Keep a copy of an output line, so that the most recent output can be
+/// included in a crash report. Used by [echo].
+pub fn remember_line(line: &str) {
+    // A panic while holding the lock shouldn't stop logging.
+    let mut lines = RECENT_LINES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if lines.len() == RECENT_LINES_LIMIT {
+        lines.pop_front();
+    }
+    lines.push_back(line.to_string());
+}
+
+/// Get the most recent output lines, oldest first.
+pub fn recent_lines() -> Vec<String> {
+    RECENT_LINES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .cloned()
+        .collect()
+}
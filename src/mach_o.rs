@@ -40,6 +40,9 @@ pub struct MachO {
     /// can look things up quickly. Thumb function symbols always have the Thumb
     /// bit set.
     pub exported_symbols: HashMap<String, u32>,
+    /// All defined symbols (local and external), sorted by address, for
+    /// symbolicating addresses in debugging output. The Thumb bit is never set.
+    pub symbols: Vec<(u32, String)>,
     /// List of addresses and names of external relocations for the dynamic
     /// linker to resolve.
     pub external_relocations: Vec<(u32, String)>,
@@ -252,6 +255,7 @@ impl MachO {
         // Info used for the result
         let mut dynamic_libraries = Vec::new();
         let mut exported_symbols = HashMap::new();
+        let mut all_symbols = Vec::new();
         let mut indirect_undef_symbols: Vec<Option<String>> = Vec::new();
         let mut external_relocations: Vec<(u32, String)> = Vec::new();
         let mut entry_point_pc: Option<u32> = None;
@@ -328,6 +332,14 @@ impl MachO {
                             if let Symbol::Debug { .. } = symbol {
                                 continue;
                             }
+                            if let Symbol::Defined {
+                                name: Some(name),
+                                entry,
+                                ..
+                            } = symbol
+                            {
+                                all_symbols.push((entry.try_into().unwrap(), name.to_string()));
+                            }
                             if let Symbol::Defined {
                                 name: Some(name),
                                 external: true,
@@ -493,11 +505,14 @@ impl MachO {
             })
             .collect();
 
+        all_symbols.sort();
+
         Ok(MachO {
             name,
             dynamic_libraries,
             sections,
             exported_symbols,
+            symbols: all_symbols,
             external_relocations,
             entry_point_pc,
         })
@@ -524,4 +539,25 @@ impl MachO {
     pub fn get_section<P: SectionPredicate>(&self, by: P) -> Option<&Section> {
         self.sections.iter().find(|section| by.test(section))
     }
+
+    /// Returns [true] if the address is within one of the binary's sections.
+    pub fn contains_addr(&self, addr: u32) -> bool {
+        self.sections
+            .iter()
+            .any(|section| addr.wrapping_sub(section.addr) < section.size)
+    }
+
+    /// Find the symbol at or preceding an address, if the address is within
+    /// the binary, and return its name and the offset from it.
+    pub fn symbolicate(&self, addr: u32) -> Option<(&str, u32)> {
+        if !self.contains_addr(addr) {
+            return None;
+        }
+        let idx = self
+            .symbols
+            .partition_point(|&(symbol_addr, _)| symbol_addr <= addr)
+            .checked_sub(1)?;
+        let (symbol_addr, ref name) = self.symbols[idx];
+        Some((name, addr - symbol_addr))
+    }
 }
@@ -191,6 +191,22 @@ impl<T: SafeRead> SafeWrite for T {}
 
 type Bytes = [u8; 1 << 32];
 
+/// Kind of guest memory access that triggers a [Watchpoint].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+/// Range of guest memory watched by the debugger, see [crate::gdb].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: GuestUSize,
+    pub size: GuestUSize,
+    pub kind: WatchKind,
+}
+
 /// The type that owns the guest memory and provides accessors for it.
 pub struct Mem {
     /// This array is 4GiB in size so that it can cover the entire 32-bit
@@ -217,6 +233,14 @@ pub struct Mem {
     bytes: *mut Bytes,
 
     allocator: allocator::Allocator,
+
+    /// Watchpoints set by the debugger. These are only checked for accesses
+    /// made by the CPU emulation via its memory callbacks, so direct memory
+    /// access must be disabled for the watched pages, see
+    /// [crate::cpu::Cpu::set_page_direct_access].
+    watchpoints: Vec<Watchpoint>,
+    /// The watchpoint that stopped the CPU and the address that was accessed.
+    watchpoint_hit: Option<(Watchpoint, GuestUSize)>,
 }
 
 impl Drop for Mem {
@@ -260,7 +284,12 @@ impl Mem {
 
         let allocator = allocator::Allocator::new();
 
-        Mem { bytes, allocator }
+        Mem {
+            bytes,
+            allocator,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+        }
     }
 
     /// Get a pointer to the full 4GiB of memory. This is only for use when
@@ -290,6 +319,56 @@ impl Mem {
         )
     }
 
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+    /// Returns [false] if there was no such watchpoint.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&w| w != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    /// Check whether a CPU access of `size` bytes at `addr` triggers a
+    /// watchpoint. If it does, the hit is recorded so it can be retrieved with
+    /// [Self::take_watchpoint_hit], and [true] is returned. The access should
+    /// then not be performed, so that it can be retried once the debugger
+    /// resumes execution.
+    pub fn check_watchpoints(
+        &mut self,
+        addr: GuestUSize,
+        size: GuestUSize,
+        is_write: bool,
+    ) -> bool {
+        if self.watchpoints.is_empty() {
+            return false;
+        }
+        let hit = self.watchpoints.iter().find(|w| {
+            let kind_matches = match w.kind {
+                WatchKind::Write => is_write,
+                WatchKind::Read => !is_write,
+                WatchKind::Access => true,
+            };
+            kind_matches
+                && u64::from(addr) < u64::from(w.addr) + u64::from(w.size)
+                && u64::from(w.addr) < u64::from(addr) + u64::from(size)
+        });
+        if let Some(&hit) = hit {
+            self.watchpoint_hit = Some((hit, addr));
+            true
+        } else {
+            false
+        }
+    }
+    pub fn take_watchpoint_hit(&mut self) -> Option<(Watchpoint, GuestUSize)> {
+        self.watchpoint_hit.take()
+    }
+
     /// Special version of [Self::bytes_at] that returns [None] rather than
     /// panicking on failure. Only for use by [crate::gdb::GdbServer].
     pub fn get_bytes_fallible(&self, addr: ConstVoidPtr, count: GuestUSize) -> Option<&[u8]> {
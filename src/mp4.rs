@@ -0,0 +1,457 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Demuxing of MPEG-4 (ISO base media file format) and QuickTime files.
+//!
+//! Only the parts needed to find the samples of each track and their formats
+//! are parsed. Edit lists and fragmented files are not supported. This is used
+//! for both movies ([crate::video]) and `.m4a` audio files ([crate::audio]).
+//!
+//! Resources:
+//! - ISO/IEC 14496-12 (ISO base media file format)
+//! - ISO/IEC 14496-15 (AVC file format)
+//! - Apple's [QuickTime File Format Specification](https://developer.apple.com/library/archive/documentation/QuickTime/QTFF/QTFFPreface/qtffPreface.html)
+
+/// Big-endian reader over a box's contents.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..count))
+            .ok_or_else(|| "Unexpected end of box".to_string())?;
+        self.pos += count;
+        Ok(bytes)
+    }
+    fn skip(&mut self, count: usize) -> Result<(), String> {
+        self.bytes(count).map(|_| ())
+    }
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    fn fourcc(&mut self) -> Result<[u8; 4], String> {
+        Ok(self.bytes(4)?.try_into().unwrap())
+    }
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        self.pos = self.data.len();
+        rest
+    }
+}
+
+/// Type and contents of a box.
+type Mp4Box<'a> = ([u8; 4], &'a [u8]);
+
+/// Split data into boxes (QuickTime calls them atoms), returning their types
+/// and contents.
+fn parse_boxes(data: &[u8]) -> Result<Vec<Mp4Box<'_>>, String> {
+    let mut boxes = Vec::new();
+    let mut reader = Reader::new(data);
+    while reader.pos + 8 <= data.len() {
+        let start = reader.pos;
+        let size = reader.u32()?;
+        let box_type = reader.fourcc()?;
+        let size = match size {
+            0 => (data.len() - start) as u64,
+            1 => reader.u64()?,
+            _ => size.into(),
+        };
+        let header_size = (reader.pos - start) as u64;
+        if size < header_size || start as u64 + size > data.len() as u64 {
+            return Err(format!(
+                "Invalid size for box {:?}",
+                String::from_utf8_lossy(&box_type)
+            ));
+        }
+        let body = reader.bytes((size - header_size) as usize)?;
+        boxes.push((box_type, body));
+    }
+    Ok(boxes)
+}
+
+fn find_box<'a>(boxes: &[Mp4Box<'a>], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes
+        .iter()
+        .find(|(found_type, _)| found_type == box_type)
+        .map(|&(_, body)| body)
+}
+
+fn require_box<'a>(boxes: &[Mp4Box<'a>], box_type: &[u8; 4]) -> Result<&'a [u8], String> {
+    find_box(boxes, box_type)
+        .ok_or_else(|| format!("Missing {:?} box", String::from_utf8_lossy(box_type)))
+}
+
+#[derive(Debug)]
+pub struct Sample {
+    /// Byte offset within the file.
+    pub offset: u64,
+    pub size: u32,
+    /// Presentation time in the track's timescale.
+    pub time: u64,
+}
+
+#[derive(Debug)]
+pub enum TrackFormat {
+    /// H.264 video (`avc1`).
+    Avc {
+        /// Size of the length prefix of each NAL unit in a sample.
+        nal_length_size: usize,
+        /// SPS and PPS NAL units.
+        parameter_sets: Vec<Vec<u8>>,
+    },
+    Audio {
+        format_id: [u8; 4],
+        channels: u16,
+        bits_per_channel: u16,
+        sample_rate: f64,
+        /// For QuickTime version 1 and 2 sound descriptions: the size of a
+        /// packet for all channels, and the number of frames in it.
+        bytes_per_frame: Option<u32>,
+        frames_per_packet: Option<u32>,
+        /// For QuickTime version 2 sound descriptions.
+        format_specific_flags: Option<u32>,
+        /// Codec configuration: the ES descriptor for AAC (`mp4a`), or the
+        /// ALACSpecificConfig for Apple Lossless (`alac`).
+        magic_cookie: Option<Vec<u8>>,
+    },
+    Other([u8; 4]),
+}
+
+#[derive(Debug)]
+pub struct Track {
+    /// Units per second for times and durations.
+    pub timescale: u32,
+    pub duration: u64,
+    pub format: TrackFormat,
+    pub samples: Vec<Sample>,
+}
+
+impl Track {
+    pub fn duration_seconds(&self) -> f64 {
+        self.duration as f64 / f64::from(self.timescale.max(1))
+    }
+}
+
+/// Parse an MPEG-4 or QuickTime file and return its audio and video tracks.
+pub fn parse_tracks(data: &[u8]) -> Result<Vec<Track>, String> {
+    let top_level = parse_boxes(data)?;
+    parse_movie_box(require_box(&top_level, b"moov")?)
+}
+
+/// Like [parse_tracks], but takes only the contents of the `moov` box, so the
+/// rest of the file doesn't need to be in memory.
+pub fn parse_movie_box(moov: &[u8]) -> Result<Vec<Track>, String> {
+    let moov = parse_boxes(moov)?;
+    let mut tracks = Vec::new();
+    for &(box_type, trak) in &moov {
+        if &box_type != b"trak" {
+            continue;
+        }
+        if let Some(track) = parse_track(trak)? {
+            tracks.push(track);
+        }
+    }
+    Ok(tracks)
+}
+
+fn parse_track(trak: &[u8]) -> Result<Option<Track>, String> {
+    let trak = parse_boxes(trak)?;
+    let mdia = parse_boxes(require_box(&trak, b"mdia")?)?;
+
+    let mut hdlr = Reader::new(require_box(&mdia, b"hdlr")?);
+    hdlr.skip(8)?; // version, flags, pre_defined/component type
+    let handler_type = hdlr.fourcc()?;
+    if &handler_type != b"vide" && &handler_type != b"soun" {
+        return Ok(None);
+    }
+
+    let mut mdhd = Reader::new(require_box(&mdia, b"mdhd")?);
+    let version = mdhd.u8()?;
+    mdhd.skip(3)?; // flags
+    let (timescale, duration) = if version == 1 {
+        mdhd.skip(16)?; // creation and modification times
+        (mdhd.u32()?, mdhd.u64()?)
+    } else {
+        mdhd.skip(8)?;
+        (mdhd.u32()?, u64::from(mdhd.u32()?))
+    };
+
+    let minf = parse_boxes(require_box(&mdia, b"minf")?)?;
+    let stbl = parse_boxes(require_box(&minf, b"stbl")?)?;
+
+    let format = parse_sample_description(require_box(&stbl, b"stsd")?, &handler_type)?;
+    let samples = parse_sample_table(&stbl, &format)?;
+
+    Ok(Some(Track {
+        timescale,
+        duration,
+        format,
+        samples,
+    }))
+}
+
+/// Parse the first entry of the `stsd` box.
+fn parse_sample_description(stsd: &[u8], handler_type: &[u8; 4]) -> Result<TrackFormat, String> {
+    let mut stsd = Reader::new(stsd);
+    stsd.skip(4)?; // version and flags
+    if stsd.u32()? == 0 {
+        return Err("Empty sample description".to_string());
+    }
+    // Only the first entry is used.
+    let entries = parse_boxes(stsd.rest())?;
+    let &(format_id, entry) = entries
+        .first()
+        .ok_or_else(|| "Empty sample description".to_string())?;
+    let mut entry = Reader::new(entry);
+    entry.skip(8)?; // reserved, data_reference_index
+
+    if handler_type == b"vide" {
+        if &format_id != b"avc1" {
+            return Ok(TrackFormat::Other(format_id));
+        }
+        // pre_defined, reserved, pre_defined, width, height, resolutions,
+        // reserved, frame_count, compressorname, depth, pre_defined
+        entry.skip(16 + 2 + 2 + 4 + 4 + 4 + 2 + 32 + 2 + 2)?;
+        let extensions = parse_boxes(entry.rest())?;
+
+        let mut avcc = Reader::new(require_box(&extensions, b"avcC")?);
+        avcc.skip(4)?; // version, profile, compatibility, level
+        let nal_length_size = usize::from(avcc.u8()? & 3) + 1;
+        let mut parameter_sets = Vec::new();
+        let sps_count = avcc.u8()? & 0x1f;
+        for _ in 0..sps_count {
+            let length = avcc.u16()?.into();
+            parameter_sets.push(avcc.bytes(length)?.to_vec());
+        }
+        let pps_count = avcc.u8()?;
+        for _ in 0..pps_count {
+            let length = avcc.u16()?.into();
+            parameter_sets.push(avcc.bytes(length)?.to_vec());
+        }
+
+        return Ok(TrackFormat::Avc {
+            nal_length_size,
+            parameter_sets,
+        });
+    }
+
+    let version = entry.u16()?;
+    entry.skip(2 + 4)?; // revision level, vendor
+    let mut channels = entry.u16()?;
+    let mut bits_per_channel = entry.u16()?;
+    entry.skip(2 + 2)?; // compression ID, packet size
+    let mut sample_rate = f64::from(entry.u32()?) / 65536.0;
+    let mut bytes_per_frame = None;
+    let mut frames_per_packet = None;
+    let mut format_specific_flags = None;
+    match version {
+        0 => (),
+        1 => {
+            frames_per_packet = Some(entry.u32()?);
+            let _bytes_per_packet = entry.u32()?;
+            bytes_per_frame = Some(entry.u32()?);
+            let _bytes_per_sample = entry.u32()?;
+        }
+        2 => {
+            let _size_of_struct_only = entry.u32()?;
+            sample_rate = f64::from_bits(entry.u64()?);
+            channels = entry.u32()? as u16;
+            entry.skip(4)?; // always 0x7F000000
+            bits_per_channel = entry.u32()? as u16;
+            format_specific_flags = Some(entry.u32()?);
+            bytes_per_frame = Some(entry.u32()?);
+            frames_per_packet = Some(entry.u32()?);
+        }
+        _ => return Err(format!("Unknown sound description version {}", version)),
+    }
+
+    // Codec configuration is stored in extension boxes. QuickTime files wrap
+    // these in a `wave` box.
+    let mut extensions = parse_boxes(entry.rest()).unwrap_or_default();
+    if let Some(wave) = find_box(&extensions, b"wave") {
+        extensions = parse_boxes(wave)?;
+    }
+    let magic_cookie = match &format_id {
+        b"mp4a" => find_box(&extensions, b"esds"),
+        b"alac" => find_box(&extensions, b"alac"),
+        _ => None,
+    }
+    .and_then(|full_box| full_box.get(4..)) // version and flags
+    .map(|cookie| cookie.to_vec());
+
+    Ok(TrackFormat::Audio {
+        format_id,
+        channels,
+        bits_per_channel,
+        sample_rate,
+        bytes_per_frame,
+        frames_per_packet,
+        format_specific_flags,
+        magic_cookie,
+    })
+}
+
+/// Find the offset, size and timestamp of each sample.
+fn parse_sample_table(
+    stbl: &[([u8; 4], &[u8])],
+    format: &TrackFormat,
+) -> Result<Vec<Sample>, String> {
+    fn full_box_entries<'a>(body: &'a [u8]) -> Result<(u32, Reader<'a>), String> {
+        let mut reader = Reader::new(body);
+        reader.skip(4)?; // version and flags
+        let count = reader.u32()?;
+        Ok((count, reader))
+    }
+
+    let mut stsz = Reader::new(require_box(stbl, b"stsz")?);
+    stsz.skip(4)?;
+    let constant_size = stsz.u32()?;
+    let sample_count = stsz.u32()?;
+    let mut sizes = Vec::with_capacity(sample_count as usize);
+    for _ in 0..sample_count {
+        sizes.push(if constant_size == 0 {
+            stsz.u32()?
+        } else {
+            constant_size
+        });
+    }
+
+    let mut chunk_offsets = Vec::new();
+    if let Some(stco) = find_box(stbl, b"stco") {
+        let (count, mut stco) = full_box_entries(stco)?;
+        for _ in 0..count {
+            chunk_offsets.push(u64::from(stco.u32()?));
+        }
+    } else {
+        let (count, mut co64) = full_box_entries(require_box(stbl, b"co64")?)?;
+        for _ in 0..count {
+            chunk_offsets.push(co64.u64()?);
+        }
+    }
+
+    // Expand the sample-to-chunk table to (chunk offset, samples in chunk)
+    // for each chunk.
+    let (count, mut stsc) = full_box_entries(require_box(stbl, b"stsc")?)?;
+    let mut sample_to_chunk = Vec::new();
+    for _ in 0..count {
+        let first_chunk = stsc.u32()?;
+        let samples_per_chunk = stsc.u32()?;
+        let _sample_description_index = stsc.u32()?;
+        sample_to_chunk.push((first_chunk as usize, samples_per_chunk as usize));
+    }
+    let mut chunks = Vec::with_capacity(chunk_offsets.len());
+    for (i, &(first_chunk, samples_per_chunk)) in sample_to_chunk.iter().enumerate() {
+        let end_chunk = sample_to_chunk
+            .get(i + 1)
+            .map_or(chunk_offsets.len() + 1, |&(next_first_chunk, _)| {
+                next_first_chunk
+            });
+        for chunk in first_chunk..end_chunk {
+            let &offset = chunk_offsets
+                .get(chunk.wrapping_sub(1))
+                .ok_or_else(|| "Invalid chunk index".to_string())?;
+            chunks.push((offset, samples_per_chunk));
+        }
+    }
+
+    let mut times = Vec::with_capacity(sizes.len());
+    let (count, mut stts) = full_box_entries(require_box(stbl, b"stts")?)?;
+    let mut time = 0u64;
+    for _ in 0..count {
+        let entry_sample_count = stts.u32()?;
+        let delta = stts.u32()?;
+        for _ in 0..entry_sample_count {
+            times.push(time);
+            time += u64::from(delta);
+        }
+    }
+    times.resize(sizes.len(), time);
+
+    // QuickTime files with uncompressed or IMA4 audio use a sample size of 1
+    // and count individual frames as samples. Each chunk is treated as a
+    // single sample instead.
+    if let (
+        1,
+        &TrackFormat::Audio {
+            channels,
+            bits_per_channel,
+            bytes_per_frame,
+            frames_per_packet,
+            ..
+        },
+    ) = (constant_size, format)
+    {
+        let bytes_per_frame = bytes_per_frame
+            .unwrap_or(u32::from(channels) * ((u32::from(bits_per_channel) + 7) / 8));
+        let frames_per_packet = frames_per_packet.unwrap_or(1).max(1);
+        let mut samples = Vec::with_capacity(chunks.len());
+        let mut frame = 0;
+        for (offset, frame_count) in chunks {
+            samples.push(Sample {
+                offset,
+                size: (frame_count as u32 / frames_per_packet) * bytes_per_frame,
+                time: times.get(frame).copied().unwrap_or(time),
+            });
+            frame += frame_count;
+        }
+        return Ok(samples);
+    }
+
+    let mut offsets = Vec::with_capacity(sizes.len());
+    for (mut offset, samples_in_chunk) in chunks {
+        for _ in 0..samples_in_chunk {
+            let Some(&size) = sizes.get(offsets.len()) else {
+                break;
+            };
+            offsets.push(offset);
+            offset += u64::from(size);
+        }
+    }
+    if offsets.len() != sizes.len() {
+        return Err("Sample table is inconsistent".to_string());
+    }
+
+    // Composition offsets, only present if the presentation order differs
+    // from the decoding order.
+    if let Some(ctts) = find_box(stbl, b"ctts") {
+        let (count, mut ctts) = full_box_entries(ctts)?;
+        let mut i = 0;
+        for _ in 0..count {
+            let entry_sample_count = ctts.u32()?;
+            let offset = ctts.u32()? as i32;
+            for _ in 0..entry_sample_count {
+                if let Some(time) = times.get_mut(i) {
+                    *time = (*time as i64 + i64::from(offset)).max(0) as u64;
+                }
+                i += 1;
+            }
+        }
+    }
+
+    Ok(offsets
+        .into_iter()
+        .zip(sizes)
+        .zip(times)
+        .map(|((offset, size), time)| Sample { offset, size, time })
+        .collect())
+}
@@ -19,6 +19,8 @@
 //! categories and dynamic class editing).
 
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{ConstPtr, ConstVoidPtr};
+use crate::options::Options;
 
 use std::collections::HashMap;
 
@@ -33,16 +35,30 @@ pub use classes::{objc_classes, Class, ClassExports, ClassTemplate};
 pub use messages::{
     autorelease, msg, msg_class, msg_send, msg_send_super2, msg_super, objc_super, release, retain,
 };
-pub use methods::{GuestIMP, HostIMP, IMP};
-pub use objects::{id, nil, AnyHostObject, HostObject, TrivialHostObject};
+pub use methods::{GuestIMP, HostIMP, Method, IMP};
+pub use objects::{
+    id, nil, release_associated_objects_of_deallocated, AnyHostObject, HostObject,
+    TrivialHostObject,
+};
 pub use selectors::{selector, SEL};
 
-use classes::{ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS};
+use classes::{
+    class_getName, class_getSuperclass, class_isMetaClass, objc_getClass, objc_lookUpClass,
+    ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS,
+};
 use messages::{objc_msgSend, objc_msgSendSuper2, objc_msgSend_stret};
-use methods::method_list_t;
-use objects::{objc_object, HostObjectEntry};
+use methods::{
+    class_addMethod, class_getClassMethod, class_getInstanceMethod, class_replaceMethod,
+    class_respondsToSelector, method_exchangeImplementations, method_getImplementation,
+    method_getName, method_list_t, method_setImplementation,
+};
+use objects::{
+    objc_getAssociatedObject, objc_object, objc_removeAssociatedObjects, objc_setAssociatedObject,
+    object_getClass, HostObjectEntry,
+};
 use properties::objc_copyStruct;
 use properties::objc_setProperty;
+use selectors::{sel_getName, sel_getUid, sel_isEqual, sel_registerName};
 
 /// Typedef for `NSZone *`. This is a [fossil type] found in the signature of
 /// `allocWithZone:` and similar methods. Its value is always ignored.
@@ -64,14 +80,50 @@ pub struct ObjC {
     ///
     /// Look at the `isa` to get the metaclass for a class.
     classes: HashMap<String, Class>,
+
+    /// Guest C strings for class names returned by `class_getName`.
+    class_name_strings: HashMap<String, ConstPtr<u8>>,
+
+    /// Handles returned by `class_getInstanceMethod` and similar, see
+    /// [methods::Method].
+    method_handles: HashMap<(Class, SEL), Method>,
+
+    /// Guest functions created to expose host method implementations to the
+    /// guest, e.g. by `method_getImplementation`. The key is the address of
+    /// the [HostIMP].
+    host_imp_functions: HashMap<usize, GuestIMP>,
+
+    /// Objects associated with other objects by `objc_setAssociatedObject`.
+    /// The [bool] is [true] if the associated object has been retained.
+    associated_objects: HashMap<id, HashMap<ConstVoidPtr, (id, bool)>>,
+    /// Retained associated objects of deallocated objects, which still need to
+    /// be released. See [release_associated_objects_of_deallocated].
+    deallocated_associations: Vec<id>,
+
+    /// Patterns from `--trace-objc=`.
+    trace_patterns: Vec<String>,
+
+    /// Number of times each unrecognized message (e.g. `-[UIView foo:]`) was
+    /// sent. Without `--ignore-unrecognized-selectors`, the first one crashes.
+    unrecognized_selectors: HashMap<String, u64>,
+    /// Copy of `ignore_unrecognized_selectors` on [Options].
+    ignore_unrecognized_selectors: bool,
 }
 
 impl ObjC {
-    pub fn new() -> ObjC {
+    pub fn new(options: &Options) -> ObjC {
         ObjC {
             selectors: HashMap::new(),
             objects: HashMap::new(),
             classes: HashMap::new(),
+            class_name_strings: HashMap::new(),
+            method_handles: HashMap::new(),
+            host_imp_functions: HashMap::new(),
+            associated_objects: HashMap::new(),
+            deallocated_associations: Vec::new(),
+            trace_patterns: options.trace_objc.clone(),
+            unrecognized_selectors: HashMap::new(),
+            ignore_unrecognized_selectors: options.ignore_unrecognized_selectors,
         }
     }
 }
@@ -82,4 +134,26 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(objc_msgSendSuper2(_, _)),
     export_c_func!(objc_setProperty(_, _, _, _, _, _)),
     export_c_func!(objc_copyStruct(_, _, _, _, _)),
+    export_c_func!(objc_getClass(_)),
+    export_c_func!(objc_lookUpClass(_)),
+    export_c_func!(class_getName(_)),
+    export_c_func!(class_getSuperclass(_)),
+    export_c_func!(class_isMetaClass(_)),
+    export_c_func!(class_getInstanceMethod(_, _)),
+    export_c_func!(class_getClassMethod(_, _)),
+    export_c_func!(class_respondsToSelector(_, _)),
+    export_c_func!(class_addMethod(_, _, _, _)),
+    export_c_func!(class_replaceMethod(_, _, _, _)),
+    export_c_func!(method_getName(_)),
+    export_c_func!(method_getImplementation(_)),
+    export_c_func!(method_setImplementation(_, _)),
+    export_c_func!(method_exchangeImplementations(_, _)),
+    export_c_func!(sel_registerName(_)),
+    export_c_func!(sel_getUid(_)),
+    export_c_func!(sel_getName(_)),
+    export_c_func!(sel_isEqual(_, _)),
+    export_c_func!(object_getClass(_)),
+    export_c_func!(objc_setAssociatedObject(_, _, _, _)),
+    export_c_func!(objc_getAssociatedObject(_, _)),
+    export_c_func!(objc_removeAssociatedObjects(_)),
 ];
@@ -18,7 +18,8 @@ use super::{
 };
 use crate::mach_o::MachO;
 use crate::mem::{guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, Mem, Ptr, SafeRead};
-use std::collections::HashMap;
+use crate::Environment;
+use std::collections::{HashMap, HashSet};
 
 /// Generic pointer to an Objective-C class or metaclass.
 ///
@@ -450,6 +451,35 @@ impl ObjC {
         crate::dyld::search_lists(CLASS_LISTS, name).map(|&(_name, ref template)| template)
     }
 
+    /// For use by `--scan-imports`: returns [true] if there is a host
+    /// implementation of the class with this name.
+    pub fn has_host_class(name: &str) -> bool {
+        Self::find_template(name).is_some()
+    }
+
+    /// For use by `--scan-imports`: get the names of all the selectors that
+    /// some class has a method for. This includes every host class, and every
+    /// class registered so far (e.g. the app's classes and categories).
+    pub fn implemented_selectors(&self, mem: &Mem) -> HashSet<String> {
+        let mut selectors = HashSet::new();
+        for &class_list in CLASS_LISTS {
+            for (_name, template) in class_list {
+                for method_list in [template.class_methods, template.instance_methods] {
+                    selectors.extend(method_list.iter().map(|&(name, _imp)| name.to_string()));
+                }
+            }
+        }
+        for &class in self.classes.values() {
+            for class in [class, Self::read_isa(class, mem)] {
+                let host_object = self.get_host_object(class).unwrap();
+                if let Some(ClassHostObject { methods, .. }) = host_object.as_any().downcast_ref() {
+                    selectors.extend(methods.keys().map(|sel| sel.as_str(mem).to_string()));
+                }
+            }
+        }
+        selectors
+    }
+
     /// For use by [crate::dyld]: get the class or metaclass referenced by an
     /// external relocation in the app binary. If we don't have an
     /// implementation of the class, a placeholder is used.
@@ -457,6 +487,18 @@ impl ObjC {
         self.link_class_inner(name, is_metaclass, mem, true)
     }
 
+    /// For use by `objc_getClass` and similar: get a class by name, linking it
+    /// if it's a host class. Returns [None] if there is no implementation of
+    /// the class.
+    pub fn find_class(&mut self, name: &str, mem: &mut Mem) -> Option<Class> {
+        if let Some(class) = self.get_class(name, /* is_metaclass: */ false, mem) {
+            let host_object = self.get_host_object(class).unwrap().as_any();
+            return (!host_object.is::<UnimplementedClass>()).then_some(class);
+        }
+        Self::find_template(name)?;
+        Some(self.get_known_class(name, mem))
+    }
+
     /// For use by host functions: get a particular class. If we don't have an
     /// implementation of the class, panic.
     pub fn get_known_class(&mut self, name: &str, mem: &mut Mem) -> Class {
@@ -662,6 +704,24 @@ impl ObjC {
         }
     }
 
+    /// Get the name of a class or metaclass, and whether it is a metaclass.
+    /// Panics if `class` isn't a known class.
+    pub fn get_class_name(&self, class: Class) -> (&str, bool) {
+        let host_object = self.get_host_object(class).unwrap().as_any();
+        if let Some(ClassHostObject {
+            name, is_metaclass, ..
+        }) = host_object.downcast_ref()
+        {
+            (name, *is_metaclass)
+        } else if let Some(UnimplementedClass { name, is_metaclass }) = host_object.downcast_ref() {
+            (name, *is_metaclass)
+        } else if let Some(FakeClass { name, is_metaclass }) = host_object.downcast_ref() {
+            (name, *is_metaclass)
+        } else {
+            panic!("{:?} is not a class", class);
+        }
+    }
+
     pub fn class_is_subclass_of(&self, class: Class, superclass: Class) -> bool {
         if class == superclass {
             return true;
@@ -682,3 +742,53 @@ impl ObjC {
         }
     }
 }
+
+/// `objc_getClass`: get a class by name, or [nil] if there is no such class.
+pub(super) fn objc_getClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    env.objc.find_class(&name, &mut env.mem).unwrap_or(nil)
+}
+
+/// `objc_lookUpClass`: the same as [objc_getClass], since we don't support
+/// class handler callbacks.
+pub(super) fn objc_lookUpClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    objc_getClass(env, name)
+}
+
+pub(super) fn class_getName(env: &mut Environment, class: Class) -> ConstPtr<u8> {
+    if class == nil {
+        return env.objc.class_name_string("nil", &mut env.mem);
+    }
+    let (name, _is_metaclass) = env.objc.get_class_name(class);
+    let name = name.to_string();
+    env.objc.class_name_string(&name, &mut env.mem)
+}
+
+pub(super) fn class_getSuperclass(env: &mut Environment, class: Class) -> Class {
+    if class == nil {
+        return nil;
+    }
+    let host_object = env.objc.get_host_object(class).unwrap();
+    if let Some(&ClassHostObject { superclass, .. }) = host_object.as_any().downcast_ref() {
+        superclass
+    } else {
+        nil
+    }
+}
+
+pub(super) fn class_isMetaClass(env: &mut Environment, class: Class) -> bool {
+    class != nil && env.objc.get_class_name(class).1
+}
+
+impl ObjC {
+    /// Get a C string for a class name that can be returned to the guest. The
+    /// strings are never freed, so they are deduplicated.
+    fn class_name_string(&mut self, name: &str, mem: &mut Mem) -> ConstPtr<u8> {
+        if let Some(&string) = self.class_name_strings.get(name) {
+            return string;
+        }
+        let string = mem.alloc_and_write_cstr(name.as_bytes()).cast_const();
+        self.class_name_strings.insert(name.to_string(), string);
+        string
+    }
+}
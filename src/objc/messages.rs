@@ -13,7 +13,8 @@
 
 use super::{id, nil, Class, ObjC, IMP, SEL};
 use crate::abi::{CallFromHost, GuestRet};
-use crate::mem::{ConstPtr, MutVoidPtr, SafeRead};
+use crate::mem::{ConstPtr, Mem, MutVoidPtr, SafeRead};
+use crate::options::glob_matches;
 use crate::Environment;
 
 /// The core implementation of `objc_msgSend`, the main function of Objective-C.
@@ -32,7 +33,12 @@ use crate::Environment;
 fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2: Option<Class>) {
     if receiver == nil {
         // https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjectiveC/Chapters/ocObjectsClasses.html#//apple_ref/doc/uid/TP30001163-CH11-SW7
-        log_dbg!("[nil {}]", selector.as_str(&env.mem));
+        let selector_str = selector.as_str(&env.mem);
+        if env.objc.should_trace_message(None, selector_str) {
+            echo!("[nil {}]", selector_str);
+        } else {
+            log_dbg!("[nil {}]", selector_str);
+        }
         env.cpu.regs_mut()[0..2].fill(0);
         return;
     }
@@ -40,6 +46,19 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
     let orig_class = super2.unwrap_or_else(|| ObjC::read_isa(receiver, &env.mem));
     assert!(orig_class != nil);
 
+    let traced = if env.objc.trace_patterns.is_empty() {
+        None
+    } else {
+        let receiver_class = ObjC::read_isa(receiver, &env.mem);
+        let (class_name, _) = env.objc.get_class_name(receiver_class);
+        env.objc
+            .should_trace_message(Some(class_name), selector.as_str(&env.mem))
+            .then(|| {
+                env.objc
+                    .describe_message(receiver_class, selector, &env.mem)
+            })
+    };
+
     // Traverse the chain of superclasses to find the method implementation.
 
     let mut class = orig_class;
@@ -47,6 +66,15 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
         if class == nil {
             assert!(class != orig_class);
 
+            let description = env.objc.describe_message(orig_class, selector, &env.mem);
+            let count = env
+                .objc
+                .unrecognized_selectors
+                .entry(description)
+                .or_insert(0);
+            *count += 1;
+            let first_time = *count == 1;
+
             let class_host_object = env.objc.get_host_object(orig_class).unwrap();
             let &super::ClassHostObject {
                 ref name,
@@ -54,6 +82,19 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
                 ..
             } = class_host_object.as_any().downcast_ref().unwrap();
 
+            if env.objc.ignore_unrecognized_selectors {
+                if first_time {
+                    log!(
+                        "Warning: {} {:?} does not respond to selector \"{}\". Behaving as if message was sent to nil.",
+                        if is_metaclass { "Class" } else { "Object" },
+                        receiver,
+                        selector.as_str(&env.mem),
+                    );
+                }
+                env.cpu.regs_mut()[0..2].fill(0);
+                return;
+            }
+
             panic!(
                 "{} {:?} ({}class \"{}\", {:?}){} does not respond to selector \"{}\"!",
                 if is_metaclass { "Class" } else { "Object" },
@@ -86,8 +127,23 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
             }
 
             if let Some(imp) = methods.get(&selector) {
+                if let Some(ref description) = traced {
+                    echo!(
+                        "{} sent to {:?}{} ({})",
+                        description,
+                        receiver,
+                        if super2.is_some() { " via super" } else { "" },
+                        match imp {
+                            IMP::Host(_) => "host implementation",
+                            IMP::Guest(_) => "guest implementation",
+                        }
+                    );
+                }
                 match imp {
-                    IMP::Host(host_imp) => host_imp.call_from_guest(env),
+                    IMP::Host(host_imp) => {
+                        env.traced_host_call = traced;
+                        host_imp.call_from_guest(env)
+                    }
                     // We can't create a new stack frame, because that would
                     // interfere with pass-through of stack arguments.
                     IMP::Guest(guest_imp) => guest_imp.call_without_pushing_stack_frame(env),
@@ -131,6 +187,43 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
     }
 }
 
+impl ObjC {
+    /// Returns [true] if a message should be logged (see `--trace-objc=`).
+    /// `class_name` is [None] for messages to `nil`.
+    fn should_trace_message(&self, class_name: Option<&str>, selector: &str) -> bool {
+        self.trace_patterns.iter().any(|pattern| {
+            glob_matches(pattern, selector)
+                || class_name.map_or(false, |class_name| glob_matches(pattern, class_name))
+        })
+    }
+
+    /// Describe a message for logging, e.g. `-[UIView setFrame:]`.
+    fn describe_message(&self, class: Class, selector: SEL, mem: &Mem) -> String {
+        let (class_name, is_metaclass) = self.get_class_name(class);
+        format!(
+            "{}[{} {}]",
+            if is_metaclass { '+' } else { '-' },
+            class_name,
+            selector.as_str(mem)
+        )
+    }
+
+    /// Print a summary of the messages that weren't recognized, if any. The
+    /// most frequently sent messages come first.
+    pub fn print_unrecognized_selectors(&self) {
+        if self.unrecognized_selectors.is_empty() {
+            return;
+        }
+        let mut unrecognized_selectors: Vec<_> = self.unrecognized_selectors.iter().collect();
+        unrecognized_selectors
+            .sort_by_key(|&(description, &count)| (std::cmp::Reverse(count), description));
+        echo!("Messages that weren't recognized:");
+        for (description, count) in unrecognized_selectors {
+            echo!("{}: sent {} times", description, count);
+        }
+    }
+}
+
 /// Standard variant of `objc_msgSend`. See [objc_msgSend_inner].
 #[allow(non_snake_case)]
 pub(super) fn objc_msgSend(env: &mut Environment, receiver: id, selector: SEL) {
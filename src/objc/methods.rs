@@ -7,6 +7,7 @@
 //!
 //! Resources:
 //! - [Apple's documentation of `class_addMethod`](https://developer.apple.com/documentation/objectivec/1418901-class_addmethod?language=objc)
+//! - [Apple's documentation of `method_exchangeImplementations`](https://developer.apple.com/documentation/objectivec/1418769-method_exchangeimplementations?language=objc)
 
 use super::{id, nil, Class, ClassHostObject, ObjC, SEL};
 use crate::abi::{CallFromGuest, DotDotDot, GuestArg, GuestFunction, GuestRet};
@@ -170,6 +171,24 @@ impl ClassHostObject {
     }
 }
 
+/// A handle for a method, as returned by `class_getInstanceMethod` etc.
+///
+/// Apple's runtime uses a pointer to a [method_t] for this. Our methods live in
+/// the method table of a [ClassHostObject] instead, so we allocate one of
+/// these for each class and selector pair the guest asks about. The selector
+/// comes first, like in [method_t], in case the guest reads it directly.
+#[repr(C, packed)]
+pub struct method_handle_t {
+    name: SEL,
+    class: Class,
+}
+unsafe impl SafeRead for method_handle_t {}
+
+/// Pointer to a [method_handle_t].
+///
+/// The name is standard Objective-C.
+pub type Method = ConstPtr<method_handle_t>;
+
 impl ObjC {
     /// For use by NSObject's getter/setter search methods.
     pub fn class_has_method(&self, class: Class, sel: SEL) -> bool {
@@ -190,3 +209,202 @@ impl ObjC {
         }
     }
 }
+
+impl ObjC {
+    /// Find the class that provides the method for `sel` to instances of
+    /// `class`, i.e. `class` or one of its superclasses.
+    fn find_method_class(&self, class: Class, sel: SEL) -> Option<Class> {
+        let mut class = class;
+        while class != nil {
+            // Unimplemented and fake classes have no methods.
+            let &ClassHostObject {
+                superclass,
+                ref methods,
+                ..
+            } = self.get_host_object(class)?.as_any().downcast_ref()?;
+            if methods.contains_key(&sel) {
+                return Some(class);
+            }
+            class = superclass;
+        }
+        None
+    }
+
+    fn get_method_handle(&mut self, class: Class, sel: SEL, mem: &mut Mem) -> Method {
+        *self.method_handles.entry((class, sel)).or_insert_with(|| {
+            mem.alloc_and_write(method_handle_t { name: sel, class })
+                .cast_const()
+        })
+    }
+}
+
+/// Get a guest function pointer for the implementation of the method `sel` of
+/// `class`. Host implementations get a guest function that calls them.
+fn get_guest_imp(env: &mut Environment, class: Class, sel: SEL) -> GuestIMP {
+    let host_imp = match env.objc.borrow::<ClassHostObject>(class).methods[&sel] {
+        IMP::Guest(guest_imp) => return guest_imp,
+        IMP::Host(host_imp) => host_imp,
+    };
+    let key = host_imp as *const dyn HostIMP as *const () as usize;
+    if let Some(&guest_imp) = env.objc.host_imp_functions.get(&key) {
+        return guest_imp;
+    }
+    let guest_imp = env.dyld.create_guest_function(
+        &mut env.mem,
+        &mut env.cpu,
+        "(host method implementation)",
+        host_imp,
+    );
+    env.objc.host_imp_functions.insert(key, guest_imp);
+    guest_imp
+}
+
+fn null_imp() -> GuestIMP {
+    GuestFunction::from_addr_with_thumb_bit(0)
+}
+
+/// Returns [true] if `class` is a class whose methods can be edited, i.e. not
+/// `nil` and not an unimplemented or fake class.
+fn is_editable_class(objc: &ObjC, class: Class) -> bool {
+    class != nil
+        && objc.get_host_object(class).map_or(false, |host_object| {
+            host_object.as_any().is::<ClassHostObject>()
+        })
+}
+
+pub(super) fn class_getInstanceMethod(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if class == nil {
+        return Ptr::null();
+    }
+    match env.objc.find_method_class(class, sel) {
+        Some(class) => env.objc.get_method_handle(class, sel, &mut env.mem),
+        None => Ptr::null(),
+    }
+}
+
+pub(super) fn class_getClassMethod(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if class == nil {
+        return Ptr::null();
+    }
+    let metaclass = ObjC::read_isa(class, &env.mem);
+    class_getInstanceMethod(env, metaclass, sel)
+}
+
+pub(super) fn class_respondsToSelector(env: &mut Environment, class: Class, sel: SEL) -> bool {
+    class != nil && env.objc.find_method_class(class, sel).is_some()
+}
+
+/// `class_addMethod`: add a method to a class, unless the class (not counting
+/// its superclasses) already has one for that selector. The type encoding is
+/// ignored.
+pub(super) fn class_addMethod(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+    imp: GuestIMP,
+    _types: ConstPtr<u8>,
+) -> bool {
+    if !is_editable_class(&env.objc, class) {
+        return false;
+    }
+    let methods = &mut env.objc.borrow_mut::<ClassHostObject>(class).methods;
+    if methods.contains_key(&sel) {
+        return false;
+    }
+    methods.insert(sel, IMP::Guest(imp));
+    true
+}
+
+/// `class_replaceMethod`: replace or add a method. Returns the previous
+/// implementation if the class (not counting its superclasses) had one.
+pub(super) fn class_replaceMethod(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+    imp: GuestIMP,
+    _types: ConstPtr<u8>,
+) -> GuestIMP {
+    if !is_editable_class(&env.objc, class) {
+        return null_imp();
+    }
+    let old_imp = if env
+        .objc
+        .borrow::<ClassHostObject>(class)
+        .methods
+        .contains_key(&sel)
+    {
+        get_guest_imp(env, class, sel)
+    } else {
+        null_imp()
+    };
+    env.objc
+        .borrow_mut::<ClassHostObject>(class)
+        .methods
+        .insert(sel, IMP::Guest(imp));
+    old_imp
+}
+
+pub(super) fn method_getName(env: &mut Environment, method: Method) -> SEL {
+    if method.is_null() {
+        return SEL::null();
+    }
+    env.mem.read(method).name
+}
+
+pub(super) fn method_getImplementation(env: &mut Environment, method: Method) -> GuestIMP {
+    if method.is_null() {
+        return null_imp();
+    }
+    let method_handle_t { name, class } = env.mem.read(method);
+    get_guest_imp(env, class, name)
+}
+
+/// `method_setImplementation`: returns the previous implementation.
+pub(super) fn method_setImplementation(
+    env: &mut Environment,
+    method: Method,
+    imp: GuestIMP,
+) -> GuestIMP {
+    if method.is_null() {
+        return null_imp();
+    }
+    let method_handle_t { name, class } = env.mem.read(method);
+    let old_imp = get_guest_imp(env, class, name);
+    env.objc
+        .borrow_mut::<ClassHostObject>(class)
+        .methods
+        .insert(name, IMP::Guest(imp));
+    old_imp
+}
+
+/// `method_exchangeImplementations`, the usual way to do "method swizzling".
+/// Host implementations can be exchanged too.
+pub(super) fn method_exchangeImplementations(env: &mut Environment, m1: Method, m2: Method) {
+    if m1 == m2 || m1.is_null() || m2.is_null() {
+        return;
+    }
+    let method_handle_t {
+        name: name1,
+        class: class1,
+    } = env.mem.read(m1);
+    let method_handle_t {
+        name: name2,
+        class: class2,
+    } = env.mem.read(m2);
+    let imp1 = env
+        .objc
+        .borrow_mut::<ClassHostObject>(class1)
+        .methods
+        .remove(&name1)
+        .unwrap();
+    let imp2 = env
+        .objc
+        .borrow_mut::<ClassHostObject>(class2)
+        .methods
+        .insert(name2, imp1)
+        .unwrap();
+    env.objc
+        .borrow_mut::<ClassHostObject>(class1)
+        .methods
+        .insert(name1, imp2);
+}
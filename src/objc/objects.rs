@@ -20,8 +20,9 @@
 //!
 //! See also: [crate::frameworks::foundation::ns_object].
 
-use super::{Class, ClassHostObject};
-use crate::mem::{guest_size_of, GuestUSize, Mem, MutPtr, Ptr, SafeRead};
+use super::{msg, release, retain, Class, ClassHostObject, ObjC};
+use crate::mem::{guest_size_of, ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead};
+use crate::Environment;
 use std::any::Any;
 use std::num::NonZeroU32;
 
@@ -256,6 +257,94 @@ impl super::ObjC {
 
         std::mem::drop(host_object);
 
+        // Releasing the associated objects needs the environment, so it has to
+        // wait, see [release_associated_objects_of_deallocated].
+        if let Some(associations) = self.associated_objects.remove(&object) {
+            self.deallocated_associations.extend(
+                associations
+                    .into_values()
+                    .filter_map(|(value, retained)| retained.then_some(value)),
+            );
+        }
+
         mem.free(object.cast());
     }
 }
+
+pub(super) fn object_getClass(env: &mut Environment, object: id) -> Class {
+    if object == nil {
+        return nil;
+    }
+    ObjC::read_isa(object, &env.mem)
+}
+
+/// Values of `objc_AssociationPolicy`.
+const OBJC_ASSOCIATION_ASSIGN: u32 = 0;
+const OBJC_ASSOCIATION_RETAIN_NONATOMIC: u32 = 1;
+const OBJC_ASSOCIATION_COPY_NONATOMIC: u32 = 3;
+const OBJC_ASSOCIATION_RETAIN: u32 = 0o1401;
+const OBJC_ASSOCIATION_COPY: u32 = 0o1403;
+
+pub(super) fn objc_setAssociatedObject(
+    env: &mut Environment,
+    object: id,
+    key: ConstVoidPtr,
+    value: id,
+    policy: u32,
+) {
+    let (value, retained) = match policy {
+        OBJC_ASSOCIATION_ASSIGN => (value, false),
+        OBJC_ASSOCIATION_RETAIN_NONATOMIC | OBJC_ASSOCIATION_RETAIN => (retain(env, value), true),
+        OBJC_ASSOCIATION_COPY_NONATOMIC | OBJC_ASSOCIATION_COPY => (msg![env; value copy], true),
+        _ => {
+            log!(
+                "Warning: unknown association policy {:#x}, treating as OBJC_ASSOCIATION_ASSIGN",
+                policy
+            );
+            (value, false)
+        }
+    };
+
+    let associations = env.objc.associated_objects.entry(object).or_default();
+    let old = if value == nil {
+        associations.remove(&key)
+    } else {
+        associations.insert(key, (value, retained))
+    };
+    if associations.is_empty() {
+        env.objc.associated_objects.remove(&object);
+    }
+
+    if let Some((old_value, true)) = old {
+        release(env, old_value);
+    }
+}
+
+pub(super) fn objc_getAssociatedObject(env: &mut Environment, object: id, key: ConstVoidPtr) -> id {
+    env.objc
+        .associated_objects
+        .get(&object)
+        .and_then(|associations| associations.get(&key))
+        .map_or(nil, |&(value, _retained)| value)
+}
+
+pub(super) fn objc_removeAssociatedObjects(env: &mut Environment, object: id) {
+    let Some(associations) = env.objc.associated_objects.remove(&object) else {
+        return;
+    };
+    for (_key, (value, retained)) in associations {
+        if retained {
+            release(env, value);
+        }
+    }
+}
+
+/// Release the retained associated objects (see `objc_setAssociatedObject`)
+/// of objects deallocated by [ObjC::dealloc_object]. That method can't do this
+/// itself because sending messages needs the environment, so this should be
+/// called after anything that might deallocate an object.
+pub fn release_associated_objects_of_deallocated(env: &mut Environment) {
+    while let Some(value) = env.objc.deallocated_associations.pop() {
+        release(env, value);
+    }
+}
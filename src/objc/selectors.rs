@@ -15,9 +15,10 @@
 //! - Apple's [The Objective-C Programming Language](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjectiveC/Chapters/ocSelectors.html)
 
 use super::ObjC;
-use crate::abi::GuestArg;
+use crate::abi::{GuestArg, GuestRet};
 use crate::mach_o::MachO;
-use crate::mem::{ConstPtr, Mem, MutPtr, Ptr};
+use crate::mem::{ConstPtr, Mem, MutPtr, Ptr, SafeRead};
+use crate::Environment;
 
 /// Create a string literal for a selector from Objective-C message syntax
 /// components. Useful for [super::objc_classes] and for [super::msg].
@@ -37,6 +38,7 @@ pub use crate::selector; // #[macro_export] is weird...
 #[repr(transparent)]
 #[allow(clippy::upper_case_acronyms)] // silly clippit, this isn't an acronym!
 pub struct SEL(ConstPtr<u8>);
+unsafe impl SafeRead for SEL {}
 
 impl GuestArg for SEL {
     const REG_COUNT: usize = <ConstPtr<u8> as GuestArg>::REG_COUNT;
@@ -44,11 +46,25 @@ impl GuestArg for SEL {
         SEL(<ConstPtr<u8> as GuestArg>::from_regs(regs))
     }
     fn to_regs(self, regs: &mut [u32]) {
-        self.0.to_regs(regs)
+        <ConstPtr<u8> as GuestArg>::to_regs(self.0, regs)
+    }
+}
+
+impl GuestRet for SEL {
+    fn from_regs(regs: &[u32]) -> Self {
+        SEL(<ConstPtr<u8> as GuestRet>::from_regs(regs))
+    }
+    fn to_regs(self, regs: &mut [u32]) {
+        <ConstPtr<u8> as GuestRet>::to_regs(self.0, regs)
     }
 }
 
 impl SEL {
+    /// The `NULL` selector, which doesn't name any method.
+    pub(super) fn null() -> SEL {
+        SEL(Ptr::null())
+    }
+
     pub fn as_str(self, mem: &Mem) -> &str {
         // selectors are probably always UTF-8 but this hasn't been verified
         mem.cstr_at_utf8(self.0).unwrap()
@@ -111,3 +127,34 @@ impl ObjC {
         }
     }
 }
+
+/// `sel_registerName`: get the selector with a particular name, registering
+/// it if it doesn't exist yet.
+pub(super) fn sel_registerName(env: &mut Environment, name: ConstPtr<u8>) -> SEL {
+    let name_str = env.mem.cstr_at_utf8(name).unwrap();
+    if let Some(sel) = env.objc.lookup_selector(name_str) {
+        return sel;
+    }
+    // The string passed in might not have a static lifetime, so it must be
+    // copied.
+    let name_str = name_str.to_string();
+    let sel = SEL(env
+        .mem
+        .alloc_and_write_cstr(name_str.as_bytes())
+        .cast_const());
+    env.objc.selectors.insert(name_str, sel);
+    sel
+}
+
+/// `sel_getUid`: the same as [sel_registerName].
+pub(super) fn sel_getUid(env: &mut Environment, name: ConstPtr<u8>) -> SEL {
+    sel_registerName(env, name)
+}
+
+pub(super) fn sel_getName(_env: &mut Environment, sel: SEL) -> ConstPtr<u8> {
+    sel.0
+}
+
+pub(super) fn sel_isEqual(_env: &mut Environment, sel1: SEL, sel2: SEL) -> bool {
+    sel1 == sel2
+}
@@ -5,6 +5,7 @@
  */
 //! Parsing and management of user-configurable options, e.g. for input methods.
 
+use crate::device::{DeviceProfile, DEVICES};
 use crate::gles::GLESImplementation;
 use crate::window::DeviceOrientation;
 use std::collections::HashMap;
@@ -16,15 +17,63 @@ pub const DOCUMENTATION: &str =
     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/OPTIONS_HELP.txt"));
 
 /// Game controller button for `--button-to-touch=` option.
-#[derive(Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Button {
     A,
     B,
     X,
     Y,
+    Back,
+    Guide,
+    Start,
+    LeftStick,
+    RightStick,
+    LeftShoulder,
+    RightShoulder,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Misc1,
+    Paddle1,
+    Paddle2,
+    Paddle3,
+    Paddle4,
+    Touchpad,
+}
+impl Button {
+    /// Parse a button name. The names are the same as SDL2's (see
+    /// `SDL_GameControllerGetStringForButton()`), but case-insensitive.
+    fn from_name(name: &str) -> Option<Button> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "a" => Button::A,
+            "b" => Button::B,
+            "x" => Button::X,
+            "y" => Button::Y,
+            "back" => Button::Back,
+            "guide" => Button::Guide,
+            "start" => Button::Start,
+            "leftstick" => Button::LeftStick,
+            "rightstick" => Button::RightStick,
+            "leftshoulder" => Button::LeftShoulder,
+            "rightshoulder" => Button::RightShoulder,
+            "dpup" => Button::DPadUp,
+            "dpdown" => Button::DPadDown,
+            "dpleft" => Button::DPadLeft,
+            "dpright" => Button::DPadRight,
+            "misc1" => Button::Misc1,
+            "paddle1" => Button::Paddle1,
+            "paddle2" => Button::Paddle2,
+            "paddle3" => Button::Paddle3,
+            "paddle4" => Button::Paddle4,
+            "touchpad" => Button::Touchpad,
+            _ => return None,
+        })
+    }
 }
 
 /// Struct containing all user-configurable options.
+#[derive(Debug)]
 pub struct Options {
     pub fullscreen: bool,
     pub initial_orientation: DeviceOrientation,
@@ -35,9 +84,21 @@ pub struct Options {
     pub x_tilt_offset: f32,
     pub y_tilt_offset: f32,
     pub button_to_touch: HashMap<Button, (f32, f32)>,
+    /// Keys are SDL2 key names in lowercase.
+    pub key_to_touch: HashMap<String, (f32, f32)>,
+    pub keyboard_tilt: bool,
     pub gles1_implementation: Option<GLESImplementation>,
     pub direct_memory_access: bool,
     pub gdb_listen_addrs: Option<Vec<SocketAddr>>,
+    pub headless: bool,
+    pub exit_after_frames: Option<u32>,
+    pub exit_after_seconds: Option<f64>,
+    pub trace_calls: Vec<String>,
+    pub call_stats: bool,
+    pub trace_objc: Vec<String>,
+    pub ignore_unrecognized_selectors: bool,
+    pub check_locks: bool,
+    pub device: &'static DeviceProfile,
 }
 
 impl Default for Options {
@@ -52,9 +113,20 @@ impl Default for Options {
             x_tilt_offset: 0.0,
             y_tilt_offset: 0.0,
             button_to_touch: HashMap::new(),
+            key_to_touch: HashMap::new(),
+            keyboard_tilt: true,
             gles1_implementation: None,
             direct_memory_access: true,
             gdb_listen_addrs: None,
+            headless: false,
+            exit_after_frames: None,
+            exit_after_seconds: None,
+            trace_calls: Vec::new(),
+            call_stats: false,
+            trace_objc: Vec::new(),
+            ignore_unrecognized_selectors: false,
+            check_locks: false,
+            device: &DEVICES[0],
         }
     }
 }
@@ -64,6 +136,18 @@ impl Options {
     /// if the option was valid and has been applied, or `Ok(false)` if the
     /// option was not recognized.
     pub fn parse_argument(&mut self, arg: &str) -> Result<bool, String> {
+        fn parse_coords(coords: &str, option: &str) -> Result<(f32, f32), String> {
+            let (x, y) = coords
+                .split_once(',')
+                .ok_or_else(|| format!("{} requires three values", option))?;
+            let x: f32 = x
+                .parse()
+                .map_err(|_| format!("Invalid X co-ordinate for {}", option))?;
+            let y: f32 = y
+                .parse()
+                .map_err(|_| format!("Invalid Y co-ordinate for {}", option))?;
+            Ok((x, y))
+        }
         fn parse_degrees(arg: &str, name: &str) -> Result<f32, String> {
             let arg: f32 = arg
                 .parse()
@@ -98,28 +182,31 @@ impl Options {
             let (button, coords) = values
                 .split_once(',')
                 .ok_or_else(|| "--button-to-touch= requires three values".to_string())?;
-            let (x, y) = coords
-                .split_once(',')
-                .ok_or_else(|| "--button-to-touch= requires three values".to_string())?;
-            let button = match button {
-                "A" => Ok(Button::A),
-                "B" => Ok(Button::B),
-                "X" => Ok(Button::X),
-                "Y" => Ok(Button::Y),
-                _ => Err("Invalid button for --button-to-touch=".to_string()),
-            }?;
-            let x: f32 = x
-                .parse()
-                .map_err(|_| "Invalid X co-ordinate for --button-to-touch=".to_string())?;
-            let y: f32 = y
-                .parse()
-                .map_err(|_| "Invalid Y co-ordinate for --button-to-touch=".to_string())?;
-            self.button_to_touch.insert(button, (x, y));
+            let button = Button::from_name(button)
+                .ok_or_else(|| "Invalid button for --button-to-touch=".to_string())?;
+            let coords = parse_coords(coords, "--button-to-touch=")?;
+            self.button_to_touch.insert(button, coords);
+        } else if let Some(values) = arg.strip_prefix("--key-to-touch=") {
+            // Key names can contain commas (e.g. the comma key is ","), so the
+            // co-ordinates are split off from the end.
+            let key = values
+                .rsplit_once(',')
+                .and_then(|(rest, _y)| rest.rsplit_once(','))
+                .map(|(key, _x)| key)
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| "--key-to-touch= requires three values".to_string())?;
+            let coords = parse_coords(&values[key.len() + 1..], "--key-to-touch=")?;
+            self.key_to_touch.insert(key.to_lowercase(), coords);
+        } else if arg == "--disable-keyboard-tilt" {
+            self.keyboard_tilt = false;
         } else if let Some(value) = arg.strip_prefix("--gles1=") {
             self.gles1_implementation = Some(
                 GLESImplementation::from_short_name(value)
                     .map_err(|_| "Unrecognized --gles1= value".to_string())?,
             );
+        } else if let Some(value) = arg.strip_prefix("--device=") {
+            self.device = DeviceProfile::from_short_name(value)
+                .ok_or_else(|| "Unrecognized --device= value".to_string())?;
         } else if arg == "--disable-direct-memory-access" {
             self.direct_memory_access = false;
         } else if let Some(address) = arg.strip_prefix("--gdb=") {
@@ -128,6 +215,34 @@ impl Options {
                 .map_err(|e| format!("Could not resolve GDB server listen address: {}", e))?
                 .collect();
             self.gdb_listen_addrs = Some(addrs);
+        } else if arg == "--headless" {
+            self.headless = true;
+        } else if let Some(value) = arg.strip_prefix("--exit-after-frames=") {
+            self.exit_after_frames = Some(
+                value
+                    .parse()
+                    .map_err(|_| "Invalid frame count for --exit-after-frames=".to_string())?,
+            );
+        } else if let Some(value) = arg.strip_prefix("--exit-after-seconds=") {
+            let seconds: f64 = value
+                .parse()
+                .map_err(|_| "Invalid duration for --exit-after-seconds=".to_string())?;
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err("Duration for --exit-after-seconds= is out of range".to_string());
+            }
+            self.exit_after_seconds = Some(seconds);
+        } else if let Some(value) = arg.strip_prefix("--trace-calls=") {
+            self.trace_calls
+                .extend(value.split(',').map(|pattern| pattern.to_string()));
+        } else if arg == "--call-stats" {
+            self.call_stats = true;
+        } else if let Some(value) = arg.strip_prefix("--trace-objc=") {
+            self.trace_objc
+                .extend(value.split(',').map(|pattern| pattern.to_string()));
+        } else if arg == "--ignore-unrecognized-selectors" {
+            self.ignore_unrecognized_selectors = true;
+        } else if arg == "--check-locks" {
+            self.check_locks = true;
         } else {
             return Ok(false);
         };
@@ -135,6 +250,41 @@ impl Options {
     }
 }
 
+/// Match `name` against a pattern given to an option like `--trace-calls=`,
+/// where `*` matches any sequence of characters and `?` matches any single
+/// character.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+    let (mut p, mut n) = (0, 0);
+    // Position of the most recent `*` in the pattern, and the position in the
+    // name it was last tried at. Backtracking only ever needs to go back to
+    // the most recent `*`, which keeps this linear in practice rather than
+    // exponential.
+    let mut last_star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                last_star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => {
+                // Let the most recent `*` consume one more character.
+                let Some((star_p, star_n)) = last_star else {
+                    return false;
+                };
+                last_star = Some((star_p, star_n + 1));
+                p = star_p + 1;
+                n = star_n + 1;
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
 /// Try to get app-specific options from a file.
 ///
 /// Returns [Ok] if there is no error when reading the file, otherwise [Err].
@@ -177,3 +327,43 @@ pub fn get_options_from_file<F: Read>(file: F, app_id: &str) -> Result<Option<St
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::glob_matches;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "a"));
+        assert!(glob_matches("abc", "abc"));
+        assert!(!glob_matches("abc", "abd"));
+        assert!(!glob_matches("abc", "ab"));
+        assert!(!glob_matches("ab", "abc"));
+
+        assert!(glob_matches("a?c", "abc"));
+        assert!(!glob_matches("a?c", "ac"));
+
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "abc"));
+        assert!(glob_matches("gl*", "glBindTexture"));
+        assert!(glob_matches("*Texture", "glBindTexture"));
+        assert!(glob_matches("gl*Tex*", "glBindTexture"));
+        assert!(!glob_matches("gl*Tex*", "alBindTexture"));
+        assert!(glob_matches("*a*b", "xaxxb"));
+        assert!(!glob_matches("*a*b", "xaxxbx"));
+        assert!(glob_matches("a**b", "ab"));
+        assert!(glob_matches("*?", "a"));
+        assert!(!glob_matches("*?", ""));
+        assert!(glob_matches("init*:", "initWithFrame:"));
+    }
+
+    #[test]
+    fn test_glob_matches_pathological() {
+        // This took exponential time with a naive recursive matcher.
+        let name = "a".repeat(100);
+        let pattern = format!("{}b", "a*".repeat(20));
+        assert!(!glob_matches(&pattern, &name));
+        assert!(glob_matches(&"a*".repeat(20), &name));
+    }
+}
@@ -0,0 +1,337 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Movie file decoding.
+//!
+//! This is an abstraction over the container and codec support, usage of which
+//! should be confined to this module. Currently supported are MPEG-4 and
+//! QuickTime files (`.mp4`, `.m4v`, `.mov`) containing H.264 video, which is
+//! what iPhone OS's movie player supports, and uncompressed, IMA4, AAC or
+//! Apple Lossless audio. There are no suitable Rust libraries for H.264, so
+//! touchHLE has its own decoder. Compressed audio is decoded by
+//! [crate::audio::AudioDecoder]. The container parsing is in [crate::mp4],
+//! since `.m4a` audio files use it too.
+
+mod h264;
+
+use crate::audio::{decode_ima4_interleaved, pcm_to_i16, AudioDecoder, AudioFormat};
+use crate::mp4::{self, Sample, Track, TrackFormat};
+
+/// A decoded video frame in RGBA8 format.
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Decoded audio as interleaved signed 16-bit samples.
+pub struct PcmAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<i16>,
+}
+
+struct VideoTrack {
+    track: Track,
+    decoder: h264::Decoder,
+    nal_length_size: usize,
+    next_sample: usize,
+}
+
+impl VideoTrack {
+    /// Reset the decoder and feed it the parameter sets from the sample
+    /// description, so decoding can start from the first sample.
+    fn rewind(&mut self) -> Result<(), String> {
+        let TrackFormat::Avc {
+            ref parameter_sets, ..
+        } = self.track.format
+        else {
+            unreachable!();
+        };
+        self.decoder = h264::Decoder::new();
+        self.decoder
+            .decode_access_unit(parameter_sets.iter().map(|nal| &nal[..]))?;
+        self.next_sample = 0;
+        Ok(())
+    }
+}
+
+struct AudioTrack {
+    track: Track,
+    /// Decoder for compressed formats (AAC and Apple Lossless). This is
+    /// created when decoding starts.
+    decoder: Option<AudioDecoder>,
+    next_sample: usize,
+}
+
+/// Get the bytes of a sample from the movie file.
+fn sample_bytes<'a>(data: &'a [u8], sample: &Sample) -> Result<&'a [u8], String> {
+    data.get(sample.offset as usize..)
+        .and_then(|rest| rest.get(..sample.size as usize))
+        .ok_or_else(|| "Sample is out of bounds".to_string())
+}
+
+/// A movie file being played. The whole file is kept in memory, since movies
+/// bundled with apps are small and the samples of the different tracks are
+/// interleaved, but the video and audio are decoded a little at a time.
+pub struct Movie {
+    data: Vec<u8>,
+    duration: f64,
+    video: Option<VideoTrack>,
+    audio: Option<AudioTrack>,
+}
+
+impl Movie {
+    /// Parse a movie file. Fails if the file has no supported tracks.
+    pub fn open(data: Vec<u8>) -> Result<Movie, String> {
+        let mut duration: f64 = 0.0;
+        let mut video = None;
+        let mut audio = None;
+        for track in mp4::parse_tracks(&data)? {
+            duration = duration.max(track.duration_seconds());
+            match track.format {
+                TrackFormat::Avc {
+                    nal_length_size, ..
+                } if video.is_none() => {
+                    let mut video_track = VideoTrack {
+                        track,
+                        decoder: h264::Decoder::new(),
+                        nal_length_size,
+                        next_sample: 0,
+                    };
+                    video_track.rewind()?;
+                    video = Some(video_track);
+                }
+                TrackFormat::Audio { .. } if audio.is_none() => {
+                    audio = Some(AudioTrack {
+                        track,
+                        decoder: None,
+                        next_sample: 0,
+                    })
+                }
+                TrackFormat::Other(format_id) => {
+                    log!(
+                        "Warning: unsupported video format {:?}, ignoring track",
+                        String::from_utf8_lossy(&format_id)
+                    );
+                }
+                _ => {
+                    log!("Warning: ignoring additional movie track");
+                }
+            }
+        }
+        if video.is_none() && audio.is_none() {
+            return Err("No supported tracks".to_string());
+        }
+        Ok(Movie {
+            data,
+            duration,
+            video,
+            audio,
+        })
+    }
+
+    /// Duration in seconds.
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// Go back to the start of the movie.
+    pub fn rewind(&mut self) -> Result<(), String> {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.decoder = None;
+            audio.next_sample = 0;
+        }
+        match self.video.as_mut() {
+            Some(video) => video.rewind(),
+            None => Ok(()),
+        }
+    }
+
+    /// Presentation time in seconds of the next video frame, if there is one.
+    pub fn next_frame_time(&self) -> Option<f64> {
+        let video = self.video.as_ref()?;
+        let sample = video.track.samples.get(video.next_sample)?;
+        Some(sample.time as f64 / f64::from(video.track.timescale.max(1)))
+    }
+
+    /// Decode the next video frame. Returns [None] if the end of the video has
+    /// been reached or if the frame produced no picture.
+    pub fn decode_next_frame(&mut self) -> Result<Option<Frame>, String> {
+        let Some(video) = self.video.as_mut() else {
+            return Ok(None);
+        };
+        let Some(sample) = video.track.samples.get(video.next_sample) else {
+            return Ok(None);
+        };
+        video.next_sample += 1;
+
+        let bytes = sample_bytes(&self.data, sample)?;
+        // Samples are sequences of NAL units with big-endian length prefixes.
+        let mut nal_units = Vec::new();
+        let mut rest = bytes;
+        while rest.len() >= video.nal_length_size {
+            let (length, after) = rest.split_at(video.nal_length_size);
+            let length = length
+                .iter()
+                .fold(0usize, |acc, &byte| (acc << 8) | usize::from(byte));
+            let nal_unit = after
+                .get(..length)
+                .ok_or_else(|| "NAL unit is out of bounds".to_string())?;
+            nal_units.push(nal_unit);
+            rest = &after[length..];
+        }
+
+        let Some(picture) = video.decoder.decode_access_unit(nal_units)? else {
+            return Ok(None);
+        };
+        Ok(Some(picture_to_rgba(&picture)))
+    }
+
+    /// Returns [true] if the movie has an audio track.
+    pub fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    /// Decode about `seconds` worth of the audio track, continuing from where
+    /// the last call left off. Returns [None] once the end of the track has
+    /// been reached, or if there is no audio track.
+    pub fn decode_audio_chunk(&mut self, seconds: f64) -> Result<Option<PcmAudio>, String> {
+        let Some(audio) = self.audio.as_mut() else {
+            return Ok(None);
+        };
+        let track = &audio.track;
+        let Some(first_sample) = track.samples.get(audio.next_sample) else {
+            return Ok(None);
+        };
+        let timescale = f64::from(track.timescale.max(1));
+        let end_time = first_sample.time as f64 / timescale + seconds;
+        let TrackFormat::Audio {
+            format_id,
+            channels,
+            bits_per_channel,
+            sample_rate,
+            format_specific_flags,
+            ref magic_cookie,
+            ..
+        } = track.format
+        else {
+            unreachable!();
+        };
+
+        // Uncompressed and IMA4 samples are whole chunks of packets, so they
+        // can be decoded separately too.
+        let mut packets = Vec::new();
+        while let Some(sample) = track.samples.get(audio.next_sample) {
+            if !packets.is_empty() && sample.time as f64 / timescale >= end_time {
+                break;
+            }
+            packets.push(sample_bytes(&self.data, sample)?);
+            audio.next_sample += 1;
+        }
+
+        // Each sample is a compressed packet that must be decoded separately.
+        if let b"mp4a" | b"alac" = &format_id {
+            let format = if &format_id == b"mp4a" {
+                AudioFormat::Aac
+            } else {
+                AudioFormat::AppleLossless
+            };
+            if audio.decoder.is_none() {
+                audio.decoder = Some(AudioDecoder::new(
+                    &format,
+                    sample_rate as u32,
+                    channels.into(),
+                    magic_cookie.as_deref(),
+                )?);
+            }
+            let decoder = audio.decoder.as_mut().unwrap();
+            let mut samples = Vec::new();
+            let mut channels = channels;
+            for packet in packets {
+                match decoder.decode_packet(packet, &mut samples) {
+                    Ok(decoded_channels) => channels = decoded_channels as u16,
+                    Err(e) => {
+                        log_dbg!("Couldn't decode audio packet: {}", e);
+                    }
+                }
+            }
+            return Ok(Some(PcmAudio {
+                sample_rate: sample_rate as u32,
+                channels,
+                samples,
+            }));
+        }
+
+        let bytes = packets.concat();
+        let bytes_per_sample = usize::from(bits_per_channel / 8);
+
+        let samples = match &format_id {
+            b"raw " => pcm_to_i16(&bytes, 1, false, false, true),
+            b"twos" => pcm_to_i16(&bytes, bytes_per_sample, false, true, true),
+            b"sowt" => pcm_to_i16(&bytes, bytes_per_sample, false, true, false),
+            b"lpcm" => {
+                // kAudioFormatFlagIsFloat and kAudioFormatFlagIsBigEndian
+                let flags = format_specific_flags.unwrap_or(0);
+                pcm_to_i16(
+                    &bytes,
+                    bytes_per_sample,
+                    flags & 1 != 0,
+                    true,
+                    flags & 2 != 0,
+                )
+            }
+            // Packets for each channel alternate, so this needs to be
+            // interleaved.
+            b"ima4" => decode_ima4_interleaved(&bytes, channels.into()),
+            _ => {
+                return Err(format!(
+                    "Unsupported audio format {:?}",
+                    String::from_utf8_lossy(&format_id)
+                ))
+            }
+        };
+
+        Ok(Some(PcmAudio {
+            sample_rate: sample_rate as u32,
+            channels,
+            samples,
+        }))
+    }
+}
+
+/// Crop and convert a picture from YCbCr (ITU-R BT.601, limited range) to
+/// RGBA8.
+fn picture_to_rgba(picture: &h264::Picture) -> Frame {
+    let (crop_left, crop_right, crop_top, crop_bottom) = picture.crop;
+    let width = picture.width - crop_left - crop_right;
+    let height = picture.height - crop_top - crop_bottom;
+    let chroma_width = picture.width / 2;
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in crop_top..crop_top + height {
+        for x in crop_left..crop_left + width {
+            let luma = i32::from(picture.luma[y * picture.width + x]) - 16;
+            let chroma_idx = (y / 2) * chroma_width + x / 2;
+            let cb = i32::from(picture.chroma[0][chroma_idx]) - 128;
+            let cr = i32::from(picture.chroma[1][chroma_idx]) - 128;
+            let r = (298 * luma + 409 * cr + 128) >> 8;
+            let g = (298 * luma - 100 * cb - 208 * cr + 128) >> 8;
+            let b = (298 * luma + 516 * cb + 128) >> 8;
+            pixels.extend_from_slice(&[
+                r.clamp(0, 255) as u8,
+                g.clamp(0, 255) as u8,
+                b.clamp(0, 255) as u8,
+                255,
+            ]);
+        }
+    }
+
+    Frame {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    }
+}
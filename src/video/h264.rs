@@ -0,0 +1,610 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! H.264 (MPEG-4 Part 10, AVC) video decoding.
+//!
+//! This supports the subset of H.264 used by the Baseline profile, which is
+//! what iPhone OS devices of the era could play back: progressive 4:2:0 8-bit
+//! video with CAVLC entropy coding and I and P slices. Streams using other
+//! features (CABAC, B slices, interlacing, slice groups, weighted prediction,
+//! 8x8 transforms, scaling matrices) are rejected with an error.
+//!
+//! Pictures are output in decoding order, which matches the display order for
+//! streams without B slices.
+//!
+//! Resources:
+//! - [ITU-T Recommendation H.264](https://www.itu.int/rec/T-REC-H.264), referred
+//!   to as "the spec" in this module's code. Section numbers refer to the
+//!   2003 to 2010 editions.
+
+mod bitstream;
+mod cavlc;
+mod deblock;
+mod inter;
+mod intra;
+mod macroblock;
+mod params;
+mod picture;
+mod slice_header;
+mod transform;
+
+pub use picture::Picture;
+
+use bitstream::{unescape_rbsp, BitReader};
+use params::{Pps, Sps};
+use picture::DecodingPicture;
+use slice_header::{MemoryManagementOperation, RefPicListModification, SliceHeader, SliceType};
+use std::rc::Rc;
+
+struct ReferencePicture {
+    pic: Rc<Picture>,
+    frame_num: u32,
+    /// [None] for short-term references.
+    long_term_frame_idx: Option<u32>,
+}
+
+/// The picture currently being decoded and the information from its first
+/// slice header that is needed once it's complete.
+struct CurrentPicture {
+    decoding: DecodingPicture,
+    sps_id: u32,
+    is_idr: bool,
+    is_reference: bool,
+    frame_num: u32,
+    long_term_reference: bool,
+    memory_management_operations: Option<Vec<MemoryManagementOperation>>,
+}
+
+pub struct Decoder {
+    sps: Vec<Option<Sps>>,
+    pps: Vec<Option<Pps>>,
+    /// Decoded picture buffer, only containing reference pictures.
+    dpb: Vec<ReferencePicture>,
+    /// [None] means "no long-term frame indices".
+    max_long_term_frame_idx: Option<u32>,
+    next_picture_id: u32,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder {
+            sps: (0..32).map(|_| None).collect(),
+            pps: (0..256).map(|_| None).collect(),
+            dpb: Vec::new(),
+            max_long_term_frame_idx: None,
+            next_picture_id: 0,
+        }
+    }
+
+    /// Decode an access unit, given as a sequence of NAL units (without start
+    /// codes or length prefixes). Returns the decoded picture, if the access
+    /// unit contained one.
+    pub fn decode_access_unit<'a>(
+        &mut self,
+        nal_units: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<Option<Rc<Picture>>, String> {
+        let mut current = None;
+        for nal_unit in nal_units {
+            let Some((&header, payload)) = nal_unit.split_first() else {
+                continue;
+            };
+            let nal_ref_idc = (header >> 5) & 3;
+            let nal_unit_type = header & 0x1f;
+            let rbsp = unescape_rbsp(payload);
+            let mut reader = BitReader::new(&rbsp);
+            match nal_unit_type {
+                1 | 5 => {
+                    let is_idr = nal_unit_type == 5;
+                    self.decode_slice(&mut current, &mut reader, is_idr, nal_ref_idc)?;
+                }
+                7 => {
+                    let sps = Sps::parse(&mut reader)?;
+                    log_dbg!("SPS: {:?}", sps);
+                    let id = sps.id as usize;
+                    self.sps[id] = Some(sps);
+                }
+                8 => {
+                    let pps = Pps::parse(&mut reader)?;
+                    log_dbg!("PPS: {:?}", pps);
+                    let id = pps.id as usize;
+                    self.pps[id] = Some(pps);
+                }
+                2..=4 => return Err("Data partitioning is not supported".to_string()),
+                // SEI, access unit delimiters, filler data etc can be ignored.
+                _ => (),
+            }
+        }
+
+        let Some(current) = current else {
+            return Ok(None);
+        };
+        Ok(Some(self.finish_picture(current)))
+    }
+
+    fn params(&self, pps_id: u32) -> Result<(&Sps, &Pps), String> {
+        let pps = self
+            .pps
+            .get(pps_id as usize)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| format!("Missing PPS {}", pps_id))?;
+        let sps = self
+            .sps
+            .get(pps.sps_id as usize)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| format!("Missing SPS {}", pps.sps_id))?;
+        Ok((sps, pps))
+    }
+
+    fn decode_slice(
+        &mut self,
+        current: &mut Option<CurrentPicture>,
+        reader: &mut BitReader,
+        is_idr: bool,
+        nal_ref_idc: u8,
+    ) -> Result<(), String> {
+        let header = SliceHeader::parse(reader, is_idr, nal_ref_idc, |pps_id| self.params(pps_id))?;
+        // Redundant slices are only useful if the primary slices were lost.
+        if header.redundant_pic_cnt > 0 {
+            return Ok(());
+        }
+        let picture_id = self.next_picture_id;
+        if current.is_none() {
+            self.next_picture_id = picture_id.wrapping_add(1);
+        }
+        let (sps, pps) = self.params(header.pps_id)?;
+
+        if let Some(current) = current {
+            if current.sps_id != sps.id {
+                return Err("SPS changed within a picture".to_string());
+            }
+        } else {
+            *current = Some(CurrentPicture {
+                decoding: DecodingPicture::new(picture_id, sps),
+                sps_id: sps.id,
+                is_idr,
+                is_reference: nal_ref_idc != 0,
+                frame_num: header.frame_num,
+                long_term_reference: header.long_term_reference,
+                memory_management_operations: header.memory_management_operations.clone(),
+            });
+        }
+        let current = current.as_mut().unwrap();
+
+        let ref_list = if header.slice_type == SliceType::P {
+            self.build_ref_pic_list(sps, &header)?
+        } else {
+            Vec::new()
+        };
+        macroblock::decode_slice_data(&mut current.decoding, reader, &header, pps, &ref_list)
+    }
+
+    /// `FrameNumWrap` of a short-term reference picture.
+    fn frame_num_wrap(reference: &ReferencePicture, frame_num: u32, max_frame_num: u32) -> i64 {
+        if reference.frame_num > frame_num {
+            i64::from(reference.frame_num) - i64::from(max_frame_num)
+        } else {
+            reference.frame_num.into()
+        }
+    }
+
+    /// Construct `RefPicList0` for a P slice (section 8.2.4 of the spec).
+    fn build_ref_pic_list(
+        &self,
+        sps: &Sps,
+        header: &SliceHeader,
+    ) -> Result<Vec<Rc<Picture>>, String> {
+        let max_frame_num = 1 << sps.log2_max_frame_num;
+        let frame_num = header.frame_num;
+
+        let mut short_term: Vec<&ReferencePicture> = self
+            .dpb
+            .iter()
+            .filter(|reference| reference.long_term_frame_idx.is_none())
+            .collect();
+        short_term
+            .sort_by_key(|&reference| -Self::frame_num_wrap(reference, frame_num, max_frame_num));
+        let mut long_term: Vec<&ReferencePicture> = self
+            .dpb
+            .iter()
+            .filter(|reference| reference.long_term_frame_idx.is_some())
+            .collect();
+        long_term.sort_by_key(|reference| reference.long_term_frame_idx);
+        let mut list: Vec<Rc<Picture>> = short_term
+            .into_iter()
+            .chain(long_term)
+            .map(|reference| reference.pic.clone())
+            .collect();
+
+        let mut pic_num_pred = i64::from(frame_num);
+        for (ref_idx, &modification) in header.ref_pic_list_modifications.iter().enumerate() {
+            let pic = match modification {
+                RefPicListModification::ShortTermSubtract(abs_diff_pic_num)
+                | RefPicListModification::ShortTermAdd(abs_diff_pic_num) => {
+                    let abs_diff_pic_num = i64::from(abs_diff_pic_num);
+                    let max_frame_num = i64::from(max_frame_num);
+                    let mut pic_num_no_wrap = match modification {
+                        RefPicListModification::ShortTermSubtract(_) => {
+                            pic_num_pred - abs_diff_pic_num
+                        }
+                        _ => pic_num_pred + abs_diff_pic_num,
+                    };
+                    if pic_num_no_wrap < 0 {
+                        pic_num_no_wrap += max_frame_num;
+                    } else if pic_num_no_wrap >= max_frame_num {
+                        pic_num_no_wrap -= max_frame_num;
+                    }
+                    pic_num_pred = pic_num_no_wrap;
+                    let pic_num = if pic_num_no_wrap > i64::from(frame_num) {
+                        pic_num_no_wrap - max_frame_num
+                    } else {
+                        pic_num_no_wrap
+                    };
+                    self.dpb.iter().find(|reference| {
+                        reference.long_term_frame_idx.is_none()
+                            && Self::frame_num_wrap(reference, frame_num, max_frame_num as u32)
+                                == pic_num
+                    })
+                }
+                RefPicListModification::LongTerm(long_term_pic_num) => self
+                    .dpb
+                    .iter()
+                    .find(|reference| reference.long_term_frame_idx == Some(long_term_pic_num)),
+            };
+            let pic = pic
+                .ok_or_else(|| "Missing picture for reference list modification".to_string())?
+                .pic
+                .clone();
+            list.insert(ref_idx.min(list.len()), pic.clone());
+            let mut i = ref_idx + 1;
+            while i < list.len() {
+                if Rc::ptr_eq(&list[i], &pic) {
+                    list.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        list.truncate(header.num_ref_idx_l0_active as usize);
+        Ok(list)
+    }
+
+    /// Deblock the picture and do reference picture marking (section 8.2.5 of
+    /// the spec).
+    fn finish_picture(&mut self, current: CurrentPicture) -> Rc<Picture> {
+        let CurrentPicture {
+            mut decoding,
+            sps_id,
+            is_idr,
+            is_reference,
+            mut frame_num,
+            long_term_reference,
+            memory_management_operations,
+        } = current;
+
+        deblock::deblock_picture(&mut decoding);
+        let pic = Rc::new(decoding.pic);
+        if !is_reference {
+            return pic;
+        }
+
+        let sps = self.sps[sps_id as usize].as_ref().unwrap();
+        let max_frame_num = 1u32 << sps.log2_max_frame_num;
+        let max_num_ref_frames = sps.max_num_ref_frames.max(1) as usize;
+
+        let mut current_long_term_frame_idx = None;
+        if is_idr {
+            self.dpb.clear();
+            if long_term_reference {
+                current_long_term_frame_idx = Some(0);
+                self.max_long_term_frame_idx = Some(0);
+            } else {
+                self.max_long_term_frame_idx = None;
+            }
+        } else if let Some(operations) = memory_management_operations {
+            for operation in operations {
+                self.apply_memory_management_operation(
+                    operation,
+                    frame_num,
+                    max_frame_num,
+                    &mut current_long_term_frame_idx,
+                );
+                if let MemoryManagementOperation::UnmarkAll = operation {
+                    // The current picture is treated as having frame_num 0
+                    // from now on.
+                    frame_num = 0;
+                }
+            }
+        } else if self.dpb.len() >= max_num_ref_frames {
+            // Sliding window: drop the oldest short-term reference.
+            let oldest = self
+                .dpb
+                .iter()
+                .enumerate()
+                .filter(|(_, reference)| reference.long_term_frame_idx.is_none())
+                .min_by_key(|(_, reference)| {
+                    Self::frame_num_wrap(reference, frame_num, max_frame_num)
+                })
+                .map(|(i, _)| i);
+            if let Some(oldest) = oldest {
+                self.dpb.remove(oldest);
+            }
+        }
+
+        // TODO: gaps in frame_num are not handled.
+        self.dpb.push(ReferencePicture {
+            pic: pic.clone(),
+            frame_num,
+            long_term_frame_idx: current_long_term_frame_idx,
+        });
+        // Guard against broken streams exceeding the DPB size.
+        while self.dpb.len() > max_num_ref_frames {
+            self.dpb.remove(0);
+        }
+
+        pic
+    }
+
+    fn apply_memory_management_operation(
+        &mut self,
+        operation: MemoryManagementOperation,
+        frame_num: u32,
+        max_frame_num: u32,
+        current_long_term_frame_idx: &mut Option<u32>,
+    ) {
+        let short_term_position = |dpb: &[ReferencePicture], difference_of_pic_nums: u32| {
+            let pic_num = i64::from(frame_num) - i64::from(difference_of_pic_nums);
+            dpb.iter().position(|reference| {
+                reference.long_term_frame_idx.is_none()
+                    && Self::frame_num_wrap(reference, frame_num, max_frame_num) == pic_num
+            })
+        };
+        let unmark_long_term = |dpb: &mut Vec<ReferencePicture>, long_term_frame_idx: u32| {
+            dpb.retain(|reference| reference.long_term_frame_idx != Some(long_term_frame_idx));
+        };
+
+        match operation {
+            MemoryManagementOperation::UnmarkShortTerm {
+                difference_of_pic_nums,
+            } => {
+                if let Some(i) = short_term_position(&self.dpb, difference_of_pic_nums) {
+                    self.dpb.remove(i);
+                }
+            }
+            MemoryManagementOperation::UnmarkLongTerm { long_term_pic_num } => {
+                unmark_long_term(&mut self.dpb, long_term_pic_num);
+            }
+            MemoryManagementOperation::ShortTermToLongTerm {
+                difference_of_pic_nums,
+                long_term_frame_idx,
+            } => {
+                unmark_long_term(&mut self.dpb, long_term_frame_idx);
+                if let Some(i) = short_term_position(&self.dpb, difference_of_pic_nums) {
+                    self.dpb[i].long_term_frame_idx = Some(long_term_frame_idx);
+                }
+            }
+            MemoryManagementOperation::SetMaxLongTermFrameIdx { max_plus_1 } => {
+                self.dpb.retain(|reference| {
+                    reference
+                        .long_term_frame_idx
+                        .map_or(true, |idx| idx < max_plus_1)
+                });
+                self.max_long_term_frame_idx = max_plus_1.checked_sub(1);
+            }
+            MemoryManagementOperation::UnmarkAll => {
+                self.dpb.clear();
+                self.max_long_term_frame_idx = None;
+            }
+            MemoryManagementOperation::CurrentToLongTerm {
+                long_term_frame_idx,
+            } => {
+                unmark_long_term(&mut self.dpb, long_term_frame_idx);
+                *current_long_term_frame_idx = Some(long_term_frame_idx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes RBSP bits for building test streams.
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bit_count: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: u32) {
+            for i in (0..count).rev() {
+                if self.bit_count % 8 == 0 {
+                    self.bytes.push(0);
+                }
+                let bit = ((value >> i) & 1) as u8;
+                *self.bytes.last_mut().unwrap() |= bit << (7 - self.bit_count % 8);
+                self.bit_count += 1;
+            }
+        }
+        fn ue(&mut self, value: u32) {
+            let len = 32 - (value + 1).leading_zeros();
+            self.bits(0, len - 1);
+            self.bits(value + 1, len);
+        }
+        fn se(&mut self, value: i32) {
+            self.ue(if value > 0 {
+                value as u32 * 2 - 1
+            } else {
+                value.unsigned_abs() * 2
+            });
+        }
+        fn align(&mut self) {
+            while self.bit_count % 8 != 0 {
+                self.bits(0, 1);
+            }
+        }
+        /// Add the RBSP trailing bits and produce a NAL unit with emulation
+        /// prevention bytes.
+        fn finish_nal_unit(mut self, header: u8) -> Vec<u8> {
+            self.bits(1, 1);
+            self.align();
+            let mut nal_unit = vec![header];
+            let mut zeros = 0;
+            for byte in self.bytes {
+                if zeros == 2 && byte <= 3 {
+                    nal_unit.push(3);
+                    zeros = 0;
+                }
+                zeros = if byte == 0 { zeros + 1 } else { 0 };
+                nal_unit.push(byte);
+            }
+            nal_unit
+        }
+    }
+
+    /// Baseline profile SPS 0, with no POC LSBs and one reference frame.
+    fn sps(log2_max_frame_num_minus4: u32, width_in_mbs: u32, height_in_mbs: u32) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(66, 8); // profile_idc
+        w.bits(0, 8); // constraint flags
+        w.bits(30, 8); // level_idc
+        w.ue(0); // seq_parameter_set_id
+        w.ue(log2_max_frame_num_minus4);
+        w.ue(2); // pic_order_cnt_type
+        w.ue(1); // max_num_ref_frames
+        w.bits(0, 1); // gaps_in_frame_num_value_allowed_flag
+        w.ue(width_in_mbs - 1);
+        w.ue(height_in_mbs - 1);
+        w.bits(1, 1); // frame_mbs_only_flag
+        w.bits(1, 1); // direct_8x8_inference_flag
+        w.bits(0, 1); // frame_cropping_flag
+        w.bits(0, 1); // vui_parameters_present_flag
+        w.finish_nal_unit(0x67)
+    }
+
+    /// PPS 0, which allows deblocking to be disabled per slice.
+    fn pps() -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.ue(0); // pic_parameter_set_id
+        w.ue(0); // seq_parameter_set_id
+        w.bits(0, 1); // entropy_coding_mode_flag
+        w.bits(0, 1); // bottom_field_pic_order_in_frame_present_flag
+        w.ue(0); // num_slice_groups_minus1
+        w.ue(0); // num_ref_idx_l0_default_active_minus1
+        w.ue(0); // num_ref_idx_l1_default_active_minus1
+        w.bits(0, 1); // weighted_pred_flag
+        w.bits(0, 2); // weighted_bipred_idc
+        w.se(0); // pic_init_qp_minus26
+        w.se(0); // pic_init_qs_minus26
+        w.se(0); // chroma_qp_index_offset
+        w.bits(1, 1); // deblocking_filter_control_present_flag
+        w.bits(0, 1); // constrained_intra_pred_flag
+        w.bits(0, 1); // redundant_pic_cnt_present_flag
+        w.finish_nal_unit(0x68)
+    }
+
+    fn pcm_luma(mb: usize, x: usize, y: usize) -> u8 {
+        (mb * 64 + y * 8 + x / 2 + 1) as u8
+    }
+    fn pcm_chroma(plane: usize, mb: usize, x: usize, y: usize) -> u8 {
+        (plane * 50 + mb * 10 + y + x + 100) as u8
+    }
+
+    /// IDR picture made of I_PCM macroblocks.
+    fn idr_slice(mb_count: usize) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.ue(0); // first_mb_in_slice
+        w.ue(7); // slice_type (I)
+        w.ue(0); // pic_parameter_set_id
+        w.bits(0, 4); // frame_num
+        w.ue(0); // idr_pic_id
+        w.bits(0, 1); // no_output_of_prior_pics_flag
+        w.bits(0, 1); // long_term_reference_flag
+        w.se(0); // slice_qp_delta
+        w.ue(1); // disable_deblocking_filter_idc
+        for mb in 0..mb_count {
+            w.ue(25); // mb_type (I_PCM)
+            w.align();
+            for y in 0..16 {
+                for x in 0..16 {
+                    w.bits(pcm_luma(mb, x, y).into(), 8);
+                }
+            }
+            for plane in 0..2 {
+                for y in 0..8 {
+                    for x in 0..8 {
+                        w.bits(pcm_chroma(plane, mb, x, y).into(), 8);
+                    }
+                }
+            }
+        }
+        w.finish_nal_unit(0x65)
+    }
+
+    /// Reference P picture where every macroblock is skipped.
+    fn skipped_p_slice(frame_num: u32, mb_count: u32) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.ue(0); // first_mb_in_slice
+        w.ue(5); // slice_type (P)
+        w.ue(0); // pic_parameter_set_id
+        w.bits(frame_num, 4);
+        w.bits(0, 1); // num_ref_idx_active_override_flag
+        w.bits(0, 1); // ref_pic_list_modification_flag_l0
+        w.bits(0, 1); // adaptive_ref_pic_marking_mode_flag
+        w.se(0); // slice_qp_delta
+        w.ue(1); // disable_deblocking_filter_idc
+        w.ue(mb_count); // mb_skip_run
+        w.finish_nal_unit(0x41)
+    }
+
+    fn check_pcm_picture(pic: &Picture, width_in_mbs: usize) {
+        assert_eq!((pic.width, pic.height), (width_in_mbs * 16, 16));
+        for mb in 0..width_in_mbs {
+            for y in 0..16 {
+                for x in 0..16 {
+                    assert_eq!(pic.luma[y * pic.width + mb * 16 + x], pcm_luma(mb, x, y));
+                }
+            }
+            for (plane, samples) in pic.chroma.iter().enumerate() {
+                for y in 0..8 {
+                    for x in 0..8 {
+                        assert_eq!(
+                            samples[y * pic.width / 2 + mb * 8 + x],
+                            pcm_chroma(plane, mb, x, y)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decode_pcm_and_skipped_pictures() {
+        let mut decoder = Decoder::new();
+        let (sps, pps, idr) = (sps(0, 2, 1), pps(), idr_slice(2));
+        let idr_pic = decoder
+            .decode_access_unit([&sps[..], &pps[..], &idr[..]])
+            .unwrap()
+            .unwrap();
+        check_pcm_picture(&idr_pic, 2);
+
+        // Skipped macroblocks are copied from the reference picture.
+        let p = skipped_p_slice(1, 2);
+        let p_pic = decoder.decode_access_unit([&p[..]]).unwrap().unwrap();
+        assert_ne!(p_pic.id, idr_pic.id);
+        check_pcm_picture(&p_pic, 2);
+    }
+
+    #[test]
+    fn reject_out_of_range_sps() {
+        let mut decoder = Decoder::new();
+        assert!(decoder.decode_access_unit([&sps(12, 2, 1)[..]]).is_ok());
+        assert!(decoder.decode_access_unit([&sps(13, 2, 1)[..]]).is_err());
+        assert!(decoder.decode_access_unit([&sps(0, 544, 1)[..]]).is_err());
+        assert!(decoder.decode_access_unit([&sps(0, 1, 544)[..]]).is_err());
+        assert!(decoder.decode_access_unit([&sps(0, 200, 200)[..]]).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Reading of raw byte sequence payloads (RBSPs) bit by bit.
+
+/// Remove the emulation prevention bytes from a NAL unit's payload, giving the
+/// RBSP (section 7.4.1 of the spec).
+pub fn unescape_rbsp(payload: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(payload.len());
+    let mut zeros = 0;
+    for &byte in payload {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    fn bits_left(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.pos)
+    }
+
+    pub fn is_byte_aligned(&self) -> bool {
+        self.pos % 8 == 0
+    }
+
+    /// Get the next 16 bits without advancing, padding with zeroes at the end
+    /// of the data. Used for variable-length code lookups.
+    pub fn peek_16(&self) -> u32 {
+        let byte_pos = self.pos / 8;
+        let mut window: u32 = 0;
+        for i in 0..3 {
+            window = (window << 8) | u32::from(*self.data.get(byte_pos + i).unwrap_or(&0));
+        }
+        (window >> (8 - self.pos % 8)) & 0xffff
+    }
+
+    pub fn skip(&mut self, bits: usize) -> Result<(), String> {
+        if bits > self.bits_left() {
+            return Err("Unexpected end of bitstream".to_string());
+        }
+        self.pos += bits;
+        Ok(())
+    }
+
+    /// `u(1)`
+    pub fn read_bit(&mut self) -> Result<bool, String> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /// `u(n)` for `n` up to 32.
+    pub fn read_bits(&mut self, bits: u32) -> Result<u32, String> {
+        assert!(bits <= 32);
+        if bits as usize > self.bits_left() {
+            return Err("Unexpected end of bitstream".to_string());
+        }
+        let mut value: u64 = 0;
+        for _ in 0..bits {
+            let byte = self.data[self.pos / 8];
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | u64::from(bit);
+            self.pos += 1;
+        }
+        Ok(value as u32)
+    }
+
+    /// `ue(v)`: unsigned Exp-Golomb code.
+    pub fn read_ue(&mut self) -> Result<u32, String> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err("Invalid Exp-Golomb code".to_string());
+            }
+        }
+        let suffix = self.read_bits(leading_zeros)?;
+        Ok(((1u64 << leading_zeros) - 1 + u64::from(suffix)) as u32)
+    }
+
+    /// `se(v)`: signed Exp-Golomb code.
+    pub fn read_se(&mut self) -> Result<i32, String> {
+        let code_num = self.read_ue()?;
+        let magnitude = ((code_num as i64 + 1) / 2) as i32;
+        Ok(if code_num % 2 == 1 {
+            magnitude
+        } else {
+            -magnitude
+        })
+    }
+
+    /// `te(v)`: truncated Exp-Golomb code with the range `0..=max`.
+    pub fn read_te(&mut self, max: u32) -> Result<u32, String> {
+        if max == 1 {
+            Ok(u32::from(!self.read_bit()?))
+        } else {
+            self.read_ue()
+        }
+    }
+
+    /// Implements `more_rbsp_data()`: returns [true] if there is more data
+    /// before the RBSP trailing bits.
+    pub fn more_rbsp_data(&self) -> bool {
+        let Some(last_byte_pos) = self.data.iter().rposition(|&byte| byte != 0) else {
+            return false;
+        };
+        // The stop bit is the last 1 bit in the RBSP.
+        let stop_bit_pos =
+            last_byte_pos * 8 + 7 - self.data[last_byte_pos].trailing_zeros() as usize;
+        self.pos < stop_bit_pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exp_golomb() {
+        // 1 | 010 | 011 | 00100 | 00101 | 1
+        let data = [0b1010_0110, 0b0100_0010, 0b1100_0000];
+        let mut reader = BitReader::new(&data);
+        assert_eq!(reader.read_ue(), Ok(0));
+        assert_eq!(reader.read_ue(), Ok(1));
+        assert_eq!(reader.read_se(), Ok(-1));
+        assert_eq!(reader.read_se(), Ok(2));
+        assert_eq!(reader.read_se(), Ok(-2));
+        assert_eq!(reader.read_te(1), Ok(0));
+    }
+
+    #[test]
+    fn rbsp_trailing_bits() {
+        let data = [0b1011_0000, 0b1000_0000, 0x00];
+        let mut reader = BitReader::new(&data);
+        assert!(reader.more_rbsp_data());
+        reader.skip(8).unwrap();
+        assert!(!reader.more_rbsp_data());
+    }
+
+    #[test]
+    fn emulation_prevention() {
+        assert_eq!(
+            unescape_rbsp(&[0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03, 0x00, 0x03]),
+            vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! CAVLC parsing of residual blocks (section 9.2 of the spec).
+//!
+//! The code tables are stored as pairs of arrays of code lengths and code
+//! values, with a length of zero marking unused entries.
+
+use super::bitstream::BitReader;
+
+/// Find the index of the code in a table that matches the next bits and
+/// consume it.
+fn read_vlc(reader: &mut BitReader, lens: &[u8], codes: &[u8]) -> Result<usize, String> {
+    let next_bits = reader.peek_16();
+    for (i, (&len, &code)) in lens.iter().zip(codes).enumerate() {
+        if len != 0 && next_bits >> (16 - len) == u32::from(code) {
+            reader.skip(len.into())?;
+            return Ok(i);
+        }
+    }
+    Err("Invalid CAVLC code".to_string())
+}
+
+/// Parse a `residual_block_cavlc()`. `coeff_level` must be zeroed and have at
+/// least `end_idx + 1` elements. `n_c` is the number of non-zero coefficients
+/// predicted from the neighbouring blocks, or -1 for chroma DC.
+///
+/// Returns the number of non-zero coefficients (`TotalCoeff(coeff_token)`).
+pub fn read_residual_block(
+    reader: &mut BitReader,
+    coeff_level: &mut [i32],
+    start_idx: usize,
+    end_idx: usize,
+    max_num_coeff: usize,
+    n_c: i32,
+) -> Result<u8, String> {
+    let token = match n_c {
+        -1 => read_vlc(
+            reader,
+            &CHROMA_DC_COEFF_TOKEN_LEN,
+            &CHROMA_DC_COEFF_TOKEN_BITS,
+        )?,
+        0..=1 => read_vlc(reader, &COEFF_TOKEN_LEN[0], &COEFF_TOKEN_BITS[0])?,
+        2..=3 => read_vlc(reader, &COEFF_TOKEN_LEN[1], &COEFF_TOKEN_BITS[1])?,
+        4..=7 => read_vlc(reader, &COEFF_TOKEN_LEN[2], &COEFF_TOKEN_BITS[2])?,
+        _ => read_vlc(reader, &COEFF_TOKEN_LEN[3], &COEFF_TOKEN_BITS[3])?,
+    };
+    let total_coeff = token / 4;
+    let trailing_ones = token % 4;
+    if total_coeff == 0 {
+        return Ok(0);
+    }
+    if total_coeff > max_num_coeff {
+        return Err("Too many coefficients in residual block".to_string());
+    }
+
+    let mut levels = [0i32; 16];
+    let mut suffix_length: u32 = if total_coeff > 10 && trailing_ones < 3 {
+        1
+    } else {
+        0
+    };
+    for (i, level) in levels.iter_mut().enumerate().take(total_coeff) {
+        if i < trailing_ones {
+            *level = if reader.read_bit()? { -1 } else { 1 };
+            continue;
+        }
+
+        let mut level_prefix: u32 = 0;
+        while !reader.read_bit()? {
+            level_prefix += 1;
+            if level_prefix > 32 {
+                return Err("Invalid level_prefix".to_string());
+            }
+        }
+        let mut level_code: i32 = (level_prefix.min(15) << suffix_length) as i32;
+        let level_suffix_size = if level_prefix == 14 && suffix_length == 0 {
+            4
+        } else if level_prefix >= 15 {
+            level_prefix - 3
+        } else {
+            suffix_length
+        };
+        if level_suffix_size > 0 {
+            level_code += reader.read_bits(level_suffix_size)? as i32;
+        }
+        if level_prefix >= 15 && suffix_length == 0 {
+            level_code += 15;
+        }
+        if level_prefix >= 16 {
+            level_code += (1 << (level_prefix - 3)) - 4096;
+        }
+        if i == trailing_ones && trailing_ones < 3 {
+            level_code += 2;
+        }
+        *level = if level_code % 2 == 0 {
+            (level_code + 2) >> 1
+        } else {
+            (-level_code - 1) >> 1
+        };
+
+        if suffix_length == 0 {
+            suffix_length = 1;
+        }
+        if level.abs() > (3 << (suffix_length - 1)) && suffix_length < 6 {
+            suffix_length += 1;
+        }
+    }
+
+    let mut zeros_left = if total_coeff < end_idx - start_idx + 1 {
+        let vlc_idx = total_coeff - 1;
+        if n_c == -1 {
+            read_vlc(
+                reader,
+                &CHROMA_DC_TOTAL_ZEROS_LEN[vlc_idx],
+                &CHROMA_DC_TOTAL_ZEROS_BITS[vlc_idx],
+            )?
+        } else {
+            read_vlc(
+                reader,
+                &TOTAL_ZEROS_LEN[vlc_idx],
+                &TOTAL_ZEROS_BITS[vlc_idx],
+            )?
+        }
+    } else {
+        0
+    };
+    if total_coeff + zeros_left > end_idx - start_idx + 1 {
+        return Err("Invalid total_zeros".to_string());
+    }
+
+    let mut runs = [0usize; 16];
+    for run in runs.iter_mut().take(total_coeff - 1) {
+        if zeros_left == 0 {
+            break;
+        }
+        let vlc_idx = zeros_left.min(7) - 1;
+        *run = read_vlc(reader, &RUN_BEFORE_LEN[vlc_idx], &RUN_BEFORE_BITS[vlc_idx])?;
+        if *run > zeros_left {
+            return Err("Invalid run_before".to_string());
+        }
+        zeros_left -= *run;
+    }
+    runs[total_coeff - 1] = zeros_left;
+
+    let mut coeff_num = start_idx;
+    for i in (0..total_coeff).rev() {
+        coeff_num += runs[i];
+        coeff_level[coeff_num] = levels[i];
+        coeff_num += 1;
+    }
+
+    Ok(total_coeff as u8)
+}
+
+/// Mapping of `coded_block_pattern` code numbers to values for intra
+/// macroblocks (table 9-4 of the spec).
+pub const CODED_BLOCK_PATTERN_INTRA: [u8; 48] = [
+    47, 31, 15, 0, 23, 27, 29, 30, 7, 11, 13, 14, 39, 43, 45, 46, 16, 3, 5, 10, 12, 19, 21, 26, 28,
+    35, 37, 42, 44, 1, 2, 4, 8, 17, 18, 20, 24, 6, 9, 22, 25, 32, 33, 34, 36, 40, 38, 41,
+];
+/// Mapping of `coded_block_pattern` code numbers to values for inter
+/// macroblocks (table 9-4 of the spec).
+pub const CODED_BLOCK_PATTERN_INTER: [u8; 48] = [
+    0, 16, 1, 2, 4, 8, 32, 3, 5, 10, 12, 15, 47, 7, 11, 13, 14, 6, 9, 31, 35, 37, 42, 44, 33, 34,
+    36, 40, 39, 43, 45, 46, 17, 18, 20, 24, 19, 21, 26, 28, 23, 27, 29, 30, 22, 25, 38, 41,
+];
+
+// Tables 9-5, 9-7, 9-8, 9-9 and 9-10 of the spec. The coeff_token tables are
+// indexed by TotalCoeff * 4 + TrailingOnes, the total_zeros tables by
+// tzVlcIndex - 1 and then total_zeros, and the run_before tables by
+// Min(zerosLeft, 7) - 1 and then run_before.
+
+const COEFF_TOKEN_LEN: [[u8; 68]; 4] = [
+    [
+        1, 0, 0, 0, 6, 2, 0, 0, 8, 6, 3, 0, 9, 8, 7, 5, 10, 9, 8, 6, 11, 10, 9, 7, 13, 11, 10, 8,
+        13, 13, 11, 9, 13, 13, 13, 10, 14, 14, 13, 11, 14, 14, 14, 13, 15, 15, 14, 14, 15, 15, 15,
+        14, 16, 15, 15, 15, 16, 16, 16, 15, 16, 16, 16, 16, 16, 16, 16, 16,
+    ],
+    [
+        2, 0, 0, 0, 6, 2, 0, 0, 6, 5, 3, 0, 7, 6, 6, 4, 8, 6, 6, 4, 8, 7, 7, 5, 9, 8, 8, 6, 11, 9,
+        9, 6, 11, 11, 11, 7, 12, 11, 11, 9, 12, 12, 12, 11, 12, 12, 12, 11, 13, 13, 13, 12, 13, 13,
+        13, 13, 13, 14, 13, 13, 14, 14, 14, 13, 14, 14, 14, 14,
+    ],
+    [
+        4, 0, 0, 0, 6, 4, 0, 0, 6, 5, 4, 0, 6, 5, 5, 4, 7, 5, 5, 4, 7, 5, 5, 4, 7, 6, 6, 4, 7, 6,
+        6, 4, 8, 7, 7, 5, 8, 8, 7, 6, 9, 8, 8, 7, 9, 9, 8, 8, 9, 9, 9, 8, 10, 9, 9, 9, 10, 10, 10,
+        10, 10, 10, 10, 10, 10, 10, 10, 10,
+    ],
+    [
+        6, 0, 0, 0, 6, 6, 0, 0, 6, 6, 6, 0, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
+        6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6,
+        6, 6, 6, 6, 6, 6, 6, 6,
+    ],
+];
+const COEFF_TOKEN_BITS: [[u8; 68]; 4] = [
+    [
+        1, 0, 0, 0, 5, 1, 0, 0, 7, 4, 1, 0, 7, 6, 5, 3, 7, 6, 5, 3, 7, 6, 5, 4, 15, 6, 5, 4, 11,
+        14, 5, 4, 8, 10, 13, 4, 15, 14, 9, 4, 11, 10, 13, 12, 15, 14, 9, 12, 11, 10, 13, 8, 15, 1,
+        9, 12, 11, 14, 13, 8, 7, 10, 9, 12, 4, 6, 5, 8,
+    ],
+    [
+        3, 0, 0, 0, 11, 2, 0, 0, 7, 7, 3, 0, 7, 10, 9, 5, 7, 6, 5, 4, 4, 6, 5, 6, 7, 6, 5, 8, 15,
+        6, 5, 4, 11, 14, 13, 4, 15, 10, 9, 4, 11, 14, 13, 12, 8, 10, 9, 8, 15, 14, 13, 12, 11, 10,
+        9, 12, 7, 11, 6, 8, 9, 8, 10, 1, 7, 6, 5, 4,
+    ],
+    [
+        15, 0, 0, 0, 15, 14, 0, 0, 11, 15, 13, 0, 8, 12, 14, 12, 15, 10, 11, 11, 11, 8, 9, 10, 9,
+        14, 13, 9, 8, 10, 9, 8, 15, 14, 13, 13, 11, 14, 10, 12, 15, 10, 13, 12, 11, 14, 9, 12, 8,
+        10, 13, 8, 13, 7, 9, 12, 9, 12, 11, 10, 5, 8, 7, 6, 1, 4, 3, 2,
+    ],
+    [
+        3, 0, 0, 0, 0, 1, 0, 0, 4, 5, 6, 0, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+        22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44,
+        45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63,
+    ],
+];
+const CHROMA_DC_COEFF_TOKEN_LEN: [u8; 20] =
+    [2, 0, 0, 0, 6, 1, 0, 0, 6, 6, 3, 0, 6, 7, 7, 6, 6, 8, 8, 7];
+const CHROMA_DC_COEFF_TOKEN_BITS: [u8; 20] =
+    [1, 0, 0, 0, 7, 1, 0, 0, 4, 6, 1, 0, 3, 3, 2, 5, 2, 3, 2, 0];
+const TOTAL_ZEROS_LEN: [[u8; 16]; 15] = [
+    [1, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 9],
+    [3, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 6, 6, 6, 6, 0],
+    [4, 3, 3, 3, 4, 4, 3, 3, 4, 5, 5, 6, 5, 6, 0, 0],
+    [5, 3, 4, 4, 3, 3, 3, 4, 3, 4, 5, 5, 5, 0, 0, 0],
+    [4, 4, 4, 3, 3, 3, 3, 3, 4, 5, 4, 5, 0, 0, 0, 0],
+    [6, 5, 3, 3, 3, 3, 3, 3, 4, 3, 6, 0, 0, 0, 0, 0],
+    [6, 5, 3, 3, 3, 2, 3, 4, 3, 6, 0, 0, 0, 0, 0, 0],
+    [6, 4, 5, 3, 2, 2, 3, 3, 6, 0, 0, 0, 0, 0, 0, 0],
+    [6, 6, 4, 2, 2, 3, 2, 5, 0, 0, 0, 0, 0, 0, 0, 0],
+    [5, 5, 3, 2, 2, 2, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [4, 4, 3, 3, 1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [4, 4, 2, 1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [2, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+const TOTAL_ZEROS_BITS: [[u8; 16]; 15] = [
+    [1, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 1],
+    [7, 6, 5, 4, 3, 5, 4, 3, 2, 3, 2, 3, 2, 1, 0, 0],
+    [5, 7, 6, 5, 4, 3, 4, 3, 2, 3, 2, 1, 1, 0, 0, 0],
+    [3, 7, 5, 4, 6, 5, 4, 3, 3, 2, 2, 1, 0, 0, 0, 0],
+    [5, 4, 3, 7, 6, 5, 4, 3, 2, 1, 1, 0, 0, 0, 0, 0],
+    [1, 1, 7, 6, 5, 4, 3, 2, 1, 1, 0, 0, 0, 0, 0, 0],
+    [1, 1, 5, 4, 3, 3, 2, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+    [1, 1, 1, 3, 3, 2, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+    [1, 0, 1, 3, 2, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+    [1, 0, 1, 3, 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 2, 1, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+const CHROMA_DC_TOTAL_ZEROS_LEN: [[u8; 4]; 3] = [[1, 2, 3, 3], [1, 2, 2, 0], [1, 1, 0, 0]];
+const CHROMA_DC_TOTAL_ZEROS_BITS: [[u8; 4]; 3] = [[1, 1, 1, 0], [1, 1, 0, 0], [1, 0, 0, 0]];
+const RUN_BEFORE_LEN: [[u8; 15]; 7] = [
+    [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [1, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [2, 2, 2, 3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [2, 2, 3, 3, 3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [2, 3, 3, 3, 3, 3, 3, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 3, 3, 3, 3, 3, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+];
+const RUN_BEFORE_BITS: [[u8; 15]; 7] = [
+    [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 2, 3, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 0, 1, 3, 2, 5, 4, 0, 0, 0, 0, 0, 0, 0, 0],
+    [7, 6, 5, 4, 3, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that no code in a table is a prefix of another.
+    fn assert_prefix_free(lens: &[u8], codes: &[u8]) {
+        for (i, (&len_a, &code_a)) in lens.iter().zip(codes).enumerate() {
+            for (j, (&len_b, &code_b)) in lens.iter().zip(codes).enumerate() {
+                if i == j || len_a == 0 || len_b == 0 || len_a > len_b {
+                    continue;
+                }
+                assert_ne!(u32::from(code_b) >> (len_b - len_a), u32::from(code_a));
+            }
+        }
+    }
+
+    #[test]
+    fn code_tables_are_prefix_free() {
+        for i in 0..4 {
+            assert_prefix_free(&COEFF_TOKEN_LEN[i], &COEFF_TOKEN_BITS[i]);
+        }
+        assert_prefix_free(&CHROMA_DC_COEFF_TOKEN_LEN, &CHROMA_DC_COEFF_TOKEN_BITS);
+        for i in 0..15 {
+            assert_prefix_free(&TOTAL_ZEROS_LEN[i], &TOTAL_ZEROS_BITS[i]);
+        }
+        for i in 0..3 {
+            assert_prefix_free(
+                &CHROMA_DC_TOTAL_ZEROS_LEN[i],
+                &CHROMA_DC_TOTAL_ZEROS_BITS[i],
+            );
+        }
+        for i in 0..7 {
+            assert_prefix_free(&RUN_BEFORE_LEN[i], &RUN_BEFORE_BITS[i]);
+        }
+    }
+
+    #[test]
+    fn residual_block() {
+        // Coefficients 3, -1, 0, 0, -1, 1, 0, 1 in zigzag order (nC = 0):
+        // coeff_token (TotalCoeff 5, TrailingOnes 3): 0000100
+        // trailing ones signs (1, 1, -1): 001
+        // levels (-1, 3): 01, 0010
+        // total_zeros (3): 111
+        // run_before (1, 0, 2): 10, 1, 00
+        let data = [0b0000_1000, 0b0101_0010, 0b1111_0100, 0b1000_0000];
+        let mut reader = BitReader::new(&data);
+        let mut coeffs = [0; 16];
+        assert_eq!(
+            read_residual_block(&mut reader, &mut coeffs, 0, 15, 16, 0),
+            Ok(5)
+        );
+        assert_eq!(coeffs, [3, -1, 0, 0, -1, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,330 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Deblocking filter (section 8.7 of the spec).
+
+use super::picture::{DecodingPicture, NOT_DECODED};
+use super::transform::chroma_qp;
+
+/// Table 8-16 of the spec, indexed by `indexA`.
+const ALPHA: [i32; 52] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 4, 5, 6, 7, 8, 9, 10, 12, 13, 15, 17, 20,
+    22, 25, 28, 32, 36, 40, 45, 50, 56, 63, 71, 80, 90, 101, 113, 127, 144, 162, 182, 203, 226,
+    255, 255,
+];
+/// Table 8-16 of the spec, indexed by `indexB`.
+const BETA: [i32; 52] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 6, 6, 7, 7, 8, 8,
+    9, 9, 10, 10, 11, 11, 12, 12, 13, 13, 14, 14, 15, 15, 16, 16, 17, 17, 18, 18,
+];
+/// Table 8-17 of the spec, indexed by `indexA` and then `bS` - 1.
+const TC0: [[i32; 3]; 52] = [
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 1],
+    [0, 0, 1],
+    [0, 0, 1],
+    [0, 0, 1],
+    [0, 1, 1],
+    [0, 1, 1],
+    [1, 1, 1],
+    [1, 1, 1],
+    [1, 1, 1],
+    [1, 1, 1],
+    [1, 1, 2],
+    [1, 1, 2],
+    [1, 1, 2],
+    [1, 1, 2],
+    [1, 2, 3],
+    [1, 2, 3],
+    [2, 2, 3],
+    [2, 2, 4],
+    [2, 3, 4],
+    [2, 3, 4],
+    [3, 3, 5],
+    [3, 4, 6],
+    [3, 4, 6],
+    [4, 5, 7],
+    [4, 5, 8],
+    [4, 6, 9],
+    [5, 7, 10],
+    [6, 8, 11],
+    [6, 8, 13],
+    [7, 10, 14],
+    [8, 11, 16],
+    [9, 12, 18],
+    [10, 13, 20],
+    [11, 15, 23],
+    [13, 17, 25],
+];
+
+/// Filter thresholds for one edge.
+struct EdgeParams {
+    /// Boundary strength for each group of four luma samples along the edge.
+    bs: [u8; 4],
+    index_a: usize,
+    alpha: i32,
+    beta: i32,
+}
+
+impl EdgeParams {
+    fn new(bs: [u8; 4], qp_av: i32, alpha_offset: i32, beta_offset: i32) -> EdgeParams {
+        let index_a = (qp_av + alpha_offset).clamp(0, 51) as usize;
+        let index_b = (qp_av + beta_offset).clamp(0, 51) as usize;
+        EdgeParams {
+            bs,
+            index_a,
+            alpha: ALPHA[index_a],
+            beta: BETA[index_b],
+        }
+    }
+}
+
+/// Filter the samples across one edge. `q0` is the offset of the first sample
+/// on the right of or below the edge, `across` is the distance between
+/// samples perpendicular to the edge, and `along` is the distance between
+/// samples along the edge.
+fn filter_edge(
+    plane: &mut [u8],
+    q0: usize,
+    across: usize,
+    along: usize,
+    length: usize,
+    chroma: bool,
+    params: &EdgeParams,
+) {
+    let samples_per_bs = length / 4;
+    for k in 0..length {
+        let bs = params.bs[k / samples_per_bs];
+        if bs == 0 {
+            continue;
+        }
+        let q = q0 + k * along;
+        let get = |i: isize| i32::from(plane[(q as isize + i * across as isize) as usize]);
+        let (p0, p1, q0, q1) = (get(-1), get(-2), get(0), get(1));
+        if (p0 - q0).abs() >= params.alpha
+            || (p1 - p0).abs() >= params.beta
+            || (q1 - q0).abs() >= params.beta
+        {
+            continue;
+        }
+        let (p2, q2) = if chroma { (0, 0) } else { (get(-3), get(2)) };
+        let a_p = (p2 - p0).abs();
+        let a_q = (q2 - q0).abs();
+
+        // Offsets of the samples to write and their new values.
+        let mut writes = [(0isize, 0i32); 6];
+        let mut write_count = 0;
+        let mut write = |i: isize, value: i32| {
+            writes[write_count] = (i, value);
+            write_count += 1;
+        };
+
+        if bs < 4 {
+            let tc0 = TC0[params.index_a][usize::from(bs) - 1];
+            let tc = if chroma {
+                tc0 + 1
+            } else {
+                tc0 + i32::from(a_p < params.beta) + i32::from(a_q < params.beta)
+            };
+            let delta = ((((q0 - p0) << 2) + (p1 - q1) + 4) >> 3).clamp(-tc, tc);
+            write(-1, (p0 + delta).clamp(0, 255));
+            write(0, (q0 - delta).clamp(0, 255));
+            if !chroma && a_p < params.beta {
+                write(
+                    -2,
+                    p1 + ((p2 + ((p0 + q0 + 1) >> 1) - (p1 << 1)) >> 1).clamp(-tc0, tc0),
+                );
+            }
+            if !chroma && a_q < params.beta {
+                write(
+                    1,
+                    q1 + ((q2 + ((p0 + q0 + 1) >> 1) - (q1 << 1)) >> 1).clamp(-tc0, tc0),
+                );
+            }
+        } else if chroma {
+            write(-1, (2 * p1 + p0 + q1 + 2) >> 2);
+            write(0, (2 * q1 + q0 + p1 + 2) >> 2);
+        } else {
+            let strong = (p0 - q0).abs() < ((params.alpha >> 2) + 2);
+            if a_p < params.beta && strong {
+                let p3 = get(-4);
+                write(-1, (p2 + 2 * p1 + 2 * p0 + 2 * q0 + q1 + 4) >> 3);
+                write(-2, (p2 + p1 + p0 + q0 + 2) >> 2);
+                write(-3, (2 * p3 + 3 * p2 + p1 + p0 + q0 + 4) >> 3);
+            } else {
+                write(-1, (2 * p1 + p0 + q1 + 2) >> 2);
+            }
+            if a_q < params.beta && strong {
+                let q3 = get(3);
+                write(0, (p1 + 2 * p0 + 2 * q0 + 2 * q1 + q2 + 4) >> 3);
+                write(1, (p0 + q0 + q1 + q2 + 2) >> 2);
+                write(2, (2 * q3 + 3 * q2 + q1 + q0 + p0 + 4) >> 3);
+            } else {
+                write(0, (2 * q1 + q0 + p1 + 2) >> 2);
+            }
+        }
+
+        for &(i, value) in &writes[..write_count] {
+            plane[(q as isize + i * across as isize) as usize] = value as u8;
+        }
+    }
+}
+
+/// Derive the boundary strength for the edge between two 4x4 luma blocks.
+fn boundary_strength(
+    pic: &DecodingPicture,
+    p_mb: usize,
+    q_mb: usize,
+    p_idx: usize,
+    q_idx: usize,
+    mb_edge: bool,
+) -> u8 {
+    if pic.mbs[p_mb].kind.is_intra() || pic.mbs[q_mb].kind.is_intra() {
+        return if mb_edge { 4 } else { 3 };
+    }
+    if pic.total_coeff[p_idx] != 0 || pic.total_coeff[q_idx] != 0 {
+        return 2;
+    }
+    let (p_mv, q_mv) = (pic.mvs[p_idx], pic.mvs[q_idx]);
+    if pic.ref_pic_ids[p_idx] != pic.ref_pic_ids[q_idx]
+        || (p_mv[0] - q_mv[0]).abs() >= 4
+        || (p_mv[1] - q_mv[1]).abs() >= 4
+    {
+        1
+    } else {
+        0
+    }
+}
+
+/// Apply the deblocking filter to a fully decoded picture.
+pub fn deblock_picture(pic: &mut DecodingPicture) {
+    let luma_stride = pic.pic.width;
+    let chroma_stride = luma_stride / 2;
+
+    for mb_addr in 0..pic.mbs.len() {
+        let q_info = pic.mbs[mb_addr];
+        if q_info.slice == NOT_DECODED {
+            continue;
+        }
+        let slice = &pic.slices[q_info.slice as usize];
+        if slice.disable_deblocking_filter_idc == 1 {
+            continue;
+        }
+        let (alpha_offset, beta_offset) = (slice.alpha_offset, slice.beta_offset);
+        let chroma_qp_index_offset = slice.chroma_qp_index_offset;
+
+        let mb_x = mb_addr % pic.width_in_mbs;
+        let mb_y = mb_addr / pic.width_in_mbs;
+        let filter_mb_edge = |neighbour: usize| {
+            let info = pic.mbs[neighbour];
+            info.slice != NOT_DECODED
+                && (slice.disable_deblocking_filter_idc != 2 || info.slice == q_info.slice)
+        };
+        let filter_left = mb_x > 0 && filter_mb_edge(mb_addr - 1);
+        let filter_top = mb_y > 0 && filter_mb_edge(mb_addr - pic.width_in_mbs);
+
+        for vertical in [true, false] {
+            for edge in 0..4 {
+                if edge == 0 && !(if vertical { filter_left } else { filter_top }) {
+                    continue;
+                }
+                let p_mb = match (edge, vertical) {
+                    (0, true) => mb_addr - 1,
+                    (0, false) => mb_addr - pic.width_in_mbs,
+                    _ => mb_addr,
+                };
+
+                let mut bs = [0; 4];
+                for (i, bs) in bs.iter_mut().enumerate() {
+                    let (x, y) = if vertical { (edge, i) } else { (i, edge) };
+                    let q_idx = pic.block_index(mb_addr, 4, x, y);
+                    let p_idx = if vertical {
+                        q_idx - 1
+                    } else {
+                        q_idx - pic.width_in_mbs * 4
+                    };
+                    *bs = boundary_strength(pic, p_mb, mb_addr, p_idx, q_idx, edge == 0);
+                }
+                if bs == [0; 4] {
+                    continue;
+                }
+
+                let p_qp = pic.mbs[p_mb].qp;
+                let q_qp = q_info.qp;
+                let params = EdgeParams::new(bs, (p_qp + q_qp + 1) >> 1, alpha_offset, beta_offset);
+                let (luma_q0, luma_across, luma_along) = if vertical {
+                    (
+                        mb_y * 16 * luma_stride + mb_x * 16 + edge * 4,
+                        1,
+                        luma_stride,
+                    )
+                } else {
+                    (
+                        (mb_y * 16 + edge * 4) * luma_stride + mb_x * 16,
+                        luma_stride,
+                        1,
+                    )
+                };
+                filter_edge(
+                    &mut pic.pic.luma,
+                    luma_q0,
+                    luma_across,
+                    luma_along,
+                    16,
+                    false,
+                    &params,
+                );
+
+                // Chroma edges are at every other luma edge.
+                if edge % 2 != 0 {
+                    continue;
+                }
+                let (chroma_q0, chroma_across, chroma_along) = if vertical {
+                    (
+                        mb_y * 8 * chroma_stride + mb_x * 8 + edge * 2,
+                        1,
+                        chroma_stride,
+                    )
+                } else {
+                    (
+                        (mb_y * 8 + edge * 2) * chroma_stride + mb_x * 8,
+                        chroma_stride,
+                        1,
+                    )
+                };
+                for (component, plane) in pic.pic.chroma.iter_mut().enumerate() {
+                    let offset = chroma_qp_index_offset[component];
+                    let qp_av = (chroma_qp(p_qp, offset) + chroma_qp(q_qp, offset) + 1) >> 1;
+                    let params = EdgeParams::new(bs, qp_av, alpha_offset, beta_offset);
+                    filter_edge(
+                        plane,
+                        chroma_q0,
+                        chroma_across,
+                        chroma_along,
+                        8,
+                        true,
+                        &params,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Inter prediction sample interpolation (section 8.4.2.2 of the spec).
+
+use super::picture::Picture;
+
+/// Accessor for a plane that clamps coordinates to the picture's edges.
+struct Plane<'a> {
+    samples: &'a [u8],
+    width: isize,
+    height: isize,
+}
+
+impl Plane<'_> {
+    fn get(&self, x: isize, y: isize) -> i32 {
+        let x = x.clamp(0, self.width - 1);
+        let y = y.clamp(0, self.height - 1);
+        self.samples[(y * self.width + x) as usize].into()
+    }
+
+    /// Unclipped result of the 6-tap filter at the horizontal half-sample
+    /// position to the right of (x, y) (`b1` in the spec).
+    fn half_h(&self, x: isize, y: isize) -> i32 {
+        self.get(x - 2, y) - 5 * self.get(x - 1, y) + 20 * self.get(x, y) + 20 * self.get(x + 1, y)
+            - 5 * self.get(x + 2, y)
+            + self.get(x + 3, y)
+    }
+
+    /// Unclipped result of the 6-tap filter at the vertical half-sample
+    /// position below (x, y) (`h1` in the spec).
+    fn half_v(&self, x: isize, y: isize) -> i32 {
+        self.get(x, y - 2) - 5 * self.get(x, y - 1) + 20 * self.get(x, y) + 20 * self.get(x, y + 1)
+            - 5 * self.get(x, y + 2)
+            + self.get(x, y + 3)
+    }
+
+    /// Unclipped result of the 6-tap filter at the centre half-sample position
+    /// (`j1` in the spec).
+    fn half_hv(&self, x: isize, y: isize) -> i32 {
+        self.half_h(x, y - 2) - 5 * self.half_h(x, y - 1)
+            + 20 * self.half_h(x, y)
+            + 20 * self.half_h(x, y + 1)
+            - 5 * self.half_h(x, y + 2)
+            + self.half_h(x, y + 3)
+    }
+}
+
+fn clip1(value: i32) -> i32 {
+    value.clamp(0, 255)
+}
+
+fn luma_sample(plane: &Plane, x: isize, y: isize, x_frac: i32, y_frac: i32) -> i32 {
+    let g = || plane.get(x, y);
+    let h_ = || plane.get(x + 1, y);
+    let m_ = || plane.get(x, y + 1);
+    let b = || clip1((plane.half_h(x, y) + 16) >> 5);
+    let h = || clip1((plane.half_v(x, y) + 16) >> 5);
+    let m = || clip1((plane.half_v(x + 1, y) + 16) >> 5);
+    let s = || clip1((plane.half_h(x, y + 1) + 16) >> 5);
+    let j = || clip1((plane.half_hv(x, y) + 512) >> 10);
+    let avg = |a: i32, b: i32| (a + b + 1) >> 1;
+    match (x_frac, y_frac) {
+        (0, 0) => g(),
+        (0, 1) => avg(g(), h()),
+        (0, 2) => h(),
+        (0, 3) => avg(m_(), h()),
+        (1, 0) => avg(g(), b()),
+        (1, 1) => avg(b(), h()),
+        (1, 2) => avg(h(), j()),
+        (1, 3) => avg(h(), s()),
+        (2, 0) => b(),
+        (2, 1) => avg(b(), j()),
+        (2, 2) => j(),
+        (2, 3) => avg(j(), s()),
+        (3, 0) => avg(h_(), b()),
+        (3, 1) => avg(b(), m()),
+        (3, 2) => avg(j(), m()),
+        (3, 3) => avg(m(), s()),
+        _ => unreachable!(),
+    }
+}
+
+/// Predict a `width`x`height` luma block at (`x`, `y`) in `dst` from
+/// `reference`, using a motion vector in quarter-sample units.
+pub fn predict_luma(
+    reference: &Picture,
+    dst: &mut Picture,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    mv: [i32; 2],
+) {
+    let plane = Plane {
+        samples: &reference.luma,
+        width: reference.width as isize,
+        height: reference.height as isize,
+    };
+    let x_frac = mv[0] & 3;
+    let y_frac = mv[1] & 3;
+    let base_x = x as isize + (mv[0] >> 2) as isize;
+    let base_y = y as isize + (mv[1] >> 2) as isize;
+    for j in 0..height {
+        for i in 0..width {
+            let sample = luma_sample(
+                &plane,
+                base_x + i as isize,
+                base_y + j as isize,
+                x_frac,
+                y_frac,
+            );
+            dst.luma[(y + j) * dst.width + x + i] = sample as u8;
+        }
+    }
+}
+
+/// Predict a `width`x`height` block at (`x`, `y`) in both chroma planes of
+/// `dst` from `reference`. The luma motion vector is used as-is, since it is
+/// in eighth-sample units for the half-resolution chroma planes.
+pub fn predict_chroma(
+    reference: &Picture,
+    dst: &mut Picture,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    mv: [i32; 2],
+) {
+    let chroma_width = reference.width / 2;
+    let x_frac = mv[0] & 7;
+    let y_frac = mv[1] & 7;
+    let base_x = x as isize + (mv[0] >> 3) as isize;
+    let base_y = y as isize + (mv[1] >> 3) as isize;
+    for component in 0..2 {
+        let plane = Plane {
+            samples: &reference.chroma[component],
+            width: chroma_width as isize,
+            height: (reference.height / 2) as isize,
+        };
+        for j in 0..height {
+            for i in 0..width {
+                let sx = base_x + i as isize;
+                let sy = base_y + j as isize;
+                let sample = ((8 - x_frac) * (8 - y_frac) * plane.get(sx, sy)
+                    + x_frac * (8 - y_frac) * plane.get(sx + 1, sy)
+                    + (8 - x_frac) * y_frac * plane.get(sx, sy + 1)
+                    + x_frac * y_frac * plane.get(sx + 1, sy + 1)
+                    + 32)
+                    >> 6;
+                dst.chroma[component][(y + j) * chroma_width + x + i] = sample as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Intra prediction (section 8.3 of the spec).
+//!
+//! The predicted samples are written straight into the picture, using the
+//! neighbouring samples already there.
+
+/// Which neighbouring samples are available for prediction.
+#[derive(Copy, Clone)]
+pub struct Neighbours {
+    pub left: bool,
+    pub top: bool,
+    pub top_left: bool,
+    pub top_right: bool,
+}
+
+fn invalid_mode() -> String {
+    "Intra prediction mode uses unavailable samples".to_string()
+}
+
+/// Intra 4x4 luma prediction of the block at `offset` in `plane`.
+pub fn predict_4x4(
+    plane: &mut [u8],
+    offset: usize,
+    stride: usize,
+    mode: u8,
+    neighbours: Neighbours,
+) -> Result<(), String> {
+    // p[x, -1] for x = 0..7
+    let mut top = [0i32; 8];
+    // p[-1, y] for y = 0..3
+    let mut left = [0i32; 4];
+    let mut top_left = 0i32;
+    if neighbours.top {
+        for (x, sample) in top.iter_mut().enumerate() {
+            *sample = if x < 4 || neighbours.top_right {
+                plane[offset - stride + x].into()
+            } else {
+                plane[offset - stride + 3].into()
+            };
+        }
+    }
+    if neighbours.left {
+        for (y, sample) in left.iter_mut().enumerate() {
+            *sample = plane[offset + y * stride - 1].into();
+        }
+    }
+    if neighbours.top_left {
+        top_left = plane[offset - stride - 1].into();
+    }
+    // Access to p[x, y] where either x or y is -1.
+    let p = |x: i32, y: i32| -> i32 {
+        match (x, y) {
+            (-1, -1) => top_left,
+            (-1, y) => left[y as usize],
+            (x, _) => top[x as usize],
+        }
+    };
+
+    let needs = |left: bool, top: bool, top_left: bool| {
+        if (left && !neighbours.left)
+            || (top && !neighbours.top)
+            || (top_left && !neighbours.top_left)
+        {
+            Err(invalid_mode())
+        } else {
+            Ok(())
+        }
+    };
+
+    let mut pred = [[0i32; 4]; 4];
+    match mode {
+        // Vertical
+        0 => {
+            needs(false, true, false)?;
+            for row in pred.iter_mut() {
+                row.copy_from_slice(&top[..4]);
+            }
+        }
+        // Horizontal
+        1 => {
+            needs(true, false, false)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                *row = [left[y]; 4];
+            }
+        }
+        // DC
+        2 => {
+            let sum_top: i32 = top[..4].iter().sum();
+            let sum_left: i32 = left.iter().sum();
+            let dc = match (neighbours.left, neighbours.top) {
+                (true, true) => (sum_top + sum_left + 4) >> 3,
+                (true, false) => (sum_left + 2) >> 2,
+                (false, true) => (sum_top + 2) >> 2,
+                (false, false) => 128,
+            };
+            pred = [[dc; 4]; 4];
+        }
+        // Diagonal Down Left
+        3 => {
+            needs(false, true, false)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let i = (x + y) as i32;
+                    *sample = if x == 3 && y == 3 {
+                        (p(6, -1) + 3 * p(7, -1) + 2) >> 2
+                    } else {
+                        (p(i, -1) + 2 * p(i + 1, -1) + p(i + 2, -1) + 2) >> 2
+                    };
+                }
+            }
+        }
+        // Diagonal Down Right
+        4 => {
+            needs(true, true, true)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let (x, y) = (x as i32, y as i32);
+                    *sample = if x > y {
+                        (p(x - y - 2, -1) + 2 * p(x - y - 1, -1) + p(x - y, -1) + 2) >> 2
+                    } else if x < y {
+                        (p(-1, y - x - 2) + 2 * p(-1, y - x - 1) + p(-1, y - x) + 2) >> 2
+                    } else {
+                        (p(0, -1) + 2 * p(-1, -1) + p(-1, 0) + 2) >> 2
+                    };
+                }
+            }
+        }
+        // Vertical Right
+        5 => {
+            needs(true, true, true)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let (x, y) = (x as i32, y as i32);
+                    let z = 2 * x - y;
+                    *sample = if z >= 0 && z % 2 == 0 {
+                        (p(x - (y >> 1) - 1, -1) + p(x - (y >> 1), -1) + 1) >> 1
+                    } else if z > 0 {
+                        (p(x - (y >> 1) - 2, -1)
+                            + 2 * p(x - (y >> 1) - 1, -1)
+                            + p(x - (y >> 1), -1)
+                            + 2)
+                            >> 2
+                    } else if z == -1 {
+                        (p(-1, 0) + 2 * p(-1, -1) + p(0, -1) + 2) >> 2
+                    } else {
+                        (p(-1, y - 1) + 2 * p(-1, y - 2) + p(-1, y - 3) + 2) >> 2
+                    };
+                }
+            }
+        }
+        // Horizontal Down
+        6 => {
+            needs(true, true, true)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let (x, y) = (x as i32, y as i32);
+                    let z = 2 * y - x;
+                    *sample = if z >= 0 && z % 2 == 0 {
+                        (p(-1, y - (x >> 1) - 1) + p(-1, y - (x >> 1)) + 1) >> 1
+                    } else if z > 0 {
+                        (p(-1, y - (x >> 1) - 2)
+                            + 2 * p(-1, y - (x >> 1) - 1)
+                            + p(-1, y - (x >> 1))
+                            + 2)
+                            >> 2
+                    } else if z == -1 {
+                        (p(-1, 0) + 2 * p(-1, -1) + p(0, -1) + 2) >> 2
+                    } else {
+                        (p(x - 1, -1) + 2 * p(x - 2, -1) + p(x - 3, -1) + 2) >> 2
+                    };
+                }
+            }
+        }
+        // Vertical Left
+        7 => {
+            needs(false, true, false)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let i = x as i32 + (y as i32 >> 1);
+                    *sample = if y % 2 == 0 {
+                        (p(i, -1) + p(i + 1, -1) + 1) >> 1
+                    } else {
+                        (p(i, -1) + 2 * p(i + 1, -1) + p(i + 2, -1) + 2) >> 2
+                    };
+                }
+            }
+        }
+        // Horizontal Up
+        8 => {
+            needs(true, false, false)?;
+            for (y, row) in pred.iter_mut().enumerate() {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    let (x, y) = (x as i32, y as i32);
+                    let z = x + 2 * y;
+                    let i = y + (x >> 1);
+                    *sample = match z {
+                        0 | 2 | 4 => (p(-1, i) + p(-1, i + 1) + 1) >> 1,
+                        1 | 3 => (p(-1, i) + 2 * p(-1, i + 1) + p(-1, i + 2) + 2) >> 2,
+                        5 => (p(-1, 2) + 3 * p(-1, 3) + 2) >> 2,
+                        _ => p(-1, 3),
+                    };
+                }
+            }
+        }
+        _ => return Err(format!("Invalid Intra 4x4 prediction mode {}", mode)),
+    }
+
+    for (y, row) in pred.iter().enumerate() {
+        for (x, &sample) in row.iter().enumerate() {
+            plane[offset + y * stride + x] = sample as u8;
+        }
+    }
+    Ok(())
+}
+
+/// Shared implementation of Intra 16x16 luma prediction and chroma prediction
+/// for the vertical, horizontal and plane modes. `size` is 16 for luma and 8
+/// for chroma.
+fn predict_directional(
+    plane: &mut [u8],
+    offset: usize,
+    stride: usize,
+    size: usize,
+    mode: DirectionalMode,
+    neighbours: Neighbours,
+) -> Result<(), String> {
+    match mode {
+        DirectionalMode::Vertical => {
+            if !neighbours.top {
+                return Err(invalid_mode());
+            }
+            for y in 0..size {
+                plane.copy_within(offset - stride..offset - stride + size, offset + y * stride);
+            }
+        }
+        DirectionalMode::Horizontal => {
+            if !neighbours.left {
+                return Err(invalid_mode());
+            }
+            for y in 0..size {
+                let row = offset + y * stride;
+                let sample = plane[row - 1];
+                plane[row..row + size].fill(sample);
+            }
+        }
+        DirectionalMode::Plane => {
+            if !neighbours.top || !neighbours.left || !neighbours.top_left {
+                return Err(invalid_mode());
+            }
+            let top = |x: isize| i32::from(plane[(offset as isize - stride as isize + x) as usize]);
+            let left =
+                |y: isize| i32::from(plane[(offset as isize + y * stride as isize - 1) as usize]);
+            let half = (size / 2) as isize;
+            let mut h = 0;
+            let mut v = 0;
+            for i in 0..half {
+                h += (i as i32 + 1) * (top(half + i) - top(half - 2 - i));
+                v += (i as i32 + 1) * (left(half + i) - left(half - 2 - i));
+            }
+            // The top-left sample is reached by top(-1) and left(-1).
+            let (b, c) = if size == 16 {
+                ((5 * h + 32) >> 6, (5 * v + 32) >> 6)
+            } else {
+                ((34 * h + 32) >> 6, (34 * v + 32) >> 6)
+            };
+            let last = size as isize - 1;
+            let a = 16 * (left(last) + top(last));
+            let centre = half as i32 - 1;
+            for y in 0..size {
+                for x in 0..size {
+                    let value = (a + b * (x as i32 - centre) + c * (y as i32 - centre) + 16) >> 5;
+                    plane[offset + y * stride + x] = value.clamp(0, 255) as u8;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone)]
+enum DirectionalMode {
+    Vertical,
+    Horizontal,
+    Plane,
+}
+
+/// Intra 16x16 luma prediction of the macroblock at `offset` in `plane`.
+pub fn predict_16x16(
+    plane: &mut [u8],
+    offset: usize,
+    stride: usize,
+    mode: u8,
+    neighbours: Neighbours,
+) -> Result<(), String> {
+    let directional = match mode {
+        0 => DirectionalMode::Vertical,
+        1 => DirectionalMode::Horizontal,
+        3 => DirectionalMode::Plane,
+        2 => {
+            let sum_top: u32 = if neighbours.top {
+                plane[offset - stride..offset - stride + 16]
+                    .iter()
+                    .map(|&sample| u32::from(sample))
+                    .sum()
+            } else {
+                0
+            };
+            let sum_left: u32 = if neighbours.left {
+                (0..16)
+                    .map(|y| u32::from(plane[offset + y * stride - 1]))
+                    .sum()
+            } else {
+                0
+            };
+            let dc = match (neighbours.left, neighbours.top) {
+                (true, true) => (sum_top + sum_left + 16) >> 5,
+                (true, false) => (sum_left + 8) >> 4,
+                (false, true) => (sum_top + 8) >> 4,
+                (false, false) => 128,
+            };
+            for y in 0..16 {
+                plane[offset + y * stride..offset + y * stride + 16].fill(dc as u8);
+            }
+            return Ok(());
+        }
+        _ => return Err(format!("Invalid Intra 16x16 prediction mode {}", mode)),
+    };
+    predict_directional(plane, offset, stride, 16, directional, neighbours)
+}
+
+/// Chroma intra prediction of the 8x8 block at `offset` in `plane`.
+pub fn predict_chroma(
+    plane: &mut [u8],
+    offset: usize,
+    stride: usize,
+    mode: u8,
+    neighbours: Neighbours,
+) -> Result<(), String> {
+    let directional = match mode {
+        1 => DirectionalMode::Horizontal,
+        2 => DirectionalMode::Vertical,
+        3 => DirectionalMode::Plane,
+        0 => {
+            // Each 4x4 block has its own DC value, and which neighbours are
+            // preferred depends on the block's position.
+            for block_y in 0..2 {
+                for block_x in 0..2 {
+                    let block = offset + block_y * 4 * stride + block_x * 4;
+                    let sum_top: u32 = if neighbours.top {
+                        (0..4)
+                            .map(|x| u32::from(plane[offset - stride + block_x * 4 + x]))
+                            .sum()
+                    } else {
+                        0
+                    };
+                    let sum_left: u32 = if neighbours.left {
+                        (0..4)
+                            .map(|y| u32::from(plane[offset + (block_y * 4 + y) * stride - 1]))
+                            .sum()
+                    } else {
+                        0
+                    };
+                    let both = (sum_top + sum_left + 4) >> 3;
+                    let top = (sum_top + 2) >> 2;
+                    let left = (sum_left + 2) >> 2;
+                    let dc = match (block_x, block_y, neighbours.left, neighbours.top) {
+                        (_, _, false, false) => 128,
+                        (0, 0, true, true) | (1, 1, true, true) => both,
+                        (1, 0, _, true) => top,
+                        (0, 1, true, _) => left,
+                        (_, _, true, false) => left,
+                        (_, _, false, true) => top,
+                        _ => unreachable!(),
+                    };
+                    for y in 0..4 {
+                        plane[block + y * stride..block + y * stride + 4].fill(dc as u8);
+                    }
+                }
+            }
+            return Ok(());
+        }
+        _ => return Err(format!("Invalid chroma prediction mode {}", mode)),
+    };
+    predict_directional(plane, offset, stride, 8, directional, neighbours)
+}
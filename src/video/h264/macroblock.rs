@@ -0,0 +1,774 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Slice data and macroblock decoding (sections 7.3.4, 7.3.5, 8.3, 8.4 and
+//! 8.5 of the spec).
+
+use super::bitstream::BitReader;
+use super::cavlc::{read_residual_block, CODED_BLOCK_PATTERN_INTER, CODED_BLOCK_PATTERN_INTRA};
+use super::intra::{self, Neighbours};
+use super::params::Pps;
+use super::picture::{DecodingPicture, MbInfo, MbKind, Picture, SliceParams};
+use super::slice_header::{SliceHeader, SliceType};
+use super::{inter, transform};
+use std::rc::Rc;
+
+/// Coordinates (in 4x4 blocks) of each luma 4x4 block within a macroblock, in
+/// decoding order (`luma4x4BlkIdx`).
+fn block_coords(blk_idx: usize) -> (usize, usize) {
+    (
+        (blk_idx / 4 % 2) * 2 + blk_idx % 2,
+        (blk_idx / 8) * 2 + (blk_idx / 2) % 2,
+    )
+}
+
+/// Inverse of [block_coords].
+fn block_idx(x: usize, y: usize) -> usize {
+    (y / 2) * 8 + (x / 2) * 4 + (y % 2) * 2 + x % 2
+}
+
+/// Special cases of motion vector prediction for two-partition macroblocks.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum PartitionShape {
+    Other,
+    Upper16x8,
+    Lower16x8,
+    Left8x16,
+    Right8x16,
+}
+
+/// An inter prediction block in 4x4 block units, relative to the macroblock.
+struct Partition {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    shape: PartitionShape,
+    ref_idx: u8,
+    mvd: [i32; 2],
+}
+
+/// Coefficients of a macroblock, in zigzag scan order.
+struct Residual {
+    luma_dc: [i32; 16],
+    /// Indexed by `luma4x4BlkIdx`. For Intra 16x16 macroblocks, the first
+    /// coefficient of each block is unused.
+    luma: [[i32; 16]; 16],
+    chroma_dc: [[i32; 4]; 2],
+    /// The first coefficient of each block is unused.
+    chroma_ac: [[[i32; 16]; 4]; 2],
+}
+
+struct SliceDecoder<'a, 'b> {
+    pic: &'a mut DecodingPicture,
+    reader: &'a mut BitReader<'b>,
+    ref_list: &'a [Rc<Picture>],
+    slice_type: SliceType,
+    slice: u32,
+    num_ref_idx_active: u32,
+    constrained_intra_pred: bool,
+    chroma_qp_index_offset: [i32; 2],
+    /// `QP_Y` of the previous macroblock, or the slice QP.
+    qp: i32,
+}
+
+/// Decode the macroblocks of a slice into `pic`. `reader` must be positioned
+/// after the slice header.
+pub fn decode_slice_data(
+    pic: &mut DecodingPicture,
+    reader: &mut BitReader,
+    header: &SliceHeader,
+    pps: &Pps,
+    ref_list: &[Rc<Picture>],
+) -> Result<(), String> {
+    let slice = pic.slices.len() as u32;
+    pic.slices.push(SliceParams {
+        disable_deblocking_filter_idc: header.disable_deblocking_filter_idc,
+        alpha_offset: header.slice_alpha_c0_offset,
+        beta_offset: header.slice_beta_offset,
+        chroma_qp_index_offset: pps.chroma_qp_index_offset,
+    });
+
+    let qp = pps.pic_init_qp + header.slice_qp_delta;
+    if !(0..=51).contains(&qp) {
+        return Err(format!("Invalid slice QP {}", qp));
+    }
+
+    let mb_count = pic.width_in_mbs * pic.height_in_mbs;
+    let mut decoder = SliceDecoder {
+        pic,
+        reader,
+        ref_list,
+        slice_type: header.slice_type,
+        slice,
+        num_ref_idx_active: header.num_ref_idx_l0_active,
+        constrained_intra_pred: pps.constrained_intra_pred,
+        chroma_qp_index_offset: pps.chroma_qp_index_offset,
+        qp,
+    };
+
+    let too_many_mbs = || "Too many macroblocks in slice".to_string();
+    let mut mb_addr = header.first_mb_in_slice;
+    loop {
+        if decoder.slice_type == SliceType::P {
+            let mb_skip_run = decoder.reader.read_ue()? as usize;
+            for _ in 0..mb_skip_run {
+                if mb_addr >= mb_count {
+                    return Err(too_many_mbs());
+                }
+                decoder.decode_skipped_macroblock(mb_addr)?;
+                mb_addr += 1;
+            }
+            if mb_skip_run > 0 && !decoder.reader.more_rbsp_data() {
+                break;
+            }
+        }
+        if mb_addr >= mb_count {
+            return Err(too_many_mbs());
+        }
+        decoder.decode_macroblock(mb_addr)?;
+        mb_addr += 1;
+        if !decoder.reader.more_rbsp_data() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl SliceDecoder<'_, '_> {
+    /// Top-left luma sample of a macroblock.
+    fn mb_origin(&self, mb_addr: usize) -> (usize, usize) {
+        (
+            (mb_addr % self.pic.width_in_mbs) * 16,
+            (mb_addr / self.pic.width_in_mbs) * 16,
+        )
+    }
+
+    /// Record the macroblock's type and reset the information that isn't
+    /// relevant for it.
+    fn start_macroblock(&mut self, mb_addr: usize, kind: MbKind) {
+        self.pic.mbs[mb_addr] = MbInfo {
+            slice: self.slice,
+            kind,
+            qp: self.qp,
+        };
+        for y in 0..4 {
+            for x in 0..4 {
+                let idx = self.pic.block_index(mb_addr, 4, x, y);
+                self.pic.intra4x4_modes[idx] = if kind.is_intra() { 2 } else { -1 };
+                if kind.is_intra() {
+                    self.pic.mvs[idx] = [0, 0];
+                    self.pic.ref_idx[idx] = -1;
+                    self.pic.ref_pic_ids[idx] = u32::MAX;
+                }
+            }
+        }
+    }
+
+    fn read_mb_qp_delta(&mut self, mb_addr: usize) -> Result<(), String> {
+        let mb_qp_delta = self.reader.read_se()?;
+        if !(-26..=25).contains(&mb_qp_delta) {
+            return Err(format!("Invalid mb_qp_delta {}", mb_qp_delta));
+        }
+        self.qp = (self.qp + mb_qp_delta + 52) % 52;
+        self.pic.mbs[mb_addr].qp = self.qp;
+        Ok(())
+    }
+
+    fn read_coded_block_pattern(&mut self, intra: bool) -> Result<u8, String> {
+        let code_num = self.reader.read_ue()? as usize;
+        let table = if intra {
+            &CODED_BLOCK_PATTERN_INTRA
+        } else {
+            &CODED_BLOCK_PATTERN_INTER
+        };
+        table
+            .get(code_num)
+            .copied()
+            .ok_or_else(|| "Invalid coded_block_pattern".to_string())
+    }
+
+    fn decode_macroblock(&mut self, mb_addr: usize) -> Result<(), String> {
+        let mb_type = self.reader.read_ue()?;
+        let intra_mb_type = match self.slice_type {
+            SliceType::P if mb_type < 5 => return self.decode_inter_macroblock(mb_addr, mb_type),
+            SliceType::P => mb_type - 5,
+            SliceType::I => mb_type,
+        };
+        match intra_mb_type {
+            0 => self.decode_intra4x4_macroblock(mb_addr),
+            1..=24 => self.decode_intra16x16_macroblock(mb_addr, intra_mb_type - 1),
+            25 => self.decode_pcm_macroblock(mb_addr),
+            _ => Err(format!("Invalid mb_type {}", mb_type)),
+        }
+    }
+
+    /// `nC` for a luma block (or a chroma AC block if `chroma` is provided),
+    /// derived from the number of coefficients in the neighbouring blocks.
+    fn predict_total_coeff(
+        &self,
+        mb_addr: usize,
+        chroma: Option<usize>,
+        x: usize,
+        y: usize,
+    ) -> i32 {
+        let (totals, blocks_per_mb) = match chroma {
+            Some(component) => (&self.pic.chroma_total_coeff[component], 2),
+            None => (&self.pic.total_coeff, 4),
+        };
+        let get = |x: isize, y: isize| {
+            self.pic
+                .neighbour_block(mb_addr, blocks_per_mb, x, y)
+                .map(|(idx, _)| i32::from(totals[idx]))
+        };
+        match (
+            get(x as isize - 1, y as isize),
+            get(x as isize, y as isize - 1),
+        ) {
+            (Some(a), Some(b)) => (a + b + 1) >> 1,
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => 0,
+        }
+    }
+
+    fn read_residual(
+        &mut self,
+        mb_addr: usize,
+        coded_block_pattern: u8,
+        intra16x16: bool,
+    ) -> Result<Residual, String> {
+        let mut residual = Residual {
+            luma_dc: [0; 16],
+            luma: [[0; 16]; 16],
+            chroma_dc: [[0; 4]; 2],
+            chroma_ac: [[[0; 16]; 4]; 2],
+        };
+
+        if intra16x16 {
+            let n_c = self.predict_total_coeff(mb_addr, None, 0, 0);
+            read_residual_block(self.reader, &mut residual.luma_dc, 0, 15, 16, n_c)?;
+        }
+        for blk_idx in 0..16 {
+            let (x, y) = block_coords(blk_idx);
+            let total_coeff = if coded_block_pattern & (1 << (blk_idx / 4)) != 0 {
+                let n_c = self.predict_total_coeff(mb_addr, None, x, y);
+                let coeffs = &mut residual.luma[blk_idx];
+                if intra16x16 {
+                    read_residual_block(self.reader, &mut coeffs[1..], 0, 14, 15, n_c)?
+                } else {
+                    read_residual_block(self.reader, coeffs, 0, 15, 16, n_c)?
+                }
+            } else {
+                0
+            };
+            let idx = self.pic.block_index(mb_addr, 4, x, y);
+            self.pic.total_coeff[idx] = total_coeff;
+        }
+
+        let chroma_pattern = coded_block_pattern >> 4;
+        if chroma_pattern != 0 {
+            for dc in residual.chroma_dc.iter_mut() {
+                read_residual_block(self.reader, dc, 0, 3, 4, -1)?;
+            }
+        }
+        for component in 0..2 {
+            for blk_idx in 0..4 {
+                let (x, y) = (blk_idx % 2, blk_idx / 2);
+                let total_coeff = if chroma_pattern & 2 != 0 {
+                    let n_c = self.predict_total_coeff(mb_addr, Some(component), x, y);
+                    let coeffs = &mut residual.chroma_ac[component][blk_idx][1..];
+                    read_residual_block(self.reader, coeffs, 0, 14, 15, n_c)?
+                } else {
+                    0
+                };
+                let idx = self.pic.block_index(mb_addr, 2, x, y);
+                self.pic.chroma_total_coeff[component][idx] = total_coeff;
+            }
+        }
+
+        Ok(residual)
+    }
+
+    fn add_luma_residual(&mut self, mb_addr: usize, blk_idx: usize, block: &[i32; 16]) {
+        if block.iter().all(|&coeff| coeff == 0) {
+            return;
+        }
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        let (x, y) = block_coords(blk_idx);
+        let stride = self.pic.pic.width;
+        let offset = (mb_y + y * 4) * stride + mb_x + x * 4;
+        transform::idct_4x4_add(block, &mut self.pic.pic.luma, offset, stride);
+    }
+
+    fn add_chroma_residual(&mut self, mb_addr: usize, residual: &Residual) {
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        let stride = self.pic.pic.width / 2;
+        for component in 0..2 {
+            let qp = transform::chroma_qp(self.qp, self.chroma_qp_index_offset[component]);
+            let dc = transform::chroma_dc_transform(&residual.chroma_dc[component], qp);
+            for (blk_idx, coeffs) in residual.chroma_ac[component].iter().enumerate() {
+                let block = transform::scale_4x4(coeffs, qp, Some(dc[blk_idx]));
+                if block.iter().all(|&coeff| coeff == 0) {
+                    continue;
+                }
+                let offset = (mb_y / 2 + (blk_idx / 2) * 4) * stride + mb_x / 2 + (blk_idx % 2) * 4;
+                transform::idct_4x4_add(
+                    &block,
+                    &mut self.pic.pic.chroma[component],
+                    offset,
+                    stride,
+                );
+            }
+        }
+    }
+
+    /// Whether the neighbouring macroblock at the given offset can be used for
+    /// intra prediction.
+    fn intra_mb_available(&self, mb_addr: usize, dx: isize, dy: isize) -> bool {
+        let mb_x = (mb_addr % self.pic.width_in_mbs) as isize + dx;
+        let mb_y = (mb_addr / self.pic.width_in_mbs) as isize + dy;
+        if !self.pic.mb_available(mb_addr, mb_x, mb_y) {
+            return false;
+        }
+        let neighbour = mb_y as usize * self.pic.width_in_mbs + mb_x as usize;
+        !self.constrained_intra_pred || self.pic.mbs[neighbour].kind.is_intra()
+    }
+
+    fn intra_mb_neighbours(&self, mb_addr: usize) -> Neighbours {
+        Neighbours {
+            left: self.intra_mb_available(mb_addr, -1, 0),
+            top: self.intra_mb_available(mb_addr, 0, -1),
+            top_left: self.intra_mb_available(mb_addr, -1, -1),
+            top_right: self.intra_mb_available(mb_addr, 1, -1),
+        }
+    }
+
+    fn predict_chroma_intra(&mut self, mb_addr: usize, mode: u8) -> Result<(), String> {
+        let neighbours = self.intra_mb_neighbours(mb_addr);
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        let stride = self.pic.pic.width / 2;
+        let offset = (mb_y / 2) * stride + mb_x / 2;
+        for plane in self.pic.pic.chroma.iter_mut() {
+            intra::predict_chroma(plane, offset, stride, mode, neighbours)?;
+        }
+        Ok(())
+    }
+
+    fn read_intra_chroma_pred_mode(&mut self) -> Result<u8, String> {
+        let mode = self.reader.read_ue()?;
+        if mode > 3 {
+            return Err(format!("Invalid intra_chroma_pred_mode {}", mode));
+        }
+        Ok(mode as u8)
+    }
+
+    /// Derive `predIntra4x4PredMode` for a block.
+    fn predict_intra4x4_mode(&self, mb_addr: usize, x: usize, y: usize) -> u8 {
+        let mode_of = |neighbour: Option<(usize, usize)>| -> Option<u8> {
+            let (idx, neighbour_mb) = neighbour?;
+            match self.pic.mbs[neighbour_mb].kind {
+                MbKind::Inter if self.constrained_intra_pred => None,
+                MbKind::Intra4x4 => Some(self.pic.intra4x4_modes[idx] as u8),
+                _ => Some(2),
+            }
+        };
+        let a = mode_of(
+            self.pic
+                .neighbour_block(mb_addr, 4, x as isize - 1, y as isize),
+        );
+        let b = mode_of(
+            self.pic
+                .neighbour_block(mb_addr, 4, x as isize, y as isize - 1),
+        );
+        match (a, b) {
+            (Some(a), Some(b)) => a.min(b),
+            _ => 2,
+        }
+    }
+
+    fn decode_intra4x4_macroblock(&mut self, mb_addr: usize) -> Result<(), String> {
+        self.start_macroblock(mb_addr, MbKind::Intra4x4);
+
+        let mut modes = [0u8; 16];
+        for (blk_idx, mode) in modes.iter_mut().enumerate() {
+            let (x, y) = block_coords(blk_idx);
+            let predicted = self.predict_intra4x4_mode(mb_addr, x, y);
+            *mode = if self.reader.read_bit()? {
+                predicted
+            } else {
+                let rem_intra4x4_pred_mode = self.reader.read_bits(3)? as u8;
+                if rem_intra4x4_pred_mode < predicted {
+                    rem_intra4x4_pred_mode
+                } else {
+                    rem_intra4x4_pred_mode + 1
+                }
+            };
+            let idx = self.pic.block_index(mb_addr, 4, x, y);
+            self.pic.intra4x4_modes[idx] = *mode as i8;
+        }
+        let chroma_mode = self.read_intra_chroma_pred_mode()?;
+        let coded_block_pattern = self.read_coded_block_pattern(true)?;
+        if coded_block_pattern != 0 {
+            self.read_mb_qp_delta(mb_addr)?;
+        }
+        let residual = self.read_residual(mb_addr, coded_block_pattern, false)?;
+
+        let mb_neighbours = self.intra_mb_neighbours(mb_addr);
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        let stride = self.pic.pic.width;
+        for (blk_idx, &mode) in modes.iter().enumerate() {
+            let (x, y) = block_coords(blk_idx);
+            let neighbours = Neighbours {
+                left: x > 0 || mb_neighbours.left,
+                top: y > 0 || mb_neighbours.top,
+                top_left: match (x > 0, y > 0) {
+                    (true, true) => true,
+                    (false, true) => mb_neighbours.left,
+                    (true, false) => mb_neighbours.top,
+                    (false, false) => mb_neighbours.top_left,
+                },
+                top_right: if y == 0 {
+                    if x < 3 {
+                        mb_neighbours.top
+                    } else {
+                        mb_neighbours.top_right
+                    }
+                } else {
+                    x < 3 && block_idx(x + 1, y - 1) < blk_idx
+                },
+            };
+            let offset = (mb_y + y * 4) * stride + mb_x + x * 4;
+            intra::predict_4x4(&mut self.pic.pic.luma, offset, stride, mode, neighbours)?;
+            let block = transform::scale_4x4(&residual.luma[blk_idx], self.qp, None);
+            self.add_luma_residual(mb_addr, blk_idx, &block);
+        }
+
+        self.predict_chroma_intra(mb_addr, chroma_mode)?;
+        self.add_chroma_residual(mb_addr, &residual);
+        Ok(())
+    }
+
+    /// `type_idx` is `mb_type` minus 1 for I slices.
+    fn decode_intra16x16_macroblock(
+        &mut self,
+        mb_addr: usize,
+        type_idx: u32,
+    ) -> Result<(), String> {
+        self.start_macroblock(mb_addr, MbKind::Intra16x16);
+
+        let pred_mode = (type_idx % 4) as u8;
+        let chroma_pattern = ((type_idx / 4) % 3) as u8;
+        let luma_pattern = if type_idx >= 12 { 15 } else { 0 };
+        let coded_block_pattern = luma_pattern | (chroma_pattern << 4);
+
+        let chroma_mode = self.read_intra_chroma_pred_mode()?;
+        self.read_mb_qp_delta(mb_addr)?;
+        let residual = self.read_residual(mb_addr, coded_block_pattern, true)?;
+
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        let stride = self.pic.pic.width;
+        let neighbours = self.intra_mb_neighbours(mb_addr);
+        intra::predict_16x16(
+            &mut self.pic.pic.luma,
+            mb_y * stride + mb_x,
+            stride,
+            pred_mode,
+            neighbours,
+        )?;
+        let dc = transform::luma_dc_transform(&residual.luma_dc, self.qp);
+        for (blk_idx, coeffs) in residual.luma.iter().enumerate() {
+            let (x, y) = block_coords(blk_idx);
+            let block = transform::scale_4x4(coeffs, self.qp, Some(dc[y * 4 + x]));
+            self.add_luma_residual(mb_addr, blk_idx, &block);
+        }
+
+        self.predict_chroma_intra(mb_addr, chroma_mode)?;
+        self.add_chroma_residual(mb_addr, &residual);
+        Ok(())
+    }
+
+    fn decode_pcm_macroblock(&mut self, mb_addr: usize) -> Result<(), String> {
+        self.start_macroblock(mb_addr, MbKind::IPcm);
+        // QP_Y is 0 for deblocking purposes, but the QP used for predicting the
+        // next macroblock's QP is unchanged.
+        self.pic.mbs[mb_addr].qp = 0;
+
+        while !self.reader.is_byte_aligned() {
+            self.reader.skip(1)?;
+        }
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        let stride = self.pic.pic.width;
+        for y in 0..16 {
+            for x in 0..16 {
+                self.pic.pic.luma[(mb_y + y) * stride + mb_x + x] = self.reader.read_bits(8)? as u8;
+            }
+        }
+        for plane in self.pic.pic.chroma.iter_mut() {
+            for y in 0..8 {
+                for x in 0..8 {
+                    plane[(mb_y / 2 + y) * (stride / 2) + mb_x / 2 + x] =
+                        self.reader.read_bits(8)? as u8;
+                }
+            }
+        }
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let idx = self.pic.block_index(mb_addr, 4, x, y);
+                self.pic.total_coeff[idx] = 16;
+            }
+        }
+        for y in 0..2 {
+            for x in 0..2 {
+                let idx = self.pic.block_index(mb_addr, 2, x, y);
+                self.pic.chroma_total_coeff[0][idx] = 16;
+                self.pic.chroma_total_coeff[1][idx] = 16;
+            }
+        }
+        Ok(())
+    }
+
+    /// Motion information of the 4x4 block at the given coordinates relative
+    /// to the current macroblock: the reference index (-1 for intra blocks)
+    /// and the motion vector. Returns [None] if the block is not available.
+    /// `assigned` records which blocks of the current macroblock have already
+    /// been given a motion vector.
+    fn neighbour_motion(
+        &self,
+        mb_addr: usize,
+        assigned: &[bool; 16],
+        x: isize,
+        y: isize,
+    ) -> Option<(i8, [i32; 2])> {
+        if (0..4).contains(&x) && (0..4).contains(&y) && !assigned[(y * 4 + x) as usize] {
+            return None;
+        }
+        let (idx, _) = self.pic.neighbour_block(mb_addr, 4, x, y)?;
+        Some((self.pic.ref_idx[idx], self.pic.mvs[idx]))
+    }
+
+    /// Motion vector prediction (section 8.4.1.3 of the spec).
+    fn predict_mv(&self, mb_addr: usize, assigned: &[bool; 16], partition: &Partition) -> [i32; 2] {
+        let (x, y) = (partition.x as isize, partition.y as isize);
+        let ref_idx = partition.ref_idx as i8;
+        let a = self.neighbour_motion(mb_addr, assigned, x - 1, y);
+        let mut b = self.neighbour_motion(mb_addr, assigned, x, y - 1);
+        let mut c = self
+            .neighbour_motion(mb_addr, assigned, x + partition.width as isize, y - 1)
+            .or_else(|| self.neighbour_motion(mb_addr, assigned, x - 1, y - 1));
+
+        let directional = match partition.shape {
+            PartitionShape::Other => None,
+            PartitionShape::Upper16x8 => b,
+            PartitionShape::Lower16x8 | PartitionShape::Left8x16 => a,
+            PartitionShape::Right8x16 => c,
+        };
+        if let Some((neighbour_ref_idx, mv)) = directional {
+            if neighbour_ref_idx == ref_idx {
+                return mv;
+            }
+        }
+
+        if b.is_none() && c.is_none() && a.is_some() {
+            b = a;
+            c = a;
+        }
+        let (ref_a, mv_a) = a.unwrap_or((-1, [0, 0]));
+        let (ref_b, mv_b) = b.unwrap_or((-1, [0, 0]));
+        let (ref_c, mv_c) = c.unwrap_or((-1, [0, 0]));
+        match (ref_a == ref_idx, ref_b == ref_idx, ref_c == ref_idx) {
+            (true, false, false) => mv_a,
+            (false, true, false) => mv_b,
+            (false, false, true) => mv_c,
+            _ => {
+                let median = |a: i32, b: i32, c: i32| a.max(b).min(a.min(b).max(c));
+                [
+                    median(mv_a[0], mv_b[0], mv_c[0]),
+                    median(mv_a[1], mv_b[1], mv_c[1]),
+                ]
+            }
+        }
+    }
+
+    /// Record a partition's motion and perform motion-compensated prediction.
+    fn predict_partition(
+        &mut self,
+        mb_addr: usize,
+        assigned: &mut [bool; 16],
+        partition: &Partition,
+        mv: [i32; 2],
+    ) -> Result<(), String> {
+        let reference = self
+            .ref_list
+            .get(usize::from(partition.ref_idx))
+            .ok_or_else(|| "Missing reference picture".to_string())?;
+        for y in partition.y..partition.y + partition.height {
+            for x in partition.x..partition.x + partition.width {
+                let idx = self.pic.block_index(mb_addr, 4, x, y);
+                self.pic.mvs[idx] = mv;
+                self.pic.ref_idx[idx] = partition.ref_idx as i8;
+                self.pic.ref_pic_ids[idx] = reference.id;
+                assigned[y * 4 + x] = true;
+            }
+        }
+
+        let (mb_x, mb_y) = self.mb_origin(mb_addr);
+        inter::predict_luma(
+            reference,
+            &mut self.pic.pic,
+            mb_x + partition.x * 4,
+            mb_y + partition.y * 4,
+            partition.width * 4,
+            partition.height * 4,
+            mv,
+        );
+        inter::predict_chroma(
+            reference,
+            &mut self.pic.pic,
+            mb_x / 2 + partition.x * 2,
+            mb_y / 2 + partition.y * 2,
+            partition.width * 2,
+            partition.height * 2,
+            mv,
+        );
+        Ok(())
+    }
+
+    fn decode_skipped_macroblock(&mut self, mb_addr: usize) -> Result<(), String> {
+        self.start_macroblock(mb_addr, MbKind::Inter);
+        for y in 0..4 {
+            for x in 0..4 {
+                let idx = self.pic.block_index(mb_addr, 4, x, y);
+                self.pic.total_coeff[idx] = 0;
+            }
+        }
+        for y in 0..2 {
+            for x in 0..2 {
+                let idx = self.pic.block_index(mb_addr, 2, x, y);
+                self.pic.chroma_total_coeff[0][idx] = 0;
+                self.pic.chroma_total_coeff[1][idx] = 0;
+            }
+        }
+
+        let mut assigned = [false; 16];
+        let partition = Partition {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 4,
+            shape: PartitionShape::Other,
+            ref_idx: 0,
+            mvd: [0, 0],
+        };
+        let a = self.neighbour_motion(mb_addr, &assigned, -1, 0);
+        let b = self.neighbour_motion(mb_addr, &assigned, 0, -1);
+        let mv = match (a, b) {
+            (None, _) | (_, None) | (Some((0, [0, 0])), _) | (_, Some((0, [0, 0]))) => [0, 0],
+            _ => self.predict_mv(mb_addr, &assigned, &partition),
+        };
+        self.predict_partition(mb_addr, &mut assigned, &partition, mv)
+    }
+
+    fn read_ref_idx(&mut self, present: bool) -> Result<u8, String> {
+        if !present {
+            return Ok(0);
+        }
+        let max = self.num_ref_idx_active - 1;
+        let ref_idx = self.reader.read_te(max)?;
+        if ref_idx > max {
+            return Err(format!("Invalid ref_idx_l0 {}", ref_idx));
+        }
+        Ok(ref_idx as u8)
+    }
+
+    fn read_mvd(&mut self) -> Result<[i32; 2], String> {
+        Ok([self.reader.read_se()?, self.reader.read_se()?])
+    }
+
+    fn decode_inter_macroblock(&mut self, mb_addr: usize, mb_type: u32) -> Result<(), String> {
+        self.start_macroblock(mb_addr, MbKind::Inter);
+
+        let partition = |x, y, width, height, shape| Partition {
+            x,
+            y,
+            width,
+            height,
+            shape,
+            ref_idx: 0,
+            mvd: [0, 0],
+        };
+        let ref_idx_present = self.num_ref_idx_active > 1;
+        let mut partitions = Vec::with_capacity(16);
+        if mb_type < 3 {
+            match mb_type {
+                0 => partitions.push(partition(0, 0, 4, 4, PartitionShape::Other)),
+                1 => {
+                    partitions.push(partition(0, 0, 4, 2, PartitionShape::Upper16x8));
+                    partitions.push(partition(0, 2, 4, 2, PartitionShape::Lower16x8));
+                }
+                _ => {
+                    partitions.push(partition(0, 0, 2, 4, PartitionShape::Left8x16));
+                    partitions.push(partition(2, 0, 2, 4, PartitionShape::Right8x16));
+                }
+            }
+            for partition in partitions.iter_mut() {
+                partition.ref_idx = self.read_ref_idx(ref_idx_present)?;
+            }
+            for partition in partitions.iter_mut() {
+                partition.mvd = self.read_mvd()?;
+            }
+        } else {
+            let mut sub_mb_types = [0; 4];
+            for sub_mb_type in sub_mb_types.iter_mut() {
+                *sub_mb_type = self.reader.read_ue()?;
+                if *sub_mb_type > 3 {
+                    return Err(format!("Invalid sub_mb_type {}", sub_mb_type));
+                }
+            }
+            // P_8x8ref0 has no reference indices.
+            let mut ref_idxs = [0; 4];
+            for ref_idx in ref_idxs.iter_mut() {
+                *ref_idx = self.read_ref_idx(ref_idx_present && mb_type == 3)?;
+            }
+            for (i, &sub_mb_type) in sub_mb_types.iter().enumerate() {
+                let (x, y) = ((i % 2) * 2, (i / 2) * 2);
+                let sub_partitions: &[(usize, usize, usize, usize)] = match sub_mb_type {
+                    0 => &[(0, 0, 2, 2)],
+                    1 => &[(0, 0, 2, 1), (0, 1, 2, 1)],
+                    2 => &[(0, 0, 1, 2), (1, 0, 1, 2)],
+                    _ => &[(0, 0, 1, 1), (1, 0, 1, 1), (0, 1, 1, 1), (1, 1, 1, 1)],
+                };
+                for &(sub_x, sub_y, width, height) in sub_partitions {
+                    let mut partition =
+                        partition(x + sub_x, y + sub_y, width, height, PartitionShape::Other);
+                    partition.ref_idx = ref_idxs[i];
+                    partition.mvd = self.read_mvd()?;
+                    partitions.push(partition);
+                }
+            }
+        }
+
+        let coded_block_pattern = self.read_coded_block_pattern(false)?;
+        if coded_block_pattern != 0 {
+            self.read_mb_qp_delta(mb_addr)?;
+        }
+        let residual = self.read_residual(mb_addr, coded_block_pattern, false)?;
+
+        let mut assigned = [false; 16];
+        for partition in &partitions {
+            let mvp = self.predict_mv(mb_addr, &assigned, partition);
+            let mv = [mvp[0] + partition.mvd[0], mvp[1] + partition.mvd[1]];
+            self.predict_partition(mb_addr, &mut assigned, partition, mv)?;
+        }
+
+        for (blk_idx, coeffs) in residual.luma.iter().enumerate() {
+            let block = transform::scale_4x4(coeffs, self.qp, None);
+            self.add_luma_residual(mb_addr, blk_idx, &block);
+        }
+        self.add_chroma_residual(mb_addr, &residual);
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Sequence and picture parameter sets (sections 7.3.2.1 and 7.3.2.2 of the
+//! spec).
+
+use super::bitstream::BitReader;
+
+/// `MaxFS` for level 5.1, the highest level in the spec (Table A-1).
+const MAX_FRAME_SIZE_IN_MBS: usize = 36864;
+/// Neither dimension may exceed `Sqrt(MaxFS * 8)` (section A.3.1 item f).
+const MAX_DIMENSION_IN_MBS: usize = 543;
+
+#[derive(Debug)]
+pub struct Sps {
+    pub id: u32,
+    pub log2_max_frame_num: u32,
+    pub pic_order_cnt_type: u32,
+    pub log2_max_pic_order_cnt_lsb: u32,
+    pub delta_pic_order_always_zero: bool,
+    pub max_num_ref_frames: u32,
+    pub width_in_mbs: usize,
+    pub height_in_mbs: usize,
+    /// Cropping rectangle in luma samples: left, right, top, bottom.
+    pub crop: (usize, usize, usize, usize),
+}
+
+impl Sps {
+    pub fn parse(reader: &mut BitReader) -> Result<Sps, String> {
+        let profile_idc = reader.read_bits(8)?;
+        let _constraint_flags = reader.read_bits(8)?;
+        let _level_idc = reader.read_bits(8)?;
+        let id = reader.read_ue()?;
+        if id >= 32 {
+            return Err(format!("Invalid SPS ID {}", id));
+        }
+
+        if matches!(
+            profile_idc,
+            100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+        ) {
+            let chroma_format_idc = reader.read_ue()?;
+            if chroma_format_idc != 1 {
+                return Err(format!(
+                    "Unsupported chroma_format_idc {}",
+                    chroma_format_idc
+                ));
+            }
+            let bit_depth_luma = reader.read_ue()? + 8;
+            let bit_depth_chroma = reader.read_ue()? + 8;
+            if bit_depth_luma != 8 || bit_depth_chroma != 8 {
+                return Err("Unsupported bit depth".to_string());
+            }
+            let _qpprime_y_zero_transform_bypass = reader.read_bit()?;
+            if reader.read_bit()? {
+                return Err("Unsupported SPS scaling matrices".to_string());
+            }
+        }
+
+        let log2_max_frame_num_minus4 = reader.read_ue()?;
+        if log2_max_frame_num_minus4 > 12 {
+            return Err(format!(
+                "Invalid log2_max_frame_num_minus4 {}",
+                log2_max_frame_num_minus4
+            ));
+        }
+        let log2_max_frame_num = log2_max_frame_num_minus4 + 4;
+        let pic_order_cnt_type = reader.read_ue()?;
+        let mut log2_max_pic_order_cnt_lsb = 0;
+        let mut delta_pic_order_always_zero = false;
+        match pic_order_cnt_type {
+            0 => {
+                let log2_max_pic_order_cnt_lsb_minus4 = reader.read_ue()?;
+                if log2_max_pic_order_cnt_lsb_minus4 > 12 {
+                    return Err(format!(
+                        "Invalid log2_max_pic_order_cnt_lsb_minus4 {}",
+                        log2_max_pic_order_cnt_lsb_minus4
+                    ));
+                }
+                log2_max_pic_order_cnt_lsb = log2_max_pic_order_cnt_lsb_minus4 + 4;
+            }
+            1 => {
+                delta_pic_order_always_zero = reader.read_bit()?;
+                let _offset_for_non_ref_pic = reader.read_se()?;
+                let _offset_for_top_to_bottom_field = reader.read_se()?;
+                let num_ref_frames_in_pic_order_cnt_cycle = reader.read_ue()?;
+                for _ in 0..num_ref_frames_in_pic_order_cnt_cycle {
+                    let _offset_for_ref_frame = reader.read_se()?;
+                }
+            }
+            2 => (),
+            _ => return Err(format!("Invalid pic_order_cnt_type {}", pic_order_cnt_type)),
+        }
+
+        let max_num_ref_frames = reader.read_ue()?;
+        let _gaps_in_frame_num_value_allowed = reader.read_bit()?;
+        let width_in_mbs = reader.read_ue()? as usize + 1;
+        let height_in_map_units = reader.read_ue()? as usize + 1;
+        if width_in_mbs > MAX_DIMENSION_IN_MBS
+            || height_in_map_units > MAX_DIMENSION_IN_MBS
+            || width_in_mbs * height_in_map_units > MAX_FRAME_SIZE_IN_MBS
+        {
+            return Err(format!(
+                "Frame size of {}x{} macroblocks exceeds the level limits",
+                width_in_mbs, height_in_map_units
+            ));
+        }
+        let frame_mbs_only = reader.read_bit()?;
+        if !frame_mbs_only {
+            return Err("Interlaced video is not supported".to_string());
+        }
+        let _direct_8x8_inference = reader.read_bit()?;
+        let crop = if reader.read_bit()? {
+            // CropUnitX and CropUnitY are both 2 for 4:2:0 progressive video.
+            (
+                reader.read_ue()? as usize * 2,
+                reader.read_ue()? as usize * 2,
+                reader.read_ue()? as usize * 2,
+                reader.read_ue()? as usize * 2,
+            )
+        } else {
+            (0, 0, 0, 0)
+        };
+        // VUI parameters are ignored.
+
+        if width_in_mbs * 16 <= crop.0 + crop.1 || height_in_map_units * 16 <= crop.2 + crop.3 {
+            return Err("Invalid frame cropping".to_string());
+        }
+
+        Ok(Sps {
+            id,
+            log2_max_frame_num,
+            pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb,
+            delta_pic_order_always_zero,
+            max_num_ref_frames,
+            width_in_mbs,
+            height_in_mbs: height_in_map_units,
+            crop,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Pps {
+    pub id: u32,
+    pub sps_id: u32,
+    pub bottom_field_pic_order_in_frame_present: bool,
+    pub num_ref_idx_l0_default_active: u32,
+    pub pic_init_qp: i32,
+    /// Cb and Cr offsets.
+    pub chroma_qp_index_offset: [i32; 2],
+    pub deblocking_filter_control_present: bool,
+    pub constrained_intra_pred: bool,
+    pub redundant_pic_cnt_present: bool,
+}
+
+impl Pps {
+    pub fn parse(reader: &mut BitReader) -> Result<Pps, String> {
+        let id = reader.read_ue()?;
+        if id >= 256 {
+            return Err(format!("Invalid PPS ID {}", id));
+        }
+        let sps_id = reader.read_ue()?;
+        if reader.read_bit()? {
+            return Err("CABAC is not supported".to_string());
+        }
+        let bottom_field_pic_order_in_frame_present = reader.read_bit()?;
+        if reader.read_ue()? != 0 {
+            return Err("Slice groups are not supported".to_string());
+        }
+        let num_ref_idx_l0_default_active = reader.read_ue()? + 1;
+        let _num_ref_idx_l1_default_active = reader.read_ue()? + 1;
+        let weighted_pred = reader.read_bit()?;
+        let _weighted_bipred_idc = reader.read_bits(2)?;
+        if weighted_pred {
+            return Err("Weighted prediction is not supported".to_string());
+        }
+        let pic_init_qp = 26 + reader.read_se()?;
+        let _pic_init_qs = 26 + reader.read_se()?;
+        let chroma_qp_index_offset = reader.read_se()?;
+        let deblocking_filter_control_present = reader.read_bit()?;
+        let constrained_intra_pred = reader.read_bit()?;
+        let redundant_pic_cnt_present = reader.read_bit()?;
+
+        let mut second_chroma_qp_index_offset = chroma_qp_index_offset;
+        if reader.more_rbsp_data() {
+            if reader.read_bit()? {
+                return Err("8x8 transforms are not supported".to_string());
+            }
+            if reader.read_bit()? {
+                return Err("Unsupported PPS scaling matrices".to_string());
+            }
+            second_chroma_qp_index_offset = reader.read_se()?;
+        }
+
+        Ok(Pps {
+            id,
+            sps_id,
+            bottom_field_pic_order_in_frame_present,
+            num_ref_idx_l0_default_active,
+            pic_init_qp,
+            chroma_qp_index_offset: [chroma_qp_index_offset, second_chroma_qp_index_offset],
+            deblocking_filter_control_present,
+            constrained_intra_pred,
+            redundant_pic_cnt_present,
+        })
+    }
+}
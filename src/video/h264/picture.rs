@@ -0,0 +1,169 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Decoded pictures and the per-macroblock state kept while decoding one.
+
+use super::params::Sps;
+
+/// A decoded frame in 4:2:0 YCbCr. The dimensions are always a multiple of the
+/// macroblock size, cropping is applied later.
+pub struct Picture {
+    /// Unique identifier, used to tell whether two blocks refer to the same
+    /// reference picture.
+    pub id: u32,
+    pub width: usize,
+    pub height: usize,
+    pub luma: Vec<u8>,
+    /// Cb and Cr planes, each half the width and height of the luma plane.
+    pub chroma: [Vec<u8>; 2],
+    /// Cropping rectangle from the SPS in luma samples: left, right, top,
+    /// bottom.
+    pub crop: (usize, usize, usize, usize),
+}
+
+impl Picture {
+    fn new(id: u32, width: usize, height: usize, crop: (usize, usize, usize, usize)) -> Picture {
+        Picture {
+            id,
+            width,
+            height,
+            crop,
+            luma: vec![0; width * height],
+            chroma: [vec![0; width * height / 4], vec![0; width * height / 4]],
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MbKind {
+    Intra4x4,
+    Intra16x16,
+    IPcm,
+    /// Includes skipped macroblocks.
+    Inter,
+}
+
+impl MbKind {
+    pub fn is_intra(self) -> bool {
+        self != MbKind::Inter
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct MbInfo {
+    /// Index of the slice this macroblock belongs to, or [NOT_DECODED].
+    pub slice: u32,
+    pub kind: MbKind,
+    /// QP_Y, as used by the deblocking filter.
+    pub qp: i32,
+}
+
+pub const NOT_DECODED: u32 = u32::MAX;
+
+/// Parameters that can change from slice to slice and that are needed after
+/// the slice has been decoded.
+pub struct SliceParams {
+    pub disable_deblocking_filter_idc: u32,
+    pub alpha_offset: i32,
+    pub beta_offset: i32,
+    pub chroma_qp_index_offset: [i32; 2],
+}
+
+/// The picture currently being decoded. Information about 4x4 blocks is stored
+/// in picture-wide arrays in raster order, so that neighbouring blocks in
+/// other macroblocks are easy to find.
+pub struct DecodingPicture {
+    pub pic: Picture,
+    pub width_in_mbs: usize,
+    pub height_in_mbs: usize,
+    pub mbs: Vec<MbInfo>,
+    pub slices: Vec<SliceParams>,
+    /// Number of non-zero coefficients in each 4x4 luma block.
+    pub total_coeff: Vec<u8>,
+    /// Number of non-zero AC coefficients in each 4x4 Cb and Cr block.
+    pub chroma_total_coeff: [Vec<u8>; 2],
+    /// Intra 4x4 prediction mode of each 4x4 luma block, 2 (DC) for
+    /// macroblocks not using Intra 4x4 prediction, or -1 for inter macroblocks.
+    pub intra4x4_modes: Vec<i8>,
+    /// Motion vector of each 4x4 luma block (zero for intra macroblocks).
+    pub mvs: Vec<[i32; 2]>,
+    /// Reference index of each 4x4 luma block, or -1 for intra macroblocks.
+    pub ref_idx: Vec<i8>,
+    /// ID of the reference picture of each 4x4 luma block.
+    pub ref_pic_ids: Vec<u32>,
+}
+
+impl DecodingPicture {
+    pub fn new(id: u32, sps: &Sps) -> DecodingPicture {
+        let width_in_mbs = sps.width_in_mbs;
+        let height_in_mbs = sps.height_in_mbs;
+        let mb_count = width_in_mbs * height_in_mbs;
+        DecodingPicture {
+            pic: Picture::new(id, width_in_mbs * 16, height_in_mbs * 16, sps.crop),
+            width_in_mbs,
+            height_in_mbs,
+            mbs: vec![
+                MbInfo {
+                    slice: NOT_DECODED,
+                    kind: MbKind::Inter,
+                    qp: 0,
+                };
+                mb_count
+            ],
+            slices: Vec::new(),
+            total_coeff: vec![0; mb_count * 16],
+            chroma_total_coeff: [vec![0; mb_count * 4], vec![0; mb_count * 4]],
+            intra4x4_modes: vec![-1; mb_count * 16],
+            mvs: vec![[0, 0]; mb_count * 16],
+            ref_idx: vec![-1; mb_count * 16],
+            ref_pic_ids: vec![u32::MAX; mb_count * 16],
+        }
+    }
+
+    /// Returns [true] if the macroblock at the given coordinates has been
+    /// decoded and belongs to the same slice as the macroblock `current`.
+    pub fn mb_available(&self, current: usize, mb_x: isize, mb_y: isize) -> bool {
+        if mb_x < 0 || mb_y < 0 || mb_x >= self.width_in_mbs as isize {
+            return false;
+        }
+        let mb_addr = mb_y as usize * self.width_in_mbs + mb_x as usize;
+        mb_addr <= current && self.mbs[mb_addr].slice == self.mbs[current].slice
+    }
+
+    /// Find the block at the given coordinates relative to the top-left block
+    /// of macroblock `current`, in a grid of `blocks_per_mb` square blocks per
+    /// macroblock. Returns the index of the block in the picture-wide arrays
+    /// and the address of the macroblock containing it, or [None] if that
+    /// macroblock is not available.
+    pub fn neighbour_block(
+        &self,
+        current: usize,
+        blocks_per_mb: usize,
+        x: isize,
+        y: isize,
+    ) -> Option<(usize, usize)> {
+        let n = blocks_per_mb as isize;
+        let mb_x = (current % self.width_in_mbs) as isize;
+        let mb_y = (current / self.width_in_mbs) as isize;
+        let block_x = mb_x * n + x;
+        let block_y = mb_y * n + y;
+        let neighbour_mb_x = block_x.div_euclid(n);
+        let neighbour_mb_y = block_y.div_euclid(n);
+        if !self.mb_available(current, neighbour_mb_x, neighbour_mb_y) {
+            return None;
+        }
+        let index = block_y as usize * self.width_in_mbs * blocks_per_mb + block_x as usize;
+        let mb_addr = neighbour_mb_y as usize * self.width_in_mbs + neighbour_mb_x as usize;
+        Some((index, mb_addr))
+    }
+
+    /// Index of a block within macroblock `mb_addr` in the picture-wide
+    /// arrays.
+    pub fn block_index(&self, mb_addr: usize, blocks_per_mb: usize, x: usize, y: usize) -> usize {
+        let mb_x = mb_addr % self.width_in_mbs;
+        let mb_y = mb_addr / self.width_in_mbs;
+        (mb_y * blocks_per_mb + y) * self.width_in_mbs * blocks_per_mb + mb_x * blocks_per_mb + x
+    }
+}
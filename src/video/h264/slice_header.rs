@@ -0,0 +1,197 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Slice headers (section 7.3.3 of the spec).
+
+use super::bitstream::BitReader;
+use super::params::{Pps, Sps};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SliceType {
+    P,
+    I,
+}
+
+/// `modification_of_pic_nums_idc` and its associated value.
+#[derive(Debug, Copy, Clone)]
+pub enum RefPicListModification {
+    ShortTermSubtract(u32),
+    ShortTermAdd(u32),
+    LongTerm(u32),
+}
+
+/// `memory_management_control_operation` and its associated values.
+#[derive(Debug, Copy, Clone)]
+pub enum MemoryManagementOperation {
+    UnmarkShortTerm {
+        difference_of_pic_nums: u32,
+    },
+    UnmarkLongTerm {
+        long_term_pic_num: u32,
+    },
+    ShortTermToLongTerm {
+        difference_of_pic_nums: u32,
+        long_term_frame_idx: u32,
+    },
+    SetMaxLongTermFrameIdx {
+        max_plus_1: u32,
+    },
+    UnmarkAll,
+    CurrentToLongTerm {
+        long_term_frame_idx: u32,
+    },
+}
+
+#[derive(Debug)]
+pub struct SliceHeader {
+    pub first_mb_in_slice: usize,
+    pub slice_type: SliceType,
+    pub pps_id: u32,
+    pub frame_num: u32,
+    pub redundant_pic_cnt: u32,
+    pub num_ref_idx_l0_active: u32,
+    pub ref_pic_list_modifications: Vec<RefPicListModification>,
+    /// For IDR pictures.
+    pub long_term_reference: bool,
+    /// [None] if the sliding window should be used.
+    pub memory_management_operations: Option<Vec<MemoryManagementOperation>>,
+    pub slice_qp_delta: i32,
+    pub disable_deblocking_filter_idc: u32,
+    pub slice_alpha_c0_offset: i32,
+    pub slice_beta_offset: i32,
+}
+
+impl SliceHeader {
+    /// Parse the slice header. The PPS ID is read first so the right parameter
+    /// sets can be provided by `get_params`.
+    pub fn parse<'a>(
+        reader: &mut BitReader,
+        is_idr: bool,
+        nal_ref_idc: u8,
+        get_params: impl FnOnce(u32) -> Result<(&'a Sps, &'a Pps), String>,
+    ) -> Result<SliceHeader, String> {
+        let first_mb_in_slice = reader.read_ue()? as usize;
+        let slice_type = match reader.read_ue()? % 5 {
+            0 => SliceType::P,
+            2 => SliceType::I,
+            1 => return Err("B slices are not supported".to_string()),
+            _ => return Err("SP and SI slices are not supported".to_string()),
+        };
+        let pps_id = reader.read_ue()?;
+        let (sps, pps) = get_params(pps_id)?;
+
+        let frame_num = reader.read_bits(sps.log2_max_frame_num)?;
+        if is_idr {
+            let _idr_pic_id = reader.read_ue()?;
+        }
+        if sps.pic_order_cnt_type == 0 {
+            let _pic_order_cnt_lsb = reader.read_bits(sps.log2_max_pic_order_cnt_lsb)?;
+            if pps.bottom_field_pic_order_in_frame_present {
+                let _delta_pic_order_cnt_bottom = reader.read_se()?;
+            }
+        }
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero {
+            let _delta_pic_order_cnt_0 = reader.read_se()?;
+            if pps.bottom_field_pic_order_in_frame_present {
+                let _delta_pic_order_cnt_1 = reader.read_se()?;
+            }
+        }
+        let redundant_pic_cnt = if pps.redundant_pic_cnt_present {
+            reader.read_ue()?
+        } else {
+            0
+        };
+
+        let mut num_ref_idx_l0_active = pps.num_ref_idx_l0_default_active;
+        let mut ref_pic_list_modifications = Vec::new();
+        if slice_type == SliceType::P {
+            if reader.read_bit()? {
+                num_ref_idx_l0_active = reader.read_ue()? + 1;
+            }
+            if num_ref_idx_l0_active > 32 {
+                return Err("Invalid num_ref_idx_l0_active_minus1".to_string());
+            }
+            if reader.read_bit()? {
+                loop {
+                    ref_pic_list_modifications.push(match reader.read_ue()? {
+                        0 => RefPicListModification::ShortTermSubtract(reader.read_ue()? + 1),
+                        1 => RefPicListModification::ShortTermAdd(reader.read_ue()? + 1),
+                        2 => RefPicListModification::LongTerm(reader.read_ue()?),
+                        3 => break,
+                        _ => return Err("Invalid modification_of_pic_nums_idc".to_string()),
+                    });
+                }
+            }
+        }
+
+        let mut long_term_reference = false;
+        let mut memory_management_operations = None;
+        if nal_ref_idc != 0 {
+            if is_idr {
+                let _no_output_of_prior_pics = reader.read_bit()?;
+                long_term_reference = reader.read_bit()?;
+            } else if reader.read_bit()? {
+                let mut operations = Vec::new();
+                loop {
+                    use MemoryManagementOperation as Op;
+                    operations.push(match reader.read_ue()? {
+                        0 => break,
+                        1 => Op::UnmarkShortTerm {
+                            difference_of_pic_nums: reader.read_ue()? + 1,
+                        },
+                        2 => Op::UnmarkLongTerm {
+                            long_term_pic_num: reader.read_ue()?,
+                        },
+                        3 => Op::ShortTermToLongTerm {
+                            difference_of_pic_nums: reader.read_ue()? + 1,
+                            long_term_frame_idx: reader.read_ue()?,
+                        },
+                        4 => Op::SetMaxLongTermFrameIdx {
+                            max_plus_1: reader.read_ue()?,
+                        },
+                        5 => Op::UnmarkAll,
+                        6 => Op::CurrentToLongTerm {
+                            long_term_frame_idx: reader.read_ue()?,
+                        },
+                        _ => return Err("Invalid memory_management_control_operation".to_string()),
+                    });
+                }
+                memory_management_operations = Some(operations);
+            }
+        }
+
+        let slice_qp_delta = reader.read_se()?;
+
+        let mut disable_deblocking_filter_idc = 0;
+        let mut slice_alpha_c0_offset = 0;
+        let mut slice_beta_offset = 0;
+        if pps.deblocking_filter_control_present {
+            disable_deblocking_filter_idc = reader.read_ue()?;
+            if disable_deblocking_filter_idc > 2 {
+                return Err("Invalid disable_deblocking_filter_idc".to_string());
+            }
+            if disable_deblocking_filter_idc != 1 {
+                slice_alpha_c0_offset = reader.read_se()? * 2;
+                slice_beta_offset = reader.read_se()? * 2;
+            }
+        }
+
+        Ok(SliceHeader {
+            first_mb_in_slice,
+            slice_type,
+            pps_id,
+            frame_num,
+            redundant_pic_cnt,
+            num_ref_idx_l0_active,
+            ref_pic_list_modifications,
+            long_term_reference,
+            memory_management_operations,
+            slice_qp_delta,
+            disable_deblocking_filter_idc,
+            slice_alpha_c0_offset,
+            slice_beta_offset,
+        })
+    }
+}
@@ -0,0 +1,151 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Scaling and inverse transforms for residual blocks (section 8.5 of the
+//! spec). Only flat scaling matrices are supported.
+
+/// Raster index of each coefficient in zigzag scan order.
+const ZIGZAG_4X4: [usize; 16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+
+/// `normAdjust4x4` values for the three groups of positions.
+const NORM_ADJUST_4X4: [[i32; 3]; 6] = [
+    [10, 16, 13],
+    [11, 18, 14],
+    [13, 20, 16],
+    [14, 23, 18],
+    [16, 25, 20],
+    [18, 29, 23],
+];
+
+/// Chroma QP (`QP_C`) for each value of `qP_I` (table 8-15 of the spec).
+pub fn chroma_qp(qp_y: i32, offset: i32) -> i32 {
+    const TABLE: [i32; 22] = [
+        29, 30, 31, 32, 32, 33, 34, 34, 35, 35, 36, 36, 37, 37, 37, 38, 38, 38, 39, 39, 39, 39,
+    ];
+    let qp_i = (qp_y + offset).clamp(0, 51);
+    if qp_i < 30 {
+        qp_i
+    } else {
+        TABLE[(qp_i - 30) as usize]
+    }
+}
+
+fn level_scale(qp: i32, raster_idx: usize) -> i32 {
+    let (i, j) = (raster_idx / 4, raster_idx % 4);
+    let group = if i % 2 == 0 && j % 2 == 0 {
+        0
+    } else if i % 2 == 1 && j % 2 == 1 {
+        1
+    } else {
+        2
+    };
+    16 * NORM_ADJUST_4X4[(qp % 6) as usize][group]
+}
+
+/// Scale the coefficients of a 4x4 block given in zigzag scan order, producing
+/// a raster-order block. If `dc` is provided, it is the already-scaled DC
+/// coefficient, used for Intra 16x16 luma and chroma blocks.
+pub fn scale_4x4(coeffs: &[i32; 16], qp: i32, dc: Option<i32>) -> [i32; 16] {
+    let mut block = [0; 16];
+    for (scan_idx, &coeff) in coeffs.iter().enumerate() {
+        if coeff == 0 {
+            continue;
+        }
+        let raster_idx = ZIGZAG_4X4[scan_idx];
+        let scaled = coeff * level_scale(qp, raster_idx);
+        block[raster_idx] = if qp >= 24 {
+            scaled << (qp / 6 - 4)
+        } else {
+            (scaled + (1 << (3 - qp / 6))) >> (4 - qp / 6)
+        };
+    }
+    if let Some(dc) = dc {
+        block[0] = dc;
+    }
+    block
+}
+
+/// Inverse transform and scale the Intra 16x16 luma DC coefficients given in
+/// zigzag scan order. The result is in raster order of the 4x4 blocks.
+pub fn luma_dc_transform(coeffs: &[i32; 16], qp: i32) -> [i32; 16] {
+    let mut c = [0; 16];
+    for (scan_idx, &coeff) in coeffs.iter().enumerate() {
+        c[ZIGZAG_4X4[scan_idx]] = coeff;
+    }
+
+    // f = H * c * H, where H is the 4x4 Hadamard matrix.
+    let mut tmp = [0; 16];
+    for i in 0..4 {
+        let row = &c[i * 4..i * 4 + 4];
+        let s01 = row[0] + row[1];
+        let d01 = row[0] - row[1];
+        let s23 = row[2] + row[3];
+        let d23 = row[2] - row[3];
+        tmp[i * 4] = s01 + s23;
+        tmp[i * 4 + 1] = s01 - s23;
+        tmp[i * 4 + 2] = d01 - d23;
+        tmp[i * 4 + 3] = d01 + d23;
+    }
+    let mut f = [0; 16];
+    for j in 0..4 {
+        let s01 = tmp[j] + tmp[4 + j];
+        let d01 = tmp[j] - tmp[4 + j];
+        let s23 = tmp[8 + j] + tmp[12 + j];
+        let d23 = tmp[8 + j] - tmp[12 + j];
+        f[j] = s01 + s23;
+        f[4 + j] = s01 - s23;
+        f[8 + j] = d01 - d23;
+        f[12 + j] = d01 + d23;
+    }
+
+    let scale = level_scale(qp, 0);
+    f.map(|value| {
+        if qp >= 36 {
+            (value * scale) << (qp / 6 - 6)
+        } else {
+            (value * scale + (1 << (5 - qp / 6))) >> (6 - qp / 6)
+        }
+    })
+}
+
+/// Inverse transform and scale the 2x2 chroma DC coefficients.
+pub fn chroma_dc_transform(c: &[i32; 4], qp: i32) -> [i32; 4] {
+    let f = [
+        c[0] + c[1] + c[2] + c[3],
+        c[0] - c[1] + c[2] - c[3],
+        c[0] + c[1] - c[2] - c[3],
+        c[0] - c[1] - c[2] + c[3],
+    ];
+    let scale = level_scale(qp, 0);
+    f.map(|value| ((value * scale) << (qp / 6)) >> 5)
+}
+
+/// Inverse transform a raster-order 4x4 block of scaled coefficients and add
+/// the result to the prediction samples at `offset` in `plane`.
+pub fn idct_4x4_add(block: &[i32; 16], plane: &mut [u8], offset: usize, stride: usize) {
+    let mut tmp = [0; 16];
+    for i in 0..4 {
+        let d = &block[i * 4..i * 4 + 4];
+        let e0 = d[0] + d[2];
+        let e1 = d[0] - d[2];
+        let e2 = (d[1] >> 1) - d[3];
+        let e3 = d[1] + (d[3] >> 1);
+        tmp[i * 4] = e0 + e3;
+        tmp[i * 4 + 1] = e1 + e2;
+        tmp[i * 4 + 2] = e1 - e2;
+        tmp[i * 4 + 3] = e0 - e3;
+    }
+    for j in 0..4 {
+        let g0 = tmp[j] + tmp[8 + j];
+        let g1 = tmp[j] - tmp[8 + j];
+        let g2 = (tmp[4 + j] >> 1) - tmp[12 + j];
+        let g3 = tmp[4 + j] + (tmp[12 + j] >> 1);
+        let residuals = [g0 + g3, g1 + g2, g1 - g2, g0 - g3];
+        for (i, residual) in residuals.into_iter().enumerate() {
+            let sample = &mut plane[offset + i * stride + j];
+            *sample = (i32::from(*sample) + ((residual + 32) >> 6)).clamp(0, 255) as u8;
+        }
+    }
+}
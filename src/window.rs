@@ -26,7 +26,7 @@ use std::f32::consts::FRAC_PI_2;
 use std::num::NonZeroU32;
 use std::time::{Duration, Instant};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum DeviceOrientation {
     Portrait,
     LandscapeLeft,
@@ -68,6 +68,42 @@ fn set_sdl2_orientation(orientation: DeviceOrientation) {
     );
 }
 
+/// SDL's `SDL_TOUCH_MOUSEID`: the mouse ID used for mouse events that SDL
+/// synthesized from touch events.
+const SDL_TOUCH_MOUSEID: u32 = u32::MAX;
+/// SDL's `SDL_MOUSE_TOUCHID`: the touch ID used for touch events that SDL
+/// synthesized from mouse events.
+const SDL_MOUSE_TOUCHID: i64 = -1;
+
+/// Identifies the finger (or whatever is pretending to be a finger) that a
+/// touch event belongs to, so that simultaneous touches can be told apart.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum FingerId {
+    /// The mouse's left button.
+    Mouse,
+    /// A second finger that mirrors the mouse around the centre of the screen,
+    /// for pinch and rotation gestures. See [Window::poll_for_events].
+    MouseMirror,
+    /// The controller's right analog stick.
+    VirtualCursor,
+    /// A controller button mapped with `--button-to-touch=`.
+    Button(crate::options::Button),
+    /// A keyboard key mapped with `--key-to-touch=`, identified by its SDL
+    /// keycode.
+    Key(i32),
+    /// A finger on a real touchscreen, identified by SDL's touch device ID and
+    /// finger ID.
+    Touchscreen(i64, i64),
+}
+
+/// Returns [true] if the touch device's touches correspond directly to points
+/// on the screen, and are not synthesized from mouse input.
+fn is_touchscreen(touch_id: i64) -> bool {
+    touch_id != SDL_MOUSE_TOUCHID
+        && unsafe { sdl2_sys::SDL_GetTouchDeviceType(touch_id) }
+            == sdl2_sys::SDL_TouchDeviceType::SDL_TOUCH_DEVICE_DIRECT
+}
+
 #[derive(Debug)]
 pub enum Event {
     /// User requested quit.
@@ -78,9 +114,9 @@ pub enum Event {
     /// OS has informed touchHLE it will soon terminate.
     /// (iOS `applicationWillTerminate:`, Android `onDestroy()`)
     AppWillTerminate,
-    TouchDown((f32, f32)),
-    TouchMove((f32, f32)),
-    TouchUp((f32, f32)),
+    TouchDown(FingerId, (f32, f32)),
+    TouchMove(FingerId, (f32, f32)),
+    TouchUp(FingerId, (f32, f32)),
 }
 
 pub enum GLVersion {
@@ -180,6 +216,23 @@ pub struct Window {
     _sensor_ctx: sdl2::SensorSubsystem,
     accelerometer: Option<sdl2::sensor::Sensor>,
     virtual_cursor_last: Option<(f32, f32, bool, bool)>,
+    /// Whether [FingerId::MouseMirror] is currently touching the screen.
+    mouse_mirror_down: bool,
+    /// Last position of the mouse while the left button is held, in window
+    /// co-ordinates.
+    mouse_last: Option<(f32, f32)>,
+    /// Number of frames presented with [Self::swap_window].
+    frame_count: u64,
+    /// Copy of `exit_after_frames` on [Options].
+    exit_after_frames: Option<u32>,
+    /// Derived from `exit_after_seconds` on [Options].
+    exit_deadline: Option<Instant>,
+    /// Set once one of the above limits has been reached and the app has been
+    /// told to terminate. If it hasn't exited by this time, touchHLE exits.
+    forced_exit_time: Option<Instant>,
+    /// Set once [Self::forced_exit_time] has passed. See
+    /// [Self::forced_exit_due].
+    forced_exit_due: bool,
 }
 impl Window {
     /// Returns [true] if touchHLE is running on a device where we should always
@@ -195,6 +248,16 @@ impl Window {
         launch_image: Option<Image>,
         options: &Options,
     ) -> Window {
+        if options.headless {
+            // The offscreen driver renders to EGL pbuffers rather than windows,
+            // so no display server is needed. Mesa is asked to use its software
+            // rasterizer so that no GPU is needed either.
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+            if env::var_os("LIBGL_ALWAYS_SOFTWARE").is_none() {
+                env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+            }
+        }
+
         let sdl_ctx = sdl2::init().unwrap();
         let video_ctx = sdl_ctx.video().unwrap();
 
@@ -225,7 +288,8 @@ impl Window {
         // TODO: some apps specify their orientation in Info.plist, we could use
         // that here.
         let device_orientation = options.initial_orientation;
-        let fullscreen = options.fullscreen;
+        // There's no screen to fill in headless mode.
+        let fullscreen = options.fullscreen && !options.headless;
 
         let mut window = if Self::rotatable_fullscreen() {
             // Without this, SDL will force fullscreen mode to be portrait.
@@ -291,6 +355,17 @@ impl Window {
         #[cfg(target_os = "macos")]
         let max_height = window.size().1;
 
+        for key_name in options.key_to_touch.keys() {
+            // Key events are matched by keycode name, so scancode names
+            // aren't accepted here.
+            if sdl2::keyboard::Keycode::from_name(key_name).is_none() {
+                log!(
+                    "Warning: {:?} is not a recognized key name, --key-to-touch= will have no effect for it.",
+                    key_name
+                );
+            }
+        }
+
         let mut window = Window {
             _sdl_ctx: sdl_ctx,
             video_ctx,
@@ -315,6 +390,15 @@ impl Window {
             _sensor_ctx: sensor_ctx,
             accelerometer,
             virtual_cursor_last: None,
+            mouse_mirror_down: false,
+            mouse_last: None,
+            frame_count: 0,
+            exit_after_frames: options.exit_after_frames,
+            exit_deadline: options
+                .exit_after_seconds
+                .map(|seconds| Instant::now() + Duration::from_secs_f64(seconds)),
+            forced_exit_time: None,
+            forced_exit_due: false,
         };
 
         // Set up OpenGL ES context used for splash screen and app UI rendering
@@ -340,7 +424,13 @@ impl Window {
     ///
     /// Since polling can be quite expensive, this function will skip it if it
     /// was called too recently.
+    ///
+    /// Holding Shift while dragging with the mouse adds a second finger that
+    /// mirrors the mouse around the centre of the screen, so pinch and
+    /// rotation gestures can be performed without a touchscreen.
     pub fn poll_for_events(&mut self, options: &Options) {
+        self.check_exit_limits();
+
         let now = Instant::now();
         // poll roughly twice per frame to try to avoid missing frames sometimes
         if now.duration_since(self.last_polled) < Duration::from_secs_f64(1.0 / 120.0) {
@@ -372,42 +462,205 @@ impl Window {
             let out_y = (y + 0.5) * out_h as f32;
             (out_x, out_y)
         }
-        fn translate_button(button: sdl2::controller::Button) -> Option<crate::options::Button> {
+        fn translate_button(button: sdl2::controller::Button) -> crate::options::Button {
+            use crate::options::Button as B;
+            use sdl2::controller::Button as SB;
             match button {
-                sdl2::controller::Button::A => Some(crate::options::Button::A),
-                sdl2::controller::Button::B => Some(crate::options::Button::B),
-                sdl2::controller::Button::X => Some(crate::options::Button::X),
-                sdl2::controller::Button::Y => Some(crate::options::Button::Y),
-                _ => None,
+                SB::A => B::A,
+                SB::B => B::B,
+                SB::X => B::X,
+                SB::Y => B::Y,
+                SB::Back => B::Back,
+                SB::Guide => B::Guide,
+                SB::Start => B::Start,
+                SB::LeftStick => B::LeftStick,
+                SB::RightStick => B::RightStick,
+                SB::LeftShoulder => B::LeftShoulder,
+                SB::RightShoulder => B::RightShoulder,
+                SB::DPadUp => B::DPadUp,
+                SB::DPadDown => B::DPadDown,
+                SB::DPadLeft => B::DPadLeft,
+                SB::DPadRight => B::DPadRight,
+                SB::Misc1 => B::Misc1,
+                SB::Paddle1 => B::Paddle1,
+                SB::Paddle2 => B::Paddle2,
+                SB::Paddle3 => B::Paddle3,
+                SB::Paddle4 => B::Paddle4,
+                SB::Touchpad => B::Touchpad,
             }
         }
 
+        fn mirror_coords(window: &Window, (x, y): (f32, f32)) -> (f32, f32) {
+            let (vx, vy, vw, vh) = window.viewport();
+            let centre_x = vx as f32 + vw as f32 / 2.0;
+            let centre_y = vy as f32 + vh as f32 / 2.0;
+            (centre_x * 2.0 - x, centre_y * 2.0 - y)
+        }
+
         let mut controller_updated = false;
         while self.enable_event_polling {
             let Some(event) = self.event_pump.poll_event() else {
                 break;
             };
             use sdl2::event::Event as E;
-            self.event_queue.push_back(match event {
+            use sdl2::keyboard::Scancode;
+            // Used when an SDL event produces a second touch event.
+            let mut mirror_event = None;
+            let new_event = match event {
                 E::Quit { .. } => Event::Quit,
-                // TODO: support for multi-touch
+                // Mouse events that SDL synthesized from touchscreen input are
+                // ignored, since the finger events are handled directly.
                 E::MouseButtonDown {
                     x,
                     y,
                     mouse_btn: MouseButton::Left,
+                    which,
                     ..
-                } => Event::TouchDown(transform_input_coords(self, (x as f32, y as f32), false)),
+                } if which != SDL_TOUCH_MOUSEID => {
+                    let coords = (x as f32, y as f32);
+                    self.mouse_last = Some(coords);
+                    let keyboard = self.event_pump.keyboard_state();
+                    if keyboard.is_scancode_pressed(Scancode::LShift)
+                        || keyboard.is_scancode_pressed(Scancode::RShift)
+                    {
+                        self.mouse_mirror_down = true;
+                        let mirrored = mirror_coords(self, coords);
+                        mirror_event = Some(Event::TouchDown(
+                            FingerId::MouseMirror,
+                            transform_input_coords(self, mirrored, false),
+                        ));
+                    }
+                    Event::TouchDown(FingerId::Mouse, transform_input_coords(self, coords, false))
+                }
                 E::MouseMotion {
-                    x, y, mousestate, ..
-                } if mousestate.left() => {
-                    Event::TouchMove(transform_input_coords(self, (x as f32, y as f32), false))
+                    x,
+                    y,
+                    mousestate,
+                    which,
+                    ..
+                } if mousestate.left() && which != SDL_TOUCH_MOUSEID => {
+                    let coords = (x as f32, y as f32);
+                    self.mouse_last = Some(coords);
+                    if self.mouse_mirror_down {
+                        let mirrored = mirror_coords(self, coords);
+                        mirror_event = Some(Event::TouchMove(
+                            FingerId::MouseMirror,
+                            transform_input_coords(self, mirrored, false),
+                        ));
+                    }
+                    Event::TouchMove(FingerId::Mouse, transform_input_coords(self, coords, false))
                 }
                 E::MouseButtonUp {
                     x,
                     y,
                     mouse_btn: MouseButton::Left,
+                    which,
+                    ..
+                } if which != SDL_TOUCH_MOUSEID => {
+                    let coords = (x as f32, y as f32);
+                    self.mouse_last = None;
+                    if self.mouse_mirror_down {
+                        self.mouse_mirror_down = false;
+                        let mirrored = mirror_coords(self, coords);
+                        mirror_event = Some(Event::TouchUp(
+                            FingerId::MouseMirror,
+                            transform_input_coords(self, mirrored, false),
+                        ));
+                    }
+                    Event::TouchUp(FingerId::Mouse, transform_input_coords(self, coords, false))
+                }
+                // Pressing or releasing Shift mid-drag adds or removes the
+                // mirrored finger.
+                E::KeyDown {
+                    scancode: Some(Scancode::LShift | Scancode::RShift),
+                    repeat: false,
+                    ..
+                } if !self.mouse_mirror_down && self.mouse_last.is_some() => {
+                    self.mouse_mirror_down = true;
+                    let mirrored = mirror_coords(self, self.mouse_last.unwrap());
+                    Event::TouchDown(
+                        FingerId::MouseMirror,
+                        transform_input_coords(self, mirrored, false),
+                    )
+                }
+                E::KeyUp {
+                    scancode: Some(Scancode::LShift | Scancode::RShift),
+                    ..
+                } if self.mouse_mirror_down && self.mouse_last.is_some() => {
+                    self.mouse_mirror_down = false;
+                    let mirrored = mirror_coords(self, self.mouse_last.unwrap());
+                    Event::TouchUp(
+                        FingerId::MouseMirror,
+                        transform_input_coords(self, mirrored, false),
+                    )
+                }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                }
+                | E::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    let name = keycode.name().to_lowercase();
+                    let Some(&(x, y)) = options.key_to_touch.get(&name) else {
+                        continue;
+                    };
+                    let finger = FingerId::Key(keycode as i32);
+                    match event {
+                        E::KeyUp { .. } => {
+                            Event::TouchUp(finger, transform_input_coords(self, (x, y), true))
+                        }
+                        E::KeyDown { .. } => {
+                            Event::TouchDown(finger, transform_input_coords(self, (x, y), true))
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                // Touch events that SDL synthesized from mouse input are
+                // ignored, since the mouse events are handled directly. So are
+                // touches on trackpads, which don't correspond to a point on
+                // the screen.
+                E::FingerDown {
+                    touch_id,
+                    finger_id,
+                    x,
+                    y,
+                    ..
+                } if is_touchscreen(touch_id) => {
+                    let coords = self.finger_coords((x, y));
+                    Event::TouchDown(
+                        FingerId::Touchscreen(touch_id, finger_id),
+                        transform_input_coords(self, coords, false),
+                    )
+                }
+                E::FingerMotion {
+                    touch_id,
+                    finger_id,
+                    x,
+                    y,
                     ..
-                } => Event::TouchUp(transform_input_coords(self, (x as f32, y as f32), false)),
+                } if is_touchscreen(touch_id) => {
+                    let coords = self.finger_coords((x, y));
+                    Event::TouchMove(
+                        FingerId::Touchscreen(touch_id, finger_id),
+                        transform_input_coords(self, coords, false),
+                    )
+                }
+                E::FingerUp {
+                    touch_id,
+                    finger_id,
+                    x,
+                    y,
+                    ..
+                } if is_touchscreen(touch_id) => {
+                    let coords = self.finger_coords((x, y));
+                    Event::TouchUp(
+                        FingerId::Touchscreen(touch_id, finger_id),
+                        transform_input_coords(self, coords, false),
+                    )
+                }
                 E::ControllerDeviceAdded { which, .. } => {
                     self.controller_added(which);
                     continue;
@@ -420,18 +673,17 @@ impl Window {
                 // handled with polling, rather than being event-based.
                 E::ControllerButtonUp { button, .. } | E::ControllerButtonDown { button, .. } => {
                     controller_updated = true;
-                    let Some(button) = translate_button(button) else {
-                        continue;
-                    };
+                    let button = translate_button(button);
                     let Some(&(x, y)) = options.button_to_touch.get(&button) else {
                         continue;
                     };
+                    let finger = FingerId::Button(button);
                     match event {
                         E::ControllerButtonUp { .. } => {
-                            Event::TouchUp(transform_input_coords(self, (x, y), true))
+                            Event::TouchUp(finger, transform_input_coords(self, (x, y), true))
                         }
                         E::ControllerButtonDown { .. } => {
-                            Event::TouchDown(transform_input_coords(self, (x, y), true))
+                            Event::TouchDown(finger, transform_input_coords(self, (x, y), true))
                         }
                         _ => unreachable!(),
                     }
@@ -459,7 +711,11 @@ impl Window {
                     continue;
                 }
                 _ => continue,
-            })
+            };
+            self.event_queue.push_back(new_event);
+            if let Some(mirror_event) = mirror_event {
+                self.event_queue.push_back(mirror_event);
+            }
         }
 
         if controller_updated {
@@ -468,20 +724,73 @@ impl Window {
             self.virtual_cursor_last = Some((new_x, new_y, new_pressed, visible));
             self.event_queue
                 .push_back(match (old_pressed, new_pressed) {
-                    (false, true) => {
-                        Event::TouchDown(transform_input_coords(self, (new_x, new_y), false))
-                    }
-                    (true, false) => {
-                        Event::TouchUp(transform_input_coords(self, (new_x, new_y), false))
-                    }
-                    _ if (new_x, new_y) != (old_x, old_y) && new_pressed => {
-                        Event::TouchMove(transform_input_coords(self, (new_x, new_y), false))
-                    }
+                    (false, true) => Event::TouchDown(
+                        FingerId::VirtualCursor,
+                        transform_input_coords(self, (new_x, new_y), false),
+                    ),
+                    (true, false) => Event::TouchUp(
+                        FingerId::VirtualCursor,
+                        transform_input_coords(self, (new_x, new_y), false),
+                    ),
+                    _ if (new_x, new_y) != (old_x, old_y) && new_pressed => Event::TouchMove(
+                        FingerId::VirtualCursor,
+                        transform_input_coords(self, (new_x, new_y), false),
+                    ),
                     _ => return,
                 });
         }
     }
 
+    /// Returns [true] if the app was told to terminate because of
+    /// `--exit-after-frames=` or `--exit-after-seconds=` but didn't do so in
+    /// time. The caller should then exit with [Environment::exit].
+    ///
+    /// [Environment::exit]: crate::Environment::exit
+    pub fn forced_exit_due(&self) -> bool {
+        self.forced_exit_due
+    }
+
+    /// Tell the app to terminate if the limit set by `--exit-after-frames=` or
+    /// `--exit-after-seconds=` has been reached, and note if it took too long
+    /// to do so.
+    fn check_exit_limits(&mut self) {
+        let now = Instant::now();
+        if let Some(forced_exit_time) = self.forced_exit_time {
+            if now >= forced_exit_time {
+                self.forced_exit_due = true;
+            }
+            return;
+        }
+
+        let limit = if self
+            .exit_after_frames
+            .map_or(false, |limit| self.frame_count >= u64::from(limit))
+        {
+            format!("{} frames", self.frame_count)
+        } else if self.exit_deadline.map_or(false, |deadline| now >= deadline) {
+            "time limit".to_string()
+        } else {
+            return;
+        };
+
+        echo!("Reached {}, telling the app to terminate.", limit);
+        self.forced_exit_time = Some(now + Duration::from_secs(5));
+        match self.high_priority_event {
+            None => self.high_priority_event = Some(Event::AppWillTerminate),
+            Some(Event::AppWillTerminate) => (),
+            // Another high-priority event (e.g. the app resigning active) is
+            // pending, so deliver this one straight after it.
+            Some(_) => self.event_queue.push_front(Event::AppWillTerminate),
+        }
+    }
+
+    /// Convert touchscreen co-ordinates, which SDL normalizes to the range
+    /// [0, 1], to window co-ordinates like those of mouse events.
+    fn finger_coords(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (width, height) = self.window.size();
+        (x * width as f32, y * height as f32)
+    }
+
     /// Pop an event from the queue (in FIFO order, except for high priority
     /// events)
     pub fn pop_event(&mut self) -> Option<Event> {
@@ -508,7 +817,7 @@ impl Window {
         let controller = self.controllers.remove(idx);
         log!("Warning: Controller disconnected: {}", controller.name());
     }
-    pub fn print_accelerometer_notice(&self) {
+    pub fn print_accelerometer_notice(&self, options: &Options) {
         log!("This app uses the accelerometer.");
         if !self.controllers.is_empty() {
             log!("Your connected controller's left analog stick will be used for accelerometer simulation.");
@@ -518,15 +827,20 @@ impl Window {
         } else if self.accelerometer.is_some() {
             log!("Your device's accelerometer will be used for accelerometer simulation.");
             log!("Connect a controller if you would prefer to use an analog stick.");
-        } else if self.controllers.is_empty() {
+        } else if self.controllers.is_empty() && !options.keyboard_tilt {
             log!("Connect a controller to get accelerometer simulation.");
         }
+        if options.keyboard_tilt {
+            log!("You can also hold the arrow keys or WASD to tilt the device.");
+        }
     }
 
     /// Get the real or simulated accelerometer output.
     /// See also [crate::frameworks::uikit::ui_accelerometer].
     pub fn get_acceleration(&self, options: &Options) -> (f32, f32, f32) {
-        if self.controllers.is_empty() {
+        let (keyboard_x, keyboard_y) = self.get_keyboard_tilt(options);
+
+        if self.controllers.is_empty() && (keyboard_x, keyboard_y) == (0.0, 0.0) {
             if let Some(ref accelerometer) = self.accelerometer {
                 let data = accelerometer.get_data().unwrap();
                 let sdl2::sensor::SensorData::Accel(data) = data else { panic!(); };
@@ -544,6 +858,8 @@ impl Window {
 
         // Get left analog stick input. The range is [-1, 1] on each axis.
         let (x, y, _) = self.get_controller_stick(options, true);
+        // The keyboard simulates an analog stick too.
+        let (x, y) = (x + keyboard_x, y + keyboard_y);
 
         // Correct for window rotation
         let [x, y] = self.input_rotation_matrix().transform([x, y]);
@@ -665,6 +981,47 @@ impl Window {
         (x, y, pressed)
     }
 
+    /// Get simulated analog stick input from the arrow keys and WASD, for tilt
+    /// simulation. Keys mapped with `--key-to-touch=` are ignored. The range is
+    /// [-1, 1] on each axis.
+    fn get_keyboard_tilt(&self, options: &Options) -> (f32, f32) {
+        use sdl2::keyboard::{Keycode, Scancode};
+
+        if !options.keyboard_tilt {
+            return (0.0, 0.0);
+        }
+
+        let keyboard = self.event_pump.keyboard_state();
+        // Scancodes are used so that WASD is in the same place on all keyboard
+        // layouts.
+        let pressed = |scancodes: [Scancode; 2]| {
+            scancodes.into_iter().any(|scancode| {
+                let mapped_to_touch = Keycode::from_scancode(scancode).map_or(false, |keycode| {
+                    options
+                        .key_to_touch
+                        .contains_key(&keycode.name().to_lowercase())
+                });
+                keyboard.is_scancode_pressed(scancode) && !mapped_to_touch
+            })
+        };
+
+        let (mut x, mut y) = (0.0, 0.0);
+        if pressed([Scancode::Left, Scancode::A]) {
+            x -= 1.0;
+        }
+        if pressed([Scancode::Right, Scancode::D]) {
+            x += 1.0;
+        }
+        // Like an analog stick, -y is up.
+        if pressed([Scancode::Up, Scancode::W]) {
+            y -= 1.0;
+        }
+        if pressed([Scancode::Down, Scancode::S]) {
+            y += 1.0;
+        }
+        (x, y)
+    }
+
     pub fn create_gl_context(&self, version: GLVersion) -> Result<GLContext, String> {
         let attr = self.video_ctx.gl_attr();
         match version {
@@ -726,9 +1083,88 @@ impl Window {
         let (vx, vy, vw, vh) = self.viewport();
         let viewport = (vx, vy + self.viewport_y_offset(), vw, vh);
 
+        // hold onto GL context so the image doesn't disappear, and hold
+        // onto image so we can rotate later if necessary
+        let image = self.splash_image.take().unwrap();
+        self.display_image(image.pixels(), image.dimensions(), viewport, matrix);
+        self.splash_image = Some(image);
+    }
+
+    /// Display a frame of a movie, provided as RGBA8 pixel data in
+    /// top-to-bottom row order, replacing whatever the app has presented.
+    ///
+    /// iPhone OS always plays movies in landscape orientation, so the frame is
+    /// rotated if the device is currently in portrait orientation. `scale` is
+    /// given the size in pixels of the landscape viewport and should return the
+    /// size the frame should be scaled to. The frame is centered and cropped to
+    /// the viewport if it is larger.
+    pub fn display_movie_frame(
+        &mut self,
+        pixels: &[u8],
+        (width, height): (u32, u32),
+        scale: impl FnOnce((f32, f32)) -> (f32, f32),
+    ) {
+        let rotated = self.device_orientation == DeviceOrientation::Portrait;
+        let rotation = if rotated {
+            Matrix::z_rotation(-FRAC_PI_2)
+        } else {
+            Matrix::identity()
+        };
+        let matrix = rotation.multiply(&Matrix::y_flip());
+
+        let (vx, vy, vw, vh) = self.viewport();
+        let (landscape_width, landscape_height) = if rotated { (vh, vw) } else { (vw, vh) };
+        let (scaled_width, scaled_height) =
+            scale((landscape_width as f32, landscape_height as f32));
+
+        // Crop the frame so that the part that is displayed fits the viewport.
+        let crop_width = (width as f32 * (landscape_width as f32 / scaled_width).min(1.0)) as u32;
+        let crop_height =
+            (height as f32 * (landscape_height as f32 / scaled_height).min(1.0)) as u32;
+        let scaled_width = scaled_width.min(landscape_width as f32) as u32;
+        let scaled_height = scaled_height.min(landscape_height as f32) as u32;
+        let cropped;
+        let pixels = if (crop_width, crop_height) != (width, height) {
+            let (crop_x, crop_y) = ((width - crop_width) / 2, (height - crop_height) / 2);
+            cropped = pixels
+                .chunks_exact(width as usize * 4)
+                .skip(crop_y as usize)
+                .take(crop_height as usize)
+                .flat_map(|row| &row[crop_x as usize * 4..(crop_x + crop_width) as usize * 4])
+                .copied()
+                .collect::<Vec<u8>>();
+            &cropped[..]
+        } else {
+            pixels
+        };
+
+        let (frame_width, frame_height) = if rotated {
+            (scaled_height, scaled_width)
+        } else {
+            (scaled_width, scaled_height)
+        };
+        let viewport = (
+            vx + (vw - frame_width) / 2,
+            vy + (vh - frame_height) / 2 + self.viewport_y_offset(),
+            frame_width,
+            frame_height,
+        );
+        self.display_image(pixels, (crop_width, crop_height), viewport, matrix);
+    }
+
+    /// Upload an RGBA8 image to a texture and present it with the internal
+    /// OpenGL ES context.
+    fn display_image(
+        &mut self,
+        pixels: &[u8],
+        (width, height): (u32, u32),
+        viewport: (u32, u32, u32, u32),
+        matrix: Matrix<2>,
+    ) {
+        assert!(pixels.len() == width as usize * height as usize * 4);
+
         self.make_internal_gl_ctx_current();
 
-        let image = self.splash_image.as_ref().unwrap();
         let gl_ctx = self.internal_gl_ctx.as_deref_mut().unwrap();
 
         use crate::gles::gles11_raw as gles11; // constants only
@@ -737,7 +1173,6 @@ impl Window {
             let mut texture = 0;
             gl_ctx.GenTextures(1, &mut texture);
             gl_ctx.BindTexture(gles11::TEXTURE_2D, texture);
-            let (width, height) = image.dimensions();
             gl_ctx.TexImage2D(
                 gles11::TEXTURE_2D,
                 0,
@@ -747,7 +1182,7 @@ impl Window {
                 0,
                 gles11::RGBA,
                 gles11::UNSIGNED_BYTE,
-                image.pixels().as_ptr() as *const _,
+                pixels.as_ptr() as *const _,
             );
             gl_ctx.TexParameteri(
                 gles11::TEXTURE_2D,
@@ -768,15 +1203,13 @@ impl Window {
         };
 
         self.window.gl_swap_window();
-
-        // hold onto GL context so the image doesn't disappear, and hold
-        // onto image so we can rotate later if necessary
     }
 
     /// Swap front-buffer and back-buffer so the result of OpenGL rendering is
     /// presented.
-    pub fn swap_window(&self) {
+    pub fn swap_window(&mut self) {
         self.window.gl_swap_window();
+        self.frame_count += 1;
     }
 
     /// Consider the emulated device to be rotated to a particular orientation.
@@ -8,7 +8,16 @@ This is a main file for the TestApp which is used for integration testing.
 This code supposed to be compiled with iPhone SDK and Xcode 3.1 Developer Tools
 for Mac OS X v10.5
 */
+#import <Foundation/Foundation.h>
+#include <dlfcn.h>
 #include <errno.h>
+#include <fcntl.h>
+#include <mach/mach.h>
+#include <objc/message.h>
+#include <objc/runtime.h>
+#include <pthread.h>
+#include <semaphore.h>
+#include <setjmp.h>
 #include <stdarg.h>
 #include <stdio.h>
 #include <stdlib.h>
@@ -70,7 +79,62 @@ int test_sscanf() {
   if (!(matched == 2 && a == 111 && b == 42))
     return -1;
   matched = sscanf("abc", "%d.%d", &a, &b);
-  return (matched == 0) ? 0 : -1;
+  if (matched != 0)
+    return -1;
+  matched = sscanf(" -12 +3", "%d%d", &a, &b);
+  if (!(matched == 2 && a == -12 && b == 3))
+    return -1;
+  matched = sscanf("  ", "%d", &a);
+  return (matched == EOF) ? 0 : -1;
+}
+
+int test_stdio_files() {
+  FILE *file = tmpfile();
+  if (file == NULL)
+    return -1;
+  int res = 0;
+  if (fprintf(file, "%d,%d\nsecond line\n", 42, 7) != 17)
+    res = -1;
+  if (ferror(file) || feof(file))
+    res = -1;
+  rewind(file);
+
+  int a, b;
+  if (fscanf(file, "%d,%d\n", &a, &b) != 2 || a != 42 || b != 7)
+    res = -1;
+
+  int c = fgetc(file);
+  if (c != 's' || ungetc(c, file) != 's')
+    res = -1;
+  char line[32];
+  if (fgets(line, sizeof(line), file) != line ||
+      strcmp(line, "second line\n") != 0)
+    res = -1;
+  // A line longer than the buffer is split.
+  rewind(file);
+  if (fgets(line, 3, file) != line || strcmp(line, "42") != 0)
+    res = -1;
+  if (fgets(line, 0, file) != NULL)
+    res = -1;
+
+  fseek(file, 0, SEEK_END);
+  if (fgetc(file) != EOF || !feof(file) || ferror(file))
+    res = -1;
+  if (fgets(line, sizeof(line), file) != NULL)
+    res = -1;
+  if (fscanf(file, "%d", &a) != EOF)
+    res = -1;
+  clearerr(file);
+  if (feof(file))
+    res = -1;
+
+  if (fseek(file, 0, 42) != -1)
+    res = -1;
+  if (setvbuf(file, NULL, 42, 0) != EOF)
+    res = -1;
+  if (fclose(file) != 0)
+    res = -1;
+  return res;
 }
 
 int test_errno() { return (errno == 0) ? 0 : -1; }
@@ -84,6 +148,254 @@ int test_realloc() {
   return res == 0 ? 0 : -1;
 }
 
+int test_sem() {
+  sem_t *sem = sem_open("/test_sem", O_CREAT, 0644, 1);
+  if (sem == SEM_FAILED)
+    return -1;
+  int res = 0;
+  if (sem_wait(sem) != 0)
+    res = -1;
+  // The value is now zero.
+  if (sem_trywait(sem) != -1)
+    res = -1;
+  sem_post(sem);
+  if (sem_trywait(sem) != 0)
+    res = -1;
+  sem_close(sem);
+  sem_unlink("/test_sem");
+  return res;
+}
+
+int test_mach_semaphore() {
+  semaphore_t sem1, sem2;
+  if (semaphore_create(mach_task_self(), &sem1, SYNC_POLICY_FIFO, 0) !=
+      KERN_SUCCESS)
+    return -1;
+  int res = 0;
+  mach_timespec_t short_wait = {0, 1000000}; // 1ms
+  if (semaphore_timedwait(sem1, short_wait) != KERN_OPERATION_TIMED_OUT)
+    res = -1;
+  mach_timespec_t bad_wait = {0, -1};
+  if (semaphore_timedwait(sem1, bad_wait) != KERN_INVALID_VALUE)
+    res = -1;
+  semaphore_signal(sem1);
+  if (semaphore_timedwait(sem1, short_wait) != KERN_SUCCESS)
+    res = -1;
+  semaphore_destroy(mach_task_self(), sem1);
+  if (semaphore_signal(sem1) != KERN_INVALID_ARGUMENT)
+    res = -1;
+  // The port names of destroyed semaphores aren't reused.
+  if (semaphore_create(mach_task_self(), &sem2, SYNC_POLICY_FIFO, 0) !=
+      KERN_SUCCESS)
+    return -1;
+  if (sem2 == sem1)
+    res = -1;
+  semaphore_destroy(mach_task_self(), sem2);
+  return res;
+}
+
+pthread_mutex_t cond_mutex;
+pthread_cond_t cond = PTHREAD_COND_INITIALIZER;
+int cond_flag;
+
+void *cond_thread(void *arg) {
+  pthread_mutex_lock(&cond_mutex);
+  cond_flag = 1;
+  pthread_cond_signal(&cond);
+  pthread_mutex_unlock(&cond_mutex);
+  return arg;
+}
+
+int test_pthread_cond() {
+  pthread_mutexattr_t attr;
+  pthread_mutexattr_init(&attr);
+  pthread_mutexattr_settype(&attr, PTHREAD_MUTEX_RECURSIVE);
+  pthread_mutex_init(&cond_mutex, &attr);
+  pthread_mutexattr_destroy(&attr);
+
+  int res = 0;
+  // Waiting must release both lock levels, or the other thread can never
+  // lock the mutex.
+  pthread_mutex_lock(&cond_mutex);
+  pthread_mutex_lock(&cond_mutex);
+  // A timeout in the past is reached immediately.
+  struct timespec abstime = {0, 0};
+  if (pthread_cond_timedwait(&cond, &cond_mutex, &abstime) != ETIMEDOUT)
+    res = -1;
+  pthread_t thread;
+  if (pthread_create(&thread, NULL, &cond_thread, (void *)42) != 0)
+    return -1;
+  while (!cond_flag)
+    pthread_cond_wait(&cond, &cond_mutex);
+  // Both lock levels are restored afterwards.
+  if (pthread_mutex_unlock(&cond_mutex) != 0)
+    res = -1;
+  if (pthread_mutex_unlock(&cond_mutex) != 0)
+    res = -1;
+  if (pthread_mutex_unlock(&cond_mutex) != EPERM)
+    res = -1;
+  void *ret;
+  if (pthread_join(thread, &ret) != 0 || ret != (void *)42)
+    res = -1;
+  // A locked mutex can't be destroyed.
+  pthread_mutex_lock(&cond_mutex);
+  if (pthread_mutex_destroy(&cond_mutex) != EBUSY)
+    res = -1;
+  pthread_mutex_unlock(&cond_mutex);
+  if (pthread_mutex_destroy(&cond_mutex) != 0)
+    res = -1;
+  return res;
+}
+
+jmp_buf test_jmp_buf;
+
+#ifndef __thumb__
+// Only ARM code can use VFP instructions.
+void set_vfp_state(unsigned d8_low, unsigned fpscr) {
+  __asm__ volatile("fmdrr d8, %0, %0\n\tfmxr fpscr, %1"
+                   :
+                   : "r"(d8_low), "r"(fpscr));
+}
+void get_vfp_state(unsigned *d8_low, unsigned *fpscr) {
+  unsigned d8_low_value, fpscr_value;
+  __asm__ volatile("fmrdl %0, d8\n\tfmrx %1, fpscr"
+                   : "=r"(d8_low_value), "=r"(fpscr_value));
+  *d8_low = d8_low_value;
+  *fpscr = fpscr_value;
+}
+#endif
+
+void __attribute__((noinline)) longjmp_with_clobbered_registers(int val) {
+  // The compiler saves these registers in the prologue, but longjmp() skips
+  // the epilogue that would restore them.
+  __asm__ volatile("mov r4, #0\n\tmov r5, #0\n\tmov r6, #0\n\t"
+                   "mov r8, r4\n\tmov r10, r4\n\tmov r11, r4"
+                   :
+                   :
+                   : "r4", "r5", "r6", "r8", "r10", "r11");
+#ifndef __thumb__
+  set_vfp_state(0, 0);
+#endif
+  longjmp(test_jmp_buf, val);
+}
+
+int test_setjmp() {
+  // Unmodified after setjmp(), so these keep their values after longjmp().
+  int a = atoi("11"), b = atoi("22"), c = atoi("33"), d = atoi("44"),
+      e = atoi("55"), f = atoi("66");
+  volatile int passes = 0;
+  volatile int res = 0;
+#ifndef __thumb__
+  // d8 and round-towards-zero mode.
+  set_vfp_state(0x12345678, 0x00C00000);
+#endif
+  int ret = setjmp(test_jmp_buf);
+  passes++;
+  if (passes == 1) {
+    if (ret != 0)
+      return -1;
+    longjmp_with_clobbered_registers(0);
+  } else if (passes == 2) {
+    // longjmp() with 0 makes setjmp() return 1 instead.
+    if (ret != 1)
+      res = -1;
+    longjmp_with_clobbered_registers(42);
+  }
+  if (passes != 3 || ret != 42)
+    res = -1;
+  if (a != 11 || b != 22 || c != 33 || d != 44 || e != 55 || f != 66)
+    res = -1;
+#ifndef __thumb__
+  unsigned d8_low, fpscr;
+  get_vfp_state(&d8_low, &fpscr);
+  if (d8_low != 0x12345678 || (fpscr & 0x00C00000) != 0x00C00000)
+    res = -1;
+  set_vfp_state(0, 0);
+#endif
+  // Host functions can still call back into guest code afterwards, which
+  // relies on the host-to-guest call depth being right.
+  if (test_qsort() != 0)
+    res = -1;
+  return res;
+}
+
+@interface TestRuntimeObject : NSObject
+- (int)value;
+@end
+@implementation TestRuntimeObject
+- (int)value {
+  return 1;
+}
+@end
+
+int released_trackers;
+
+@interface TestReleaseTracker : NSObject
+@end
+@implementation TestReleaseTracker
+- (void)dealloc {
+  released_trackers++;
+  [super dealloc];
+}
+@end
+
+int returns_two(id self, SEL _cmd) { return 2; }
+int returns_three(id self, SEL _cmd) { return 3; }
+
+int test_objc_runtime() {
+  Class cls = objc_getClass("TestRuntimeObject");
+  if (cls == Nil)
+    return -1;
+  TestRuntimeObject *obj = [TestRuntimeObject new];
+  int res = 0;
+
+  // Existing methods can't be added again, new ones can.
+  if (class_addMethod(cls, @selector(value), (IMP)&returns_two, "i@:"))
+    res = -1;
+  SEL other_value = sel_registerName("otherValue");
+  if (!class_addMethod(cls, other_value, (IMP)&returns_two, "i@:"))
+    res = -1;
+  if (((int (*)(id, SEL))objc_msgSend)(obj, other_value) != 2)
+    res = -1;
+
+  Method method = class_getInstanceMethod(cls, @selector(value));
+  IMP old_imp = method_setImplementation(method, (IMP)&returns_three);
+  if ([obj value] != 3)
+    res = -1;
+  method_setImplementation(method, old_imp);
+  if ([obj value] != 1)
+    res = -1;
+
+  // NULL methods and bad classes are rejected.
+  if (method_getName(NULL) != NULL ||
+      method_setImplementation(NULL, old_imp) != NULL)
+    res = -1;
+  if (class_addMethod(Nil, sel_registerName("noClass"), (IMP)&returns_two,
+                      "i@:"))
+    res = -1;
+
+  // Associated objects are missing from this SDK's headers, but touchHLE
+  // provides them through dlsym().
+  void *libsystem = dlopen("/usr/lib/libSystem.B.dylib", RTLD_LAZY);
+  void (*set_associated_object)(id, const void *, id, unsigned) =
+      dlsym(libsystem, "objc_setAssociatedObject");
+  id (*get_associated_object)(id, const void *) =
+      dlsym(libsystem, "objc_getAssociatedObject");
+  static char key;
+  TestReleaseTracker *tracker = [TestReleaseTracker new];
+  // OBJC_ASSOCIATION_RETAIN_NONATOMIC
+  set_associated_object(obj, &key, tracker, 1);
+  [tracker release];
+  if (released_trackers != 0 || get_associated_object(obj, &key) != tracker)
+    res = -1;
+  // The value is released once the object is deallocated.
+  [obj release];
+  if (released_trackers != 1)
+    res = -1;
+  dlclose(libsystem);
+  return res;
+}
+
 #define FUNC_DEF(func)                                                         \
   { &func, #func }
 struct {
@@ -91,7 +403,10 @@ struct {
   const char *name;
 } test_func_array[] = {
     FUNC_DEF(test_qsort), FUNC_DEF(test_vsnprintf), FUNC_DEF(test_sscanf),
-    FUNC_DEF(test_errno), FUNC_DEF(test_realloc),
+    FUNC_DEF(test_errno), FUNC_DEF(test_realloc), FUNC_DEF(test_stdio_files),
+    FUNC_DEF(test_sem), FUNC_DEF(test_mach_semaphore),
+    FUNC_DEF(test_pthread_cond), FUNC_DEF(test_objc_runtime),
+    FUNC_DEF(test_setjmp),
 };
 
 int main(int argc, char *argv[]) {
@@ -24,25 +24,33 @@ fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
         .position(|window| window == needle)
 }
 
-#[test]
-fn run_test_app() -> Result<(), Box<dyn std::error::Error>> {
-    let binary_name = "touchHLE";
-    let binary_path = target_dir().join(format!("{}{}", binary_name, env::consts::EXE_SUFFIX));
-
-    let mut cmd = Command::new(binary_path);
-
+fn test_app_path() -> std::io::Result<std::path::PathBuf> {
     let mut test_app_path = current_dir()?;
     test_app_path.push("tests");
     test_app_path.push("TestApp.app");
+    Ok(test_app_path)
+}
 
-    let output = cmd
-        .arg(test_app_path)
+fn run_touchhle(args: &[&std::ffi::OsStr]) -> std::process::Output {
+    let binary_name = "touchHLE";
+    let binary_path = target_dir().join(format!("{}{}", binary_name, env::consts::EXE_SUFFIX));
+
+    let output = Command::new(binary_path)
+        .args(args)
         .output()
         .expect("failed to execute process");
 
     std::io::stdout().write_all(&output.stdout).unwrap();
     std::io::stderr().write_all(&output.stderr).unwrap();
 
+    output
+}
+
+#[test]
+fn run_test_app() -> Result<(), Box<dyn std::error::Error>> {
+    let test_app_path = test_app_path()?;
+    let output = run_touchhle(&[test_app_path.as_os_str()]);
+
     assert!(output.status.success());
     // sanity check: check that emulation actually happened
     assert_ne!(
@@ -52,3 +60,22 @@ fn run_test_app() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn run_test_app_headless() -> Result<(), Box<dyn std::error::Error>> {
+    let test_app_path = test_app_path()?;
+    // The time limit is only a safety net, the test app exits by itself.
+    let output = run_touchhle(&[
+        "--headless".as_ref(),
+        "--exit-after-seconds=60".as_ref(),
+        test_app_path.as_os_str(),
+    ]);
+
+    assert!(output.status.success());
+    assert_ne!(
+        find_subsequence(output.stderr.as_slice(), b"CPU emulation begins now."),
+        None
+    );
+
+    Ok(())
+}